@@ -1,8 +1,14 @@
+use crate::line_index::LineIndex;
+use crate::parser::{parse_section_header, strip_prefix_ignore_case, AssDocument, AssTime, Event};
 use dashmap::DashMap;
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, Location, NumberOrString,
+    Position, Range, Url,
+};
 
 #[derive(Debug, Clone)]
 pub struct PerformanceMetrics {
@@ -17,17 +23,17 @@ pub struct PerformanceMetrics {
 #[derive(Debug, Clone)]
 pub struct StyleInheritance {
     pub name: String,
+    /// Line of the style's definition.
+    pub line: u32,
     pub parent: Option<String>,
     pub properties: HashMap<String, String>,
 }
 
-#[derive(Debug)]
-pub struct TimingOverlap {
-    pub line1: usize,
-    pub line2: usize,
-    pub start_time: String,
-    pub end_time: String,
-    pub overlap_duration: Duration,
+/// A problem the advanced checks found on one line of the document.
+#[derive(Debug, Clone)]
+pub struct AdvancedWarning {
+    pub line: u32,
+    pub message: String,
 }
 
 static PERFORMANCE_CACHE: Lazy<DashMap<String, PerformanceMetrics>> = Lazy::new(DashMap::new);
@@ -37,7 +43,8 @@ static STYLE_CACHE: Lazy<Arc<Mutex<HashMap<String, StyleInheritance>>>> =
 pub struct AdvancedFeatures {
     file_path: String,
     styles: HashMap<String, StyleInheritance>,
-    timing_overlaps: Vec<TimingOverlap>,
+    /// Report overlapping events across layers too, not just within one.
+    pub strict_overlaps: bool,
 }
 
 impl AdvancedFeatures {
@@ -45,31 +52,28 @@ impl AdvancedFeatures {
         Self {
             file_path,
             styles: HashMap::new(),
-            timing_overlaps: Vec::new(),
+            strict_overlaps: false,
         }
     }
 
-    pub fn analyze_style_inheritance(&mut self, content: &str) -> Vec<String> {
+    pub fn analyze_style_inheritance(&mut self, index: &LineIndex) -> Vec<AdvancedWarning> {
         let mut warnings = Vec::new();
         self.styles.clear();
 
-        let lines: Vec<&str> = content.lines().collect();
+        let lines = index.lines();
         let mut in_styles_section = false;
 
-        for line in lines {
+        for (line_num, line) in lines.iter().enumerate() {
             let trimmed = line.trim();
 
             // Check for styles section
-            if trimmed.starts_with("[V4") && trimmed.contains("Styles]") {
-                in_styles_section = true;
-                continue;
-            } else if trimmed.starts_with("[") && trimmed.ends_with("]") {
-                in_styles_section = false;
+            if let Some(header) = parse_section_header(trimmed) {
+                in_styles_section = header.name == "V4+ Styles" || header.name == "V4 Styles";
                 continue;
             }
 
-            if in_styles_section && trimmed.starts_with("Style:") {
-                if let Some(style) = self.parse_style_line(trimmed) {
+            if in_styles_section && strip_prefix_ignore_case(trimmed, "Style:").is_some() {
+                if let Some(style) = self.parse_style_line(trimmed, line_num as u32) {
                     self.styles.insert(style.name.clone(), style);
                 }
             }
@@ -79,15 +83,22 @@ impl AdvancedFeatures {
         for (name, style) in &self.styles {
             if let Some(parent) = &style.parent {
                 if self.has_circular_reference(name, parent, &mut Vec::new()) {
-                    warnings.push(format!("Circular style inheritance detected: {name}"));
+                    warnings.push(AdvancedWarning {
+                        line: style.line,
+                        message: format!("Circular style inheritance detected: {name}"),
+                    });
                 }
             }
 
             // Check for unused properties
             if style.properties.is_empty() {
-                warnings.push(format!("Style '{name}' has no properties defined"));
+                warnings.push(AdvancedWarning {
+                    line: style.line,
+                    message: format!("Style '{name}' has no properties defined"),
+                });
             }
         }
+        warnings.sort_by_key(|warning| warning.line);
 
         // Cache styles for future use
         if let Ok(mut cache) = STYLE_CACHE.lock() {
@@ -98,13 +109,15 @@ impl AdvancedFeatures {
         warnings
     }
 
-    fn parse_style_line(&self, line: &str) -> Option<StyleInheritance> {
+    fn parse_style_line(&self, line: &str, line_num: u32) -> Option<StyleInheritance> {
         let parts: Vec<&str> = line.split(',').collect();
         if parts.len() < 2 {
             return None;
         }
 
-        let name = parts[0].replace("Style:", "").trim().to_string();
+        let name = strip_prefix_ignore_case(parts[0], "Style:")?
+            .trim()
+            .to_string();
         let mut properties = HashMap::new();
 
         // Parse style properties (simplified for demonstration)
@@ -114,6 +127,7 @@ impl AdvancedFeatures {
 
         Some(StyleInheritance {
             name,
+            line: line_num,
             parent: None, // Would need format specification to determine parent
             properties,
         })
@@ -140,97 +154,75 @@ impl AdvancedFeatures {
         false
     }
 
-    pub fn detect_timing_overlaps(&mut self, content: &str) -> Vec<String> {
-        let mut warnings = Vec::new();
-        self.timing_overlaps.clear();
-
-        let mut dialogue_lines = Vec::new();
-        let lines: Vec<&str> = content.lines().collect();
-        let mut in_events_section = false;
-
-        for (line_num, line) in lines.iter().enumerate() {
-            let trimmed = line.trim();
-
-            if trimmed.starts_with("[Events]") {
-                in_events_section = true;
-                continue;
-            } else if trimmed.starts_with("[") && trimmed.ends_with("]") {
-                in_events_section = false;
-                continue;
-            }
-
-            if in_events_section && trimmed.starts_with("Dialogue:") {
-                if let Some(timing) = self.parse_dialogue_timing(trimmed, line_num) {
-                    dialogue_lines.push(timing);
-                }
-            }
-        }
-
-        // Check for overlaps
-        for i in 0..dialogue_lines.len() {
-            for j in i + 1..dialogue_lines.len() {
-                if let Some(overlap) =
-                    self.check_timing_overlap(&dialogue_lines[i], &dialogue_lines[j])
-                {
-                    warnings.push(format!(
-                        "Timing overlap detected between lines {} and {} (duration: {}ms, start: {}, end: {})",
-                        overlap.line1 + 1,
-                        overlap.line2 + 1,
-                        overlap.overlap_duration.as_millis(),
-                        overlap.start_time,
-                        overlap.end_time
-                    ));
-                    self.timing_overlaps.push(overlap);
+    /// Events on screen at the same time on the same layer, or on any layer in
+    /// strict mode. Each overlap is reported on the later-starting event's
+    /// times, pointing back at the event it overlaps. Events are swept in
+    /// start order, keeping only those still on screen. `progress` is called
+    /// with the events swept so far and the total.
+    pub fn detect_timing_overlaps(
+        &self,
+        document: &AssDocument,
+        uri: &Url,
+        progress: &mut dyn FnMut(usize, usize),
+    ) -> Vec<Diagnostic> {
+        let mut events: Vec<(&Event, AssTime, AssTime)> = document
+            .events
+            .iter()
+            .filter(|event| event.event_type == "Dialogue")
+            .filter_map(|event| Some((event, event.start?, event.end?)))
+            .filter(|(_, start, end)| start < end)
+            .collect();
+        events.sort_by_key(|&(event, start, _)| (start, event.range.start.line));
+
+        let mut diagnostics = Vec::new();
+        let mut on_screen: Vec<(&Event, AssTime)> = Vec::new();
+        let total = events.len();
+        for (swept, (event, start, end)) in events.into_iter().enumerate() {
+            progress(swept, total);
+            on_screen.retain(|&(_, other_end)| other_end > start);
+            for &(other, other_end) in &on_screen {
+                if other.layer != event.layer && !self.strict_overlaps {
+                    continue;
                 }
+                let overlap_end = end.min(other_end);
+                let overlap = overlap_end.saturating_sub(start);
+                let line = event.range.start.line;
+                let span = event.timing_span();
+                let other_span = other.timing_span();
+                let other_line = other.range.start.line;
+                diagnostics.push(Diagnostic {
+                    range: Range::new(
+                        Position::new(line, span.start as u32),
+                        Position::new(line, span.end as u32),
+                    ),
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(NumberOrString::String("timing_overlap".to_string())),
+                    code_description: None,
+                    source: Some("ass-lsp-advanced".to_string()),
+                    message: format!(
+                        "Overlaps line {} on layer {} for {:.2}s ({start} to {overlap_end})",
+                        other_line + 1,
+                        other.layer,
+                        overlap.centiseconds() as f64 / 100.0
+                    ),
+                    related_information: Some(vec![DiagnosticRelatedInformation {
+                        location: Location::new(
+                            uri.clone(),
+                            Range::new(
+                                Position::new(other_line, other_span.start as u32),
+                                Position::new(other_line, other_span.end as u32),
+                            ),
+                        ),
+                        message: "Overlapping event".to_string(),
+                    }]),
+                    tags: None,
+                    data: None,
+                });
             }
+            on_screen.push((event, end));
         }
-
-        warnings
-    }
-
-    fn parse_dialogue_timing(
-        &self,
-        line: &str,
-        line_num: usize,
-    ) -> Option<((String, String), usize)> {
-        let parts: Vec<&str> = line.split(',').collect();
-        if parts.len() < 3 {
-            return None;
-        }
-
-        let start_time = parts[1].trim().to_string();
-        let end_time = parts[2].trim().to_string();
-
-        Some(((start_time, end_time), line_num))
-    }
-
-    fn check_timing_overlap(
-        &self,
-        line1: &((String, String), usize),
-        line2: &((String, String), usize),
-    ) -> Option<TimingOverlap> {
-        let ((start1, end1), line_num1) = line1;
-        let ((start2, end2), line_num2) = line2;
-
-        // Simplified overlap detection (would need proper time parsing)
-        if start1 < end2 && start2 < end1 {
-            // Calculate overlap duration (simplified)
-            let overlap_duration = if start1 > start2 {
-                Duration::from_millis(100) // Placeholder - would calculate actual overlap
-            } else {
-                Duration::from_millis(50) // Placeholder - would calculate actual overlap
-            };
-
-            Some(TimingOverlap {
-                line1: *line_num1,
-                line2: *line_num2,
-                start_time: start1.clone(),
-                end_time: end1.clone(),
-                overlap_duration,
-            })
-        } else {
-            None
-        }
+        progress(total, total);
+        diagnostics
     }
 
     pub fn record_performance_metrics(&self, metrics: PerformanceMetrics) {
@@ -270,58 +262,34 @@ impl AdvancedFeatures {
         suggestions
     }
 
-    pub fn validate_advanced(&self, content: &str) -> Vec<String> {
+    pub fn validate_advanced(&self, index: &LineIndex) -> Vec<AdvancedWarning> {
         let mut warnings = Vec::new();
 
         // Check for common ASS issues
-        let lines: Vec<&str> = content.lines().collect();
+        let lines = index.lines();
 
         for (line_num, line) in lines.iter().enumerate() {
             let trimmed = line.trim();
-
-            // Check for malformed override tags
-            if trimmed.contains('{') && !trimmed.contains('}') {
-                warnings.push(format!("Line {}: Unclosed override tag", line_num + 1));
-            }
+            let mut warn = |message: &str| {
+                warnings.push(AdvancedWarning {
+                    line: line_num as u32,
+                    message: message.to_string(),
+                })
+            };
 
             // Check for invalid escape sequences
             if trimmed.contains("\\\\") && !trimmed.contains("\\N") && !trimmed.contains("\\n") {
-                warnings.push(format!(
-                    "Line {}: Potentially invalid escape sequence",
-                    line_num + 1
-                ));
+                warn("Potentially invalid escape sequence");
             }
 
             // Check for extremely long lines that might cause rendering issues
             if trimmed.len() > 500 {
-                warnings.push(format!(
-                    "Line {}: Very long line may cause rendering issues",
-                    line_num + 1
-                ));
+                warn("Very long line may cause rendering issues");
             }
         }
 
         warnings
     }
-
-    pub fn get_timing_summary(&self) -> String {
-        if self.timing_overlaps.is_empty() {
-            return "No timing overlaps detected".to_string();
-        }
-
-        let mut summary = format!("Found {} timing overlaps:\n", self.timing_overlaps.len());
-        for overlap in &self.timing_overlaps {
-            summary.push_str(&format!(
-                "- Lines {}-{}: {} to {} ({}ms overlap)\n",
-                overlap.line1 + 1,
-                overlap.line2 + 1,
-                overlap.start_time,
-                overlap.end_time,
-                overlap.overlap_duration.as_millis()
-            ));
-        }
-        summary
-    }
 }
 
 #[allow(dead_code)]
@@ -331,3 +299,95 @@ pub fn clear_caches() {
         cache.clear();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::AssParser;
+
+    const HEADER: &str = "[Script Info]\nScriptType: v4.00+\n\n[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n";
+
+    fn overlaps(events: &[&str], strict: bool) -> Vec<Diagnostic> {
+        let text = format!("{HEADER}{}\n", events.join("\n"));
+        let document = AssParser::new().parse(&text);
+        let mut advanced = AdvancedFeatures::new("test.ass".to_string());
+        advanced.strict_overlaps = strict;
+        let uri = Url::parse("file:///tmp/test.ass").unwrap();
+        advanced.detect_timing_overlaps(&document, &uri, &mut |_, _| {})
+    }
+
+    #[test]
+    fn overlaps_are_reported_within_a_layer_unless_strict() {
+        let events = [
+            "Dialogue: 0,0:00:01.00,0:00:04.00,Default,,0,0,0,,Speaker",
+            "Dialogue: 1,0:00:02.00,0:00:03.00,Default,,0,0,0,,Sign above",
+            "Dialogue: 0,0:00:03.50,0:00:06.00,Default,,0,0,0,,Next line",
+        ];
+        let found = overlaps(&events, false);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].range.start.line, 7);
+        let related = &found[0].related_information.as_ref().unwrap()[0];
+        assert_eq!(related.location.range.start.line, 5);
+
+        let found = overlaps(&events, true);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].range.start.line, 6);
+        assert!(found[0].message.starts_with("Overlaps line 6 on layer 0"));
+    }
+
+    #[test]
+    fn overlap_message_gives_the_shared_time() {
+        let events = [
+            "Dialogue: 0,0:00:01.00,0:00:04.00,Default,,0,0,0,,First",
+            "Dialogue: 0,0:00:03.25,0:00:05.00,Default,,0,0,0,,Starts early",
+            "Dialogue: 0,0:00:05.00,0:00:06.00,Default,,0,0,0,,Touches, doesn't overlap",
+        ];
+        let found = overlaps(&events, false);
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].message,
+            "Overlaps line 6 on layer 0 for 0.75s (0:00:03.25 to 0:00:04.00)"
+        );
+        let line = events[1];
+        let span = found[0].range.start.character as usize..found[0].range.end.character as usize;
+        assert_eq!(&line[span], "0:00:03.25,0:00:05.00");
+    }
+
+    #[test]
+    fn lowercase_style_lines_are_read_for_inheritance() {
+        let text = include_str!("../tests/fixtures/lowercase.ass");
+        let index = LineIndex::new(text.to_string(), crate::line_index::PositionEncoding::Utf16);
+        let mut advanced = AdvancedFeatures::new("lowercase.ass".to_string());
+        assert!(advanced.analyze_style_inheritance(&index).is_empty());
+        let mut names: Vec<(&str, u32)> = advanced
+            .styles
+            .values()
+            .map(|style| (style.name.as_str(), style.line))
+            .collect();
+        names.sort();
+        assert_eq!(names, [("Default", 8), ("Sign", 9)]);
+    }
+
+    #[test]
+    fn advanced_warnings_carry_the_line_they_were_found_on() {
+        let long = "word ".repeat(120);
+        let text = format!(
+            "{HEADER}Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,{long}\n\
+             Dialogue: 0,0:00:02.00,0:00:03.00,Default,,0,0,0,,Fine\n\
+             Dialogue: 0,0:00:03.00,0:00:04.00,Default,,0,0,0,,C:\\\\path\n"
+        );
+        let index = LineIndex::new(text, crate::line_index::PositionEncoding::Utf16);
+        let warnings = AdvancedFeatures::new("test.ass".to_string()).validate_advanced(&index);
+        let found: Vec<(u32, &str)> = warnings
+            .iter()
+            .map(|warning| (warning.line, warning.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (5, "Very long line may cause rendering issues"),
+                (7, "Potentially invalid escape sequence")
+            ]
+        );
+    }
+}
@@ -0,0 +1,76 @@
+/// Unicode characters that set text direction explicitly, with their names.
+/// Renderers differ in which they honour, and editors leave them behind
+/// invisibly when text is copied between applications.
+pub const DIRECTIONAL_CONTROLS: &[(char, &str)] = &[
+    ('\u{061C}', "ARABIC LETTER MARK"),
+    ('\u{200E}', "LEFT-TO-RIGHT MARK"),
+    ('\u{200F}', "RIGHT-TO-LEFT MARK"),
+    ('\u{202A}', "LEFT-TO-RIGHT EMBEDDING"),
+    ('\u{202B}', "RIGHT-TO-LEFT EMBEDDING"),
+    ('\u{202C}', "POP DIRECTIONAL FORMATTING"),
+    ('\u{202D}', "LEFT-TO-RIGHT OVERRIDE"),
+    ('\u{202E}', "RIGHT-TO-LEFT OVERRIDE"),
+    ('\u{2066}', "LEFT-TO-RIGHT ISOLATE"),
+    ('\u{2067}', "RIGHT-TO-LEFT ISOLATE"),
+    ('\u{2068}', "FIRST STRONG ISOLATE"),
+    ('\u{2069}', "POP DIRECTIONAL ISOLATE"),
+];
+
+/// The right-to-left mark, which renderers treat as a strong right-to-left
+/// character without drawing anything.
+pub const RLM: char = '\u{200F}';
+
+/// Blocks whose letters are strongly right-to-left: Hebrew, Arabic, Syriac,
+/// Thaana, NKo and their supplements and presentation forms.
+const RTL_BLOCKS: &[(char, char)] = &[
+    ('\u{0590}', '\u{08FF}'),
+    ('\u{FB1D}', '\u{FDFF}'),
+    ('\u{FE70}', '\u{FEFF}'),
+    ('\u{10800}', '\u{10FFF}'),
+    ('\u{1E800}', '\u{1EFFF}'),
+];
+
+/// How a character takes part in bidi reordering, simplified to what the
+/// checks need.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BidiClass {
+    /// A right-to-left letter.
+    Rtl,
+    /// A letter of any other script.
+    Ltr,
+    /// Digits, punctuation, symbols and spaces, which take their direction
+    /// from the text around them.
+    Neutral,
+}
+
+/// Classifies `ch` by block and letter-ness, which is close enough to the
+/// Unicode bidi classes for spotting mixed-direction lines. The direction
+/// marks count as letters of their direction, as they do in the bidi
+/// algorithm.
+pub fn bidi_class(ch: char) -> BidiClass {
+    if ch == RLM || ch == '\u{061C}' {
+        return BidiClass::Rtl;
+    }
+    if ch == '\u{200E}' {
+        return BidiClass::Ltr;
+    }
+    if !ch.is_alphabetic() || ch.is_numeric() {
+        return BidiClass::Neutral;
+    }
+    if RTL_BLOCKS
+        .iter()
+        .any(|&(first, last)| (first..=last).contains(&ch))
+    {
+        BidiClass::Rtl
+    } else {
+        BidiClass::Ltr
+    }
+}
+
+/// The name of a directional control character, or `None` for other characters.
+pub fn directional_control(ch: char) -> Option<&'static str> {
+    DIRECTIONAL_CONTROLS
+        .iter()
+        .find(|(control, _)| *control == ch)
+        .map(|(_, name)| *name)
+}
@@ -0,0 +1,254 @@
+use crate::encoding::{self, DecodedText};
+use crate::parser::AssParser;
+use crate::render::RenderTarget;
+use crate::suppression::SuppressionProvider;
+use crate::validation::{ValidationOptions, ValidationProvider};
+use encoding_rs::{Encoding, UTF_8};
+use std::path::Path;
+use tower_lsp::lsp_types::{DiagnosticSeverity, NumberOrString, Url};
+
+const USAGE: &str = "usage: ass-lsp lint [--check-fonts] [--check-equivalent-styles]
+                    [--check-padding] [--check-event-order] [--check-bidi-punctuation]
+                    [--strict-compat]
+                    [--render-target <libass|vsfilter>] [--encoding <label>] <files>...
+       ass-lsp fmt [--check] [--write-utf8] [--encoding <label>] <files>...";
+
+#[derive(Debug, Default)]
+struct Options {
+    check: bool,
+    write_utf8: bool,
+    check_fonts: bool,
+    check_equivalent_styles: bool,
+    check_padding: bool,
+    check_event_order: bool,
+    check_bidi_punctuation: bool,
+    strict_compat: bool,
+    render_target: RenderTarget,
+    fallback: Option<&'static Encoding>,
+    files: Vec<String>,
+}
+
+/// Runs a command-line mode if one was requested. Returns the process exit
+/// code, or `None` when the server should start instead.
+pub fn run(args: &[String]) -> Option<i32> {
+    let (command, rest) = args.split_first()?;
+    let run_command: fn(&Options) -> i32 = match command.as_str() {
+        "lint" => lint,
+        "fmt" => fmt,
+        _ => return None,
+    };
+
+    match parse_options(rest) {
+        Ok(options) if !options.files.is_empty() => Some(run_command(&options)),
+        Ok(_) => {
+            eprintln!("{USAGE}");
+            Some(2)
+        }
+        Err(message) => {
+            eprintln!("error: {message}\n{USAGE}");
+            Some(2)
+        }
+    }
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--check" => options.check = true,
+            "--write-utf8" => options.write_utf8 = true,
+            "--check-fonts" => options.check_fonts = true,
+            "--check-equivalent-styles" => options.check_equivalent_styles = true,
+            "--check-padding" => options.check_padding = true,
+            "--check-event-order" => options.check_event_order = true,
+            "--check-bidi-punctuation" => options.check_bidi_punctuation = true,
+            "--strict-compat" => options.strict_compat = true,
+            "--render-target" => {
+                let target = args.next().ok_or("--render-target needs a value")?;
+                options.render_target = target
+                    .parse()
+                    .map_err(|_| format!("unknown render target: {target}"))?;
+            }
+            "--encoding" => {
+                let label = args.next().ok_or("--encoding needs a value")?;
+                let encoding = encoding::encoding_for_label(label)
+                    .ok_or_else(|| format!("unknown encoding: {label}"))?;
+                options.fallback = Some(encoding);
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown flag: {flag}")),
+            file => options.files.push(file.to_string()),
+        }
+    }
+
+    Ok(options)
+}
+
+fn read_file(path: &str, options: &Options) -> Result<DecodedText, String> {
+    let bytes = std::fs::read(Path::new(path)).map_err(|e| e.to_string())?;
+    encoding::decode(&bytes, options.fallback)
+}
+
+fn lint(options: &Options) -> i32 {
+    let parser = AssParser::new();
+    let validation = ValidationProvider::with_options(ValidationOptions {
+        check_missing_fonts: options.check_fonts,
+        check_equivalent_styles: options.check_equivalent_styles,
+        check_padding: options.check_padding,
+        check_event_order: options.check_event_order,
+        check_bidi_punctuation: options.check_bidi_punctuation,
+        strict_compat: options.strict_compat,
+        render_target: options.render_target,
+        ..ValidationOptions::default()
+    });
+    let suppression = SuppressionProvider::new();
+    let mut exit_code = 0;
+
+    for path in &options.files {
+        let decoded = match read_file(path, options) {
+            Ok(decoded) => decoded,
+            Err(message) => {
+                println!("{path}: error: {message}");
+                exit_code = 1;
+                continue;
+            }
+        };
+
+        let Some(uri) = std::path::absolute(path)
+            .ok()
+            .and_then(|path| Url::from_file_path(path).ok())
+        else {
+            println!("{path}: error: path cannot be turned into a file URI");
+            exit_code = 1;
+            continue;
+        };
+        let document = parser.parse(&decoded.text);
+        let diagnostics = validation.validate(&document, &uri);
+        let diagnostics = suppression.apply(&decoded.text, diagnostics);
+
+        for diagnostic in &diagnostics {
+            let severity = match diagnostic.severity {
+                Some(DiagnosticSeverity::ERROR) => {
+                    exit_code = 1;
+                    "error"
+                }
+                Some(DiagnosticSeverity::WARNING) => "warning",
+                Some(DiagnosticSeverity::INFORMATION) => "info",
+                _ => "hint",
+            };
+            println!(
+                "{path}:{}:{}: {severity}: {}",
+                diagnostic.range.start.line + 1,
+                diagnostic.range.start.character + 1,
+                diagnostic.message
+            );
+        }
+        println!(
+            "{path}: {} diagnostics ({})",
+            diagnostics.len(),
+            decoded.describe()
+        );
+
+        // Summarize fonts that need collecting before muxing
+        if options.check_fonts {
+            let mut missing: Vec<&str> = document
+                .styles
+                .iter()
+                .filter(|style| {
+                    diagnostics.iter().any(|d| {
+                        d.range == style.range
+                            && d.code == Some(NumberOrString::String("missing_font".to_string()))
+                    })
+                })
+                .map(|style| style.fontname.as_str())
+                .collect();
+            missing.sort_unstable();
+            missing.dedup();
+            if !missing.is_empty() {
+                println!("{path}: missing fonts: {}", missing.join(", "));
+            }
+        }
+    }
+
+    exit_code
+}
+
+fn fmt(options: &Options) -> i32 {
+    let parser = AssParser::new();
+    let mut exit_code = 0;
+
+    for path in &options.files {
+        let decoded = match read_file(path, options) {
+            Ok(decoded) => decoded,
+            Err(message) => {
+                println!("{path}: error: {message}");
+                exit_code = 1;
+                continue;
+            }
+        };
+
+        let formatted = parser.format(&decoded.text);
+        let convert = options.write_utf8 && decoded.encoding != UTF_8;
+        if formatted == decoded.text && !convert {
+            continue;
+        }
+
+        if options.check {
+            println!("{path}: would reformat ({})", decoded.describe());
+            exit_code = 1;
+            continue;
+        }
+
+        let bytes = if options.write_utf8 {
+            formatted.into_bytes()
+        } else {
+            encoding::encode(&formatted, &decoded)
+        };
+        match std::fs::write(path, bytes) {
+            Ok(()) => println!("{path}: formatted ({})", decoded.describe()),
+            Err(e) => {
+                println!("{path}: error: {e}");
+                exit_code = 1;
+            }
+        }
+    }
+
+    exit_code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opt_in_checks_have_flags() {
+        let args = ["--check-bidi-punctuation", "--check-event-order", "a.ass"].map(String::from);
+        let options = parse_options(&args).unwrap();
+        assert!(options.check_bidi_punctuation && options.check_event_order);
+        assert!(!options.check_padding);
+        assert_eq!(options.files, ["a.ass"]);
+    }
+
+    #[test]
+    fn fmt_check_leaves_unchanged_utf16_file_byte_identical() {
+        let script = "[Script Info]\r\nTitle: 字幕\r\nScriptType: v4.00+\r\n\r\n[Events]\r\nFormat: Layer, Start, End, Style, Text\r\nDialogue: 0,0:00:01.00,0:00:02.00,Default,こんにちは\r\n";
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend(script.encode_utf16().flat_map(u16::to_le_bytes));
+        let path = std::env::temp_dir().join(format!("ass-lsp-fmt-{}.ass", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+
+        let mut options = Options {
+            check: true,
+            files: vec![path.to_string_lossy().into_owned()],
+            ..Options::default()
+        };
+        assert_eq!(fmt(&options), 0);
+        assert_eq!(std::fs::read(&path).unwrap(), bytes);
+
+        options.check = false;
+        assert_eq!(fmt(&options), 0);
+        assert_eq!(std::fs::read(&path).unwrap(), bytes);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
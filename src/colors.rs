@@ -0,0 +1,288 @@
+use crate::line_index::LineIndex;
+use crate::metadata::{override_tag_name, ColorContext, COLOR_TAGS};
+use crate::parser::{AssColor, AssDocument, Event};
+use crate::text::{parse_transform, tokenize, TextToken};
+use std::ops::Range as Span;
+use tower_lsp::lsp_types::*;
+
+/// A colour written in the document, and what a picked colour rewrites.
+struct ColorSite {
+    line: u32,
+    /// Byte span of the colour value on the line.
+    span: Span<usize>,
+    color: AssColor,
+    context: ColorContext,
+    /// Where an override tag colour's alpha is set; `None` on style lines,
+    /// whose values carry their own.
+    alpha: Option<AlphaSite>,
+}
+
+/// Override tags keep colour and alpha apart. The alpha shown with a tag's
+/// colour is the one the same run of tags gives its component, through
+/// `\alpha` or `\1a`-`\4a`, and opaque without either.
+enum AlphaSite {
+    /// The value of the `\1a`-`\4a` tag that sets it.
+    Tag(Span<usize>),
+    /// Where a `\1a`-`\4a` tag would go to take effect: after the colour
+    /// and any `\alpha` of the run. `tag` is its name, e.g. `1a`.
+    Insert { at: usize, tag: String },
+}
+
+impl ColorSite {
+    fn range(&self) -> Range {
+        Range::new(
+            Position::new(self.line, self.span.start as u32),
+            Position::new(self.line, self.span.end as u32),
+        )
+    }
+}
+
+/// The colours of style lines and of `\c` and `\1c`-`\4c` tags, including
+/// those a `\t` animates to. Values that don't parse are left to the
+/// validator.
+pub fn document_colors(document: &AssDocument, index: &LineIndex) -> Vec<ColorInformation> {
+    color_sites(document)
+        .into_iter()
+        .map(|site| ColorInformation {
+            range: index.range(site.range()),
+            color: lsp_color(site.color),
+        })
+        .collect()
+}
+
+/// How to write `color` at the colour value at `range`: `&HAABBGGRR` on
+/// style lines, `&HBBGGRR&` in override tags with the alpha set by editing
+/// or adding the tag's `\1a`-`\4a` when it changes.
+pub fn color_presentations(
+    document: &AssDocument,
+    index: &LineIndex,
+    range: Range,
+    color: Color,
+) -> Vec<ColorPresentation> {
+    let (line, column) = index.clamp(range.start);
+    let Some(site) = color_sites(document)
+        .into_iter()
+        .find(|site| site.line as usize == line && site.span.start == column)
+    else {
+        return Vec::new();
+    };
+    let picked = ass_color(color);
+    let label = picked.canonical(site.context);
+
+    let alpha = format!("&H{:02X}&", picked.a);
+    let edit = |span: Span<usize>, new_text: String| TextEdit {
+        range: index.range(Range::new(
+            Position::new(site.line, span.start as u32),
+            Position::new(site.line, span.end as u32),
+        )),
+        new_text,
+    };
+    let additional_text_edits = match &site.alpha {
+        Some(_) if picked.a == site.color.a => None,
+        Some(AlphaSite::Tag(span)) => Some(vec![edit(span.clone(), alpha)]),
+        Some(AlphaSite::Insert { at, tag }) => {
+            Some(vec![edit(*at..*at, format!("\\{tag}{alpha}"))])
+        }
+        None => None,
+    };
+    vec![ColorPresentation {
+        text_edit: Some(TextEdit {
+            range,
+            new_text: label.clone(),
+        }),
+        label,
+        additional_text_edits,
+    }]
+}
+
+fn color_sites(document: &AssDocument) -> Vec<ColorSite> {
+    let mut sites = Vec::new();
+    for style in &document.styles {
+        for ((name, value), span) in style.fields.iter().zip(&style.field_spans) {
+            if !name.to_ascii_lowercase().ends_with("colour") {
+                continue;
+            }
+            if let Ok((color, _)) = AssColor::parse_in(value, ColorContext::StyleField) {
+                sites.push(ColorSite {
+                    line: style.range.start.line,
+                    span: span.clone(),
+                    color,
+                    context: ColorContext::StyleField,
+                    alpha: None,
+                });
+            }
+        }
+    }
+    for event in &document.events {
+        event_sites(&mut sites, event);
+    }
+    sites
+}
+
+/// Colour tags of an event, taken a run at a time: the tags between two
+/// pieces of text apply together, even across adjacent blocks.
+fn event_sites(sites: &mut Vec<ColorSite>, event: &Event) {
+    let line = event.range.start.line;
+    let base = event.text_start as usize;
+    let mut run = Vec::new();
+    for token in tokenize(&event.text) {
+        match token {
+            TextToken::Tag { tag, span } => {
+                if let Some(Ok(transform)) = parse_transform(tag, span.clone()) {
+                    run_sites(sites, line, base, &transform.tags);
+                }
+                run.push((tag, span));
+            }
+            TextToken::Text { .. } => {
+                run_sites(sites, line, base, &run);
+                run.clear();
+            }
+        }
+    }
+    run_sites(sites, line, base, &run);
+}
+
+fn run_sites(sites: &mut Vec<ColorSite>, line: u32, base: usize, tags: &[(&str, Span<usize>)]) {
+    for (tag, span) in tags {
+        let Some(name) = override_tag_name(tag).filter(|name| COLOR_TAGS.contains(name)) else {
+            continue;
+        };
+        let (value, value_span) = tag_value(tag, span, name, base);
+        if value.is_empty() || value.starts_with('(') {
+            continue;
+        }
+        let Ok((mut color, _)) = AssColor::parse_in(value, ColorContext::OverrideTag) else {
+            continue;
+        };
+
+        let alpha_tag = format!("{}a", if name == "c" { "1" } else { &name[..1] });
+        let last_alpha = tags.iter().rev().find_map(|(tag, span)| {
+            let name = override_tag_name(tag)
+                .filter(|name| *name == "alpha" || *name == alpha_tag.as_str())?;
+            let (value, value_span) = tag_value(tag, span, name, base);
+            // Alpha is read like a colour and keeps its low byte
+            let (alpha, _) = AssColor::parse_in(value, ColorContext::OverrideTag).ok()?;
+            Some((name, value_span, base + span.end, alpha.r))
+        });
+        let alpha = match last_alpha {
+            Some((name, value_span, _, alpha)) if name != "alpha" => {
+                color.a = alpha;
+                AlphaSite::Tag(value_span)
+            }
+            Some((_, _, tag_end, alpha)) => {
+                color.a = alpha;
+                AlphaSite::Insert {
+                    at: tag_end.max(base + span.end),
+                    tag: alpha_tag,
+                }
+            }
+            None => AlphaSite::Insert {
+                at: base + span.end,
+                tag: alpha_tag,
+            },
+        };
+        sites.push(ColorSite {
+            line,
+            span: value_span,
+            color,
+            context: ColorContext::OverrideTag,
+            alpha: Some(alpha),
+        });
+    }
+}
+
+/// A tag's value, trimmed, and its byte span on the event line.
+fn tag_value<'a>(
+    tag: &'a str,
+    span: &Span<usize>,
+    name: &str,
+    base: usize,
+) -> (&'a str, Span<usize>) {
+    let rest = &tag[name.len()..];
+    let value = rest.trim();
+    // Past the backslash and the name
+    let start = base + span.start + 1 + name.len() + rest.len() - rest.trim_start().len();
+    (value, start..start + value.len())
+}
+
+/// ASS alpha counts up to transparent, LSP alpha up to opaque.
+fn lsp_color(color: AssColor) -> Color {
+    Color {
+        red: f32::from(color.r) / 255.0,
+        green: f32::from(color.g) / 255.0,
+        blue: f32::from(color.b) / 255.0,
+        alpha: f32::from(255 - color.a) / 255.0,
+    }
+}
+
+fn ass_color(color: Color) -> AssColor {
+    let byte = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    AssColor {
+        r: byte(color.red),
+        g: byte(color.green),
+        b: byte(color.blue),
+        a: 255 - byte(color.alpha),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_index::PositionEncoding;
+    use crate::parser::AssParser;
+
+    const TEXT: &str = "[V4+ Styles]\nFormat: Name, Fontname, Fontsize, PrimaryColour\nStyle: Default,Arial,48,&H80FF8000\n\n[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\nDialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,{\\1c&H0000FF&\\1a&H40&}Red {\\3c&HFF0000&}blue\n";
+
+    /// Each colour the document shows, written back as it was picked.
+    fn round_trip(text: &str) -> Vec<(String, Option<Vec<TextEdit>>)> {
+        let document = AssParser::new().parse(text);
+        let index = LineIndex::new(text.to_string(), PositionEncoding::Utf16);
+        document_colors(&document, &index)
+            .into_iter()
+            .flat_map(|info| color_presentations(&document, &index, info.range, info.color))
+            .map(|presentation| (presentation.label, presentation.additional_text_edits))
+            .collect()
+    }
+
+    #[test]
+    fn picked_colours_are_written_back_with_their_alpha() {
+        assert_eq!(
+            round_trip(TEXT),
+            [
+                ("&H80FF8000".to_string(), None),
+                ("&H0000FF&".to_string(), None),
+                ("&HFF0000&".to_string(), None),
+            ]
+        );
+
+        let document = AssParser::new().parse(TEXT);
+        let index = LineIndex::new(TEXT.to_string(), PositionEncoding::Utf16);
+        let colors = document_colors(&document, &index);
+        assert_eq!(colors[0].color.alpha, 127.0 / 255.0);
+        assert_eq!(colors[1].color.alpha, 191.0 / 255.0);
+
+        // A new alpha edits the run's \1a, or adds a \3a after the colour
+        let transparent = |info: &ColorInformation| Color {
+            alpha: 0.0,
+            ..info.color
+        };
+        let edits = |info: &ColorInformation| {
+            color_presentations(&document, &index, info.range, transparent(info))[0]
+                .additional_text_edits
+                .clone()
+                .unwrap()
+        };
+        let line = TEXT.lines().nth(6).unwrap();
+        let red_alpha = edits(&colors[1]);
+        assert_eq!(red_alpha[0].new_text, "&HFF&");
+        let start = red_alpha[0].range.start.character as usize;
+        assert_eq!(
+            &line[start..red_alpha[0].range.end.character as usize],
+            "&H40&"
+        );
+        let blue_alpha = edits(&colors[2]);
+        assert_eq!(blue_alpha[0].new_text, "\\3a&HFF&");
+        let at = blue_alpha[0].range.start.character as usize;
+        assert!(line[..at].ends_with("\\3c&HFF0000&"));
+    }
+}
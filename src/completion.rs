@@ -1,7 +1,73 @@
+use crate::hover::override_tag_info;
+use crate::line_index::LineIndex;
+use crate::metadata::{style_field, ColorContext, ATTACHMENT_EMBEDDING};
+use crate::parser::{
+    attachment_header_key, default_event_format, default_style_format, event_format_at,
+    field_index_at, is_attachment_data, is_attachment_section, strip_prefix_ignore_case,
+    style_format_at, AssDocument, Section, EVENT_FORMAT, V4_PLUS_STYLE_FORMAT,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use tower_lsp::lsp_types::*;
 
-#[derive(Debug)]
+/// Where completion candidates come from. Several sources can apply at one
+/// position, e.g. event types, a Format line and section headers at the start
+/// of an empty Events line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CompletionSource {
+    OverrideTags,
+    EventTypes,
+    /// A whole Format line for a section that doesn't have one yet.
+    FormatLine,
+    EventFields,
+    StyleFields,
+    StyleValues,
+    ScriptInfoKeys,
+    /// The document's style names, in the Style field of an event line.
+    EventStyles,
+    AttachmentHeaders,
+    Sections,
+}
+
+/// The order sources are listed in when several apply, most specific first.
+pub const DEFAULT_COMPLETION_SOURCES: [CompletionSource; 10] = [
+    CompletionSource::OverrideTags,
+    CompletionSource::EventTypes,
+    CompletionSource::FormatLine,
+    CompletionSource::EventFields,
+    CompletionSource::StyleFields,
+    CompletionSource::StyleValues,
+    CompletionSource::ScriptInfoKeys,
+    CompletionSource::EventStyles,
+    CompletionSource::AttachmentHeaders,
+    CompletionSource::Sections,
+];
+
+/// What an item documents, kept in its `data` so the documentation is only
+/// built when the client resolves the item.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+enum ItemData {
+    OverrideTag { tag: String },
+    StyleField { field: String },
+    StyleValue { field: String, value: String },
+    AttachmentHeader,
+}
+
+impl ItemData {
+    fn to_value(&self) -> Option<serde_json::Value> {
+        serde_json::to_value(self).ok()
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct CompletionProvider {
+    /// Sources in priority order; a source left out is disabled.
+    pub sources: Vec<CompletionSource>,
+    /// Caps the merged list. A capped list is marked incomplete, so clients
+    /// ask again as the user keeps typing.
+    pub max_items: Option<usize>,
     override_tags: Vec<&'static str>,
     script_info_keys: Vec<&'static str>,
     style_fields: Vec<&'static str>,
@@ -11,6 +77,8 @@ pub struct CompletionProvider {
 impl CompletionProvider {
     pub fn new() -> Self {
         Self {
+            sources: DEFAULT_COMPLETION_SOURCES.to_vec(),
+            max_items: None,
             override_tags: vec![
                 "\\pos", "\\move", "\\org", "\\clip", "\\iclip", "\\fscx", "\\fscy", "\\fsp",
                 "\\frx", "\\fry", "\\frz", "\\fr", "\\fn", "\\fs", "\\fe", "\\b", "\\i", "\\u",
@@ -37,120 +105,124 @@ impl CompletionProvider {
                 "Active Line",
                 "Video Position",
             ],
-            style_fields: vec![
-                "Name",
-                "Fontname",
-                "Fontsize",
-                "PrimaryColour",
-                "SecondaryColour",
-                "OutlineColour",
-                "BackColour",
-                "Bold",
-                "Italic",
-                "Underline",
-                "StrikeOut",
-                "ScaleX",
-                "ScaleY",
-                "Spacing",
-                "Angle",
-                "BorderStyle",
-                "Outline",
-                "Shadow",
-                "Alignment",
-                "MarginL",
-                "MarginR",
-                "MarginV",
-                "Encoding",
-            ],
-            event_fields: vec![
-                "Layer", "Start", "End", "Style", "Name", "MarginL", "MarginR", "MarginV",
-                "Effect", "Text",
-            ],
+            style_fields: V4_PLUS_STYLE_FORMAT.to_vec(),
+            event_fields: EVENT_FORMAT.to_vec(),
         }
     }
 
-    pub fn provide_completions(&self, text: &str, position: Position) -> Vec<CompletionItem> {
-        let lines: Vec<&str> = text.lines().collect();
-        let line_idx = position.line as usize;
+    /// Collects candidates from every source that applies at `position`,
+    /// in `sources` order. Each item's sortText is its source's bucket then
+    /// its rank within the source, and a label offered by an earlier source
+    /// is not repeated by a later one. `None` where nothing completes, so
+    /// that trigger characters typed as text, such as a comma in dialogue,
+    /// cost no more than that.
+    pub fn provide_completions(
+        &self,
+        document: &AssDocument,
+        index: &LineIndex,
+        position: Position,
+    ) -> Option<CompletionList> {
+        let lines = index.lines();
+        let (line_idx, char_idx) = index.clamp(position);
+        let prefix = &lines[line_idx][..char_idx];
 
-        if line_idx >= lines.len() {
-            return Vec::new();
+        let section = document.section_at(line_idx as u32);
+        if is_plain_text(document, section, lines[line_idx], line_idx, char_idx) {
+            return None;
         }
-
-        let current_line = lines[line_idx];
-        let char_idx = position.character as usize;
-        let prefix = if char_idx <= current_line.len() {
-            &current_line[..char_idx]
-        } else {
-            current_line
-        };
-
-        // Determine context
-        let context = self.determine_context(text, position);
-
-        match context {
-            CompletionContext::OverrideTags => self.complete_override_tags(prefix),
-            CompletionContext::ScriptInfo => self.complete_script_info(prefix),
-            CompletionContext::StyleFormat => self.complete_style_format(prefix),
-            CompletionContext::EventFormat => self.complete_event_format(prefix),
-            CompletionContext::Section => self.complete_sections(prefix),
-            CompletionContext::EventType => self.complete_event_types(prefix),
-            _ => Vec::new(),
-        }
-    }
-
-    fn determine_context(&self, text: &str, position: Position) -> CompletionContext {
-        let lines: Vec<&str> = text.lines().collect();
-        let line_idx = position.line as usize;
-
-        if line_idx >= lines.len() {
-            return CompletionContext::None;
+        let applicable = applicable_sources(section, &lines, line_idx, char_idx);
+        if applicable.is_empty() {
+            return None;
         }
 
-        let current_line = lines[line_idx];
-
-        // Check if we're in an override tag
-        if current_line.contains('{') && !current_line[..position.character as usize].contains('}')
-        {
-            return CompletionContext::OverrideTags;
+        let mut items = Vec::new();
+        let mut seen = HashSet::new();
+        for (bucket, source) in self.sources.iter().enumerate() {
+            if !applicable.contains(source) {
+                continue;
+            }
+            let candidates = match source {
+                CompletionSource::OverrideTags => self.complete_override_tags(prefix),
+                CompletionSource::ScriptInfoKeys => {
+                    self.complete_script_info(document, index, line_idx, prefix)
+                }
+                CompletionSource::StyleFields => self.complete_style_format(prefix),
+                CompletionSource::StyleValues => {
+                    self.complete_style_value(&lines, line_idx, prefix)
+                }
+                CompletionSource::EventFields => self.complete_event_format(prefix),
+                CompletionSource::EventTypes => self.complete_event_types(prefix),
+                CompletionSource::EventStyles => self.complete_event_style(document),
+                CompletionSource::FormatLine => section
+                    .map(|section| self.complete_format_line(section))
+                    .unwrap_or_default(),
+                CompletionSource::AttachmentHeaders => section
+                    .map(|section| {
+                        self.complete_attachment_header(
+                            attachment_header_key(&section.name),
+                            prefix,
+                        )
+                    })
+                    .unwrap_or_default(),
+                CompletionSource::Sections => self.complete_sections(prefix),
+            };
+            for (rank, mut item) in candidates.into_iter().enumerate() {
+                if !seen.insert(item.label.clone()) {
+                    continue;
+                }
+                item.sort_text = Some(format!("{bucket:02}.{rank:04}"));
+                items.push(item);
+            }
         }
 
-        // Find current section
-        let mut current_section = None;
-        for i in (0..=line_idx).rev() {
-            if lines[i].starts_with('[') && lines[i].ends_with(']') {
-                current_section = Some(lines[i]);
-                break;
-            }
+        let is_incomplete = self.max_items.is_some_and(|max| items.len() > max);
+        if let Some(max) = self.max_items {
+            items.truncate(max);
         }
+        Some(CompletionList {
+            is_incomplete,
+            items,
+        })
+    }
 
-        match current_section {
-            Some("[Script Info]") => CompletionContext::ScriptInfo,
-            Some(section) if section.contains("Styles") => {
-                if current_line.starts_with("Format:") {
-                    CompletionContext::StyleFormat
-                } else {
-                    CompletionContext::None
-                }
+    /// Adds the detail and documentation left out of the items `completion`
+    /// returns. Tags are documented as hover documents them.
+    pub fn resolve(&self, mut item: CompletionItem) -> CompletionItem {
+        let Some(data) = item
+            .data
+            .clone()
+            .and_then(|data| serde_json::from_value::<ItemData>(data).ok())
+        else {
+            return item;
+        };
+        let documentation = match data {
+            ItemData::OverrideTag { tag } => {
+                item.detail = Some(self.get_tag_description(&tag));
+                Some(override_tag_info(&tag))
             }
-            Some("[Events]") => {
-                if current_line.starts_with("Format:") {
-                    CompletionContext::EventFormat
-                } else if current_line.is_empty() || current_line.ends_with(':') {
-                    CompletionContext::EventType
-                } else {
-                    CompletionContext::None
-                }
+            ItemData::StyleField { field } => {
+                style_field(&field).map(|field| field.description.to_string())
             }
-            None => {
-                if current_line.starts_with('[') || current_line.is_empty() {
-                    CompletionContext::Section
-                } else {
-                    CompletionContext::None
+            ItemData::StyleValue { field, value } => style_field(&field).and_then(|field| {
+                if field.name.to_ascii_lowercase().ends_with("colour") {
+                    let grammar = ColorContext::StyleField.grammar();
+                    return Some(format!("Canonical form: {}.", grammar.canonical));
                 }
-            }
-            _ => CompletionContext::None,
-        }
+                field
+                    .values
+                    .iter()
+                    .find(|(known, _)| *known == value)
+                    .map(|(_, meaning)| meaning.to_string())
+            }),
+            ItemData::AttachmentHeader => Some(ATTACHMENT_EMBEDDING.to_string()),
+        };
+        item.documentation = documentation.map(|value| {
+            Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            })
+        });
+        item
     }
 
     fn complete_override_tags(&self, prefix: &str) -> Vec<CompletionItem> {
@@ -163,37 +235,88 @@ impl CompletionProvider {
             .map(|tag| CompletionItem {
                 label: tag.to_string(),
                 kind: Some(CompletionItemKind::FUNCTION),
-                detail: Some(self.get_tag_description(tag)),
-                documentation: Some(Documentation::String(self.get_tag_documentation(tag))),
                 insert_text: Some(self.get_tag_insert_text(tag)),
                 insert_text_format: Some(InsertTextFormat::SNIPPET),
+                data: ItemData::OverrideTag {
+                    tag: tag.to_string(),
+                }
+                .to_value(),
                 ..Default::default()
             })
             .collect()
     }
 
-    fn complete_script_info(&self, prefix: &str) -> Vec<CompletionItem> {
-        let key_prefix = if let Some(_colon_pos) = prefix.rfind(':') {
-            // If there's already a colon, don't suggest keys
+    fn complete_script_info(
+        &self,
+        document: &AssDocument,
+        index: &LineIndex,
+        line_idx: usize,
+        prefix: &str,
+    ) -> Vec<CompletionItem> {
+        // If there's already a colon, don't suggest keys
+        if prefix.contains(':') {
             return Vec::new();
-        } else {
-            prefix.trim()
-        };
+        }
+        let key_prefix = prefix.trim_start();
+        // Replaces the whole typed key, which may be several words
+        let range = Range::new(
+            index.position(line_idx, prefix.len() - key_prefix.len()),
+            index.position(line_idx, prefix.len()),
+        );
+
+        // Keys the document already uses that aren't standard, such as a
+        // group's localized notes, so they complete after their first use
+        let mut custom_keys: Vec<&str> = document
+            .script_info
+            .keys()
+            .map(String::as_str)
+            .filter(|key| {
+                !self
+                    .script_info_keys
+                    .iter()
+                    .any(|known| known.eq_ignore_ascii_case(key))
+            })
+            .collect();
+        custom_keys.sort_unstable();
 
         self.script_info_keys
             .iter()
-            .filter(|key| key.to_lowercase().starts_with(&key_prefix.to_lowercase()))
-            .map(|key| CompletionItem {
+            .map(|key| (*key, "Script Info Property"))
+            .chain(
+                custom_keys
+                    .into_iter()
+                    .map(|key| (key, "Custom Script Info Property")),
+            )
+            .filter(|(key, _)| starts_with_caseless(key, key_prefix))
+            .map(|(key, detail)| CompletionItem {
                 label: key.to_string(),
                 kind: Some(CompletionItemKind::PROPERTY),
-                detail: Some("Script Info Property".to_string()),
-                insert_text: Some(format!("{key}: $0")),
+                detail: Some(detail.to_string()),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                    range,
+                    format!("{key}: $0"),
+                ))),
                 insert_text_format: Some(InsertTextFormat::SNIPPET),
                 ..Default::default()
             })
             .collect()
     }
 
+    fn complete_attachment_header(&self, key: &'static str, prefix: &str) -> Vec<CompletionItem> {
+        if !key.starts_with(prefix.trim()) {
+            return Vec::new();
+        }
+        vec![CompletionItem {
+            label: key.to_string(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            detail: Some("Attachment header".to_string()),
+            insert_text: Some(format!("{key}: $0")),
+            insert_text_format: Some(InsertTextFormat::SNIPPET),
+            data: ItemData::AttachmentHeader.to_value(),
+            ..Default::default()
+        }]
+    }
+
     fn complete_style_format(&self, _prefix: &str) -> Vec<CompletionItem> {
         self.style_fields
             .iter()
@@ -201,6 +324,56 @@ impl CompletionProvider {
                 label: field.to_string(),
                 kind: Some(CompletionItemKind::FIELD),
                 detail: Some("Style Field".to_string()),
+                data: ItemData::StyleField {
+                    field: field.to_string(),
+                }
+                .to_value(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn complete_style_value(
+        &self,
+        lines: &[&str],
+        line_idx: usize,
+        prefix: &str,
+    ) -> Vec<CompletionItem> {
+        let format = style_format_at(lines, line_idx);
+        let Some(field) = field_index_at(prefix, prefix.len())
+            .and_then(|index| format.get(index))
+            .and_then(|name| style_field(name))
+        else {
+            return Vec::new();
+        };
+
+        if field.name.to_ascii_lowercase().ends_with("colour") {
+            let grammar = ColorContext::StyleField.grammar();
+            return vec![CompletionItem {
+                label: grammar.example.to_string(),
+                kind: Some(CompletionItemKind::COLOR),
+                detail: Some(field.name.to_string()),
+                data: ItemData::StyleValue {
+                    field: field.name.to_string(),
+                    value: grammar.example.to_string(),
+                }
+                .to_value(),
+                ..Default::default()
+            }];
+        }
+
+        field
+            .values
+            .iter()
+            .map(|(value, _)| CompletionItem {
+                label: value.to_string(),
+                kind: Some(CompletionItemKind::VALUE),
+                detail: Some(field.name.to_string()),
+                data: ItemData::StyleValue {
+                    field: field.name.to_string(),
+                    value: value.to_string(),
+                }
+                .to_value(),
                 ..Default::default()
             })
             .collect()
@@ -218,6 +391,43 @@ impl CompletionProvider {
             .collect()
     }
 
+    fn complete_event_style(&self, document: &AssDocument) -> Vec<CompletionItem> {
+        let mut seen = HashSet::new();
+        document
+            .styles
+            .iter()
+            .filter(|style| seen.insert(style.name.as_str()))
+            .map(|style| CompletionItem {
+                label: style.name.clone(),
+                kind: Some(CompletionItemKind::CLASS),
+                detail: Some(format!("{} {}", style.fontname, style.fontsize)),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn complete_format_line(&self, section: &Section) -> Vec<CompletionItem> {
+        if section
+            .content
+            .iter()
+            .any(|line| strip_prefix_ignore_case(line.trim(), "Format:").is_some())
+        {
+            return Vec::new();
+        }
+        let format = if section.name == "Events" {
+            default_event_format()
+        } else {
+            default_style_format(&section.name)
+        };
+        vec![CompletionItem {
+            label: "Format:".to_string(),
+            kind: Some(CompletionItemKind::SNIPPET),
+            detail: Some(format!("{} columns", section.name)),
+            insert_text: Some(format!("Format: {}", format.join(", "))),
+            ..Default::default()
+        }]
+    }
+
     fn complete_sections(&self, _prefix: &str) -> Vec<CompletionItem> {
         vec![
             CompletionItem {
@@ -303,29 +513,14 @@ impl CompletionProvider {
         }
     }
 
-    fn get_tag_documentation(&self, tag: &str) -> String {
-        match tag {
-            "\\pos" => "\\pos(x,y) - Sets the position of the subtitle".to_string(),
-            "\\move" => {
-                "\\move(x1,y1,x2,y2,t1,t2) - Moves subtitle from (x1,y1) to (x2,y2)".to_string()
-            }
-            "\\c" | "\\1c" => "\\c&Hbbggrr& - Sets the primary text color".to_string(),
-            "\\b" => "\\b1 or \\b0 - Enable or disable bold formatting".to_string(),
-            "\\i" => "\\i1 or \\i0 - Enable or disable italic formatting".to_string(),
-            "\\fn" => "\\fnFontName - Changes the font name".to_string(),
-            "\\fs" => "\\fsSize - Changes the font size".to_string(),
-            "\\an" => "\\anN - Sets alignment using numpad notation (1-9)".to_string(),
-            "\\k" => "\\kDuration - Karaoke timing in centiseconds".to_string(),
-            "\\t" => "\\t(tags) or \\t(t1,t2,tags) - Transforms tags over time".to_string(),
-            _ => format!("{tag} - ASS override tag"),
-        }
-    }
-
     fn get_tag_insert_text(&self, tag: &str) -> String {
         match tag {
             "\\pos" => "\\pos(${1:x},${2:y})".to_string(),
             "\\move" => "\\move(${1:x1},${2:y1},${3:x2},${4:y2})".to_string(),
-            "\\c" | "\\1c" | "\\2c" | "\\3c" | "\\4c" => format!("{tag}${{1:&Hffffff&}}"),
+            "\\c" | "\\1c" | "\\2c" | "\\3c" | "\\4c" => format!(
+                "{tag}${{1:{}}}",
+                ColorContext::OverrideTag.grammar().example
+            ),
             "\\fn" => "\\fn${1:Arial}".to_string(),
             "\\fs" => "\\fs${1:20}".to_string(),
             "\\b" | "\\i" | "\\u" | "\\s" => format!("{tag}${{1:1}}"),
@@ -341,13 +536,402 @@ impl CompletionProvider {
     }
 }
 
-#[derive(Debug, PartialEq)]
-enum CompletionContext {
-    None,
-    OverrideTags,
-    ScriptInfo,
-    StyleFormat,
-    EventFormat,
-    Section,
-    EventType,
+/// Whether `text` starts with `prefix`, ignoring case in any script.
+fn starts_with_caseless(text: &str, prefix: &str) -> bool {
+    let mut text = text.chars().flat_map(char::to_lowercase);
+    prefix
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|ch| text.next() == Some(ch))
+}
+
+/// Whether the cursor is where `,` and `:` are ordinary text: in an
+/// event's Text field outside an override block, or in attachment data.
+/// Uses the parsed event's text column rather than counting fields, unless
+/// the line is short of fields, as while it's being typed, when the parser
+/// takes its last field for the text.
+fn is_plain_text(
+    document: &AssDocument,
+    section: Option<&Section>,
+    current_line: &str,
+    line_idx: usize,
+    char_idx: usize,
+) -> bool {
+    let typed = document
+        .event_at(line_idx as u32)
+        .filter(|event| event.field_count_mismatch.is_none())
+        .and_then(|event| current_line.get(event.text_start as usize..char_idx));
+    if let Some(typed) = typed {
+        return typed.rfind('{') <= typed.rfind('}');
+    }
+    section.is_some_and(|section| is_attachment_section(&section.name))
+        && is_attachment_data(current_line.trim())
+}
+
+/// Returns the sources that apply on line `line_idx` with the cursor at
+/// `char_idx`, in no particular order.
+fn applicable_sources(
+    section: Option<&Section>,
+    lines: &[&str],
+    line_idx: usize,
+    char_idx: usize,
+) -> Vec<CompletionSource> {
+    let current_line = lines[line_idx];
+    // Check if we're in an override block
+    let typed = &current_line[..char_idx];
+    if typed.rfind('{') > typed.rfind('}') {
+        return vec![CompletionSource::OverrideTags];
+    }
+
+    let is_format_line = strip_prefix_ignore_case(current_line, "Format:").is_some();
+    // A new section can start on any blank line
+    let starts_section = current_line.is_empty() || current_line.starts_with('[');
+
+    let mut sources = Vec::new();
+    match section.map(|section| section.name.as_str()) {
+        Some("Script Info") => sources.push(CompletionSource::ScriptInfoKeys),
+        Some(section) if section.contains("Styles") => {
+            if is_format_line {
+                sources.push(CompletionSource::StyleFields);
+            } else if strip_prefix_ignore_case(current_line, "Style:").is_some() {
+                sources.push(CompletionSource::StyleValues);
+            } else if current_line.is_empty() {
+                sources.push(CompletionSource::FormatLine);
+            }
+        }
+        Some("Events") => {
+            let is_event_line = ["Dialogue:", "Comment:"]
+                .iter()
+                .any(|prefix| strip_prefix_ignore_case(current_line, prefix).is_some());
+            if is_format_line {
+                sources.push(CompletionSource::EventFields);
+            } else if is_event_line {
+                let format = event_format_at(lines, line_idx);
+                let field = field_index_at(current_line, char_idx).and_then(|i| format.get(i));
+                if field.is_some_and(|field| field.eq_ignore_ascii_case("Style")) {
+                    sources.push(CompletionSource::EventStyles);
+                }
+            } else if current_line.is_empty() || current_line.ends_with(':') {
+                sources.push(CompletionSource::EventTypes);
+                if current_line.is_empty() {
+                    sources.push(CompletionSource::FormatLine);
+                }
+            }
+        }
+        // Data lines are uppercase and punctuation, so a lowercase word
+        // without a colon can only be the start of a header
+        Some(section)
+            if is_attachment_section(section)
+                && !current_line.contains(':')
+                && !is_attachment_data(current_line.trim()) =>
+        {
+            sources.push(CompletionSource::AttachmentHeaders);
+        }
+        _ => {}
+    }
+    if starts_section {
+        sources.push(CompletionSource::Sections);
+    }
+    sources
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hover::HoverProvider;
+    use crate::line_index::PositionEncoding;
+    use crate::parser::AssParser;
+
+    /// The position just after the `field`th comma of `line`.
+    fn field_start(index: &LineIndex, line: usize, field: usize) -> Position {
+        let text = index.lines()[line];
+        let column = text.match_indices(',').nth(field - 1).unwrap().0 + 1;
+        Position::new(line as u32, column as u32)
+    }
+
+    /// A script whose sections are missing their Format lines, so an empty
+    /// line in each is a position where several sources apply.
+    const UNFORMATTED: &str = "[Script Info]\nTitle: Demo\nScriptType: v4.00+\n\n\
+                               [V4+ Styles]\n\n\
+                               [Events]\n\n";
+
+    /// Every item at the start of `line` as `sortText label`, in the order a
+    /// client shows them.
+    fn listing(provider: &CompletionProvider, line: u32) -> Vec<String> {
+        let index = LineIndex::new(UNFORMATTED.to_string(), PositionEncoding::Utf16);
+        let document = AssParser::new().parse(UNFORMATTED);
+        let list = provider
+            .provide_completions(&document, &index, Position::new(line, 0))
+            .unwrap();
+        let mut items: Vec<String> = list
+            .items
+            .iter()
+            .map(|item| format!("{} {}", item.sort_text.as_deref().unwrap(), item.label))
+            .collect();
+        items.sort();
+        items
+    }
+
+    #[test]
+    fn empty_events_line_lists_event_types_then_format_then_sections() {
+        assert_eq!(
+            listing(&CompletionProvider::new(), 7),
+            vec![
+                "01.0000 Dialogue:",
+                "01.0001 Comment:",
+                "02.0000 Format:",
+                "09.0000 [Script Info]",
+                "09.0001 [V4+ Styles]",
+                "09.0002 [Events]",
+                "09.0003 [Fonts]",
+                "09.0004 [Graphics]",
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_styles_line_lists_format_then_sections() {
+        assert_eq!(
+            listing(&CompletionProvider::new(), 5),
+            vec![
+                "02.0000 Format:",
+                "09.0000 [Script Info]",
+                "09.0001 [V4+ Styles]",
+                "09.0002 [Events]",
+                "09.0003 [Fonts]",
+                "09.0004 [Graphics]",
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_script_info_line_lists_keys_once_then_sections() {
+        assert_eq!(
+            listing(&CompletionProvider::new(), 3),
+            vec![
+                "06.0000 Title",
+                "06.0001 ScriptType",
+                "06.0002 WrapStyle",
+                "06.0003 PlayResX",
+                "06.0004 PlayResY",
+                "06.0005 ScaledBorderAndShadow",
+                "06.0006 Video File",
+                "06.0007 Video Aspect Ratio",
+                "06.0008 Video Zoom",
+                "06.0009 Video Position",
+                "06.0010 Last Style Storage",
+                "06.0011 Audio File",
+                "06.0012 Video Zoom Percent",
+                "06.0013 Scroll Position",
+                "06.0014 Active Line",
+                "09.0000 [Script Info]",
+                "09.0001 [V4+ Styles]",
+                "09.0002 [Events]",
+                "09.0003 [Fonts]",
+                "09.0004 [Graphics]",
+            ],
+            "a key listed twice is only offered at its first rank"
+        );
+    }
+
+    #[test]
+    fn sources_follow_the_configured_order_and_disabled_ones_are_left_out() {
+        let provider = CompletionProvider {
+            sources: vec![CompletionSource::Sections, CompletionSource::EventTypes],
+            ..CompletionProvider::new()
+        };
+        assert_eq!(
+            listing(&provider, 7),
+            vec![
+                "00.0000 [Script Info]",
+                "00.0001 [V4+ Styles]",
+                "00.0002 [Events]",
+                "00.0003 [Fonts]",
+                "00.0004 [Graphics]",
+                "01.0000 Dialogue:",
+                "01.0001 Comment:",
+            ]
+        );
+    }
+
+    #[test]
+    fn capped_list_keeps_the_highest_priority_items_and_is_incomplete() {
+        let text = UNFORMATTED.to_string();
+        let index = LineIndex::new(text.clone(), PositionEncoding::Utf16);
+        let provider = CompletionProvider {
+            max_items: Some(3),
+            ..CompletionProvider::new()
+        };
+        let list = provider
+            .provide_completions(&AssParser::new().parse(&text), &index, Position::new(7, 0))
+            .unwrap();
+        assert!(list.is_incomplete);
+        let labels: Vec<&str> = list.items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, vec!["Dialogue:", "Comment:", "Format:"]);
+    }
+
+    /// A script that used a Cyrillic key once and is typing it again on
+    /// line 3.
+    fn localized(typed: &str) -> String {
+        format!("[Script Info]\nScriptType: v4.00+\nПримечание: первая серия\n{typed}\n")
+    }
+
+    #[test]
+    fn custom_cyrillic_key_completes_from_a_two_character_prefix() {
+        let provider = CompletionProvider::new();
+        for typed in ["Пр", "пр", "ПР"] {
+            let text = localized(typed);
+            let index = LineIndex::new(text.clone(), PositionEncoding::Utf16);
+            let list = provider
+                .provide_completions(&AssParser::new().parse(&text), &index, Position::new(3, 2))
+                .unwrap();
+            let labels: Vec<&str> = list.items.iter().map(|item| item.label.as_str()).collect();
+            assert_eq!(labels, vec!["Примечание"], "typed {typed}");
+            let Some(CompletionTextEdit::Edit(edit)) = &list.items[0].text_edit else {
+                panic!("no text edit");
+            };
+            assert_eq!(
+                edit.range,
+                Range::new(Position::new(3, 0), Position::new(3, 2))
+            );
+            assert_eq!(edit.new_text, "Примечание: $0");
+        }
+    }
+
+    #[test]
+    fn cursor_inside_a_multi_byte_character_does_not_panic() {
+        let text = localized("Пр");
+        let document = AssParser::new().parse(&text);
+        let provider = CompletionProvider::new();
+        // Byte 1 is inside П, and 3 inside р
+        let index = LineIndex::new(text.clone(), PositionEncoding::Utf8);
+        for column in 0..=4 {
+            let _ = provider.provide_completions(&document, &index, Position::new(3, column));
+        }
+        let list = provider
+            .provide_completions(&document, &index, Position::new(3, 3))
+            .unwrap();
+        let labels: Vec<&str> = list.items.iter().map(|item| item.label.as_str()).collect();
+        assert!(labels.contains(&"Примечание"), "{labels:?}");
+    }
+
+    #[test]
+    fn commas_in_dialogue_text_complete_nothing_but_field_commas_do() {
+        let text = "[V4+ Styles]\nFormat: Name, Fontname, Fontsize, PrimaryColour\nStyle: Sign,Arial,40,&H00FFFFFF\n\n\
+                    [Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+                    Dialogue: 0,0:00:01.00,0:00:02.00,,,0,0,0,,Well, see https://example.com:8080, then\n\
+                    [Fonts]\nfontname: a.ttf\nM0,4)\n";
+        let index = LineIndex::new(text.to_string(), PositionEncoding::Utf16);
+        let document = AssParser::new().parse(text);
+        let provider = CompletionProvider::new();
+        let complete = |line: u32, column: usize| {
+            provider.provide_completions(&document, &index, Position::new(line, column as u32))
+        };
+        let dialogue = index.lines()[6];
+
+        // After the comma and colons typed in the text, not even an empty list
+        let comma = dialogue.find("Well,").unwrap() + 5;
+        assert_eq!(complete(6, comma), None);
+        let colon = dialogue.find(":8080").unwrap() + 1;
+        assert_eq!(complete(6, colon), None);
+        // Nor in attachment data
+        assert_eq!(complete(9, 2), None);
+
+        // After the comma that opens the Style field, the styles
+        let style = field_start(&index, 6, 3);
+        let list = provider
+            .provide_completions(&document, &index, style)
+            .unwrap();
+        let labels: Vec<&str> = list.items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, vec!["Sign"]);
+    }
+
+    #[test]
+    fn border_style_values_are_completed_with_their_meaning() {
+        let text = include_str!("../tests/fixtures/boxed_styles.ass");
+        let index = LineIndex::new(text.to_string(), PositionEncoding::Utf16);
+        let document = AssParser::new().parse(text);
+        let provider = CompletionProvider::new();
+
+        let line = index
+            .lines()
+            .iter()
+            .position(|l| l.starts_with("Style: Boxed"))
+            .unwrap();
+        let list = provider
+            .provide_completions(&document, &index, field_start(&index, line, 15))
+            .unwrap();
+        let labels: Vec<&str> = list.items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, vec!["1", "3", "4"]);
+
+        let boxed = list
+            .items
+            .into_iter()
+            .find(|item| item.label == "3")
+            .unwrap();
+        let resolved = provider.resolve(boxed);
+        let Some(Documentation::MarkupContent(docs)) = resolved.documentation else {
+            panic!("no documentation for BorderStyle 3");
+        };
+        assert!(docs.value.contains("Opaque box"));
+    }
+
+    #[test]
+    fn resolved_tags_are_documented_as_hover_documents_them() {
+        let text = "[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+                    Dialogue: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,{\\fad(100,200)\\bord2}Hi\n";
+        let index = LineIndex::new(text.to_string(), PositionEncoding::Utf16);
+        let document = AssParser::new().parse(text);
+        let provider = CompletionProvider::new();
+        let line = index.lines()[2];
+
+        for tag in ["\\fad", "\\bord"] {
+            let column = (line.find(tag).unwrap() + tag.len()) as u32;
+            let list = provider
+                .provide_completions(&document, &index, Position::new(2, column))
+                .unwrap();
+            let item = list
+                .items
+                .into_iter()
+                .find(|item| item.label == tag)
+                .unwrap_or_else(|| panic!("no item for {tag}"));
+            assert!(item.documentation.is_none() && item.detail.is_none());
+            assert!(item.insert_text.is_some() || item.text_edit.is_some());
+
+            let resolved = provider.resolve(item);
+            assert!(resolved.detail.is_some());
+            let Some(Documentation::MarkupContent(docs)) = resolved.documentation else {
+                panic!("no documentation for {tag}");
+            };
+            let hover = HoverProvider::new()
+                .provide_hover(&document, &index, Position::new(2, column - 1))
+                .unwrap();
+            let HoverContents::Scalar(MarkedString::String(shown)) = hover.contents else {
+                panic!("hover for {tag} is not a string");
+            };
+            // Hover may add a note on how this script scales the value
+            assert!(shown.starts_with(&docs.value), "{tag}: {shown}");
+        }
+    }
+
+    #[test]
+    fn lowercase_headers_and_prefixes_pick_the_same_sources() {
+        let text = include_str!("../tests/fixtures/lowercase.ass");
+        let lines: Vec<&str> = text.lines().collect();
+        let document = AssParser::new().parse(text);
+        let sources = |line: usize, column: usize| {
+            let section = document.sections.iter().find(|section| {
+                section.range.start.line as usize <= line && line <= section.range.end.line as usize
+            });
+            applicable_sources(section, &lines, line, column)
+        };
+        assert_eq!(sources(2, 0), [CompletionSource::ScriptInfoKeys]);
+        assert_eq!(sources(7, 8), [CompletionSource::StyleFields]);
+        assert_eq!(sources(8, 7), [CompletionSource::StyleValues]);
+        assert_eq!(sources(12, 8), [CompletionSource::EventFields]);
+        let style = lines[13].match_indices(',').nth(2).unwrap().0 + 1;
+        assert_eq!(sources(13, style), [CompletionSource::EventStyles]);
+        let style = lines[14].match_indices(',').nth(2).unwrap().0 + 1;
+        assert_eq!(sources(14, style), [CompletionSource::EventStyles]);
+    }
 }
@@ -0,0 +1,241 @@
+use crate::line_index::LineIndex;
+use crate::parser::{AssDocument, Event, Style};
+use crate::text::{tokenize, TextToken};
+use tower_lsp::lsp_types::*;
+
+/// A style name written in an event, in its Style field or a `\r` tag.
+struct StyleUsage<'a> {
+    name: &'a str,
+    line: u32,
+    /// Byte span of the name on the event line.
+    span: std::ops::Range<usize>,
+}
+
+impl StyleUsage<'_> {
+    fn range(&self) -> Range {
+        Range::new(
+            Position::new(self.line, self.span.start as u32),
+            Position::new(self.line, self.span.end as u32),
+        )
+    }
+}
+
+/// The `Style:` line of the style named under `position`, in an event's Style
+/// field or a `\r` tag. The target selection covers the style's name. Styles
+/// that aren't defined have no definition, as `undefined_style` reports.
+pub fn style_definition(
+    uri: &Url,
+    document: &AssDocument,
+    index: &LineIndex,
+    position: Position,
+) -> Option<LocationLink> {
+    let (line_idx, char_idx) = index.clamp(position);
+    let usage = usage_at(document, line_idx as u32, char_idx)?;
+    let style = defined_style(document, usage.name)?;
+    Some(LocationLink {
+        origin_selection_range: Some(index.range(usage.range())),
+        target_uri: uri.clone(),
+        target_range: index.range(style.range),
+        target_selection_range: index.range(style.field_range("Name")?),
+    })
+}
+
+/// Every place that names the style under `position`, which may be a usage
+/// or the name on a `Style:` line. A usage counts when it resolves to the
+/// same style as [`style_definition`] would take it to; names no style
+/// defines gather the usages spelled the same, ignoring case.
+pub fn style_references(
+    uri: &Url,
+    document: &AssDocument,
+    index: &LineIndex,
+    position: Position,
+    include_declaration: bool,
+) -> Option<Vec<Location>> {
+    let (name, target, _) = name_at(document, index, position)?;
+    let mut locations = Vec::new();
+    if let Some(range) = target
+        .filter(|_| include_declaration)
+        .and_then(|style| style.field_range("Name"))
+    {
+        locations.push(Location::new(uri.clone(), index.range(range)));
+    }
+    for event in &document.events {
+        for usage in event_usages(event) {
+            let refers = match target {
+                Some(target) => defined_style(document, usage.name)
+                    .is_some_and(|style| std::ptr::eq(style, target)),
+                None => usage.name.eq_ignore_ascii_case(name),
+            };
+            if refers {
+                locations.push(Location::new(uri.clone(), index.range(usage.range())));
+            }
+        }
+    }
+    Some(locations)
+}
+
+/// The client range of the style name under `position`, for a rename to
+/// replace. Besides usages, this takes the name on a `Style:` line.
+pub fn style_name_range(
+    document: &AssDocument,
+    index: &LineIndex,
+    position: Position,
+) -> Option<Range> {
+    name_at(document, index, position).map(|(_, _, range)| index.range(range))
+}
+
+/// The style name under `position`, on a `Style:` line or in an event, with
+/// the style it resolves to and its byte range.
+fn name_at<'a>(
+    document: &'a AssDocument,
+    index: &LineIndex,
+    position: Position,
+) -> Option<(&'a str, Option<&'a Style>, Range)> {
+    let (line_idx, char_idx) = index.clamp(position);
+    let line = line_idx as u32;
+    let found = match document
+        .styles
+        .iter()
+        .find(|style| style.range.start.line == line)
+    {
+        Some(style) => {
+            let range = style.field_range("Name")?;
+            (range.start.character..=range.end.character)
+                .contains(&(char_idx as u32))
+                .then_some((style.name.as_str(), Some(style), range))
+        }
+        None => {
+            let usage = usage_at(document, line, char_idx)?;
+            Some((
+                usage.name,
+                defined_style(document, usage.name),
+                usage.range(),
+            ))
+        }
+    };
+    found.filter(|(name, _, _)| !name.is_empty())
+}
+
+/// The style name under byte column `char_idx` of an event line.
+fn usage_at(document: &AssDocument, line: u32, char_idx: usize) -> Option<StyleUsage<'_>> {
+    let event = document.event_at(line)?;
+    event_usages(event).into_iter().find(|usage| {
+        !usage.name.is_empty() && (usage.span.start..=usage.span.end).contains(&char_idx)
+    })
+}
+
+/// The style names an event uses: its Style field, then each `\r` tag that
+/// names a style.
+fn event_usages(event: &Event) -> Vec<StyleUsage<'_>> {
+    let line = event.range.start.line;
+    let mut usages = vec![StyleUsage {
+        name: &event.style,
+        line,
+        span: event.style_span.clone(),
+    }];
+    for token in tokenize(&event.text) {
+        let TextToken::Tag { tag, span } = token else {
+            continue;
+        };
+        let Some(target) = tag.strip_prefix('r') else {
+            continue;
+        };
+        if target.trim().is_empty() {
+            continue;
+        }
+        // Skip the backslash and the `r`
+        let start = event.text_start as usize + span.start + 2;
+        let start = start + target.len() - target.trim_start().len();
+        usages.push(StyleUsage {
+            name: target.trim(),
+            line,
+            span: start..start + target.trim().len(),
+        });
+    }
+    usages
+}
+
+/// The style `name` refers to: the one of that exact name, or failing that
+/// one differing only in case. The last definition wins, as in rendering.
+fn defined_style<'a>(document: &'a AssDocument, name: &str) -> Option<&'a Style> {
+    document.style(name).or_else(|| {
+        document
+            .styles
+            .iter()
+            .rev()
+            .find(|style| style.name.eq_ignore_ascii_case(name))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_index::PositionEncoding;
+    use crate::parser::AssParser;
+
+    const TEXT: &str = "[V4+ Styles]\nFormat: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\nStyle: Default,Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1\nStyle: Sign,Arial,60,&H0000FFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,3,0,8,10,10,10,1\n\n[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\nDialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,Hello\nDialogue: 0,0:00:02.00,0:00:03.00,sign,,0,0,0,,Shop{\\rDefault} and {\\r Sign}back\n";
+
+    fn uri() -> Url {
+        Url::parse("file:///tmp/signs.ass").unwrap()
+    }
+
+    #[test]
+    fn style_field_and_reset_tag_lead_to_the_style_line() {
+        let document = AssParser::new().parse(TEXT);
+        let index = LineIndex::new(TEXT.to_string(), PositionEncoding::Utf16);
+        let definition = |line, character| {
+            style_definition(&uri(), &document, &index, Position::new(line, character))
+        };
+
+        // `sign` in the Style field resolves, ignoring case, to Sign
+        let link = definition(8, 36).unwrap();
+        assert_eq!(
+            link.origin_selection_range,
+            Some(Range::new(Position::new(8, 34), Position::new(8, 38)))
+        );
+        assert_eq!(link.target_uri, uri());
+        assert_eq!(link.target_range.start.line, 3);
+        assert_eq!(
+            link.target_selection_range,
+            Range::new(Position::new(3, 7), Position::new(3, 11))
+        );
+
+        // The name in \rDefault, past its backslash and `r`
+        let link = definition(8, 55).unwrap();
+        assert_eq!(link.target_selection_range.start.line, 2);
+        assert!(definition(8, 45).is_none(), "dialogue text");
+    }
+
+    #[test]
+    fn references_cover_the_style_line_fields_and_reset_tags() {
+        let document = AssParser::new().parse(TEXT);
+        let index = LineIndex::new(TEXT.to_string(), PositionEncoding::Utf16);
+        let references = |line, character, include_declaration| {
+            style_references(
+                &uri(),
+                &document,
+                &index,
+                Position::new(line, character),
+                include_declaration,
+            )
+            .unwrap()
+            .into_iter()
+            .map(|location| {
+                let range = location.range;
+                (range.start.line, range.start.character, range.end.character)
+            })
+            .collect::<Vec<_>>()
+        };
+
+        // From the name on the Style line; `sign` and `\r Sign` both count
+        assert_eq!(
+            references(3, 9, true),
+            [(3, 7, 11), (8, 34, 38), (8, 71, 75)]
+        );
+        assert_eq!(references(8, 72, false), [(8, 34, 38), (8, 71, 75)]);
+        assert_eq!(
+            references(7, 36, true),
+            [(2, 7, 14), (7, 34, 41), (8, 54, 61)]
+        );
+    }
+}
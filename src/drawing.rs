@@ -0,0 +1,239 @@
+use crate::metadata::override_tag_name;
+use crate::text::{tokenize, TextToken};
+use std::ops::Range;
+
+/// Drawing command letters libass understands.
+const DRAWING_COMMANDS: &str = "mnlbspc";
+
+/// A `\p` tag and the text it applies to, up to the next `\p` tag.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrawingMode<'a> {
+    /// The tag's argument; 0 ends drawing mode.
+    pub scale: u32,
+    /// Span of the tag in the event text.
+    pub tag_span: Range<usize>,
+    /// Text runs that follow the tag, with other override blocks removed.
+    pub runs: Vec<&'a str>,
+}
+
+impl DrawingMode<'_> {
+    /// The text after the tag as one string of drawing commands.
+    pub fn commands(&self) -> String {
+        self.runs.join(" ")
+    }
+}
+
+/// Splits event text at its `\p` tags. Tags whose argument isn't a
+/// non-negative integer are skipped.
+pub fn drawing_modes(text: &str) -> Vec<DrawingMode<'_>> {
+    let mut modes: Vec<DrawingMode> = Vec::new();
+    for token in tokenize(text) {
+        match token {
+            TextToken::Tag { tag, span } if override_tag_name(tag) == Some("p") => {
+                if let Ok(scale) = tag[1..].trim().parse::<u32>() {
+                    modes.push(DrawingMode {
+                        scale,
+                        tag_span: span,
+                        runs: Vec::new(),
+                    });
+                }
+            }
+            TextToken::Text { text, .. } => {
+                if let Some(mode) = modes.last_mut() {
+                    mode.runs.push(text);
+                }
+            }
+            TextToken::Tag { .. } => {}
+        }
+    }
+    modes
+}
+
+/// Highest `\p` scale taken as deliberate. It already puts 2^15 units to
+/// the pixel; anything above is a typo whose divisor isn't worth printing.
+pub const MAX_DRAWING_SCALE: u32 = 16;
+
+/// What a `\p<scale>` drawing's coordinates are divided by: 2^(scale-1), so
+/// `\p1` draws at face value and each step up halves the size. `None` for
+/// `\p0`, which ends drawing mode.
+pub fn scale_divisor(scale: u32) -> Option<f64> {
+    (scale > 0).then(|| 2f64.powi(scale as i32 - 1))
+}
+
+/// Byte offsets of the command letters in drawing commands.
+pub fn command_letters(commands: &str) -> impl Iterator<Item = usize> + '_ {
+    commands
+        .char_indices()
+        .filter(|(_, ch)| DRAWING_COMMANDS.contains(*ch))
+        .map(|(i, _)| i)
+}
+
+/// A piece of a drawing: a command letter or one number.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DrawingToken {
+    Command(char),
+    Number(f64),
+}
+
+/// Splits drawing commands into letters and numbers, which may be written
+/// without spaces between them. `None` if anything else appears.
+fn drawing_tokens(commands: &str) -> Option<Vec<DrawingToken>> {
+    let mut tokens = Vec::new();
+    let mut chars = commands.char_indices().peekable();
+
+    while let Some((start, ch)) = chars.next() {
+        if ch.is_whitespace() {
+            continue;
+        }
+        if DRAWING_COMMANDS.contains(ch) {
+            tokens.push(DrawingToken::Command(ch));
+            continue;
+        }
+        if !(ch.is_ascii_digit() || matches!(ch, '-' | '+' | '.')) {
+            return None;
+        }
+        let mut end = start + ch.len_utf8();
+        while let Some(&(i, next)) = chars.peek() {
+            if !(next.is_ascii_digit() || next == '.') {
+                break;
+            }
+            end = i + next.len_utf8();
+            chars.next();
+        }
+        tokens.push(DrawingToken::Number(commands[start..end].parse().ok()?));
+    }
+    Some(tokens)
+}
+
+/// The coordinates in drawing commands, in the order written, or `None` if
+/// the text isn't a drawing: anything but command letters and numbers, or
+/// not starting with a move.
+pub fn drawing_coordinates(commands: &str) -> Option<Vec<(f64, f64)>> {
+    let tokens = drawing_tokens(commands)?;
+    if tokens.first() != Some(&DrawingToken::Command('m')) {
+        return None;
+    }
+
+    let numbers: Vec<f64> = tokens
+        .iter()
+        .filter_map(|token| match token {
+            DrawingToken::Number(number) => Some(*number),
+            DrawingToken::Command(_) => None,
+        })
+        .collect();
+    let coordinates: Vec<(f64, f64)> = numbers
+        .chunks_exact(2)
+        .map(|pair| (pair[0], pair[1]))
+        .collect();
+    (!coordinates.is_empty()).then_some(coordinates)
+}
+
+/// What is wrong with a drawing's commands, e.g. "doesn't start with a
+/// move (m)", or `None` if they are well formed: the shape starts with a
+/// move, and every command has the points it needs as whole x y pairs.
+/// Bézier curves take points in threes and splines at least three.
+pub fn drawing_problem(commands: &str) -> Option<String> {
+    let Some(tokens) = drawing_tokens(commands) else {
+        return Some("has something other than drawing commands and numbers".to_string());
+    };
+    if !matches!(tokens.first(), Some(DrawingToken::Command('m'))) {
+        return Some("doesn't start with a move (m)".to_string());
+    }
+
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        let DrawingToken::Command(command) = token else {
+            continue;
+        };
+        let mut numbers = 0;
+        while let Some(DrawingToken::Number(_)) = tokens.peek() {
+            numbers += 1;
+            tokens.next();
+        }
+        let points = numbers / 2;
+        return Some(match command {
+            _ if numbers % 2 == 1 => {
+                format!("has an odd number of coordinates ({numbers}) after `{command}`")
+            }
+            'c' if numbers > 0 => format!("has {points} points after `c`, which takes none"),
+            'c' => continue,
+            'b' if points == 0 || points % 3 != 0 => {
+                format!("has {points} points after `b`, which takes them in threes")
+            }
+            's' if points < 3 => {
+                format!("has {points} points after `s`, which takes at least 3")
+            }
+            _ if points == 0 => format!("has no points after `{command}`"),
+            _ => continue,
+        });
+    }
+    None
+}
+
+/// Bounds of a drawing in script pixels, before `\fscx`/`\fscy` scaling.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    pub min_x: f64,
+    pub min_y: f64,
+    pub max_x: f64,
+    pub max_y: f64,
+}
+
+impl BoundingBox {
+    pub fn width(&self) -> f64 {
+        self.max_x - self.min_x
+    }
+
+    pub fn height(&self) -> f64 {
+        self.max_y - self.min_y
+    }
+}
+
+/// The box around a drawing's coordinates once divided by its scale.
+pub fn bounding_box(coordinates: &[(f64, f64)], scale: u32) -> Option<BoundingBox> {
+    let divisor = scale_divisor(scale)?;
+    let (&(x, y), rest) = coordinates.split_first()?;
+    let mut bounds = BoundingBox {
+        min_x: x,
+        min_y: y,
+        max_x: x,
+        max_y: y,
+    };
+    for &(x, y) in rest {
+        bounds.min_x = bounds.min_x.min(x);
+        bounds.min_y = bounds.min_y.min(y);
+        bounds.max_x = bounds.max_x.max(x);
+        bounds.max_y = bounds.max_y.max(y);
+    }
+    Some(BoundingBox {
+        min_x: bounds.min_x / divisor,
+        min_y: bounds.min_y / divisor,
+        max_x: bounds.max_x / divisor,
+        max_y: bounds.max_y / divisor,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale_is_an_exponent_with_p1_as_the_identity() {
+        assert_eq!(scale_divisor(0), None);
+        assert_eq!(scale_divisor(1), Some(1.0));
+        assert_eq!(scale_divisor(2), Some(2.0));
+        assert_eq!(scale_divisor(3), Some(4.0));
+        assert_eq!(scale_divisor(4), Some(8.0));
+        assert_eq!(scale_divisor(MAX_DRAWING_SCALE), Some(32768.0));
+    }
+
+    #[test]
+    fn bounding_box_is_divided_by_the_scale() {
+        let coordinates = drawing_coordinates("m 0 0 l 80 0 80 40 0 40").unwrap();
+        let face_value = bounding_box(&coordinates, 1).unwrap();
+        assert_eq!((face_value.width(), face_value.height()), (80.0, 40.0));
+        let p4 = bounding_box(&coordinates, 4).unwrap();
+        assert_eq!((p4.width(), p4.height()), (10.0, 5.0));
+        assert_eq!(bounding_box(&coordinates, 0), None);
+    }
+}
@@ -0,0 +1,166 @@
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+
+/// Text decoded from disk along with what is needed to write it back unchanged.
+#[derive(Debug)]
+pub struct DecodedText {
+    pub text: String,
+    pub encoding: &'static Encoding,
+    pub has_bom: bool,
+}
+
+impl DecodedText {
+    /// Human-readable encoding label for reports, e.g. `UTF-16LE (BOM)`.
+    pub fn describe(&self) -> String {
+        if self.has_bom {
+            format!("{} (BOM)", self.encoding.name())
+        } else {
+            self.encoding.name().to_string()
+        }
+    }
+}
+
+/// Looks up a fallback encoding from a `--encoding` label such as `windows-1251` or `shift_jis`.
+pub fn encoding_for_label(label: &str) -> Option<&'static Encoding> {
+    Encoding::for_label(label.trim().as_bytes())
+}
+
+/// Decodes a subtitle file: BOM sniffing first, then a UTF-16 heuristic, then
+/// strict UTF-8, and finally the fallback codepage (Windows-1252 by default).
+pub fn decode(bytes: &[u8], fallback: Option<&'static Encoding>) -> Result<DecodedText, String> {
+    let (encoding, has_bom, payload) = match Encoding::for_bom(bytes) {
+        Some((encoding, bom_len)) => (encoding, true, &bytes[bom_len..]),
+        None => (sniff_utf16(bytes).unwrap_or(UTF_8), false, bytes),
+    };
+
+    if let Some(text) = encoding.decode_without_bom_handling_and_without_replacement(payload) {
+        return Ok(DecodedText {
+            text: text.into_owned(),
+            encoding,
+            has_bom,
+        });
+    }
+    if encoding != UTF_8 || has_bom {
+        return Err(format!("invalid {} data", encoding.name()));
+    }
+
+    let fallback = fallback.unwrap_or(WINDOWS_1252);
+    fallback
+        .decode_without_bom_handling_and_without_replacement(bytes)
+        .map(|text| DecodedText {
+            text: text.into_owned(),
+            encoding: fallback,
+            has_bom: false,
+        })
+        .ok_or_else(|| format!("not valid UTF-8 or {}", fallback.name()))
+}
+
+/// Encodes `text` back into the encoding it was read with, restoring the BOM.
+pub fn encode(text: &str, original: &DecodedText) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len() + 3);
+    let encoding = original.encoding;
+
+    if encoding == UTF_16LE || encoding == UTF_16BE {
+        let little_endian = encoding == UTF_16LE;
+        let units = std::iter::once('\u{feff}')
+            .filter(|_| original.has_bom)
+            .chain(text.chars())
+            .collect::<String>();
+        for unit in units.encode_utf16() {
+            let pair = if little_endian {
+                unit.to_le_bytes()
+            } else {
+                unit.to_be_bytes()
+            };
+            bytes.extend_from_slice(&pair);
+        }
+        return bytes;
+    }
+
+    if original.has_bom && encoding == UTF_8 {
+        bytes.extend_from_slice(b"\xEF\xBB\xBF");
+    }
+    let (encoded, _, _) = encoding.encode(text);
+    bytes.extend_from_slice(&encoded);
+    bytes
+}
+
+/// ASS files are overwhelmingly ASCII, so BOM-less UTF-16 shows up as a zero
+/// byte in every other position.
+fn sniff_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    let sample = &bytes[..bytes.len().min(1024) & !1];
+    if sample.len() < 4 {
+        return None;
+    }
+
+    let pairs = sample.len() / 2;
+    let even_zeros = sample.iter().step_by(2).filter(|&&b| b == 0).count();
+    let odd_zeros = sample
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .filter(|&&b| b == 0)
+        .count();
+
+    if odd_zeros * 10 >= pairs * 9 && even_zeros == 0 {
+        Some(UTF_16LE)
+    } else if even_zeros * 10 >= pairs * 9 && odd_zeros == 0 {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = "[Script Info]\r\nTitle: Ünïcödé 字幕\r\n\r\n[Events]\r\nDialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,こんにちは\r\n";
+
+    fn utf16le(text: &str, bom: bool) -> Vec<u8> {
+        let mut bytes = if bom { vec![0xFF, 0xFE] } else { Vec::new() };
+        bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        bytes
+    }
+
+    #[test]
+    fn utf16le_with_bom_round_trips() {
+        let bytes = utf16le(SCRIPT, true);
+        let decoded = decode(&bytes, None).unwrap();
+        assert_eq!(decoded.text, SCRIPT);
+        assert_eq!(decoded.encoding, UTF_16LE);
+        assert!(decoded.has_bom);
+        assert_eq!(decoded.describe(), "UTF-16LE (BOM)");
+        assert_eq!(encode(&decoded.text, &decoded), bytes);
+    }
+
+    #[test]
+    fn utf16_without_bom_is_sniffed() {
+        let bytes = utf16le(SCRIPT, false);
+        let decoded = decode(&bytes, None).unwrap();
+        assert_eq!(decoded.encoding, UTF_16LE);
+        assert!(!decoded.has_bom);
+        assert_eq!(encode(&decoded.text, &decoded), bytes);
+
+        let big_endian: Vec<u8> = SCRIPT.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        let decoded = decode(&big_endian, None).unwrap();
+        assert_eq!(decoded.encoding, UTF_16BE);
+        assert_eq!(encode(&decoded.text, &decoded), big_endian);
+    }
+
+    #[test]
+    fn utf8_bom_and_fallback_codepage_round_trip() {
+        let mut bytes = b"\xEF\xBB\xBF".to_vec();
+        bytes.extend_from_slice(SCRIPT.as_bytes());
+        let decoded = decode(&bytes, None).unwrap();
+        assert_eq!((decoded.encoding, decoded.has_bom), (UTF_8, true));
+        assert_eq!(encode(&decoded.text, &decoded), bytes);
+
+        let latin1 = b"Title: caf\xE9\n";
+        let decoded = decode(latin1, None).unwrap();
+        assert_eq!(
+            (decoded.encoding, decoded.text.as_str()),
+            (WINDOWS_1252, "Title: café\n")
+        );
+        assert_eq!(encode(&decoded.text, &decoded), latin1);
+    }
+}
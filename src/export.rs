@@ -0,0 +1,105 @@
+use crate::parser::{AssDocument, AssTime};
+use crate::text::rendered_rows;
+use std::fmt::Write;
+
+/// A time as SRT writes it, `HH:MM:SS,mmm`.
+pub fn srt_timestamp(time: AssTime) -> String {
+    let millis = time.as_millis();
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// The dialogue of a script as SubRip, in start time order. Comments, lines
+/// with unreadable times and lines with no visible text are left out; override
+/// tags and drawings are dropped, `\N` breaks the line and `\h` is a space.
+pub fn to_srt(document: &AssDocument) -> String {
+    let mut cues: Vec<_> = document
+        .events
+        .iter()
+        .filter(|event| event.event_type.eq_ignore_ascii_case("Dialogue"))
+        .filter_map(|event| {
+            let rows: Vec<String> = rendered_rows(&event.text)
+                .iter()
+                .map(|row| {
+                    let row: String = row
+                        .iter()
+                        .map(|c| if c.ch == '\u{a0}' { ' ' } else { c.ch })
+                        .collect();
+                    row.trim().to_string()
+                })
+                .filter(|row| !row.is_empty())
+                .collect();
+            let (start, end) = (event.start?, event.end?);
+            (!rows.is_empty()).then_some((start, end, rows.join("\n")))
+        })
+        .collect();
+    cues.sort_by_key(|&(start, ..)| start);
+
+    let mut srt = String::new();
+    for (number, (start, end, text)) in cues.iter().enumerate() {
+        if number > 0 {
+            srt.push('\n');
+        }
+        let _ = writeln!(
+            srt,
+            "{}\n{} --> {}\n{text}",
+            number + 1,
+            srt_timestamp(*start),
+            srt_timestamp(*end)
+        );
+    }
+    srt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::AssParser;
+
+    fn script(events: &str) -> AssDocument {
+        AssParser::new().parse(&format!(
+            "[Script Info]\nScriptType: v4.00+\n\n[V4+ Styles]\n\
+             Format: Name, Fontname, Fontsize, PrimaryColour\nStyle: Default,Arial,20,&H00FFFFFF\n\n[Events]\n\
+             Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+             {events}"
+        ))
+    }
+
+    #[test]
+    fn timestamps_keep_milliseconds_and_roll_over_hours() {
+        assert_eq!(srt_timestamp(AssTime(0)), "00:00:00,000");
+        assert_eq!(srt_timestamp(AssTime(123)), "00:00:01,230");
+        assert_eq!(srt_timestamp(AssTime(360000 + 6000 + 150)), "01:01:01,500");
+    }
+
+    #[test]
+    fn dialogue_is_numbered_in_start_order_without_comments() {
+        let document = script(
+            "Dialogue: 0,0:00:05.00,0:00:06.00,Default,,0,0,0,,Second\n\
+             Comment: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,Note\n\
+             Dialogue: 0,0:00:01.00,0:00:02.50,Default,,0,0,0,,First\n",
+        );
+        assert_eq!(
+            to_srt(&document),
+            "1\n00:00:01,000 --> 00:00:02,500\nFirst\n\n\
+             2\n00:00:05,000 --> 00:00:06,000\nSecond\n"
+        );
+    }
+
+    #[test]
+    fn tags_and_drawings_are_dropped_and_breaks_kept() {
+        let document = script(
+            "Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,{\\b1}One\\Ntwo\\hthree\n\
+             Dialogue: 0,0:00:03.00,0:00:04.00,Default,,0,0,0,,{\\p1}m 0 0 l 10 10{\\p0}\n",
+        );
+        assert_eq!(
+            to_srt(&document),
+            "1\n00:00:01,000 --> 00:00:02,000\nOne\ntwo three\n"
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use crate::line_index::{LineIndex, PositionEncoding};
+use crate::parser::AssParser;
+use crate::text::empty_override_blocks;
+use crate::validation::{legacy_alignment_edits, ValidationProvider};
+use tower_lsp::lsp_types::{CodeActionKind, Position, Range, TextEdit};
+
+/// Kind of the code action that applies every safe fix at once, for editors
+/// set to fix all on save.
+pub const FIX_ALL: &str = "source.fixAll.assLsp";
+
+/// Whether a code action request limited to `only` wants [`FIX_ALL`]. It is
+/// left out of unfiltered requests, as working it out formats the document.
+pub fn requested(only: Option<&[CodeActionKind]>) -> bool {
+    only.into_iter().flatten().any(|kind| {
+        FIX_ALL == kind.as_str()
+            || FIX_ALL
+                .strip_prefix(kind.as_str())
+                .is_some_and(|rest| rest.starts_with('.'))
+    })
+}
+
+/// `text` formatted, with the fixes that need no judgement applied on top:
+/// malformed times whose value is certain written out canonically, legacy
+/// `\a` tags converted to `\an` and empty override blocks removed. The fixes
+/// are worked out on the formatted text, so the edits from the document to
+/// this text already hold the formatting and formatting again on the same
+/// save finds nothing left to change.
+pub fn fix_all(text: &str, parser: &AssParser, validation: &ValidationProvider) -> String {
+    let formatted = parser.format(text);
+    let document = parser.parse(&formatted);
+    // Edit columns are then byte offsets into the formatted lines
+    let index = LineIndex::new(formatted.clone(), PositionEncoding::Utf8);
+
+    let mut edits = legacy_alignment_edits(&document, &index);
+    for event in &document.events {
+        let line = event.range.start.line;
+        let text = index.line_text(line as usize);
+        let at = |start: usize, end: usize| {
+            Range::new(
+                Position::new(line, start as u32),
+                Position::new(line, end as u32),
+            )
+        };
+        for span in [&event.start_span, &event.end_span] {
+            if let Some((span, canonical)) = validation.time_fix(text, span.clone()) {
+                edits.push(TextEdit::new(at(span.start, span.end), canonical));
+            }
+        }
+        let base = event.text_start as usize;
+        for span in empty_override_blocks(&event.text) {
+            edits.push(TextEdit::new(
+                at(base + span.start, base + span.end),
+                String::new(),
+            ));
+        }
+    }
+
+    let mut edits: Vec<(usize, usize, String)> = edits
+        .into_iter()
+        .map(|edit| {
+            let start = index.position_to_offset(edit.range.start);
+            let end = index.position_to_offset(edit.range.end);
+            (start, end, edit.new_text)
+        })
+        .collect();
+    edits.sort_by_key(|(start, _, _)| std::cmp::Reverse(*start));
+    let mut fixed = formatted;
+    let mut applied_from = usize::MAX;
+    for (start, end, new_text) in edits {
+        // A fix overlapping one already applied is left for the next pass
+        if end > applied_from {
+            continue;
+        }
+        fixed.replace_range(start..end, &new_text);
+        applied_from = start;
+    }
+    fixed
+}
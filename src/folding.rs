@@ -0,0 +1,122 @@
+use crate::line_index::LineIndex;
+use crate::parser::{is_attachment_section, AssDocument};
+use tower_lsp::lsp_types::{FoldingRange, FoldingRangeKind};
+
+/// Folds for each section, from its header to its last non-empty line; for
+/// each run of `;` comment lines; and for each run of `Comment:` events with
+/// no `Dialogue:` between them. Runs nest inside their section, and a fold
+/// needs at least two lines.
+pub fn folding_ranges(document: &AssDocument, index: &LineIndex) -> Vec<FoldingRange> {
+    let mut ranges = Vec::new();
+    for section in &document.sections {
+        push_fold(
+            &mut ranges,
+            section.range.start.line,
+            section.range.end.line,
+            FoldingRangeKind::Region,
+        );
+    }
+
+    // Attachment data lines may start with ';' without being comments
+    let in_attachment = |line: u32| {
+        document.sections.iter().any(|section| {
+            is_attachment_section(&section.name)
+                && section.range.start.line < line
+                && line <= section.range.end.line
+        })
+    };
+    let comment_lines = (0..index.line_count() as u32).map(|line| {
+        let comment = index.line_text(line as usize).trim().starts_with(';');
+        (line, comment && !in_attachment(line))
+    });
+    fold_runs(&mut ranges, comment_lines);
+    let comment_events = document
+        .events
+        .iter()
+        .map(|event| (event.range.start.line, event.event_type == "Comment"));
+    fold_runs(&mut ranges, comment_events);
+
+    ranges.sort_by_key(|range| (range.start_line, std::cmp::Reverse(range.end_line)));
+    ranges
+}
+
+fn push_fold(ranges: &mut Vec<FoldingRange>, start: u32, end: u32, kind: FoldingRangeKind) {
+    if end > start {
+        ranges.push(FoldingRange {
+            start_line: start,
+            end_line: end,
+            kind: Some(kind),
+            ..Default::default()
+        });
+    }
+}
+
+/// Folds each run of lines marked true, a run ending at the first line
+/// marked false.
+fn fold_runs(ranges: &mut Vec<FoldingRange>, lines: impl Iterator<Item = (u32, bool)>) {
+    let mut run: Option<(u32, u32)> = None;
+    for (line, marked) in lines {
+        run = match (run, marked) {
+            (Some((start, _)), true) => Some((start, line)),
+            (None, true) => Some((line, line)),
+            (Some((start, end)), false) => {
+                push_fold(ranges, start, end, FoldingRangeKind::Comment);
+                None
+            }
+            (None, false) => None,
+        };
+    }
+    if let Some((start, end)) = run {
+        push_fold(ranges, start, end, FoldingRangeKind::Comment);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_index::PositionEncoding;
+    use crate::parser::AssParser;
+
+    fn folds(text: &str) -> Vec<(u32, u32, FoldingRangeKind)> {
+        let document = AssParser::new().parse(text);
+        let index = LineIndex::new(text.to_string(), PositionEncoding::Utf16);
+        folding_ranges(&document, &index)
+            .into_iter()
+            .map(|range| (range.start_line, range.end_line, range.kind.unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn sections_fold_to_their_last_line_before_trailing_blanks() {
+        use FoldingRangeKind::{Comment, Region};
+        let text = "[Script Info]\n; Made by hand\n; for the fansub\nScriptType: v4.00+\n\n\n\
+                    [V4+ Styles]\nFormat: Name, Fontname, Fontsize, PrimaryColour\nStyle: Default,Arial,48,&H00FFFFFF\n\n\n\n\
+                    [Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+                    Comment: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,Draft one\n\
+                    Comment: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,Draft two\n\
+                    Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,Final\n\
+                    Comment: 0,0:00:03.00,0:00:04.00,Default,,0,0,0,,Lone note\n\n\n\
+                    [Fonts]\nfontname: a.ttf\n;;;;;;\n;;;;;;\n\n";
+        assert_eq!(
+            folds(text),
+            vec![
+                (0, 3, Region),
+                (1, 2, Comment),
+                (6, 8, Region),
+                (12, 17, Region),
+                (14, 15, Comment),
+                (20, 23, Region),
+            ]
+        );
+
+        // Section folds never overlap, and nested folds stay inside theirs
+        let all = folds(text);
+        let regions: Vec<_> = all.iter().filter(|fold| fold.2 == Region).collect();
+        assert!(regions.windows(2).all(|pair| pair[0].1 < pair[1].0));
+        for fold in all.iter().filter(|fold| fold.2 == Comment) {
+            assert!(regions
+                .iter()
+                .any(|region| region.0 < fold.0 && fold.1 <= region.1));
+        }
+    }
+}
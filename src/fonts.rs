@@ -0,0 +1,89 @@
+use crate::parser::Attachment;
+use once_cell::sync::Lazy;
+use std::collections::HashSet;
+use std::process::Command;
+
+/// Installed font families, enumerated once per process.
+pub static SYSTEM_FONTS: Lazy<FontCatalog> = Lazy::new(FontCatalog::from_system);
+
+/// Style suffixes renderers strip when matching a Fontname to a family.
+const STYLE_SUFFIXES: [&str; 8] = [
+    " bold",
+    " italic",
+    " regular",
+    " light",
+    " medium",
+    " semibold",
+    " black",
+    " oblique",
+];
+
+#[derive(Debug)]
+pub enum FontCatalog {
+    /// Normalized family names known to be installed.
+    Known(HashSet<String>),
+    /// Fonts could not be enumerated (e.g. a headless system without fontconfig).
+    Unavailable,
+}
+
+impl FontCatalog {
+    pub fn from_families<I: IntoIterator<Item = S>, S: AsRef<str>>(families: I) -> Self {
+        Self::Known(
+            families
+                .into_iter()
+                .map(|family| normalize_family(family.as_ref()))
+                .collect(),
+        )
+    }
+
+    fn from_system() -> Self {
+        let output = Command::new("fc-list").args([":", "family"]).output();
+        match output {
+            Ok(output) if output.status.success() && !output.stdout.is_empty() => {
+                let listing = String::from_utf8_lossy(&output.stdout);
+                Self::from_families(listing.lines().flat_map(|line| line.split(',')))
+            }
+            _ => Self::Unavailable,
+        }
+    }
+
+    /// Returns `None` when availability cannot be determined.
+    pub fn contains(&self, fontname: &str) -> Option<bool> {
+        match self {
+            Self::Known(families) => Some(families.contains(&normalize_family(fontname))),
+            Self::Unavailable => None,
+        }
+    }
+}
+
+/// Returns true if an attachment in `[Fonts]` looks like it embeds `fontname`.
+/// Embedded font filenames are derived from the family name, e.g. `arial_0.ttf`.
+pub fn is_embedded(fontname: &str, attachments: &[Attachment]) -> bool {
+    let wanted = compact(&normalize_family(fontname));
+    !wanted.is_empty()
+        && attachments
+            .iter()
+            .filter(|attachment| attachment.section == "Fonts")
+            .any(|attachment| {
+                let stem = attachment
+                    .filename
+                    .rsplit_once('.')
+                    .map_or(attachment.filename.as_str(), |(stem, _)| stem);
+                compact(stem).starts_with(&wanted)
+            })
+}
+
+fn normalize_family(name: &str) -> String {
+    let mut name = name.trim().trim_start_matches('@').to_lowercase();
+    while let Some(suffix) = STYLE_SUFFIXES.iter().find(|s| name.ends_with(*s)) {
+        name.truncate(name.len() - suffix.len());
+    }
+    name
+}
+
+fn compact(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
@@ -0,0 +1,405 @@
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use tower_lsp::lsp_types::{Diagnostic, NumberOrString, TextDocumentIdentifier};
+
+/// Custom request comparing the diagnostics published for two document versions.
+pub const DIAGNOSTICS_DELTA: &str = "ass-lsp/diagnosticsDelta";
+
+/// Published versions kept per document.
+const HISTORY_LEN: usize = 8;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticsDeltaParams {
+    pub text_document: TextDocumentIdentifier,
+    pub from: VersionRef,
+    /// Defaults to the latest published version.
+    pub to: Option<VersionRef>,
+}
+
+/// A version number, or a version relative to another one.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(untagged)]
+pub enum VersionRef {
+    Version(i32),
+    Keyword(VersionKeyword),
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VersionKeyword {
+    /// The version published before the other end of the delta (or before the
+    /// latest, for `to`).
+    Previous,
+    Latest,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticsDeltaResponse {
+    pub from_version: i32,
+    pub to_version: i32,
+    /// New in `to`.
+    pub added: Vec<Diagnostic>,
+    /// Gone in `to`; ranges are moved to `to` when their line survived the edits.
+    pub removed: Vec<Diagnostic>,
+    /// Present in both, as published for `to`.
+    pub persisting: Vec<Diagnostic>,
+}
+
+/// The diagnostics published for a document version. Lines are kept as hashes
+/// so old versions can be mapped onto new ones without holding their text.
+#[derive(Debug)]
+struct Snapshot {
+    version: i32,
+    line_hashes: Vec<u64>,
+    diagnostics: Vec<Diagnostic>,
+    /// Identifies the set for pull diagnostics; equal sets get equal ids.
+    result_id: String,
+}
+
+/// The last few published diagnostic sets of one document.
+#[derive(Debug, Default)]
+pub struct DiagnosticHistory {
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl DiagnosticHistory {
+    pub fn record(&mut self, version: i32, text: &str, diagnostics: Vec<Diagnostic>) {
+        // A republished version replaces its snapshot; an older one means the
+        // document was reopened and the history no longer applies
+        while self
+            .snapshots
+            .back()
+            .is_some_and(|snapshot| snapshot.version >= version)
+        {
+            self.snapshots.pop_back();
+        }
+        if self.snapshots.len() == HISTORY_LEN {
+            self.snapshots.pop_front();
+        }
+        let mut hasher = DefaultHasher::new();
+        serde_json::to_string(&diagnostics)
+            .unwrap_or_default()
+            .hash(&mut hasher);
+        self.snapshots.push_back(Snapshot {
+            version,
+            line_hashes: text.lines().map(hash_line).collect(),
+            diagnostics,
+            result_id: format!("{:016x}", hasher.finish()),
+        });
+    }
+
+    /// The latest published set and its result id.
+    pub fn latest(&self) -> Option<(&str, &[Diagnostic])> {
+        let snapshot = self.snapshots.back()?;
+        Some((&snapshot.result_id, &snapshot.diagnostics))
+    }
+
+    pub fn delta(
+        &self,
+        from: VersionRef,
+        to: Option<VersionRef>,
+    ) -> Result<DiagnosticsDeltaResponse, String> {
+        let latest = self
+            .snapshots
+            .len()
+            .checked_sub(1)
+            .ok_or("No diagnostics published")?;
+        let to = self.resolve(
+            to.unwrap_or(VersionRef::Keyword(VersionKeyword::Latest)),
+            latest,
+        )?;
+        let from = self.resolve(from, to)?;
+        let (older, newer) = (&self.snapshots[from], &self.snapshots[to]);
+        let map = LineMap::new(&older.line_hashes, &newer.line_hashes);
+
+        let matches = match_diagnostics(&older.diagnostics, &newer.diagnostics, &map);
+        let matched_older: BTreeSet<usize> = matches.values().copied().collect();
+        let mut response = DiagnosticsDeltaResponse {
+            from_version: older.version,
+            to_version: newer.version,
+            added: Vec::new(),
+            removed: Vec::new(),
+            persisting: Vec::new(),
+        };
+        for (index, diagnostic) in newer.diagnostics.iter().enumerate() {
+            if matches.contains_key(&index) {
+                response.persisting.push(diagnostic.clone());
+            } else {
+                response.added.push(diagnostic.clone());
+            }
+        }
+        for (index, diagnostic) in older.diagnostics.iter().enumerate() {
+            if matched_older.contains(&index) {
+                continue;
+            }
+            let mut diagnostic = diagnostic.clone();
+            if let (Some(start), Some(end)) = (
+                map.exact(diagnostic.range.start.line),
+                map.exact(diagnostic.range.end.line),
+            ) {
+                diagnostic.range.start.line = start;
+                diagnostic.range.end.line = end;
+            }
+            response.removed.push(diagnostic);
+        }
+        Ok(response)
+    }
+
+    /// Resolves `version` to a snapshot index; `previous` is relative to `base`.
+    fn resolve(&self, version: VersionRef, base: usize) -> Result<usize, String> {
+        match version {
+            VersionRef::Version(version) => self
+                .snapshots
+                .iter()
+                .position(|snapshot| snapshot.version == version)
+                .ok_or_else(|| format!("Version {version} is not in the diagnostics history")),
+            VersionRef::Keyword(VersionKeyword::Latest) => Ok(self.snapshots.len() - 1),
+            VersionRef::Keyword(VersionKeyword::Previous) => base
+                .checked_sub(1)
+                .ok_or_else(|| "No earlier version in the diagnostics history".to_string()),
+        }
+    }
+}
+
+fn hash_line(line: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    line.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Maps lines of an older version onto a newer one, treating everything
+/// between the unchanged prefix and suffix as a single edit.
+struct LineMap {
+    prefix: u32,
+    old_suffix_start: u32,
+    delta: i64,
+}
+
+impl LineMap {
+    fn new(older: &[u64], newer: &[u64]) -> Self {
+        let prefix = older.iter().zip(newer).take_while(|(a, b)| a == b).count();
+        let suffix = older
+            .iter()
+            .rev()
+            .zip(newer.iter().rev())
+            .take(older.len().min(newer.len()) - prefix)
+            .take_while(|(a, b)| a == b)
+            .count();
+        Self {
+            prefix: prefix as u32,
+            old_suffix_start: (older.len() - suffix) as u32,
+            delta: newer.len() as i64 - older.len() as i64,
+        }
+    }
+
+    /// Where an unedited line ended up.
+    fn exact(&self, line: u32) -> Option<u32> {
+        if line < self.prefix {
+            Some(line)
+        } else if line >= self.old_suffix_start {
+            Some((line as i64 + self.delta) as u32)
+        } else {
+            None
+        }
+    }
+
+    /// Like `exact`, with edited lines placed at the start of the edit.
+    fn approximate(&self, line: u32) -> u32 {
+        self.exact(line).unwrap_or(self.prefix)
+    }
+}
+
+fn match_key(diagnostic: &Diagnostic) -> String {
+    match &diagnostic.code {
+        Some(NumberOrString::String(code)) => code.clone(),
+        Some(NumberOrString::Number(code)) => code.to_string(),
+        None => diagnostic.message.clone(),
+    }
+}
+
+/// Pairs each newer diagnostic with the unmatched older one of the same code
+/// on the nearest (mapped) line. Newer diagnostics are taken in line order and
+/// ties go to the earlier line, so the result doesn't depend on hashing.
+/// Returns newer index -> older index.
+fn match_diagnostics(
+    older: &[Diagnostic],
+    newer: &[Diagnostic],
+    map: &LineMap,
+) -> HashMap<usize, usize> {
+    let mut unmatched: HashMap<String, BTreeSet<(u32, usize)>> = HashMap::new();
+    for (index, diagnostic) in older.iter().enumerate() {
+        unmatched
+            .entry(match_key(diagnostic))
+            .or_default()
+            .insert((map.approximate(diagnostic.range.start.line), index));
+    }
+
+    let mut order: Vec<usize> = (0..newer.len()).collect();
+    order.sort_by_key(|&index| (newer[index].range.start, index));
+
+    // Diagnostics that stayed on their line pair up first, so one introduced
+    // by the edit can't claim a neighbour's older twin
+    let mut matches = HashMap::new();
+    for exact in [true, false] {
+        for &index in &order {
+            if matches.contains_key(&index) {
+                continue;
+            }
+            let Some(candidates) = unmatched.get_mut(&match_key(&newer[index])) else {
+                continue;
+            };
+            let line = newer[index].range.start.line;
+            let below = candidates.range((line, 0)..).next().copied();
+            let above = candidates.range(..(line, 0)).next_back().copied();
+            let nearest = match (above, below) {
+                _ if exact => below.filter(|below| below.0 == line),
+                (Some(above), Some(below)) if line - above.0 <= below.0 - line => Some(above),
+                (above, None) => above,
+                (_, below) => below,
+            };
+            if let Some(candidate) = nearest {
+                candidates.remove(&candidate);
+                matches.insert(index, candidate.1);
+            }
+        }
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::AssParser;
+    use crate::validation::ValidationProvider;
+    use tower_lsp::lsp_types::Url;
+
+    const SCRIPT: &str = "[Script Info]\nScriptType: v4.00+\nPlayResX: 1920\nPlayResY: 1080\n\n[V4+ Styles]\nFormat: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\nStyle: Default,Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1\n\n[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\nDialogue: 0,0:00:01.00,0:00:03.00,Default,,0,0,0,,One\nDialogue: 0,0:00:04.00,0:00:06.00,Default,,0,0,0,,Two\nDialogue: 0,0:00:07.00,0:00:09.00,Default,,0,0,0,,Three\n";
+
+    /// Replaces `old` with `new` in the text before `version` and records
+    /// what validation publishes for it.
+    fn edit(
+        history: &mut DiagnosticHistory,
+        text: &mut String,
+        version: i32,
+        old: &str,
+        new: &str,
+    ) {
+        assert!(text.contains(old));
+        *text = text.replacen(old, new, 1);
+        let uri = Url::parse("file:///tmp/delta.ass").unwrap();
+        let document = AssParser::new().parse(text);
+        let diagnostics = ValidationProvider::new().validate(&document, &uri);
+        history.record(version, text, diagnostics);
+    }
+
+    fn codes(diagnostics: &[Diagnostic]) -> Vec<(u32, String)> {
+        let mut codes: Vec<(u32, String)> = diagnostics
+            .iter()
+            .map(|diagnostic| (diagnostic.range.start.line, match_key(diagnostic)))
+            .collect();
+        codes.sort();
+        codes
+    }
+
+    fn previous(history: &DiagnosticHistory) -> DiagnosticsDeltaResponse {
+        history
+            .delta(VersionRef::Keyword(VersionKeyword::Previous), None)
+            .unwrap()
+    }
+
+    #[test]
+    fn delta_follows_problems_through_a_sequence_of_edits() {
+        let mut history = DiagnosticHistory::default();
+        let mut text = String::new();
+        edit(&mut history, &mut text, 1, "", SCRIPT);
+        let baseline = history.latest().unwrap().1.len();
+
+        // A typo in a style name
+        edit(
+            &mut history,
+            &mut text,
+            2,
+            ",Default,,0,0,0,,Two",
+            ",Defualt,,0,0,0,,Two",
+        );
+        let delta = previous(&history);
+        assert_eq!((delta.from_version, delta.to_version), (1, 2));
+        assert_eq!(codes(&delta.added), [(12, "undefined_style".to_string())]);
+        assert!(delta.removed.is_empty());
+        assert_eq!(delta.persisting.len(), baseline);
+
+        // A line inserted above it moves it down without making it new
+        edit(
+            &mut history,
+            &mut text,
+            3,
+            "Dialogue: 0,0:00:01.00",
+            "Dialogue: 0,0:00:00.00,0:00:00.90,Default,,0,0,0,,Zero\nDialogue: 0,0:00:01.00",
+        );
+        let delta = previous(&history);
+        assert!(delta.added.is_empty());
+        assert!(delta.removed.is_empty());
+        assert!(codes(&delta.persisting).contains(&(13, "undefined_style".to_string())));
+
+        // Defining the missing style fixes it without touching its line
+        edit(
+            &mut history,
+            &mut text,
+            4,
+            "\n\n[Events]",
+            "\nStyle: Defualt,Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,8,10,10,10,1\n\n[Events]",
+        );
+        let delta = previous(&history);
+        assert!(delta.added.is_empty());
+        assert_eq!(codes(&delta.removed), [(14, "undefined_style".to_string())]);
+
+        // Across versions, the typo is removed below both inserted lines
+        let delta = history
+            .delta(VersionRef::Version(2), Some(VersionRef::Version(4)))
+            .unwrap();
+        assert_eq!(codes(&delta.removed), [(14, "undefined_style".to_string())]);
+        assert!(delta.added.is_empty());
+        assert_eq!(delta.persisting.len(), baseline);
+
+        // Breaking a time is new relative to every earlier version
+        edit(
+            &mut history,
+            &mut text,
+            5,
+            "0:00:07.00,0:00:09.00",
+            "0:00:09.00,0:00:07.00",
+        );
+        let delta = history.delta(VersionRef::Version(1), None).unwrap();
+        assert_eq!((delta.from_version, delta.to_version), (1, 5));
+        assert_eq!(
+            codes(&delta.added),
+            [(15, "invalid_time_order".to_string())]
+        );
+        assert!(delta.removed.is_empty());
+        assert_eq!(delta.persisting.len(), baseline);
+    }
+
+    #[test]
+    fn history_is_bounded_and_reset_by_an_older_version() {
+        let mut history = DiagnosticHistory::default();
+        for version in 1..=HISTORY_LEN as i32 + 3 {
+            history.record(version, SCRIPT, Vec::new());
+        }
+        assert_eq!(history.snapshots.len(), HISTORY_LEN);
+        assert!(history.delta(VersionRef::Version(3), None).is_err());
+        assert!(history.delta(VersionRef::Version(4), None).is_ok());
+
+        // Republishing a version replaces it; reopening at 1 starts over
+        history.record(HISTORY_LEN as i32 + 3, SCRIPT, Vec::new());
+        assert_eq!(history.snapshots.len(), HISTORY_LEN);
+        history.record(1, SCRIPT, Vec::new());
+        assert_eq!(history.snapshots.len(), 1);
+        assert!(history
+            .delta(VersionRef::Keyword(VersionKeyword::Previous), None)
+            .is_err());
+    }
+}
@@ -1,10 +1,31 @@
+use crate::drawing::{
+    bounding_box, drawing_coordinates, drawing_modes, scale_divisor, MAX_DRAWING_SCALE,
+};
+use crate::karaoke::karaoke_syllables;
+use crate::line_index::LineIndex;
+use crate::metadata::{
+    event_effect, known_tag_name, override_tag_name, style_field, ATTACHMENT_EMBEDDING,
+    BORDER_SCALED_TAGS,
+};
+use crate::parser::{
+    attachment_header_key, canonical_script_info_key, canonical_section_name, field_index_at,
+    is_attachment_data, is_attachment_section, parse_attachment_header, strip_prefix_ignore_case,
+    style_format_at, AssColor, AssDocument, AssTime, Event, MarginSide,
+};
+use crate::render::{
+    border_scaling, effective_margin, effective_play_res, missing_play_res_note, play_res,
+    play_res_entries, resolved_play_res, MarginSource, RenderTarget, ScrollEffect,
+};
+use crate::text::{parse_transform, tokenize, TextToken};
 use regex::Regex;
 use tower_lsp::lsp_types::*;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct HoverProvider {
     time_regex: Regex,
     color_regex: Regex,
+    /// Renderer whose defaults are assumed for settings a script leaves out.
+    pub render_target: RenderTarget,
 }
 
 impl HoverProvider {
@@ -12,61 +33,143 @@ impl HoverProvider {
         Self {
             time_regex: Regex::new(r"\d{1,2}:\d{2}:\d{2}\.\d{2}").unwrap(),
             color_regex: Regex::new(r"&H[0-9A-Fa-f]{6,8}").unwrap(),
+            render_target: RenderTarget::default(),
         }
     }
 
-    pub fn provide_hover(&self, text: &str, position: Position) -> Option<Hover> {
-        let lines: Vec<&str> = text.lines().collect();
-        let line_idx = position.line as usize;
+    pub fn provide_hover(
+        &self,
+        document: &AssDocument,
+        index: &LineIndex,
+        position: Position,
+    ) -> Option<Hover> {
+        let lines = index.lines();
+        let (line_idx, char_idx) = index.clamp(position);
+        let current_line = lines[line_idx];
 
-        if line_idx >= lines.len() {
+        // Embedded attachment payloads carry no hoverable tokens
+        if is_attachment_data(current_line.trim())
+            && document
+                .section_at(line_idx as u32)
+                .is_some_and(|section| is_attachment_section(&section.name))
+        {
             return None;
         }
 
-        let current_line = lines[line_idx];
-        let char_idx = position.character as usize;
-
         // Find the word or token at the cursor position
-        let token = self.get_token_at_position(current_line, char_idx)?;
+        let (token_start, token) = self.get_token_at_position(current_line, char_idx)?;
+
+        let attachment_section = document
+            .section_at(line_idx as u32)
+            .filter(|section| is_attachment_section(&section.name));
+        let line_info = if let Some(section) = attachment_section {
+            // Either part of a header line explains how attachments are written
+            parse_attachment_header(current_line.trim())
+                .map(|(key, _)| self.get_attachment_header_info(&section.name, key))
+        } else if strip_prefix_ignore_case(current_line, "Style:").is_some() {
+            // Values on a Style line are described by the column they sit in
+            self.get_style_value_info(document, &lines, line_idx, char_idx, &token)
+        } else if let Some(event) = document
+            .event_at(line_idx as u32)
+            .filter(|event| event.event_type == "Dialogue")
+        {
+            // Karaoke syllables show when they start; tags inside \t are animated
+            self.get_syllable_info(event, char_idx)
+                .or_else(|| self.get_transform_tag_info(event, char_idx))
+                .or_else(|| self.get_drawing_info(event, char_idx))
+                .or_else(|| self.get_margin_info(document, event, char_idx))
+                .or_else(|| self.get_effect_info(document, event, char_idx))
+                .or_else(|| {
+                    let info = self.get_event_type_info(&token)?;
+                    let mut info =
+                        format!("{info}\n\n{}", self.get_margins_summary(document, event));
+                    if let Some(motion) = self.get_scroll_effect_summary(document, event) {
+                        info.push_str(&format!("\n\n{motion}"));
+                    }
+                    Some(info)
+                })
+        } else {
+            None
+        };
 
         // Determine what kind of token this is and provide appropriate hover info
-        self.get_hover_content(&token, current_line)
+        let border_tag = token
+            .strip_prefix('\\')
+            .and_then(override_tag_name)
+            .is_some_and(|name| BORDER_SCALED_TAGS.contains(&name));
+        let play_res_line = document
+            .section_at(line_idx as u32)
+            .is_some_and(|section| section.name == "Script Info")
+            && current_line.split_once(':').is_some_and(|(key, _)| {
+                matches!(
+                    canonical_script_info_key(key.trim()).as_str(),
+                    "PlayResX" | "PlayResY"
+                )
+            });
+        line_info
+            .or_else(|| {
+                let content = self.get_hover_content(&token, current_line)?;
+                Some(if border_tag {
+                    format!("{content}\n\n{}", self.get_border_scaling_info(document))
+                } else if play_res_line {
+                    format!("{content}\n\n{}", self.get_play_res_info(document))
+                } else {
+                    content
+                })
+            })
             .map(|hover_content| Hover {
                 contents: HoverContents::Scalar(MarkedString::String(hover_content)),
                 range: Some(Range {
-                    start: Position::new(position.line, (char_idx - token.len()) as u32),
-                    end: Position::new(position.line, char_idx as u32),
+                    start: index.position(line_idx, token_start),
+                    end: index.position(line_idx, token_start + token.len()),
                 }),
             })
     }
 
-    fn get_token_at_position(&self, line: &str, char_idx: usize) -> Option<String> {
+    /// Returns the token under the cursor and the column it starts at.
+    fn get_token_at_position(&self, line: &str, char_idx: usize) -> Option<(usize, String)> {
         if char_idx > line.len() {
             return None;
         }
 
-        // Find word boundaries
-        let start = line[..char_idx]
-            .rfind(|c: char| c.is_whitespace() || c == ',' || c == ':' || c == '{' || c == '}')
-            .map(|i| i + 1)
-            .unwrap_or(0);
+        // Find word boundaries; a backslash starts a token, so each tag in
+        // `\bord2\blur1` is its own
+        let boundary = |c: char| c.is_whitespace() || c == ',' || c == ':' || c == '{' || c == '}';
+        let on_backslash = line[char_idx..].starts_with('\\');
+        let start = if on_backslash {
+            char_idx
+        } else {
+            line[..char_idx]
+                .rfind(|c: char| boundary(c) || c == '\\')
+                .map(|i| {
+                    if line[i..].starts_with('\\') {
+                        i
+                    } else {
+                        i + 1
+                    }
+                })
+                .unwrap_or(0)
+        };
 
-        let end = line[char_idx..]
-            .find(|c: char| c.is_whitespace() || c == ',' || c == ':' || c == '{' || c == '}')
-            .map(|i| char_idx + i)
+        let rest = char_idx + usize::from(on_backslash);
+        let end = line[rest..]
+            .find(|c: char| boundary(c) || c == '\\')
+            .map(|i| rest + i)
             .unwrap_or(line.len());
 
         if start < end {
-            Some(line[start..end].to_string())
+            Some((start, line[start..end].to_string()))
         } else {
             None
         }
     }
 
     fn get_hover_content(&self, token: &str, line: &str) -> Option<String> {
-        // Check for ASS override tags
-        if token.starts_with('\\') {
-            return self.get_override_tag_info(token);
+        // Check for ASS override tags, written with their argument
+        if let Some(tag) = token.strip_prefix('\\') {
+            let name =
+                known_tag_name(tag).map_or_else(|| token.to_string(), |name| format!("\\{name}"));
+            return Some(override_tag_info(&name));
         }
 
         // Check for time values
@@ -84,131 +187,347 @@ impl HoverProvider {
             return self.get_section_info(token);
         }
 
-        // Check for script info keys
-        if line.contains(':') && !line.starts_with("Dialogue:") && !line.starts_with("Comment:") {
-            return self.get_script_info_key_info(token);
+        // Check for script info keys; keys like "Video File" span several tokens
+        if let Some((key, _)) = line.split_once(':') {
+            if strip_prefix_ignore_case(line, "Dialogue:").is_none()
+                && strip_prefix_ignore_case(line, "Comment:").is_none()
+            {
+                let key = key.trim();
+                return self.get_script_info_key_info(if key.contains(token) {
+                    key
+                } else {
+                    token
+                });
+            }
         }
 
         // Check for event types
-        if token == "Dialogue" || token == "Comment" {
+        if token.eq_ignore_ascii_case("Dialogue") || token.eq_ignore_ascii_case("Comment") {
             return self.get_event_type_info(token);
         }
 
         None
     }
 
-    fn get_override_tag_info(&self, tag: &str) -> Option<String> {
-        let tag_name = if tag.contains('(') {
-            tag.split('(').next().unwrap_or(tag)
+    fn get_style_value_info(
+        &self,
+        document: &AssDocument,
+        lines: &[&str],
+        line_idx: usize,
+        char_idx: usize,
+        value: &str,
+    ) -> Option<String> {
+        let format = style_format_at(lines, line_idx);
+        let name = format.get(field_index_at(lines[line_idx], char_idx)?)?;
+        let field = style_field(name)?;
+
+        let mut content = format!("**{}**\n\n`{value}`\n\n{}", field.name, field.description);
+        if let Some((_, meaning)) = field.values.iter().find(|(known, _)| *known == value) {
+            content.push_str(&format!("\n\n{meaning}"));
+        }
+        if field.name.ends_with("Colour") {
+            if let Some(color_info) = self.get_color_info(value) {
+                content.push_str(&format!("\n\n{color_info}"));
+            }
+        }
+        if field.name == "Outline" || field.name == "Shadow" {
+            content.push_str(&format!("\n\n{}", self.get_border_scaling_info(document)));
+        }
+        Some(content)
+    }
+
+    /// Explains which pixels border sizes are measured in for this script.
+    fn get_border_scaling_info(&self, document: &AssDocument) -> String {
+        let scaling = border_scaling(&document.script_info, self.render_target);
+        let pixels = match (scaling.scaled, play_res(&document.script_info)) {
+            (true, Some((x, y))) => format!("script pixels ({x}x{y}), scaled to the video"),
+            (true, None) => "script pixels, scaled to the video".to_string(),
+            (false, _) => "video pixels, not scaled with the script resolution".to_string(),
+        };
+        let source = if scaling.explicit {
+            "as set by ScaledBorderAndShadow".to_string()
         } else {
-            tag
+            format!(
+                "{}'s default, since ScaledBorderAndShadow is not set",
+                self.render_target.name()
+            )
         };
+        format!("*Measured in {pixels}, {source}.*")
+    }
 
-        match tag_name {
-            "\\pos" => Some("**Position Override**\n\n`\\pos(x,y)`\n\nSets the subtitle position in pixels from the top-left corner of the video.".to_string()),
-            "\\move" => Some("**Movement Animation**\n\n`\\move(x1,y1,x2,y2[,t1,t2])`\n\nMoves the subtitle from position (x1,y1) to (x2,y2). Optional t1,t2 specify start/end times.".to_string()),
-            "\\org" => Some("**Origin Override**\n\n`\\org(x,y)`\n\nSets the origin point for rotations and scaling transformations.".to_string()),
-            "\\clip" => Some("**Clipping**\n\n`\\clip(x1,y1,x2,y2)` or `\\clip(drawing)`\n\nLimits the subtitle to only appear within the specified rectangular area or drawing shape.".to_string()),
-            "\\c" | "\\1c" => Some("**Primary Color**\n\n`\\c&Hbbggrr&` or `\\1c&Hbbggrr&`\n\nSets the primary text color in BGR (Blue-Green-Red) hexadecimal format.".to_string()),
-            "\\2c" => Some("**Secondary Color**\n\n`\\2c&Hbbggrr&`\n\nSets the secondary text color (used for karaoke highlighting).".to_string()),
-            "\\3c" => Some("**Outline Color**\n\n`\\3c&Hbbggrr&`\n\nSets the color of the text outline/border.".to_string()),
-            "\\4c" => Some("**Shadow Color**\n\n`\\4c&Hbbggrr&`\n\nSets the color of the text shadow.".to_string()),
-            "\\alpha" => Some("**Alpha Transparency**\n\n`\\alpha&Haa&`\n\nSets the overall transparency. 00 = opaque, FF = transparent.".to_string()),
-            "\\1a" => Some("**Primary Alpha**\n\n`\\1a&Haa&`\n\nSets the transparency of the primary text color.".to_string()),
-            "\\2a" => Some("**Secondary Alpha**\n\n`\\2a&Haa&`\n\nSets the transparency of the secondary text color.".to_string()),
-            "\\3a" => Some("**Outline Alpha**\n\n`\\3a&Haa&`\n\nSets the transparency of the text outline.".to_string()),
-            "\\4a" => Some("**Shadow Alpha**\n\n`\\4a&Haa&`\n\nSets the transparency of the text shadow.".to_string()),
-            "\\b" => Some("**Bold**\n\n`\\b1` or `\\b0` or `\\b<weight>`\n\nEnables (1) or disables (0) bold formatting, or sets specific font weight.".to_string()),
-            "\\i" => Some("**Italic**\n\n`\\i1` or `\\i0`\n\nEnables (1) or disables (0) italic formatting.".to_string()),
-            "\\u" => Some("**Underline**\n\n`\\u1` or `\\u0`\n\nEnables (1) or disables (0) underline formatting.".to_string()),
-            "\\s" => Some("**Strikeout**\n\n`\\s1` or `\\s0`\n\nEnables (1) or disables (0) strikethrough formatting.".to_string()),
-            "\\fn" => Some("**Font Name**\n\n`\\fn<fontname>`\n\nChanges the font family. Use font names installed on the system.".to_string()),
-            "\\fs" => Some("**Font Size**\n\n`\\fs<size>`\n\nChanges the font size in points.".to_string()),
-            "\\fscx" => Some("**Font Scale X**\n\n`\\fscx<percent>`\n\nScales the font horizontally. 100 = normal, 200 = double width.".to_string()),
-            "\\fscy" => Some("**Font Scale Y**\n\n`\\fscy<percent>`\n\nScales the font vertically. 100 = normal, 200 = double height.".to_string()),
-            "\\fsp" => Some("**Font Spacing**\n\n`\\fsp<pixels>`\n\nAdjusts character spacing. Positive values increase spacing.".to_string()),
-            "\\frx" => Some("**Rotation X**\n\n`\\frx<degrees>`\n\nRotates text around the X-axis (pitch).".to_string()),
-            "\\fry" => Some("**Rotation Y**\n\n`\\fry<degrees>`\n\nRotates text around the Y-axis (yaw).".to_string()),
-            "\\frz" | "\\fr" => Some("**Rotation Z**\n\n`\\frz<degrees>` or `\\fr<degrees>`\n\nRotates text around the Z-axis (roll). Positive values rotate counter-clockwise.".to_string()),
-            "\\bord" => Some("**Border**\n\n`\\bord<width>`\n\nSets the width of the text outline/border.".to_string()),
-            "\\shad" => Some("**Shadow**\n\n`\\shad<depth>`\n\nSets the depth of the text shadow.".to_string()),
-            "\\an" => Some("**Alignment (Numpad)**\n\n`\\an<1-9>`\n\nSets text alignment using numpad layout:\n1=bottom-left, 2=bottom-center, 3=bottom-right\n4=middle-left, 5=middle-center, 6=middle-right\n7=top-left, 8=top-center, 9=top-right".to_string()),
-            "\\a" => Some("**Alignment (Legacy)**\n\n`\\a<1-11>`\n\nLegacy alignment system. Use \\an instead for new scripts.".to_string()),
-            "\\k" => Some("**Karaoke**\n\n`\\k<duration>`\n\nKaraoke timing in centiseconds. Text will be highlighted for the specified duration.".to_string()),
-            "\\K" => Some("**Karaoke (Fill)**\n\n`\\K<duration>`\n\nSweeping karaoke effect that fills the text over the specified duration.".to_string()),
-            "\\kf" => Some("**Karaoke (Fill)**\n\n`\\kf<duration>`\n\nAlias for \\K. Sweeping karaoke effect.".to_string()),
-            "\\ko" => Some("**Karaoke (Outline)**\n\n`\\ko<duration>`\n\nKaraoke effect that sweeps the outline color.".to_string()),
-            "\\t" => Some("**Transform**\n\n`\\t([t1,t2,][accel,]tags)`\n\nAnimates the specified tags over time. Optional t1,t2 specify start/end times, accel controls acceleration.".to_string()),
-            "\\fad" => Some("**Simple Fade**\n\n`\\fad(fadein,fadeout)`\n\nSimple fade in and fade out effect. Times in milliseconds.".to_string()),
-            "\\fade" => Some("**Complex Fade**\n\n`\\fade(a1,a2,a3,t1,t2,t3,t4)`\n\nComplex fade with multiple alpha values and timing points.".to_string()),
-            "\\p" => Some("**Drawing Mode**\n\n`\\p<scale>`\n\nEnables drawing mode for vector graphics. Scale factor for coordinates.".to_string()),
-            "\\pbo" => Some("**Drawing Baseline Offset**\n\n`\\pbo<offset>`\n\nVertical offset for drawing coordinates.".to_string()),
-            "\\q" => Some("**Wrap Style**\n\n`\\q<0-3>`\n\nText wrapping style:\n0=smart wrap, 1=end-of-line wrap, 2=no wrap, 3=smart wrap with lower line wider".to_string()),
-            "\\r" => Some("**Reset**\n\n`\\r[style]`\n\nResets all override tags to the style defaults. Optional style name.".to_string()),
-            _ => Some(format!("**ASS Override Tag**\n\n`{tag}`\n\nAdvanced SubStation Alpha formatting tag.")),
+    /// The resolution the script is laid out in, and how a missing half of
+    /// the PlayRes pair is filled in.
+    fn get_play_res_info(&self, document: &AssDocument) -> String {
+        let entries = play_res_entries(document);
+        let (x, y) = resolved_play_res(&entries, self.render_target);
+        let mut info = format!(
+            "{} lays this script out at {x}x{y}.",
+            self.render_target.name()
+        );
+        if let Some((missing, note)) = missing_play_res_note(&entries) {
+            info.push_str(&format!(" {missing} is not set; {note}."));
         }
+        info
     }
 
-    fn get_time_info(&self, time: &str) -> Option<String> {
-        // Parse the time and provide duration info
-        let parts: Vec<&str> = time.split(':').collect();
-        if parts.len() == 3 {
-            if let (Ok(hours), Ok(minutes)) = (parts[0].parse::<u32>(), parts[1].parse::<u32>()) {
-                let sec_parts: Vec<&str> = parts[2].split('.').collect();
-                if sec_parts.len() == 2 {
-                    if let (Ok(seconds), Ok(centiseconds)) =
-                        (sec_parts[0].parse::<u32>(), sec_parts[1].parse::<u32>())
-                    {
-                        let total_ms =
-                            hours * 3600000 + minutes * 60000 + seconds * 1000 + centiseconds * 10;
-                        return Some(format!(
-                            "**Timestamp**\n\n`{time}`\n\nTotal duration: {total_ms}ms\n{hours}h {minutes}m {seconds}s {centiseconds}cs"
-                        ));
-                    }
-                }
+    fn get_attachment_header_info(&self, section: &str, key: &str) -> String {
+        let expected = attachment_header_key(section);
+        let usage = if key == expected {
+            format!("Starts a file embedded in [{section}], named by the rest of the line.")
+        } else {
+            format!("[{section}] attachments start with `{expected}:`; renderers skip a `{key}:` header here.")
+        };
+        format!("**{key}**\n\n{usage}\n\n{ATTACHMENT_EMBEDDING}")
+    }
+
+    /// Describes the margin field under the cursor and the margin it resolves to.
+    fn get_margin_info(
+        &self,
+        document: &AssDocument,
+        event: &Event,
+        char_idx: usize,
+    ) -> Option<String> {
+        let field = event
+            .margins
+            .iter()
+            .find(|margin| (margin.span.start..=margin.span.end).contains(&char_idx))?;
+        let margin = effective_margin(event, document.style(&event.style), field.side);
+        let inherited = if field.value.parse::<u32>() == Ok(0) {
+            "since 0 inherits it"
+        } else {
+            "since this value is not a valid margin"
+        };
+        let origin = match margin.source {
+            MarginSource::Event => "set by this event".to_string(),
+            MarginSource::Style => format!("from style {}, {inherited}", event.style),
+            MarginSource::Default => {
+                format!("the default, {inherited} and the style is not defined")
             }
+        };
+        Some(format!(
+            "**{}**\n\n`{}`\n\nEffective margin: {}px, {origin}.",
+            field.side.field_name(),
+            field.value,
+            margin.value
+        ))
+    }
+
+    /// Documents the syntax of the effect in the Effect field under the
+    /// cursor, with how it moves this event's text.
+    fn get_effect_info(
+        &self,
+        document: &AssDocument,
+        event: &Event,
+        char_idx: usize,
+    ) -> Option<String> {
+        if event.effect.is_empty()
+            || !(event.effect_span.start..=event.effect_span.end).contains(&char_idx)
+        {
+            return None;
         }
-        Some(format!("**Timestamp**\n\n`{time}`\n\nFormat: H:MM:SS.CC"))
+        let keyword = event.effect.split(';').next()?;
+        let Some(effect) = event_effect(keyword) else {
+            return Some(format!(
+                "**Effect**\n\n`{}`\n\nRenderers don't know this effect and ignore it.",
+                event.effect
+            ));
+        };
+        let mut info = format!(
+            "**Effect: {}**\n\n`{}`\n\n{}",
+            effect.keyword, effect.syntax, effect.description
+        );
+        if let Some(motion) = self.get_scroll_effect_summary(document, event) {
+            info.push_str(&format!("\n\n{motion}"));
+        }
+        Some(info)
     }
 
-    fn get_color_info(&self, color: &str) -> Option<String> {
-        if color.starts_with("&H") && color.len() >= 8 {
-            let hex = &color[2..];
-            if hex.len() >= 6 {
-                // BGR format
-                let b = u8::from_str_radix(&hex[0..2], 16).ok()?;
-                let g = u8::from_str_radix(&hex[2..4], 16).ok()?;
-                let r = u8::from_str_radix(&hex[4..6], 16).ok()?;
-                let alpha = if hex.len() >= 8 {
-                    let a = u8::from_str_radix(&hex[6..8], 16).ok()?;
-                    format!("\nAlpha: {} ({}%)", a, (255 - a) * 100 / 255)
+    /// The margins an event renders with, e.g. `L 20 (style), R 20 (style), V 35 (event)`.
+    fn get_margins_summary(&self, document: &AssDocument, event: &Event) -> String {
+        let style = document.style(&event.style);
+        let margins: Vec<String> = MarginSide::ALL
+            .into_iter()
+            .map(|side| {
+                let margin = effective_margin(event, style, side);
+                let label = side.field_name().trim_start_matches("Margin");
+                format!("{label} {} ({})", margin.value, margin.source.name())
+            })
+            .collect();
+        format!("*Margins: {}.*", margins.join(", "))
+    }
+
+    /// How a `Banner` or `Scroll` effect moves the text, and whether the event
+    /// lasts long enough for it to get across.
+    fn get_scroll_effect_summary(&self, document: &AssDocument, event: &Event) -> Option<String> {
+        let effect = ScrollEffect::parse(&event.effect)?;
+        let play_res = effective_play_res(&document.script_info);
+        let travel = effect.travel(play_res);
+        let travel_time = effect.travel_time(travel);
+        let speed = format!(
+            "{} px/s ({}ms per pixel)",
+            (effect.speed() * 10.0).round() / 10.0,
+            effect.delay()
+        );
+        let mut summary = match effect {
+            ScrollEffect::Banner { left_to_right, .. } => {
+                let direction = if left_to_right {
+                    "left to right"
                 } else {
-                    String::new()
+                    "right to left"
                 };
-
-                return Some(format!(
-                    "**Color Value**\n\n`{color}`\n\nRGB: ({r}, {g}, {b}){alpha}\nBGR Format (Blue-Green-Red)"
+                format!(
+                    "*Banner: moves {direction} at {speed}, crossing the {travel}px wide screen in {:.1}s.*",
+                    travel_time as f64 / 1000.0
+                )
+            }
+            ScrollEffect::Scroll { up, .. } => {
+                let (top, bottom) = effect.band(play_res.1)?;
+                format!(
+                    "*Scroll {}: moves at {speed} through y {top}-{bottom} ({travel}px) in {:.1}s.*",
+                    if up { "up" } else { "down" },
+                    travel_time as f64 / 1000.0
+                )
+            }
+        };
+        if let Some(duration) = event.duration().map(|duration| duration.as_millis()) {
+            if duration < travel_time {
+                summary.push_str(&format!(
+                    "\n\n**Warning:** the event ends after {:.1}s, before the text gets across.",
+                    duration as f64 / 1000.0
                 ));
             }
         }
+        Some(summary)
+    }
+
+    fn get_syllable_info(&self, event: &Event, char_idx: usize) -> Option<String> {
+        let syllable = karaoke_syllables(event)
+            .into_iter()
+            .find(|syllable| syllable.span.contains(&char_idx))?;
+        let starts_at = event.start?.centiseconds().saturating_add(syllable.offset);
+
         Some(format!(
-            "**Color Value**\n\n`{color}`\n\nASS color in BGR hexadecimal format"
+            "**Karaoke Syllable**\n\n`{}`\n\nStarts at {} (+{}cs)\nDuration: {}cs",
+            syllable.text,
+            AssTime(starts_at),
+            syllable.offset,
+            syllable.duration
         ))
     }
 
+    fn get_transform_tag_info(&self, event: &Event, char_idx: usize) -> Option<String> {
+        let offset = char_idx.checked_sub(event.text_start as usize)?;
+        let transform = tokenize(&event.text)
+            .into_iter()
+            .find_map(|token| match token {
+                TextToken::Tag { tag, span } if span.contains(&offset) => {
+                    parse_transform(tag, span)?.ok()
+                }
+                _ => None,
+            })?;
+        let (inner, _) = transform
+            .tags
+            .iter()
+            .find(|(_, span)| span.contains(&offset))?;
+        let name = override_tag_name(inner)?;
+
+        let timing = match (transform.t1, transform.t2) {
+            (Some(t1), Some(t2)) => format!("from {t1}ms to {t2}ms"),
+            _ => "over the whole event".to_string(),
+        };
+        let info = override_tag_info(&format!("\\{name}"));
+        Some(format!("{info}\n\n*Animated by `\\t` {timing}.*"))
+    }
+
+    /// The divisor a `\p` tag applies and the size of the drawing after it.
+    fn get_drawing_info(&self, event: &Event, char_idx: usize) -> Option<String> {
+        let offset = char_idx.checked_sub(event.text_start as usize)?;
+        let mode = drawing_modes(&event.text)
+            .into_iter()
+            .find(|mode| mode.tag_span.contains(&offset))?;
+        let info = override_tag_info("\\p");
+
+        let Some(divisor) = scale_divisor(mode.scale) else {
+            return Some(format!(
+                "{info}\n\n`\\p0` ends drawing mode; the text after it renders as text."
+            ));
+        };
+        if mode.scale > MAX_DRAWING_SCALE {
+            return Some(format!(
+                "{info}\n\n`\\p{}` is out of range: coordinates would be divided by 2^{}, so nothing is visible. Scales above `\\p{MAX_DRAWING_SCALE}` aren't meaningful.",
+                mode.scale,
+                mode.scale - 1
+            ));
+        }
+        let mut info = if mode.scale == 1 {
+            format!("{info}\n\n`\\p1` takes coordinates as written.")
+        } else {
+            format!(
+                "{info}\n\n`\\p{}` divides coordinates by 2^{} = {divisor}, so they are precise to 1/{divisor} pixel.",
+                mode.scale,
+                mode.scale - 1
+            )
+        };
+        if let Some(bounds) = drawing_coordinates(&mode.commands())
+            .and_then(|coordinates| bounding_box(&coordinates, mode.scale))
+        {
+            info.push_str(&format!(
+                "\n\nDrawing size: {}×{} pixels, from ({}, {}) to ({}, {}), before `\\fscx`/`\\fscy`.",
+                bounds.width(),
+                bounds.height(),
+                bounds.min_x,
+                bounds.min_y,
+                bounds.max_x,
+                bounds.max_y
+            ));
+        }
+        Some(info)
+    }
+
+    fn get_time_info(&self, time: &str) -> Option<String> {
+        // Parse the time and provide duration info
+        if let Ok(parsed) = time.parse::<AssTime>() {
+            let total_cs = parsed.centiseconds();
+            let total_ms = parsed.as_millis();
+            let hours = total_cs / 360000;
+            let minutes = total_cs / 6000 % 60;
+            let seconds = total_cs / 100 % 60;
+            let centiseconds = total_cs % 100;
+            return Some(format!(
+                "**Timestamp**\n\n`{time}`\n\nTotal duration: {total_ms}ms\n{hours}h {minutes}m {seconds}s {centiseconds}cs"
+            ));
+        }
+        Some(format!("**Timestamp**\n\n`{time}`\n\nFormat: H:MM:SS.CC"))
+    }
+
+    fn get_color_info(&self, color: &str) -> Option<String> {
+        match color.parse::<AssColor>() {
+            Ok(AssColor { r, g, b, a }) => Some(format!(
+                "**Color Value**\n\n`{color}`\n\nRGB: ({r}, {g}, {b})\nAlpha: {a} ({}% opaque)\nBGR Format (Blue-Green-Red)",
+                (255 - u32::from(a)) * 100 / 255
+            )),
+            Err(err) => Some(format!(
+                "**Color Value**\n\n`{color}`\n\nInvalid ASS color: {err}"
+            )),
+        }
+    }
+
     fn get_section_info(&self, section: &str) -> Option<String> {
-        match section {
+        let name = canonical_section_name(&section[1..section.len() - 1]);
+        match format!("[{name}]").as_str() {
             "[Script Info]" => Some("**Script Info Section**\n\nContains metadata about the subtitle script including title, resolution, and playback settings.".to_string()),
-            "[V4 Styles]" | "[V4+ Styles]" | "[v4 Styles]" | "[v4+ Styles]" => Some("**Styles Section**\n\nDefines the visual appearance of subtitle text including fonts, colors, positioning, and effects.".to_string()),
+            "[V4 Styles]" | "[V4+ Styles]" => Some("**Styles Section**\n\nDefines the visual appearance of subtitle text including fonts, colors, positioning, and effects.".to_string()),
             "[Events]" => Some("**Events Section**\n\nContains the actual subtitle dialogue, comments, and timing information.".to_string()),
             "[Fonts]" => Some("**Fonts Section**\n\nOptional section for embedding font files directly in the subtitle script.".to_string()),
             "[Graphics]" => Some("**Graphics Section**\n\nOptional section for embedding image files directly in the subtitle script.".to_string()),
+            "[Aegisub Project Garbage]" => Some("**Aegisub Project Garbage**\n\nEditor state written by Aegisub: loaded audio/video files, zoom and the active line. Ignored by renderers.".to_string()),
+            "[Aegisub Extradata]" => Some("**Aegisub Extradata**\n\nAdditional per-line data stored by Aegisub and automation scripts. Ignored by renderers.".to_string()),
             _ => Some(format!("**Section Header**\n\n`{section}`\n\nCustom section in the ASS script.")),
         }
     }
 
     fn get_script_info_key_info(&self, key: &str) -> Option<String> {
-        match key {
+        match canonical_script_info_key(key).as_str() {
             "Title" => Some("**Title**\n\nThe title of the subtitle script.".to_string()),
             "ScriptType" => Some("**Script Type**\n\nSpecifies the script format version (usually 'v4.00+').".to_string()),
             "WrapStyle" => Some("**Wrap Style**\n\nDefault text wrapping behavior:\n0=smart wrap, 1=end-of-line wrap, 2=no wrap, 3=smart wrap (lower line wider)".to_string()),
@@ -217,15 +536,215 @@ impl HoverProvider {
             "ScaledBorderAndShadow" => Some("**Scaled Border and Shadow**\n\nWhether borders and shadows scale with video resolution (yes/no).".to_string()),
             "Video File" => Some("**Video File**\n\nPath to the associated video file.".to_string()),
             "Audio File" => Some("**Audio File**\n\nPath to the associated audio file.".to_string()),
+            "Video AR Value" => Some("**Video Aspect Ratio**\n\nAegisub project setting: the aspect ratio the video is displayed at.".to_string()),
+            "Video AR Mode" => Some("**Video Aspect Ratio Mode**\n\nAegisub project setting: how the video aspect ratio was chosen (default, full, widescreen, cinematic or custom).".to_string()),
+            "Video Zoom Percent" => Some("**Video Zoom**\n\nAegisub project setting: zoom level of the video display.".to_string()),
+            "Video Position" => Some("**Video Position**\n\nAegisub project setting: the frame the video was last seeked to.".to_string()),
+            "Active Line" => Some("**Active Line**\n\nAegisub project setting: index of the event selected when the project was saved.".to_string()),
+            "Scroll Position" => Some("**Scroll Position**\n\nAegisub project setting: index of the first event visible in the subtitle grid.".to_string()),
+            "Last Style Storage" => Some("**Last Style Storage**\n\nAegisub project setting: name of the style catalog last used with this script.".to_string()),
+            "Automation Scripts" => Some("**Automation Scripts**\n\nAegisub project setting: automation scripts loaded with this file.".to_string()),
+            "Export Filters" => Some("**Export Filters**\n\nAegisub project setting: export filters enabled for this file.".to_string()),
             _ => Some(format!("**Script Info Property**\n\n`{key}`\n\nScript metadata property.")),
         }
     }
 
     fn get_event_type_info(&self, event_type: &str) -> Option<String> {
-        match event_type {
-            "Dialogue" => Some("**Dialogue Event**\n\nA subtitle line that will be displayed during playback.".to_string()),
-            "Comment" => Some("**Comment Event**\n\nA comment line that will not be displayed during playback. Used for notes and disabled subtitles.".to_string()),
+        match event_type.to_ascii_lowercase().as_str() {
+            "dialogue" => Some("**Dialogue Event**\n\nA subtitle line that will be displayed during playback.".to_string()),
+            "comment" => Some("**Comment Event**\n\nA comment line that will not be displayed during playback. Used for notes and disabled subtitles.".to_string()),
             _ => None,
         }
     }
 }
+
+/// Hover documentation of an override tag, written with its backslash;
+/// completion shows the same text.
+pub fn override_tag_info(tag: &str) -> String {
+    let tag_name = if tag.contains('(') {
+        tag.split('(').next().unwrap_or(tag)
+    } else {
+        tag
+    };
+
+    match tag_name {
+        "\\pos" => "**Position Override**\n\n`\\pos(x,y)`\n\nSets the subtitle position in pixels from the top-left corner of the video.".to_string(),
+        "\\move" => "**Movement Animation**\n\n`\\move(x1,y1,x2,y2[,t1,t2])`\n\nMoves the subtitle from position (x1,y1) to (x2,y2). Optional t1,t2 specify start/end times.".to_string(),
+        "\\org" => "**Origin Override**\n\n`\\org(x,y)`\n\nSets the origin point for rotations and scaling transformations.".to_string(),
+        "\\clip" => "**Clipping**\n\n`\\clip(x1,y1,x2,y2)` or `\\clip(drawing)`\n\nLimits the subtitle to only appear within the specified rectangular area or drawing shape.".to_string(),
+        "\\c" | "\\1c" => "**Primary Color**\n\n`\\c&Hbbggrr&` or `\\1c&Hbbggrr&`\n\nSets the primary text color in BGR (Blue-Green-Red) hexadecimal format.".to_string(),
+        "\\2c" => "**Secondary Color**\n\n`\\2c&Hbbggrr&`\n\nSets the secondary text color (used for karaoke highlighting).".to_string(),
+        "\\3c" => "**Outline Color**\n\n`\\3c&Hbbggrr&`\n\nSets the color of the text outline/border.".to_string(),
+        "\\4c" => "**Shadow Color**\n\n`\\4c&Hbbggrr&`\n\nSets the color of the text shadow.".to_string(),
+        "\\alpha" => "**Alpha Transparency**\n\n`\\alpha&Haa&`\n\nSets the overall transparency. 00 = opaque, FF = transparent.".to_string(),
+        "\\1a" => "**Primary Alpha**\n\n`\\1a&Haa&`\n\nSets the transparency of the primary text color.".to_string(),
+        "\\2a" => "**Secondary Alpha**\n\n`\\2a&Haa&`\n\nSets the transparency of the secondary text color.".to_string(),
+        "\\3a" => "**Outline Alpha**\n\n`\\3a&Haa&`\n\nSets the transparency of the text outline.".to_string(),
+        "\\4a" => "**Shadow Alpha**\n\n`\\4a&Haa&`\n\nSets the transparency of the text shadow.".to_string(),
+        "\\b" => "**Bold**\n\n`\\b1` or `\\b0` or `\\b<weight>`\n\nEnables (1) or disables (0) bold formatting, or sets specific font weight.".to_string(),
+        "\\i" => "**Italic**\n\n`\\i1` or `\\i0`\n\nEnables (1) or disables (0) italic formatting.".to_string(),
+        "\\u" => "**Underline**\n\n`\\u1` or `\\u0`\n\nEnables (1) or disables (0) underline formatting.".to_string(),
+        "\\s" => "**Strikeout**\n\n`\\s1` or `\\s0`\n\nEnables (1) or disables (0) strikethrough formatting.".to_string(),
+        "\\fn" => "**Font Name**\n\n`\\fn<fontname>`\n\nChanges the font family. Use font names installed on the system.".to_string(),
+        "\\fs" => "**Font Size**\n\n`\\fs<size>`\n\nChanges the font size in points.".to_string(),
+        "\\fscx" => "**Font Scale X**\n\n`\\fscx<percent>`\n\nScales the font horizontally. 100 = normal, 200 = double width.".to_string(),
+        "\\fscy" => "**Font Scale Y**\n\n`\\fscy<percent>`\n\nScales the font vertically. 100 = normal, 200 = double height.".to_string(),
+        "\\fsp" => "**Font Spacing**\n\n`\\fsp<pixels>`\n\nAdjusts character spacing. Positive values increase spacing.".to_string(),
+        "\\frx" => "**Rotation X**\n\n`\\frx<degrees>`\n\nRotates text around the X-axis (pitch).".to_string(),
+        "\\fry" => "**Rotation Y**\n\n`\\fry<degrees>`\n\nRotates text around the Y-axis (yaw).".to_string(),
+        "\\frz" | "\\fr" => "**Rotation Z**\n\n`\\frz<degrees>` or `\\fr<degrees>`\n\nRotates text around the Z-axis (roll). Positive values rotate counter-clockwise.".to_string(),
+        "\\bord" => "**Border**\n\n`\\bord<width>`\n\nSets the width of the text outline/border.".to_string(),
+        "\\shad" => "**Shadow**\n\n`\\shad<depth>`\n\nSets the depth of the text shadow.".to_string(),
+        "\\an" => "**Alignment (Numpad)**\n\n`\\an<1-9>`\n\nSets text alignment using numpad layout:\n1=bottom-left, 2=bottom-center, 3=bottom-right\n4=middle-left, 5=middle-center, 6=middle-right\n7=top-left, 8=top-center, 9=top-right".to_string(),
+        "\\a" => "**Alignment (Legacy)**\n\n`\\a<1-11>`\n\nLegacy alignment system. Use \\an instead for new scripts.".to_string(),
+        "\\k" => "**Karaoke**\n\n`\\k<duration>`\n\nKaraoke timing in centiseconds. Text will be highlighted for the specified duration.".to_string(),
+        "\\K" => "**Karaoke (Fill)**\n\n`\\K<duration>`\n\nSweeping karaoke effect that fills the text over the specified duration.".to_string(),
+        "\\kf" => "**Karaoke (Fill)**\n\n`\\kf<duration>`\n\nAlias for \\K. Sweeping karaoke effect.".to_string(),
+        "\\ko" => "**Karaoke (Outline)**\n\n`\\ko<duration>`\n\nKaraoke effect that sweeps the outline color.".to_string(),
+        "\\t" => "**Transform**\n\n`\\t([t1,t2,][accel,]tags)`\n\nAnimates the specified tags over time. Optional t1,t2 specify start/end times, accel controls acceleration.".to_string(),
+        "\\fad" => "**Simple Fade**\n\n`\\fad(fadein,fadeout)`\n\nSimple fade in and fade out effect. Times in milliseconds.".to_string(),
+        "\\fade" => "**Complex Fade**\n\n`\\fade(a1,a2,a3,t1,t2,t3,t4)`\n\nComplex fade with multiple alpha values and timing points.".to_string(),
+        "\\p" => "**Drawing Mode**\n\n`\\p<scale>`\n\nEnables drawing mode for vector graphics. The scale is an exponent: coordinates are divided by 2^(scale-1), and 0 turns drawing mode off.".to_string(),
+        "\\pbo" => "**Drawing Baseline Offset**\n\n`\\pbo<offset>`\n\nVertical offset for drawing coordinates.".to_string(),
+        "\\q" => "**Wrap Style**\n\n`\\q<0-3>`\n\nText wrapping style:\n0=smart wrap, 1=end-of-line wrap, 2=no wrap, 3=smart wrap with lower line wider".to_string(),
+        "\\r" => "**Reset**\n\n`\\r[style]`\n\nResets all override tags to the style defaults. Optional style name.".to_string(),
+        _ => format!("**ASS Override Tag**\n\n`{tag}`\n\nAdvanced SubStation Alpha formatting tag."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_index::PositionEncoding;
+    use crate::parser::AssParser;
+
+    fn hover_text(text: &str, line: usize, column: usize) -> Option<String> {
+        hover_with(&HoverProvider::new(), text, line, column)
+    }
+
+    fn hover_with(hover: &HoverProvider, text: &str, line: usize, column: usize) -> Option<String> {
+        let index = LineIndex::new(text.to_string(), PositionEncoding::Utf16);
+        let document = AssParser::new().parse(text);
+        let hover =
+            hover.provide_hover(&document, &index, Position::new(line as u32, column as u32))?;
+        match hover.contents {
+            HoverContents::Scalar(MarkedString::String(content)) => Some(content),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn border_style_value_explains_the_box() {
+        let text = include_str!("../tests/fixtures/boxed_styles.ass");
+        let (line, content) = text
+            .lines()
+            .enumerate()
+            .find(|(_, l)| l.starts_with("Style: Boxed"))
+            .unwrap();
+        let column = content.match_indices(',').nth(14).unwrap().0 + 1;
+        let hover = hover_text(text, line, column).unwrap();
+        assert!(hover.starts_with("**BorderStyle**\n\n`3`"));
+        assert!(hover.contains("Outline becomes the box padding"));
+    }
+
+    #[test]
+    fn outline_hover_follows_border_scaling_and_render_target() {
+        let base = include_str!("../tests/fixtures/boxed_styles.ass");
+        for target in [RenderTarget::Libass, RenderTarget::VsFilter] {
+            let mut hover = HoverProvider::new();
+            hover.render_target = target;
+            for value in [Some("yes"), Some("no"), None] {
+                let text = match value {
+                    Some(value) => base.replacen(
+                        "[Script Info]\n",
+                        &format!("[Script Info]\nScaledBorderAndShadow: {value}\n"),
+                        1,
+                    ),
+                    None => base.to_string(),
+                };
+                let (line, content) = text
+                    .lines()
+                    .enumerate()
+                    .find(|(_, l)| l.starts_with("Style: Boxed"))
+                    .unwrap();
+                let column = content.match_indices(',').nth(15).unwrap().0 + 1;
+                let hover = hover_with(&hover, &text, line, column).unwrap();
+                assert!(hover.starts_with("**Outline**"), "{hover}");
+                let expected = match (value, target) {
+                    (Some("yes"), _) => "script pixels (1920x1080), scaled to the video, as set by ScaledBorderAndShadow",
+                    (Some(_), _) => "video pixels, not scaled with the script resolution, as set by ScaledBorderAndShadow",
+                    (None, RenderTarget::Libass) => "script pixels (1920x1080), scaled to the video, libass's default",
+                    (None, RenderTarget::VsFilter) => "video pixels, not scaled with the script resolution, VSFilter's default",
+                };
+                assert!(hover.contains(expected), "{hover}");
+            }
+        }
+    }
+
+    #[test]
+    fn adjacent_tags_hover_one_at_a_time() {
+        let text = "[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\nDialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,{\\bord2\\blurr1\\fs40}Text\n";
+        let line = text.lines().nth(2).unwrap();
+        let title = |tag: &str, offset: usize| {
+            let column = line.find(tag).unwrap() + offset;
+            hover_text(text, 2, column).map(|hover| hover.lines().next().unwrap().to_string())
+        };
+        for offset in [0, 1, 5] {
+            assert_eq!(title("\\bord2", offset).as_deref(), Some("**Border**"));
+            assert_eq!(
+                title("\\fs40", offset.min(4)).as_deref(),
+                Some("**Font Size**")
+            );
+        }
+        // A typo isn't taken for the tag it starts with
+        assert_eq!(
+            title("\\blurr1", 2).as_deref(),
+            Some("**ASS Override Tag**")
+        );
+    }
+
+    #[test]
+    fn drawing_scale_hover_states_the_divisor() {
+        let script = |scale: u32| {
+            format!(
+                "[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+                 Dialogue: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,{{\\p{scale}}}m 0 0 l 64 0 64 32\n"
+            )
+        };
+        let column = |text: &str| text.lines().nth(2).unwrap().find("\\p").unwrap() + 1;
+
+        let text = script(1);
+        let info = hover_text(&text, 2, column(&text)).unwrap();
+        assert!(info.contains("`\\p1` takes coordinates as written."));
+        assert!(info.contains("Drawing size: 64×32 pixels"));
+
+        let text = script(3);
+        let info = hover_text(&text, 2, column(&text)).unwrap();
+        assert!(info.contains("divides coordinates by 2^2 = 4"));
+        assert!(info.contains("Drawing size: 16×8 pixels"));
+
+        let text = script(99);
+        let info = hover_text(&text, 2, column(&text)).unwrap();
+        assert!(info.contains("`\\p99` is out of range"));
+        assert!(!info.contains("Drawing size"));
+    }
+
+    #[test]
+    fn single_play_res_key_hover_states_the_derived_default() {
+        let text = "[Script Info]\nScriptType: v4.00+\nPlayResY: 288\n";
+        for (target, name) in [
+            (RenderTarget::Libass, "libass"),
+            (RenderTarget::VsFilter, "VSFilter"),
+        ] {
+            let mut hover = HoverProvider::new();
+            hover.render_target = target;
+            let info = hover_with(&hover, text, 2, 2).unwrap();
+            assert!(
+                info.contains(&format!(
+                    "{name} lays this script out at 384x288. PlayResX is not set; libass and VSFilter assume 4:3, giving PlayResX=384."
+                )),
+                "{info}"
+            );
+        }
+    }
+}
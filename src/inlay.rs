@@ -0,0 +1,163 @@
+use crate::line_index::LineIndex;
+use crate::parser::AssDocument;
+use crate::validation::reading_speed;
+use std::ops::RangeInclusive;
+use tower_lsp::lsp_types::*;
+
+/// Timing figures shown inline on Dialogue lines: the duration after the End
+/// field and the reading speed after the text.
+#[derive(Debug, Clone)]
+pub struct InlayHintProvider {
+    pub show_duration: bool,
+    pub show_cps: bool,
+}
+
+impl InlayHintProvider {
+    pub fn new() -> Self {
+        Self {
+            show_duration: true,
+            show_cps: true,
+        }
+    }
+
+    /// Hints for the Dialogue events on `lines`. Events with malformed or
+    /// reversed times get no duration, and those `reading_speed` can't rate
+    /// get no speed.
+    pub fn inlay_hints(
+        &self,
+        document: &AssDocument,
+        index: &LineIndex,
+        lines: RangeInclusive<u32>,
+    ) -> Vec<InlayHint> {
+        let mut hints = Vec::new();
+        for event in &document.events {
+            let line = event.range.start.line;
+            if event.event_type != "Dialogue" || !lines.contains(&line) {
+                continue;
+            }
+            if let Some(duration) = event.duration().filter(|_| self.show_duration) {
+                let centiseconds = duration.centiseconds();
+                hints.push(hint(
+                    index.position(line as usize, event.end_span.end),
+                    format!("{}.{:02}s", centiseconds / 100, centiseconds % 100),
+                    "Duration",
+                ));
+            }
+            if let Some((_, cps)) = reading_speed(event).filter(|_| self.show_cps) {
+                hints.push(hint(
+                    index.position(line as usize, event.range.end.character as usize),
+                    format!("{cps:.1} CPS"),
+                    "Characters per second",
+                ));
+            }
+        }
+        hints
+    }
+}
+
+impl Default for InlayHintProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn hint(position: Position, label: String, tooltip: &str) -> InlayHint {
+    InlayHint {
+        position,
+        label: InlayHintLabel::String(label),
+        kind: None,
+        text_edits: None,
+        tooltip: Some(InlayHintTooltip::String(tooltip.to_string())),
+        padding_left: Some(true),
+        padding_right: None,
+        data: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_index::PositionEncoding;
+    use crate::parser::AssParser;
+
+    const TEXT: &str = "[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+                        Dialogue: 0,0:00:01.00,0:00:03.45,Default,,0,0,0,,Twenty characters ok\n\
+                        Comment: 0,0:00:01.00,0:00:03.45,Default,,0,0,0,,Not shown\n\
+                        Dialogue: 0,0:00:05.00,0:00:04.00,Default,,0,0,0,,Reversed\n\
+                        Dialogue: 0,0:00:06.00,0:00:07.00,Default,,0,0,0,,日本語の字幕\n";
+
+    /// (line, character, label) of each hint, in UTF-16 columns.
+    fn hints(provider: &InlayHintProvider, lines: RangeInclusive<u32>) -> Vec<(u32, u32, String)> {
+        let document = AssParser::new().parse(TEXT);
+        let index = LineIndex::new(TEXT.to_string(), PositionEncoding::Utf16);
+        provider
+            .inlay_hints(&document, &index, lines)
+            .into_iter()
+            .map(|hint| {
+                assert_eq!(hint.padding_left, Some(true));
+                let InlayHintLabel::String(label) = hint.label else {
+                    panic!("label parts");
+                };
+                (hint.position.line, hint.position.character, label)
+            })
+            .collect()
+    }
+
+    fn end_field(line: usize) -> u32 {
+        let text = TEXT.lines().nth(line).unwrap();
+        text.match_indices(',').nth(2).unwrap().0 as u32
+    }
+
+    fn line_end(line: usize) -> u32 {
+        TEXT.lines().nth(line).unwrap().encode_utf16().count() as u32
+    }
+
+    #[test]
+    fn hints_sit_after_the_end_field_and_the_text() {
+        assert_eq!(
+            hints(&InlayHintProvider::new(), 0..=u32::MAX),
+            vec![
+                (2, end_field(2), "2.45s".to_string()),
+                (2, line_end(2), "8.2 CPS".to_string()),
+                (5, end_field(5), "1.00s".to_string()),
+                (5, line_end(5), "6.0 CPS".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_events_in_the_requested_lines_get_hints() {
+        let lines = |range| {
+            hints(&InlayHintProvider::new(), range)
+                .into_iter()
+                .map(|hint| hint.0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(lines(3..=5), vec![5, 5]);
+        assert_eq!(lines(0..=2), vec![2, 2]);
+        assert!(lines(3..=4).is_empty());
+    }
+
+    #[test]
+    fn toggles_drop_their_hints() {
+        let provider = InlayHintProvider {
+            show_duration: false,
+            show_cps: true,
+        };
+        assert!(hints(&provider, 0..=u32::MAX)
+            .iter()
+            .all(|hint| hint.2.ends_with(" CPS")));
+        let provider = InlayHintProvider {
+            show_duration: true,
+            show_cps: false,
+        };
+        assert!(hints(&provider, 0..=u32::MAX)
+            .iter()
+            .all(|hint| hint.2.ends_with('s') && !hint.2.ends_with(" CPS")));
+        let provider = InlayHintProvider {
+            show_duration: false,
+            show_cps: false,
+        };
+        assert!(hints(&provider, 0..=u32::MAX).is_empty());
+    }
+}
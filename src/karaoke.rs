@@ -0,0 +1,131 @@
+use crate::parser::Event;
+use crate::text::{tokenize, TextToken};
+use std::ops::Range;
+
+/// One karaoke syllable: the text following a `\k`, `\K`, `\kf` or `\ko` tag
+/// up to the next karaoke tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Syllable {
+    /// Visible text of the syllable, override blocks removed.
+    pub text: String,
+    /// Highlight duration in centiseconds.
+    pub duration: u32,
+    /// Start of the syllable relative to the event start, in centiseconds.
+    pub offset: u32,
+    /// Byte columns of the syllable's text on the event line.
+    pub span: Range<usize>,
+    /// Byte columns of the karaoke tag starting the syllable, backslash
+    /// included.
+    pub tag_span: Range<usize>,
+}
+
+/// Splits an event's text into karaoke syllables. Text before the first
+/// karaoke tag is not a syllable. Returns an empty list for non-karaoke lines.
+/// Each syllable starts where the one before it ends, unless a `\kt` in
+/// between moves the clock to its value.
+pub fn karaoke_syllables(event: &Event) -> Vec<Syllable> {
+    let base = event.text_start as usize;
+    let mut syllables: Vec<Syllable> = Vec::new();
+    let mut offset = 0u32;
+
+    for token in tokenize(&event.text) {
+        match token {
+            TextToken::Tag { tag, span } => {
+                if let Some(start) = tag.strip_prefix("kt").and_then(centiseconds) {
+                    offset = start;
+                    continue;
+                }
+                let Some(duration) = karaoke_duration(tag) else {
+                    continue;
+                };
+                syllables.push(Syllable {
+                    text: String::new(),
+                    duration,
+                    offset,
+                    span: base + span.end..base + span.end,
+                    tag_span: base + span.start..base + span.end,
+                });
+                offset = offset.saturating_add(duration);
+            }
+            TextToken::Text { text, span } => {
+                if let Some(syllable) = syllables.last_mut() {
+                    if syllable.text.is_empty() {
+                        syllable.span.start = base + span.start;
+                    }
+                    syllable.text.push_str(text);
+                    syllable.span.end = base + span.end;
+                }
+            }
+        }
+    }
+
+    syllables
+}
+
+/// When the karaoke of an event ends, in centiseconds from its start: the
+/// latest end of any syllable, as `\kt` may move the clock back.
+pub fn karaoke_end(syllables: &[Syllable]) -> Option<u32> {
+    syllables
+        .iter()
+        .map(|syllable| syllable.offset.saturating_add(syllable.duration))
+        .max()
+}
+
+/// Parses the duration of a karaoke tag, or returns `None` for other tags.
+fn karaoke_duration(tag: &str) -> Option<u32> {
+    let value = ["kf", "ko", "k", "K"]
+        .iter()
+        .find_map(|name| tag.strip_prefix(name))?;
+    centiseconds(value)
+}
+
+/// A karaoke tag's value, saturating rather than failing on overflow.
+fn centiseconds(value: &str) -> Option<u32> {
+    let value = value.trim();
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(value.parse().unwrap_or(u32::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::AssParser;
+
+    #[test]
+    fn syllables_follow_karaoke_tags_and_kt_moves_the_clock() {
+        let text = "[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\nDialogue: 0,0:00:01.00,0:00:03.00,Default,,0,0,0,,Intro {\\k20}Ka{\\K30\\b1}ra{\\kf50}o{\\kt10}{\\ko15}ke\nDialogue: 0,0:00:03.00,0:00:04.00,Default,,0,0,0,,No {\\b1}karaoke\n";
+        let document = AssParser::new().parse(text);
+        let event = &document.events[0];
+        let line = text.lines().nth(2).unwrap();
+
+        let syllables = karaoke_syllables(event);
+        let found: Vec<(&str, u32, u32, &str, &str)> = syllables
+            .iter()
+            .map(|syllable| {
+                (
+                    syllable.text.as_str(),
+                    syllable.duration,
+                    syllable.offset,
+                    &line[syllable.span.clone()],
+                    &line[syllable.tag_span.clone()],
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("Ka", 20, 0, "Ka", "\\k20"),
+                ("ra", 30, 20, "ra", "\\K30"),
+                ("o", 50, 50, "o", "\\kf50"),
+                ("ke", 15, 10, "ke", "\\ko15"),
+            ]
+        );
+        // The \kf ends last, though \kt moved the clock back before \ko
+        assert_eq!(karaoke_end(&syllables), Some(100));
+
+        assert!(karaoke_syllables(&document.events[1]).is_empty());
+        assert_eq!(karaoke_end(&[]), None);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::definition::style_references;
+use crate::line_index::LineIndex;
+use crate::parser::AssDocument;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::*;
+
+/// The editor command that opens a references list, taking the document, a
+/// position in it and the locations.
+pub const SHOW_REFERENCES: &str = "editor.action.showReferences";
+
+/// What a lens is about, kept in its `data` until it is resolved.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LensData {
+    pub uri: Url,
+    pub target: LensTarget,
+}
+
+impl LensData {
+    pub fn from_lens(lens: &CodeLens) -> Option<Self> {
+        serde_json::from_value(lens.data.clone()?).ok()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LensTarget {
+    /// The style defined on this line, by the name it had when the lens was
+    /// made.
+    Style { line: u32, name: String },
+    /// The `[Events]` header.
+    Events,
+}
+
+/// Unresolved lenses above each `Style:` line and the `[Events]` header.
+/// Counting waits for [`resolve_code_lens`], so only the lenses in view cost
+/// anything.
+pub fn code_lenses(uri: &Url, document: &AssDocument, index: &LineIndex) -> Vec<CodeLens> {
+    let lens = |range: Range, target| CodeLens {
+        range: index.range(range),
+        command: None,
+        data: serde_json::to_value(LensData {
+            uri: uri.clone(),
+            target,
+        })
+        .ok(),
+    };
+    let mut lenses: Vec<CodeLens> = document
+        .styles
+        .iter()
+        .map(|style| {
+            let target = LensTarget::Style {
+                line: style.range.start.line,
+                name: style.name.clone(),
+            };
+            lens(style.range, target)
+        })
+        .collect();
+    if let Some(events) = document
+        .sections
+        .iter()
+        .find(|section| section.name == "Events")
+    {
+        lenses.push(lens(events.header_range, LensTarget::Events));
+    }
+    lenses
+}
+
+/// Fills in a lens's title and command: "N uses" opening the references of
+/// a style, which count events naming it in their Style field or a `\r`
+/// tag, or the dialogue line count and runtime on `[Events]`. A style that
+/// has since moved is looked up by name.
+pub fn resolve_code_lens(
+    mut lens: CodeLens,
+    data: LensData,
+    document: &AssDocument,
+    index: &LineIndex,
+) -> CodeLens {
+    let command = match data.target {
+        LensTarget::Style { line, name } => {
+            let style = document
+                .styles
+                .iter()
+                .find(|style| style.range.start.line == line && style.name == name)
+                .or_else(|| document.style(&name));
+            let name_start = style
+                .and_then(|style| style.field_range("Name"))
+                .map(|range| index.range(range).start);
+            let locations = name_start
+                .and_then(|position| style_references(&data.uri, document, index, position, false))
+                .unwrap_or_default();
+            let title = match locations.len() {
+                1 => "1 use".to_string(),
+                uses => format!("{uses} uses"),
+            };
+            Command {
+                title,
+                command: SHOW_REFERENCES.to_string(),
+                arguments: Some(vec![
+                    serde_json::json!(data.uri),
+                    serde_json::json!(name_start.unwrap_or(lens.range.start)),
+                    serde_json::json!(locations),
+                ]),
+            }
+        }
+        LensTarget::Events => Command {
+            title: events_summary(document),
+            command: String::new(),
+            arguments: None,
+        },
+    };
+    lens.command = Some(command);
+    lens
+}
+
+/// "N dialogue lines over H:MM:SS.CC", the runtime running from the earliest
+/// start to the latest end.
+fn events_summary(document: &AssDocument) -> String {
+    let dialogue: Vec<_> = document
+        .events
+        .iter()
+        .filter(|event| event.event_type == "Dialogue")
+        .collect();
+    let count = match dialogue.len() {
+        1 => "1 dialogue line".to_string(),
+        lines => format!("{lines} dialogue lines"),
+    };
+    let start = dialogue.iter().filter_map(|event| event.start).min();
+    let end = dialogue.iter().filter_map(|event| event.end).max();
+    match (start, end) {
+        (Some(start), Some(end)) => {
+            format!("{count} over {}", end.saturating_sub(start))
+        }
+        _ => count,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_index::PositionEncoding;
+    use crate::parser::AssParser;
+
+    const TEXT: &str = "[V4+ Styles]\nFormat: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\nStyle: Default,Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1\nStyle: Sign,Arial,60,&H0000FFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,3,0,8,10,10,10,1\n\n[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\nDialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,Hello\nDialogue: 0,0:00:02.00,0:00:04.00,Default,,0,0,0,,Hi {\\rSign}there\nComment: 0,0:00:00.00,0:00:09.00,Sign,,0,0,0,,Note\nDialogue: 0,0:00:05.00,0:00:06.00,Default,,0,0,0,,Bye\n";
+
+    #[test]
+    fn lenses_count_style_uses_and_summarise_events() {
+        let uri = Url::parse("file:///tmp/lens.ass").unwrap();
+        let document = AssParser::new().parse(TEXT);
+        let index = LineIndex::new(TEXT.to_string(), PositionEncoding::Utf16);
+
+        let lenses = code_lenses(&uri, &document, &index);
+        let lines: Vec<(u32, u32)> = lenses
+            .iter()
+            .map(|lens| (lens.range.start.line, lens.range.end.line))
+            .collect();
+        assert_eq!(lines, [(2, 2), (3, 3), (5, 5)]);
+        assert!(lenses.iter().all(|lens| lens.command.is_none()));
+
+        let resolved: Vec<Command> = lenses
+            .into_iter()
+            .map(|lens| {
+                let data = LensData::from_lens(&lens).unwrap();
+                resolve_code_lens(lens, data, &document, &index)
+                    .command
+                    .unwrap()
+            })
+            .collect();
+        let titles: Vec<&str> = resolved
+            .iter()
+            .map(|command| command.title.as_str())
+            .collect();
+        // The comment counts as a use, but not towards the runtime
+        assert_eq!(
+            titles,
+            ["3 uses", "2 uses", "3 dialogue lines over 0:00:05.00"]
+        );
+
+        let sign = &resolved[1];
+        assert_eq!(sign.command, SHOW_REFERENCES);
+        let arguments = sign.arguments.as_ref().unwrap();
+        assert_eq!(arguments[0], serde_json::json!(uri));
+        assert_eq!(arguments[1], serde_json::json!(Position::new(3, 7)));
+        let locations: Vec<Location> = serde_json::from_value(arguments[2].clone()).unwrap();
+        let lines: Vec<u32> = locations
+            .iter()
+            .map(|location| location.range.start.line)
+            .collect();
+        assert_eq!(lines, [8, 9]);
+        assert!(resolved[2].command.is_empty() && resolved[2].arguments.is_none());
+    }
+}
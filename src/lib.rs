@@ -0,0 +1,97 @@
+//! Parsing, validation and formatting of Advanced SubStation Alpha (ASS/SSA)
+//! subtitle scripts, and the language server built on them.
+//!
+//! The [`prelude`] has what a program needs to work with scripts directly.
+//! Parse a script and look through it:
+//!
+//! ```
+//! use ass_lsp::prelude::*;
+//!
+//! let script = "[Script Info]\nScriptType: v4.00+\n\n\
+//!     [V4+ Styles]\nFormat: Name, Fontname, Fontsize, PrimaryColour\nStyle: Default,Arial,48,&H00FFFFFF\n\n\
+//!     [Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+//!     Dialogue: 0,0:00:01.00,0:00:03.50,Default,,0,0,0,,{\\b1}Hello\\Nworld\n";
+//! let document = AssParser::new().parse(script);
+//!
+//! let event = &document.events[0];
+//! assert_eq!(event.duration(), Some(AssTime(250)));
+//! assert_eq!(document.style(&event.style).unwrap().fontsize, 48);
+//! ```
+//!
+//! Check it, with an opt-in check turned on:
+//!
+//! ```
+//! use ass_lsp::prelude::*;
+//!
+//! let script = "[Script Info]\nScriptType: v4.00+\n\n\
+//!     [V4+ Styles]\nFormat: Name, Fontname, Fontsize, PrimaryColour\nStyle: Default,Arial,48,&H00FFFFFF\n\n\
+//!     [Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+//!     Dialogue: 0,0:00:01.00,0:00:03.50,Dfault,,0,0,0,,Hello\n";
+//! let document = AssParser::new().parse(script);
+//! let validation = ValidationProvider::with_options(ValidationOptions {
+//!     check_equivalent_styles: true,
+//!     ..ValidationOptions::default()
+//! });
+//!
+//! let uri = Url::parse("file:///episode.ass").unwrap();
+//! let codes: Vec<_> = validation
+//!     .validate(&document, &uri)
+//!     .iter()
+//!     .filter_map(DiagnosticCode::of)
+//!     .collect();
+//! assert!(codes.contains(&"undefined_style".parse().unwrap()));
+//! ```
+//!
+//! Or convert its dialogue to SubRip:
+//!
+//! ```
+//! use ass_lsp::prelude::*;
+//!
+//! let script = "[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+//!     Dialogue: 0,0:00:01.00,0:00:03.50,Default,,0,0,0,,{\\b1}Hello\\Nworld\n";
+//! let document = AssParser::new().parse(script);
+//!
+//! assert_eq!(to_srt(&document), "1\n00:00:01,000 --> 00:00:03,500\nHello\nworld\n");
+//! ```
+
+mod advanced;
+mod bidi;
+mod cli;
+mod colors;
+mod completion;
+mod definition;
+mod drawing;
+mod encoding;
+mod export;
+mod fix_all;
+mod folding;
+mod fonts;
+mod history;
+mod hover;
+mod inlay;
+mod karaoke;
+mod lens;
+mod line_index;
+mod links;
+mod metadata;
+mod on_type;
+mod parser;
+pub mod prelude;
+mod progress;
+mod reflow;
+mod rename;
+mod render;
+mod scheduler;
+mod semantic;
+mod server;
+mod settings;
+mod suppression;
+mod text;
+mod timeline;
+mod validation;
+mod video;
+mod workspace;
+mod workspace_check;
+
+pub use cli::run as run_cli;
+pub use server::serve;
@@ -0,0 +1,597 @@
+use tower_lsp::lsp_types::{
+    Position, PositionEncodingKind, Range, TextDocumentContentChangeEvent, TextEdit,
+};
+
+/// What the `character` of a client position counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PositionEncoding {
+    Utf8,
+    /// The LSP default, and the only encoding every client supports.
+    #[default]
+    Utf16,
+}
+
+impl PositionEncoding {
+    /// Picks UTF-8 when the client offers it, as it needs no conversion, and
+    /// UTF-16 otherwise.
+    pub fn negotiate(offered: Option<&[PositionEncodingKind]>) -> Self {
+        match offered {
+            Some(offered) if offered.contains(&PositionEncodingKind::UTF8) => Self::Utf8,
+            _ => Self::Utf16,
+        }
+    }
+
+    pub fn kind(self) -> PositionEncodingKind {
+        match self {
+            Self::Utf8 => PositionEncodingKind::UTF8,
+            Self::Utf16 => PositionEncodingKind::UTF16,
+        }
+    }
+}
+
+/// A document's text with its line boundaries, built once per version, and
+/// the position policy every provider shares. A trailing newline opens a final
+/// empty line, so a cursor placed after it still has a line to work with.
+///
+/// Providers work in byte columns; client positions count units of the
+/// negotiated encoding and are converted here on the way in and out.
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    text: String,
+    /// Byte offset at which each line starts.
+    line_starts: Vec<usize>,
+    encoding: PositionEncoding,
+}
+
+impl LineIndex {
+    pub fn new(text: String, encoding: PositionEncoding) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            text,
+            line_starts,
+            encoding,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// The text of line `idx` without its line ending.
+    pub fn line_text(&self, idx: usize) -> &str {
+        let start = self.line_starts[idx];
+        let end = self
+            .line_starts
+            .get(idx + 1)
+            .map_or(self.text.len(), |next| next - 1);
+        let line = &self.text[start..end];
+        line.strip_suffix('\r').unwrap_or(line)
+    }
+
+    /// Every line, including the empty line after a trailing newline.
+    pub fn lines(&self) -> Vec<&str> {
+        (0..self.line_count())
+            .map(|idx| self.line_text(idx))
+            .collect()
+    }
+
+    /// Byte offset into the text of a client position. Lines past the end
+    /// clamp to the end of the last line; columns past the line end clamp to
+    /// the line end, and a column inside a character rounds down to its start.
+    pub fn position_to_offset(&self, position: Position) -> usize {
+        let last = self.line_count() - 1;
+        let line_idx = position.line as usize;
+        if line_idx > last {
+            return self.line_starts[last] + self.line_text(last).len();
+        }
+        let line = self.line_text(line_idx);
+        self.line_starts[line_idx]
+            + match self.encoding {
+                PositionEncoding::Utf8 => floor_char_boundary(line, position.character as usize),
+                PositionEncoding::Utf16 => byte_column(line, position.character),
+            }
+    }
+
+    /// Resolves a client position to a `(line, byte column)` pair that is safe
+    /// to index with, clamped like [`Self::position_to_offset`].
+    pub fn clamp(&self, position: Position) -> (usize, usize) {
+        let offset = self.position_to_offset(position);
+        let line = self.line_of(offset);
+        (line, offset - self.line_starts[line])
+    }
+
+    /// Client position of a byte offset; offsets past the end clamp to it.
+    pub fn offset_to_position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.text.len());
+        let line = self.line_of(offset);
+        self.position(line, offset - self.line_starts[line])
+    }
+
+    fn line_of(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|&start| start <= offset) - 1
+    }
+
+    /// Client position of a byte column on a line. A line past the end, such
+    /// as the one after a final deleted line, is passed through.
+    pub fn position(&self, line: usize, column: usize) -> Position {
+        let character = match self.encoding {
+            _ if line >= self.line_count() => column as u32,
+            PositionEncoding::Utf8 => column.min(self.line_text(line).len()) as u32,
+            PositionEncoding::Utf16 => utf16_column(self.line_text(line), column),
+        };
+        Position::new(line as u32, character)
+    }
+
+    /// Client range of a range in byte columns.
+    pub fn range(&self, range: Range) -> Range {
+        let convert =
+            |position: Position| self.position(position.line as usize, position.character as usize);
+        Range::new(convert(range.start), convert(range.end))
+    }
+
+    /// Edits turning this text into `new_text`, one per run of changed lines.
+    /// Lines are compared with their line endings and aligned by looking a
+    /// short way ahead for the next equal pair, which finds the changes a
+    /// formatter makes, trimmed and inserted lines, without a full diff.
+    pub fn edits_to(&self, new_text: &str) -> Vec<TextEdit> {
+        let old: Vec<&str> = self.text.split_inclusive('\n').collect();
+        let new: Vec<&str> = new_text.split_inclusive('\n').collect();
+        let offsets = |lines: &[&str]| -> Vec<usize> {
+            let mut offsets = vec![0];
+            offsets.extend(lines.iter().scan(0, |offset, line| {
+                *offset += line.len();
+                Some(*offset)
+            }));
+            offsets
+        };
+        let (old_offsets, new_offsets) = (offsets(&old), offsets(&new));
+
+        // Runs of changed lines as (old start, old end, new start, new end)
+        let mut runs: Vec<(usize, usize, usize, usize)> = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < old.len() || j < new.len() {
+            if i < old.len() && j < new.len() && old[i] == new[j] {
+                i += 1;
+                j += 1;
+                continue;
+            }
+            let (skip_old, skip_new) = (1..=2 * DIFF_LOOKAHEAD)
+                .flat_map(|total| {
+                    (0..=total.min(DIFF_LOOKAHEAD))
+                        .map(move |a| (a, total - a))
+                        .filter(|&(_, b)| b <= DIFF_LOOKAHEAD)
+                })
+                .find(|&(a, b)| match (old.get(i + a), new.get(j + b)) {
+                    (Some(old_line), Some(new_line)) => old_line == new_line,
+                    // Whatever is left of one side replaces the rest of the other
+                    (None, None) => true,
+                    _ => false,
+                })
+                .unwrap_or((1, 1));
+            let (old_end, new_end) = ((i + skip_old).min(old.len()), (j + skip_new).min(new.len()));
+            match runs.last_mut() {
+                Some(run) if run.1 == i && run.3 == j => (run.1, run.3) = (old_end, new_end),
+                _ => runs.push((i, old_end, j, new_end)),
+            }
+            (i, j) = (old_end, new_end);
+        }
+
+        runs.into_iter()
+            .map(|(old_start, old_end, new_start, new_end)| TextEdit {
+                range: Range::new(
+                    self.offset_to_position(old_offsets[old_start]),
+                    self.offset_to_position(old_offsets[old_end]),
+                ),
+                new_text: new_text[new_offsets[new_start]..new_offsets[new_end]].to_string(),
+            })
+            .collect()
+    }
+}
+
+/// Lines looked ahead on each side for the next pair that matches again.
+const DIFF_LOOKAHEAD: usize = 32;
+
+/// Applies the changes of one `didChange` notification in order, each to the
+/// text the ones before it left, as the protocol requires. A change without
+/// a range replaces the whole text.
+pub fn apply_changes(
+    mut text: String,
+    changes: &[TextDocumentContentChangeEvent],
+    encoding: PositionEncoding,
+) -> String {
+    for change in changes {
+        let Some(range) = change.range else {
+            text = change.text.clone();
+            continue;
+        };
+        let index = LineIndex::new(text, encoding);
+        let start = index.position_to_offset(range.start);
+        let end = index.position_to_offset(range.end).max(start);
+        text = index.text;
+        text.replace_range(start..end, &change.text);
+    }
+    text
+}
+
+/// Applies the edits of one `TextEdit[]` to `text`, as a client would: all
+/// against the original text, inserts at the same place in order.
+#[cfg(test)]
+pub fn apply_edits(text: &str, encoding: PositionEncoding, edits: &[TextEdit]) -> String {
+    let index = LineIndex::new(text.to_string(), encoding);
+    let mut edits: Vec<(usize, usize, &str)> = edits
+        .iter()
+        .map(|edit| {
+            let start = index.position_to_offset(edit.range.start);
+            let end = index.position_to_offset(edit.range.end);
+            (start, end, edit.new_text.as_str())
+        })
+        .collect();
+    edits.sort_by_key(|&(start, end, _)| (start, end));
+    let mut result = String::new();
+    let mut copied = 0;
+    for (start, end, new_text) in edits {
+        assert!(start >= copied, "overlapping edits");
+        result.push_str(&text[copied..start]);
+        result.push_str(new_text);
+        copied = end;
+    }
+    result.push_str(&text[copied..]);
+    result
+}
+
+fn floor_char_boundary(line: &str, column: usize) -> usize {
+    let mut column = column.min(line.len());
+    while !line.is_char_boundary(column) {
+        column -= 1;
+    }
+    column
+}
+
+/// UTF-16 length of the part of `line` before byte `column`. Columns past the
+/// line end clamp to it.
+fn utf16_column(line: &str, column: usize) -> u32 {
+    line.char_indices()
+        .take_while(|(i, _)| *i < column)
+        .map(|(_, ch)| ch.len_utf16() as u32)
+        .sum()
+}
+
+/// Byte column in `line` of a UTF-16 column, rounded down to a char boundary
+/// and clamped to the line end.
+fn byte_column(line: &str, utf16: u32) -> usize {
+    let mut units = 0;
+    for (i, ch) in line.char_indices() {
+        units += ch.len_utf16() as u32;
+        if units > utf16 {
+            return i;
+        }
+    }
+    line.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::completion::CompletionProvider;
+    use crate::definition::{style_definition, style_name_range, style_references};
+    use crate::hover::HoverProvider;
+    use crate::inlay::InlayHintProvider;
+    use crate::on_type::on_type_edits;
+    use crate::parser::AssParser;
+    use crate::rename::{actor_range, actor_rename_edit, style_rename_edit};
+    use crate::semantic::semantic_tokens_in;
+    use tower_lsp::lsp_types::Url;
+
+    const SAMPLE: &str = include_str!("../tests/fixtures/sample.ass");
+
+    /// A small xorshift generator, so failures reproduce without a crate.
+    struct Positions(u64);
+
+    impl Positions {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 as u32
+        }
+
+        /// Mostly positions in or just past the text, sometimes anywhere.
+        fn position(&mut self, lines: u32) -> Position {
+            let line = match self.next() % 8 {
+                0 => self.next(),
+                1 => lines + self.next() % 3,
+                _ => self.next() % lines,
+            };
+            let character = match self.next() % 8 {
+                0 => u32::MAX,
+                1 => self.next(),
+                _ => self.next() % 300,
+            };
+            Position::new(line, character)
+        }
+    }
+
+    #[test]
+    fn every_provider_survives_random_positions() {
+        let uri = Url::parse("file:///tmp/sample.ass").unwrap();
+        let parser = AssParser::new();
+        let completion = CompletionProvider::new();
+        let hover = HoverProvider::new();
+        let inlay = InlayHintProvider::new();
+        let mut positions = Positions(0x9E37_79B9_7F4A_7C15);
+
+        for text in [SAMPLE.to_string(), SAMPLE.replace('\n', "\r\n")] {
+            let document = parser.parse(&text);
+            for encoding in [PositionEncoding::Utf8, PositionEncoding::Utf16] {
+                let index = LineIndex::new(text.clone(), encoding);
+                let lines = index.line_count() as u32;
+                for _ in 0..1000 {
+                    let position = positions.position(lines);
+                    let (line, column) = index.clamp(position);
+                    assert!(line < index.line_count());
+                    assert!(index.line_text(line).is_char_boundary(column));
+
+                    completion.provide_completions(&document, &index, position);
+                    hover.provide_hover(&document, &index, position);
+                    style_definition(&uri, &document, &index, position);
+                    style_references(&uri, &document, &index, position, true);
+                    style_name_range(&document, &index, position);
+                    style_rename_edit(&uri, &index, &document, position, "Renamed");
+                    actor_range(&document, &index, position);
+                    actor_rename_edit(&uri, &index, &document, position, "Renamed");
+                    for ch in ["{", "}"] {
+                        on_type_edits(&text, encoding, position, ch);
+                    }
+
+                    let other = positions.position(lines);
+                    let range = position.line.min(other.line)..=position.line.max(other.line);
+                    inlay.inlay_hints(&document, &index, range.clone());
+                    semantic_tokens_in(&document, &index, range);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn positions_past_the_end_clamp_to_it() {
+        let index = LineIndex::new(SAMPLE.to_string(), PositionEncoding::Utf16);
+        let last = index.line_count() - 1;
+        assert_eq!(index.line_text(last), "");
+        assert_eq!(index.clamp(Position::new(u32::MAX, u32::MAX)), (last, 0));
+        assert_eq!(index.clamp(Position::new(last as u32 + 7, 3)), (last, 0));
+        let first = index.line_text(0).len();
+        assert_eq!(index.clamp(Position::new(0, u32::MAX)), (0, first));
+
+        // Context at a column past the line end is the line end's
+        let document = AssParser::new().parse(SAMPLE);
+        let hover = HoverProvider::new();
+        let dialogue = (0..index.line_count())
+            .find(|&line| index.line_text(line).starts_with("Dialogue: 1"))
+            .unwrap();
+        let end = Position::new(dialogue as u32, index.line_text(dialogue).len() as u32);
+        assert_eq!(
+            hover.provide_hover(&document, &index, Position::new(dialogue as u32, u32::MAX)),
+            hover.provide_hover(&document, &index, end)
+        );
+    }
+
+    #[test]
+    fn line_after_trailing_newline_completes_like_an_empty_line() {
+        let completion = CompletionProvider::new();
+        let document = AssParser::new().parse(SAMPLE);
+        let index = LineIndex::new(SAMPLE.to_string(), PositionEncoding::Utf16);
+        let last = index.line_count() as u32 - 1;
+        let labels = |position| {
+            completion
+                .provide_completions(&document, &index, position)
+                .map(|list| {
+                    list.items
+                        .into_iter()
+                        .map(|item| item.label)
+                        .collect::<Vec<_>>()
+                })
+        };
+        let at_end = labels(Position::new(last, 0));
+        assert!(at_end
+            .as_ref()
+            .is_some_and(|labels| labels.iter().any(|label| label == "Dialogue:")));
+        assert_eq!(labels(Position::new(last + 10, u32::MAX)), at_end);
+    }
+
+    #[test]
+    fn multi_byte_columns_convert_in_both_encodings() {
+        // Bytes 2, 3, 4 and 1; UTF-16 units 1, 1, 2 and 1
+        let text = "é日😀x\r\nnext\n";
+        let utf16 = LineIndex::new(text.to_string(), PositionEncoding::Utf16);
+        let utf8 = LineIndex::new(text.to_string(), PositionEncoding::Utf8);
+        let offset =
+            |index: &LineIndex, character| index.position_to_offset(Position::new(0, character));
+
+        let expected = [
+            (0, 0),
+            (1, 2),
+            (2, 5),
+            (3, 5),
+            (4, 9),
+            (5, 10),
+            (6, 10),
+            (99, 10),
+        ];
+        for (character, byte) in expected {
+            assert_eq!(offset(&utf16, character), byte, "UTF-16 column {character}");
+        }
+        let expected = [
+            (0, 0),
+            (1, 0),
+            (2, 2),
+            (4, 2),
+            (5, 5),
+            (8, 5),
+            (9, 9),
+            (10, 10),
+            (11, 10),
+        ];
+        for (character, byte) in expected {
+            assert_eq!(offset(&utf8, character), byte, "UTF-8 column {character}");
+        }
+
+        // Between CR and LF is not a position of its own
+        let offsets = text.char_indices().chain([(text.len(), ' ')]);
+        for (byte, _) in offsets.filter(|&(byte, _)| !text[..byte].ends_with('\r')) {
+            for index in [&utf16, &utf8] {
+                let position = index.offset_to_position(byte);
+                assert_eq!(index.position_to_offset(position), byte, "offset {byte}");
+            }
+        }
+        assert_eq!(utf16.offset_to_position(9), Position::new(0, 4));
+        assert_eq!(utf8.offset_to_position(9), Position::new(0, 9));
+        assert_eq!(
+            utf16.range(Range::new(Position::new(0, 2), Position::new(0, 9))),
+            Range::new(Position::new(0, 1), Position::new(0, 4))
+        );
+    }
+
+    #[test]
+    fn columns_past_the_line_end_stay_on_their_line() {
+        let text = "日本語\r\n字幕\nlast";
+        for encoding in [PositionEncoding::Utf16, PositionEncoding::Utf8] {
+            let index = LineIndex::new(text.to_string(), encoding);
+            assert_eq!(index.line_text(0), "日本語");
+            // Before the CR, not on it or the next line
+            assert_eq!(index.clamp(Position::new(0, 100)), (0, 9));
+            assert_eq!(index.clamp(Position::new(1, 100)), (1, 6));
+            assert_eq!(index.clamp(Position::new(2, 100)), (2, 4));
+            assert_eq!(index.clamp(Position::new(3, 0)), (2, 4));
+            assert_eq!(index.position(1, 100), index.position(1, 6));
+            assert_eq!(index.offset_to_position(usize::MAX), Position::new(2, 4));
+        }
+        let utf16 = LineIndex::new(text.to_string(), PositionEncoding::Utf16);
+        assert_eq!(utf16.clamp(Position::new(0, 3)), (0, 9));
+        assert_eq!(utf16.position(1, 100), Position::new(1, 2));
+    }
+
+    /// A random char boundary of `text`.
+    fn boundary(positions: &mut Positions, text: &str) -> usize {
+        let mut offset = positions.next() as usize % (text.len() + 1);
+        while !text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        offset
+    }
+
+    #[test]
+    fn change_batches_in_any_order_rebuild_the_editor_text() {
+        const INSERTS: [&str; 8] = ["", "a", "\n", "🎉", "Жж", "{\\b1}", "\r\n", ",,"];
+        let mut positions = Positions(0xD1B5_4A32_D192_ED03);
+        for encoding in [PositionEncoding::Utf16, PositionEncoding::Utf8] {
+            let mut server = SAMPLE.replace("Hello", "Hello 🎉 Привет");
+            for _ in 0..200 {
+                // The editor applies each change as it goes, and sends the
+                // batch with ranges against the text at each step
+                let mut editor = server.clone();
+                let mut batch = Vec::new();
+                for _ in 0..=positions.next() % 5 {
+                    let a = boundary(&mut positions, &editor);
+                    let b = boundary(&mut positions, &editor);
+                    let (start, end) = (a.min(b), a.max(b));
+                    let end = start + (end - start).min(40);
+                    let end = (end..=editor.len())
+                        .find(|&end| editor.is_char_boundary(end))
+                        .unwrap();
+                    // Splitting a CRLF makes a range no client can send
+                    if editor[..start].ends_with('\r') || editor[..end].ends_with('\r') {
+                        continue;
+                    }
+                    let index = LineIndex::new(editor.clone(), encoding);
+                    let range = Range::new(
+                        index.offset_to_position(start),
+                        index.offset_to_position(end),
+                    );
+                    let insert = INSERTS[positions.next() as usize % INSERTS.len()];
+                    editor.replace_range(start..end, insert);
+                    batch.push(TextDocumentContentChangeEvent {
+                        range: Some(range),
+                        range_length: None,
+                        text: insert.to_string(),
+                    });
+                }
+                server = apply_changes(server, &batch, encoding);
+                assert_eq!(server, editor);
+            }
+        }
+    }
+
+    #[test]
+    fn each_change_applies_to_the_text_the_ones_before_left() {
+        let change = |range: Option<Range>, text: &str| TextDocumentContentChangeEvent {
+            range,
+            range_length: None,
+            text: text.to_string(),
+        };
+        let batch = [
+            // Bottom first, then top, then a change against the new text
+            change(
+                Some(Range::new(Position::new(1, 0), Position::new(1, 3))),
+                "two",
+            ),
+            change(
+                Some(Range::new(Position::new(0, 0), Position::new(0, 0))),
+                "zero\n",
+            ),
+            change(
+                Some(Range::new(Position::new(2, 3), Position::new(2, 3))),
+                "!",
+            ),
+        ];
+        let text = apply_changes("one\n𝄞ab\n".to_string(), &batch, PositionEncoding::Utf16);
+        // 𝄞 is two UTF-16 units, so the first change takes it and the a
+        assert_eq!(text, "zero\none\ntwo!b\n");
+
+        let batch = [
+            change(
+                Some(Range::new(Position::new(0, 0), Position::new(0, 3))),
+                "gone",
+            ),
+            change(None, "fresh\n"),
+            change(
+                Some(Range::new(Position::new(0, 5), Position::new(0, 5))),
+                "er",
+            ),
+        ];
+        let text = apply_changes("one\n".to_string(), &batch, PositionEncoding::Utf16);
+        assert_eq!(text, "fresher\n");
+    }
+
+    #[test]
+    fn edits_cover_only_the_changed_regions() {
+        let old: String = (0..100).map(|i| format!("line {i}\n")).collect();
+        let new = old
+            .replace("line 10\n", "line ten\n")
+            .replace("line 60\nline 61\n", "line 60\ninserted\nline 61\n")
+            .replace("line 90\n", "");
+        let index = LineIndex::new(old.clone(), PositionEncoding::Utf16);
+        let edits = index.edits_to(&new);
+        let spans: Vec<_> = edits
+            .iter()
+            .map(|edit| {
+                (
+                    edit.range.start.line,
+                    edit.range.end.line,
+                    edit.new_text.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            spans,
+            vec![(10, 11, "line ten\n"), (61, 61, "inserted\n"), (90, 91, "")]
+        );
+        assert_eq!(apply_edits(&old, PositionEncoding::Utf16, &edits), new);
+        assert!(index.edits_to(&old).is_empty());
+    }
+}
@@ -0,0 +1,221 @@
+use crate::line_index::LineIndex;
+use crate::parser::AssDocument;
+use std::path::{Component, Path, PathBuf};
+use tower_lsp::lsp_types::{DocumentLink, Position, Range, Url};
+
+/// Sections that name the media a script was timed against.
+const MEDIA_SECTIONS: [&str; 2] = ["Script Info", "Aegisub Project Garbage"];
+
+/// Aegisub's placeholder for audio loaded from the video file.
+const VIDEO_PLACEHOLDER: &str = "?video";
+/// Aegisub's placeholder for generated blank video or silent audio.
+const DUMMY_PLACEHOLDER: &str = "?dummy";
+/// Aegisub's token for the script's directory.
+const SCRIPT_TOKEN: &str = "?script";
+
+/// A `Video File` or `Audio File` line.
+struct MediaEntry<'a> {
+    video: bool,
+    line: u32,
+    /// Byte span of the value.
+    span: std::ops::Range<usize>,
+    value: &'a str,
+}
+
+/// Links for the `Video File` and `Audio File` entries, covering just the
+/// path. Relative paths resolve against the document's directory, and audio
+/// taken from the video (`?video`) links to the video file. Whether the file
+/// exists is left to [`resolve_link`].
+pub fn document_links(uri: &Url, document: &AssDocument, index: &LineIndex) -> Vec<DocumentLink> {
+    let base = base_dir(uri);
+    let entries = media_entries(document);
+    let video = entries
+        .iter()
+        .find(|entry| entry.video)
+        .and_then(|entry| media_path(entry.value, base.as_deref()));
+
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let path = if entry.value.eq_ignore_ascii_case(VIDEO_PLACEHOLDER) {
+                video.clone()?
+            } else {
+                media_path(entry.value, base.as_deref())?
+            };
+            let range = Range::new(
+                Position::new(entry.line, entry.span.start as u32),
+                Position::new(entry.line, entry.span.end as u32),
+            );
+            Some(DocumentLink {
+                range: index.range(range),
+                target: Some(Url::from_file_path(&path).ok()?),
+                tooltip: None,
+                data: None,
+            })
+        })
+        .collect()
+}
+
+/// The video file the script was timed against, if it names one.
+pub fn video_path(uri: &Url, document: &AssDocument) -> Option<PathBuf> {
+    let base = base_dir(uri);
+    media_entries(document)
+        .iter()
+        .find(|entry| entry.video)
+        .and_then(|entry| media_path(entry.value, base.as_deref()))
+}
+
+fn base_dir(uri: &Url) -> Option<PathBuf> {
+    uri.to_file_path()
+        .ok()
+        .and_then(|path| path.parent().map(Path::to_path_buf))
+}
+
+/// Adds a tooltip to links whose file is missing.
+pub fn resolve_link(mut link: DocumentLink) -> DocumentLink {
+    let path = link
+        .target
+        .as_ref()
+        .and_then(|target| target.to_file_path().ok());
+    if let Some(path) = path.filter(|path| !path.exists()) {
+        link.tooltip = Some(format!("File not found: {}", path.display()));
+    }
+    link
+}
+
+fn media_entries(document: &AssDocument) -> Vec<MediaEntry<'_>> {
+    let mut entries = Vec::new();
+    for section in document.sections.iter().filter(|section| {
+        MEDIA_SECTIONS
+            .iter()
+            .any(|name| section.name.eq_ignore_ascii_case(name))
+    }) {
+        for (offset, line) in section.content.iter().enumerate().skip(1) {
+            let Some((key, rest)) = line.split_once(':') else {
+                continue;
+            };
+            let video = match key.trim() {
+                key if key.eq_ignore_ascii_case("Video File") => true,
+                key if key.eq_ignore_ascii_case("Audio File") => false,
+                _ => continue,
+            };
+            let value = rest.trim();
+            if value.is_empty() {
+                continue;
+            }
+            let start = key.len() + 1 + (rest.len() - rest.trim_start().len());
+            entries.push(MediaEntry {
+                video,
+                line: section.range.start.line + offset as u32,
+                span: start..start + value.len(),
+                value,
+            });
+        }
+    }
+    entries
+}
+
+/// The file a media value points at, or `None` for dummy media and for
+/// relative paths in a document that isn't on disk.
+fn media_path(value: &str, base: Option<&Path>) -> Option<PathBuf> {
+    if value
+        .get(..DUMMY_PLACEHOLDER.len())
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case(DUMMY_PLACEHOLDER))
+        || value.eq_ignore_ascii_case(VIDEO_PLACEHOLDER)
+    {
+        return None;
+    }
+    let value = value
+        .strip_prefix(SCRIPT_TOKEN)
+        .map_or(value, |rest| rest.trim_start_matches(['/', '\\']));
+    let path = Path::new(value);
+    if path.is_absolute() {
+        Some(path.to_path_buf())
+    } else {
+        Some(normalize(&base?.join(path)))
+    }
+}
+
+/// Folds `.` and `..` out of a joined path, so link targets read cleanly.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_index::PositionEncoding;
+    use crate::parser::AssParser;
+
+    fn links(text: &str) -> Vec<(u32, String, String)> {
+        let uri = Url::parse("file:///subs/show/ep01.ass").unwrap();
+        let document = AssParser::new().parse(text);
+        let index = LineIndex::new(text.to_string(), PositionEncoding::Utf16);
+        document_links(&uri, &document, &index)
+            .into_iter()
+            .map(|link| {
+                let line = text.lines().nth(link.range.start.line as usize).unwrap();
+                let span = link.range.start.character as usize..link.range.end.character as usize;
+                (
+                    link.range.start.line,
+                    line[span].to_string(),
+                    link.target.unwrap().path().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn media_paths_link_relative_to_the_script() {
+        let text = "[Script Info]\nTitle: Episode\n\n[Aegisub Project Garbage]\nVideo File:  ../raw/ep01.mkv\nAudio File: ?video\nKeyframes File: ep01.txt\n";
+        assert_eq!(
+            links(text),
+            [
+                (
+                    4,
+                    "../raw/ep01.mkv".to_string(),
+                    "/subs/raw/ep01.mkv".to_string()
+                ),
+                // Audio from the video links to the video
+                (5, "?video".to_string(), "/subs/raw/ep01.mkv".to_string()),
+            ]
+        );
+
+        let text = "[Aegisub Project Garbage]\nVideo File: ?dummy:23.976:40000:1920:1080:47:163:254:\nAudio File: ?script/audio/ep01.flac\n";
+        assert_eq!(
+            links(text),
+            [(
+                2,
+                "?script/audio/ep01.flac".to_string(),
+                "/subs/show/audio/ep01.flac".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn missing_files_get_a_tooltip() {
+        let link = |path: &str| DocumentLink {
+            range: Range::default(),
+            target: Some(Url::from_file_path(path).unwrap()),
+            tooltip: None,
+            data: None,
+        };
+        let missing = resolve_link(link("/no/such/dir/ep01.mkv"));
+        assert_eq!(
+            missing.tooltip.as_deref(),
+            Some("File not found: /no/such/dir/ep01.mkv")
+        );
+        let present = resolve_link(link(env!("CARGO_MANIFEST_DIR")));
+        assert_eq!(present.tooltip, None);
+    }
+}
@@ -0,0 +1,402 @@
+/// Documentation for one column of a `Style:` line, shared by completion,
+/// hover and validation.
+#[derive(Debug)]
+pub struct StyleField {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Known values and what they mean, offered as completions and shown on hover.
+    pub values: &'static [(&'static str, &'static str)],
+}
+
+/// BorderStyle value that turns Outline and Shadow into box padding and offset.
+pub const BORDER_STYLE_OPAQUE_BOX: &str = "3";
+
+/// libass's BorderStyle value for one box behind the whole event, filled
+/// with BackColour.
+pub const BORDER_STYLE_EVENT_BOX: &str = "4";
+
+/// What text is read against, and the colour field it is drawn in: the box
+/// for the boxed border styles, however much padding Outline gives it, and
+/// otherwise the outline, if the style has one.
+pub fn text_backdrop(
+    border_style: Option<&str>,
+    outline: f64,
+) -> Option<(&'static str, &'static str)> {
+    match border_style.map(str::trim) {
+        Some(BORDER_STYLE_OPAQUE_BOX) => Some(("box", "OutlineColour")),
+        Some(BORDER_STYLE_EVENT_BOX) => Some(("box", "BackColour")),
+        _ => (outline > 0.0).then_some(("outline", "OutlineColour")),
+    }
+}
+
+pub const STYLE_FIELDS: &[StyleField] = &[
+    StyleField {
+        name: "Name",
+        description: "Name of the style, referenced by events and `\\r`.",
+        values: &[],
+    },
+    StyleField {
+        name: "Fontname",
+        description: "Font family used to render the text.",
+        values: &[],
+    },
+    StyleField {
+        name: "Fontsize",
+        description: "Font size in script pixels.",
+        values: &[],
+    },
+    StyleField {
+        name: "PrimaryColour",
+        description: "Fill colour of the text, `&HAABBGGRR`.",
+        values: &[],
+    },
+    StyleField {
+        name: "SecondaryColour",
+        description: "Colour karaoke syllables have before they are highlighted.",
+        values: &[],
+    },
+    StyleField {
+        name: "OutlineColour",
+        description: "Colour of the outline, or of the box when BorderStyle is 3.",
+        values: &[],
+    },
+    StyleField {
+        name: "TertiaryColour",
+        description: "SSA name for the outline colour.",
+        values: &[],
+    },
+    StyleField {
+        name: "BackColour",
+        description: "Colour of the shadow, or of the box's shadow when BorderStyle is 3.",
+        values: &[],
+    },
+    StyleField {
+        name: "Bold",
+        description: "-1 for bold, 0 for regular, or an explicit font weight.",
+        values: &[("0", "Regular weight"), ("-1", "Bold")],
+    },
+    StyleField {
+        name: "Italic",
+        description: "-1 for italic, 0 for upright.",
+        values: &[("0", "Upright"), ("-1", "Italic")],
+    },
+    StyleField {
+        name: "Underline",
+        description: "-1 for underlined text, 0 otherwise.",
+        values: &[("0", "No underline"), ("-1", "Underline")],
+    },
+    StyleField {
+        name: "StrikeOut",
+        description: "-1 for struck-out text, 0 otherwise.",
+        values: &[("0", "No strikeout"), ("-1", "Strikeout")],
+    },
+    StyleField {
+        name: "ScaleX",
+        description: "Horizontal scale in percent.",
+        values: &[],
+    },
+    StyleField {
+        name: "ScaleY",
+        description: "Vertical scale in percent.",
+        values: &[],
+    },
+    StyleField {
+        name: "Spacing",
+        description: "Extra space between characters, in pixels.",
+        values: &[],
+    },
+    StyleField {
+        name: "Angle",
+        description: "Rotation around the Z axis, in degrees.",
+        values: &[],
+    },
+    StyleField {
+        name: "BorderStyle",
+        description: "How the border is drawn. Changes what Outline, Shadow and OutlineColour mean.",
+        values: &[
+            ("1", "Outline and drop shadow. Outline is the border width, Shadow the shadow depth."),
+            ("3", "Opaque box. Outline becomes the box padding, OutlineColour fills the box, and Shadow offsets a second box drawn in BackColour."),
+            ("4", "libass extension: one opaque box behind all lines of the event, filled with BackColour."),
+        ],
+    },
+    StyleField {
+        name: "Outline",
+        description: "Border width in pixels, or the box padding when BorderStyle is 3.",
+        values: &[],
+    },
+    StyleField {
+        name: "Shadow",
+        description: "Shadow depth in pixels, or the box shadow offset when BorderStyle is 3.",
+        values: &[],
+    },
+    StyleField {
+        name: "Alignment",
+        description: "Numpad-style position of the text on screen (1-9).",
+        values: &[
+            ("1", "Bottom left"),
+            ("2", "Bottom center"),
+            ("3", "Bottom right"),
+            ("4", "Middle left"),
+            ("5", "Middle center"),
+            ("6", "Middle right"),
+            ("7", "Top left"),
+            ("8", "Top center"),
+            ("9", "Top right"),
+        ],
+    },
+    StyleField {
+        name: "MarginL",
+        description: "Left margin in pixels.",
+        values: &[],
+    },
+    StyleField {
+        name: "MarginR",
+        description: "Right margin in pixels.",
+        values: &[],
+    },
+    StyleField {
+        name: "MarginV",
+        description: "Vertical margin in pixels: from the bottom for bottom-aligned text, from the top for top-aligned text.",
+        values: &[],
+    },
+    StyleField {
+        name: "AlphaLevel",
+        description: "SSA transparency level; unused by ASS renderers.",
+        values: &[],
+    },
+    StyleField {
+        name: "Encoding",
+        description: "Font character set, usually 1 (default).",
+        values: &[],
+    },
+];
+
+/// Looks up a style column by name, ignoring case.
+pub fn style_field(name: &str) -> Option<&'static StyleField> {
+    STYLE_FIELDS
+        .iter()
+        .find(|field| field.name.eq_ignore_ascii_case(name))
+}
+
+/// An effect an event's Effect field can name, shared by hover and validation.
+#[derive(Debug)]
+pub struct EventEffect {
+    /// What the field starts with; renderers match it ignoring case.
+    pub keyword: &'static str,
+    pub syntax: &'static str,
+    pub description: &'static str,
+    /// Integer parameters after the keyword, each preceded by `;`. Renderers
+    /// ignore the effect when fewer than `required` are given.
+    pub required: usize,
+    /// Parameters beyond this many are ignored.
+    pub max: usize,
+}
+
+pub const EVENT_EFFECTS: &[EventEffect] = &[
+    EventEffect {
+        keyword: "Banner",
+        syntax: "Banner;delay[;lefttoright[;fadeawaywidth]]",
+        description: "Moves the text across the screen, right to left unless `lefttoright` is 1, one pixel every `delay` ms. `fadeawaywidth` fades the text in and out over that many pixels at the edges.",
+        required: 1,
+        max: 3,
+    },
+    EventEffect {
+        keyword: "Scroll up",
+        syntax: "Scroll up;y1;y2;delay[;fadeawayheight]",
+        description: "Moves the text up through the band between `y1` and `y2`, one pixel every `delay` ms. `fadeawayheight` fades it in and out over that many pixels at the band's edges.",
+        required: 3,
+        max: 4,
+    },
+    EventEffect {
+        keyword: "Scroll down",
+        syntax: "Scroll down;y1;y2;delay[;fadeawayheight]",
+        description: "Moves the text down through the band between `y1` and `y2`, one pixel every `delay` ms. `fadeawayheight` fades it in and out over that many pixels at the band's edges.",
+        required: 3,
+        max: 4,
+    },
+];
+
+/// Looks up an effect by the keyword before its first `;`, ignoring case.
+pub fn event_effect(keyword: &str) -> Option<&'static EventEffect> {
+    EVENT_EFFECTS
+        .iter()
+        .find(|effect| effect.keyword.eq_ignore_ascii_case(keyword.trim()))
+}
+
+/// Effect values Aegisub's karaoke templater uses to mark its input and
+/// output lines. Renderers ignore them, which is what the templater wants.
+pub const TEMPLATER_EFFECTS: &[&str] = &["karaoke", "fx"];
+
+/// Every override tag name, without its backslash.
+pub const OVERRIDE_TAGS: &[&str] = &[
+    "pos", "move", "org", "clip", "iclip", "fscx", "fscy", "fsp", "fsc", "frx", "fry", "frz", "fr",
+    "fax", "fay", "fn", "fs", "fe", "b", "i", "u", "s", "bord", "xbord", "ybord", "shad", "xshad",
+    "yshad", "be", "blur", "c", "1c", "2c", "3c", "4c", "alpha", "1a", "2a", "3a", "4a", "an", "a",
+    "q", "r", "t", "fad", "fade", "p", "pbo", "k", "K", "kf", "ko", "kt",
+];
+
+/// Tags whose values `\t` can interpolate.
+pub const ANIMATABLE_TAGS: &[&str] = &[
+    "clip", "iclip", "fscx", "fscy", "fsp", "frx", "fry", "frz", "fr", "fax", "fay", "fs", "bord",
+    "xbord", "ybord", "shad", "xshad", "yshad", "be", "blur", "c", "1c", "2c", "3c", "4c", "alpha",
+    "1a", "2a", "3a", "4a",
+];
+
+/// Tags that place or align the whole line.
+pub const POSITIONING_TAGS: &[&str] = &["pos", "move", "org", "an", "a"];
+
+/// Tags sized in pixels that `ScaledBorderAndShadow` decides how to scale.
+pub const BORDER_SCALED_TAGS: &[&str] =
+    &["bord", "xbord", "ybord", "shad", "xshad", "yshad", "blur"];
+
+/// Tags that set a colour.
+pub const COLOR_TAGS: &[&str] = &["c", "1c", "2c", "3c", "4c"];
+
+/// Karaoke timing tags.
+pub const KARAOKE_TAGS: &[&str] = &["k", "K", "kf", "ko", "kt"];
+
+/// How an attached file is written out, shared by attachment hover and completion.
+pub const ATTACHMENT_EMBEDDING: &str = "The lines after the header hold the file UUencoded the SSA way: each 3 bytes become 4 characters from `!` to `` ` `` (6 bits + 33), in lines of 80 characters with a shorter last line. The attachment ends at the first line that is not encoded data.\n\nKeep filenames to letters, digits, `_`, `-` and `.`; short 8.3-style names are the safest, as older renderers mishandle anything else.";
+
+/// Resolves the name of an override tag written without its backslash, e.g.
+/// `fscx120` is `fscx`. The longest known name wins so `bord2` is not `b`.
+pub fn override_tag_name(tag: &str) -> Option<&'static str> {
+    OVERRIDE_TAGS
+        .iter()
+        .filter(|name| tag.starts_with(*name))
+        .max_by_key(|name| name.len())
+        .copied()
+}
+
+/// Tags that take a name, so their argument may start with a letter.
+pub const NAME_ARGUMENT_TAGS: &[&str] = &["fn", "r"];
+
+/// Tags that take a colour or alpha, which may be written without the `&`.
+pub const HEX_ARGUMENT_TAGS: &[&str] =
+    &["c", "1c", "2c", "3c", "4c", "alpha", "1a", "2a", "3a", "4a"];
+
+/// Resolves a tag like [`override_tag_name`], but only when what follows the
+/// name can be an argument. `posi(1,2)` and `blurr2` start with `pos` and
+/// `blur` yet are typos that renderers don't recognise.
+pub fn known_tag_name(tag: &str) -> Option<&'static str> {
+    let name = override_tag_name(tag)?;
+    let rest = &tag[name.len()..];
+    let known = NAME_ARGUMENT_TAGS.contains(&name)
+        || !rest.starts_with(|c: char| c.is_ascii_alphabetic())
+        || (HEX_ARGUMENT_TAGS.contains(&name) && rest.starts_with(['H', 'h']));
+    known.then_some(name)
+}
+
+/// The name a tag was written with: an optional digit followed by letters,
+/// e.g. `posi` in `posi(1,2)` or `1c` in `1c&HFF&`.
+pub fn written_tag_name(tag: &str) -> &str {
+    let digit = usize::from(tag.starts_with(|c: char| c.is_ascii_digit()));
+    let end = tag[digit..]
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .map_or(tag.len(), |i| digit + i);
+    &tag[..end]
+}
+
+/// The known tag closest to a misspelt name, if one is close enough to be
+/// what was meant. Case comes first: a name that only differs from a tag in
+/// case, like `AN` or `Pos`, resolves to that tag, with the lowercase tag
+/// winning where both cases exist. Otherwise names are compared by edit
+/// distance, ignoring case.
+pub fn closest_override_tag(name: &str) -> Option<&'static str> {
+    let lowercase = name.to_ascii_lowercase();
+    if let Some(tag) = OVERRIDE_TAGS.iter().find(|tag| **tag == lowercase) {
+        return Some(tag);
+    }
+    if let Some(tag) = OVERRIDE_TAGS
+        .iter()
+        .find(|tag| tag.eq_ignore_ascii_case(name))
+    {
+        return Some(tag);
+    }
+    OVERRIDE_TAGS
+        .iter()
+        .map(|tag| (edit_distance(&lowercase, &tag.to_ascii_lowercase()), *tag))
+        .filter(|&(distance, _)| distance <= 2 && distance * 3 <= name.len())
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, tag)| tag)
+}
+
+/// The candidate `name` is most likely a misspelling of, ignoring case: at
+/// most two edits away, and fewer than half its length.
+pub fn closest_name(name: &str, candidates: &[&'static str]) -> Option<&'static str> {
+    let lowercase = name.to_ascii_lowercase();
+    candidates
+        .iter()
+        .map(|candidate| {
+            (
+                edit_distance(&lowercase, &candidate.to_ascii_lowercase()),
+                *candidate,
+            )
+        })
+        .filter(|&(distance, _)| distance <= 2 && distance * 2 <= name.chars().count())
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance between two strings, counted in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1)
+                .min(row[j] + 1)
+                .min(diagonal + usize::from(ca != *cb));
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+/// Where a colour is written. Style fields and override tags spell colours
+/// differently; Script Info has no colour keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorContext {
+    StyleField,
+    OverrideTag,
+}
+
+/// How colours are spelled in one [`ColorContext`], shared by completion and
+/// validation.
+#[derive(Debug)]
+pub struct ColorGrammar {
+    /// What the values are called in messages.
+    pub name: &'static str,
+    /// The canonical spelling, named when a value doesn't parse.
+    pub canonical: &'static str,
+    /// A value in the canonical spelling, inserted by completion.
+    pub example: &'static str,
+    /// Older spellings renderers still read, shown with the conversion note.
+    pub legacy: &'static str,
+}
+
+const STYLE_FIELD_COLOR: ColorGrammar = ColorGrammar {
+    name: "style colours",
+    canonical: "`&HAABBGGRR`, 8 hex digits with alpha first",
+    example: "&H00FFFFFF",
+    legacy: "SSA decimal integers, negative when alpha is 128 or more, and `&H` with fewer digits or a trailing `&`",
+};
+
+const OVERRIDE_TAG_COLOR: ColorGrammar = ColorGrammar {
+    name: "override tag colours",
+    canonical: "`&HBBGGRR&`, 6 hex digits without alpha",
+    example: "&HFFFFFF&",
+    legacy: "fewer digits, a missing `&` or `H`, and 8 digits whose alpha byte is ignored; digits without `&H` are still read as hex",
+};
+
+impl ColorContext {
+    pub fn grammar(self) -> &'static ColorGrammar {
+        match self {
+            Self::StyleField => &STYLE_FIELD_COLOR,
+            Self::OverrideTag => &OVERRIDE_TAG_COLOR,
+        }
+    }
+}
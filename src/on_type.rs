@@ -0,0 +1,172 @@
+use crate::line_index::{LineIndex, PositionEncoding};
+use crate::parser::{event_format_at, field_range, parse_section_header, strip_prefix_ignore_case};
+use tower_lsp::lsp_types::*;
+
+/// Characters that trigger [`on_type_edits`].
+pub const OPEN_BRACE: &str = "{";
+pub const CLOSE_BRACE: &str = "}";
+
+/// Edits after `ch` was typed just before `position` in `text`, which
+/// already holds it. A `{` gets its `}` unless the next brace after it
+/// closes a block already; a `}` typed in front of the `}` that closes the
+/// same block replaces it, as if typed over. Only the Text field of
+/// `[Events]` lines is touched, as braces elsewhere are literal.
+pub fn on_type_edits(
+    text: &str,
+    encoding: PositionEncoding,
+    position: Position,
+    ch: &str,
+) -> Option<Vec<TextEdit>> {
+    let line_idx = position.line as usize;
+    let lines: Vec<&str> = text.lines().take(line_idx + 1).collect();
+    let line = *lines.get(line_idx)?;
+    let in_events = lines[..line_idx]
+        .iter()
+        .rev()
+        .find_map(|line| parse_section_header(line.trim()))
+        .is_some_and(|header| header.name == "Events");
+    let is_event = ["Dialogue:", "Comment:"]
+        .iter()
+        .any(|prefix| strip_prefix_ignore_case(line.trim_start(), prefix).is_some());
+    if !in_events || !is_event {
+        return None;
+    }
+
+    let line_index = LineIndex::new(line.to_string(), encoding);
+    let (_, cursor) = line_index.clamp(Position::new(0, position.character));
+    let typed = cursor
+        .checked_sub(ch.len())
+        .filter(|_| line[..cursor].ends_with(ch))?;
+    let format = event_format_at(&lines, line_idx);
+    let text_field = format
+        .iter()
+        .position(|field| field.eq_ignore_ascii_case("Text"))
+        .unwrap_or(format.len().saturating_sub(1));
+    let text_start = field_range(line, text_field)?.start;
+    if typed < text_start {
+        return None;
+    }
+
+    let at = |start: usize, end: usize| {
+        let start = line_index.position(0, start).character;
+        let end = line_index.position(0, end).character;
+        Range::new(
+            Position::new(position.line, start),
+            Position::new(position.line, end),
+        )
+    };
+    let edit = match ch {
+        OPEN_BRACE => {
+            let next_brace = line[cursor..].find(['{', '}']).map(|i| &line[cursor + i..]);
+            if next_brace.is_some_and(|rest| rest.starts_with('}')) {
+                return None;
+            }
+            TextEdit::new(at(cursor, cursor), CLOSE_BRACE.to_string())
+        }
+        CLOSE_BRACE => {
+            let before = &line[text_start..typed];
+            let open = before.rfind('{') > before.rfind('}');
+            if !open || !line[cursor..].starts_with('}') {
+                return None;
+            }
+            TextEdit::new(at(cursor, cursor + 1), String::new())
+        }
+        _ => return None,
+    };
+    Some(vec![edit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "[Script Info]\nTitle: {Braces}\n\n[Events]\n\
+                          Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n";
+
+    /// Edits after typing `ch` at the first `|` in `line`, the last line of
+    /// the script.
+    fn typed(line: &str, ch: &str) -> Option<Vec<TextEdit>> {
+        let (before, after) = line.split_once('|').unwrap();
+        let text = format!("{HEADER}{before}{after}\n");
+        let column = before.encode_utf16().count() + ch.len();
+        let position = Position::new(5, column as u32);
+        on_type_edits(&text, PositionEncoding::Utf16, position, ch)
+    }
+
+    fn insert(column: u32, text: &str) -> Option<Vec<TextEdit>> {
+        let position = Position::new(5, column);
+        Some(vec![TextEdit::new(
+            Range::new(position, position),
+            text.to_string(),
+        )])
+    }
+
+    #[test]
+    fn closing_brace_types_over_the_one_already_there() {
+        let line = "Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,{\\i1|}}Hi";
+        let column = line.find('|').unwrap() as u32 + 1;
+        assert_eq!(
+            typed(line, "}"),
+            Some(vec![TextEdit::new(
+                Range::new(Position::new(5, column), Position::new(5, column + 1)),
+                String::new(),
+            )])
+        );
+    }
+
+    #[test]
+    fn closing_brace_outside_a_block_is_left_alone() {
+        assert_eq!(
+            typed(
+                "Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,{\\i1}a|}}",
+                "}"
+            ),
+            None
+        );
+        assert_eq!(
+            typed(
+                "Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,{\\i1|} Hi",
+                "}"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn opening_brace_is_closed_unless_a_close_follows() {
+        let line = "Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,|{Hi";
+        let column = line.find('|').unwrap() as u32 + 1;
+        assert_eq!(typed(line, "{"), insert(column, "}"));
+        assert_eq!(
+            typed(
+                "Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,|{\\i1}Hi",
+                "{"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn braces_outside_the_text_field_are_literal() {
+        assert_eq!(
+            typed(
+                "Dialogue: 0,0:00:01.00,0:00:02.00,Def|{ault,,0,0,0,,Hi",
+                "{"
+            ),
+            None
+        );
+        assert_eq!(
+            on_type_edits(HEADER, PositionEncoding::Utf16, Position::new(1, 8), "{"),
+            None
+        );
+    }
+
+    #[test]
+    fn columns_follow_the_encoding() {
+        let line = "Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,日本|{";
+        let column = "Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,日本{"
+            .encode_utf16()
+            .count() as u32;
+        assert_eq!(typed(line, "{"), insert(column, "}"));
+    }
+}
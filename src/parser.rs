@@ -1,69 +1,685 @@
+use crate::metadata::ColorContext;
+use crate::render::ScrollEffect;
+use crate::text::excerpt;
+use once_cell::sync::Lazy;
 use regex::Regex;
 use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
 use tower_lsp::lsp_types::*;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct AssDocument {
-    pub sections: Vec<Section>,
+    pub(crate) sections: Vec<Section>,
     pub script_info: HashMap<String, String>,
+    /// Every key-value line of the Script Info section in order, including
+    /// keys set more than once; `script_info` keeps the last value of each.
+    pub(crate) script_info_entries: Vec<ScriptInfoEntry>,
     pub styles: Vec<Style>,
     pub events: Vec<Event>,
+    pub(crate) attachments: Vec<Attachment>,
+    pub(crate) aegisub_project: Vec<AegisubEntry>,
+    pub(crate) aegisub_extradata: Vec<AegisubEntry>,
+    pub(crate) parse_errors: Vec<ParseIssue>,
 }
 
-#[derive(Debug, Clone)]
+impl AssDocument {
+    /// The section a line belongs to: the last one whose header is at or above it.
+    pub(crate) fn section_at(&self, line: u32) -> Option<&Section> {
+        let index = self
+            .sections
+            .partition_point(|section| section.range.start.line <= line);
+        index.checked_sub(1).map(|index| &self.sections[index])
+    }
+
+    /// The style an event named `name` renders with; a later definition
+    /// replaces an earlier one.
+    pub fn style(&self, name: &str) -> Option<&Style> {
+        self.styles.iter().rev().find(|style| style.name == name)
+    }
+
+    pub(crate) fn event_at(&self, line: u32) -> Option<&Event> {
+        let index = self
+            .events
+            .partition_point(|event| event.range.start.line < line);
+        self.events
+            .get(index)
+            .filter(|event| event.range.start.line == line)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Section {
     pub name: String,
+    /// From the header through the last non-empty line before the next header.
     pub range: Range,
-    #[allow(dead_code)]
+    /// The header text itself, e.g. `[Events]`.
+    pub header_range: Range,
+    /// Set when the header was recognized despite being malformed.
+    pub header_problem: Option<HeaderProblem>,
     pub content: Vec<String>,
+    /// Text of the comment banner above the header, see [`section_banner`].
+    pub banner: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+/// The lines `AssParser::reparse` parsed again, before and after the edit.
+/// Everything below them moved by `line_delta()` lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReparsedSpan {
+    pub old: std::ops::Range<usize>,
+    pub new: std::ops::Range<usize>,
+    /// The lines the edit itself wrote, in the new text; empty for a pure
+    /// deletion.
+    pub edited: std::ops::Range<usize>,
+}
+
+impl ReparsedSpan {
+    pub fn line_delta(&self) -> i64 {
+        self.new.end as i64 - self.old.end as i64
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Style {
     pub name: String,
     pub fontname: String,
     pub fontsize: u32,
-    pub primary_colour: String,
-    #[allow(dead_code)]
-    pub secondary_colour: String,
+    /// Parsed PrimaryColour, `None` if missing or malformed.
+    pub primary: Option<AssColor>,
+    /// Parsed SecondaryColour, `None` if missing or malformed.
+    pub secondary: Option<AssColor>,
+    /// Parsed OutlineColour (TertiaryColour in SSA), `None` if missing or malformed.
+    pub outline: Option<AssColor>,
+    /// Parsed BackColour, `None` if missing or malformed.
+    pub back: Option<AssColor>,
+    /// Every field as `(format name, value)`, in the order of the section's Format line.
+    pub(crate) fields: Vec<(String, String)>,
+    /// Byte span of each field's value on the line, parallel to `fields`.
+    pub(crate) field_spans: Vec<std::ops::Range<usize>>,
     pub range: Range,
 }
 
-#[derive(Debug, Clone)]
+impl Style {
+    /// Looks up a field by its Format name, ignoring case.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Where a field's value sits on the style line, by Format name ignoring case.
+    pub fn field_range(&self, name: &str) -> Option<Range> {
+        let index = self
+            .fields
+            .iter()
+            .position(|(field, _)| field.eq_ignore_ascii_case(name))?;
+        let span = self.field_spans.get(index)?;
+        let line = self.range.start.line;
+        Some(Range {
+            start: Position::new(line, span.start as u32),
+            end: Position::new(line, span.end as u32),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Event {
     pub event_type: String,
+    /// Layer the event is drawn on; 0 when missing or unreadable, as for SSA's
+    /// `Marked` field.
+    pub layer: i32,
     pub start_time: String,
     pub end_time: String,
+    /// Parsed start time, `None` if `start_time` is malformed.
+    pub start: Option<AssTime>,
+    /// Parsed end time, `None` if `end_time` is malformed.
+    pub end: Option<AssTime>,
     pub style: String,
     pub actor: String,
+    pub effect: String,
     pub text: String,
+    /// Column on the event line where `text` begins.
+    pub(crate) text_start: u32,
+    /// `(expected, found)` field counts when the line doesn't match the Events
+    /// Format line; the fields were then mapped on a best-effort basis.
+    pub(crate) field_count_mismatch: Option<(usize, usize)>,
+    /// The margin fields the Format line has, in Format order.
+    pub(crate) margins: Vec<MarginField>,
+    /// Byte spans of the Start, End, Style, Name and Effect values on the
+    /// event line, empty if the Format line lacks the field.
+    pub(crate) start_span: std::ops::Range<usize>,
+    pub(crate) end_span: std::ops::Range<usize>,
+    pub(crate) style_span: std::ops::Range<usize>,
+    pub(crate) actor_span: std::ops::Range<usize>,
+    pub(crate) effect_span: std::ops::Range<usize>,
+    pub range: Range,
+}
+
+impl Event {
+    /// Display duration, or `None` if either time is malformed or the times are reversed.
+    pub fn duration(&self) -> Option<AssTime> {
+        self.end?.checked_sub(self.start?)
+    }
+
+    /// Byte span covering both the Start and End values, empty if either is
+    /// missing.
+    pub(crate) fn timing_span(&self) -> std::ops::Range<usize> {
+        if self.start_span.is_empty() || self.end_span.is_empty() {
+            return 0..0;
+        }
+        self.start_span.start.min(self.end_span.start)..self.start_span.end.max(self.end_span.end)
+    }
+
+    pub(crate) fn margin(&self, side: MarginSide) -> Option<&MarginField> {
+        self.margins.iter().find(|margin| margin.side == side)
+    }
+}
+
+/// An event's MarginL, MarginR or MarginV field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarginField {
+    pub side: MarginSide,
+    pub value: String,
+    /// Byte span of `value` on the event line.
+    pub span: std::ops::Range<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarginSide {
+    Left,
+    Right,
+    Vertical,
+}
+
+impl MarginSide {
+    pub const ALL: [MarginSide; 3] = [MarginSide::Left, MarginSide::Right, MarginSide::Vertical];
+
+    /// The Format name of the field, shared by styles and events.
+    pub fn field_name(self) -> &'static str {
+        match self {
+            MarginSide::Left => "MarginL",
+            MarginSide::Right => "MarginR",
+            MarginSide::Vertical => "MarginV",
+        }
+    }
+}
+
+/// A timestamp with the centisecond precision used by ASS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct AssTime(pub u32);
+
+impl AssTime {
+    pub fn centiseconds(self) -> u32 {
+        self.0
+    }
+
+    pub fn as_millis(self) -> u64 {
+        u64::from(self.0) * 10
+    }
+
+    pub fn checked_sub(self, other: AssTime) -> Option<AssTime> {
+        self.0.checked_sub(other.0).map(AssTime)
+    }
+
+    pub fn saturating_sub(self, other: AssTime) -> AssTime {
+        AssTime(self.0.saturating_sub(other.0))
+    }
+
+    /// Reads a timestamp written almost as `H:MM:SS.CC`: with a comma for the
+    /// dot, single-digit minutes or seconds, or a fraction of one to three
+    /// digits read as a decimal and rounded to centiseconds, which covers
+    /// SRT's `00:00:01,500`. Anything less certain, such as a field out of
+    /// range or a missing fraction, gives `None`.
+    pub fn parse_lenient(time_str: &str) -> Option<AssTime> {
+        let is_number = |part: &str, max_len: usize| {
+            (1..=max_len).contains(&part.len()) && part.bytes().all(|b| b.is_ascii_digit())
+        };
+        let mut parts = time_str.trim().split(':');
+        let (hours, minutes, rest) = (parts.next()?, parts.next()?, parts.next()?);
+        let (seconds, fraction) = rest.split_once(['.', ','])?;
+        if parts.next().is_some()
+            || !is_number(hours, 2)
+            || !is_number(minutes, 2)
+            || !is_number(seconds, 2)
+            || !is_number(fraction, 3)
+        {
+            return None;
+        }
+        let (minutes, seconds) = (minutes.parse::<u32>().ok()?, seconds.parse::<u32>().ok()?);
+        if minutes >= 60 || seconds >= 60 {
+            return None;
+        }
+        let millis = fraction.parse::<u32>().ok()? * 10u32.pow(3 - fraction.len() as u32);
+        let total = (hours.parse::<u32>().ok()? * 3600 + minutes * 60 + seconds) * 1000 + millis;
+        Some(AssTime((total + 5) / 10))
+    }
+}
+
+impl FromStr for AssTime {
+    type Err = ();
+
+    /// Parses `H:MM:SS.CC`. Hours may have any number of digits; arithmetic is
+    /// overflow-checked so absurd values fail instead of wrapping.
+    fn from_str(time_str: &str) -> Result<Self, Self::Err> {
+        let mut parts = time_str.trim().split(':');
+        let mut field = || parts.next().ok_or(());
+        let hours: u32 = field()?.parse().map_err(|_| ())?;
+        let minutes: u32 = field()?.parse().map_err(|_| ())?;
+        let (seconds, centiseconds) = field()?.split_once('.').ok_or(())?;
+        if parts.next().is_some() {
+            return Err(());
+        }
+        let seconds: u32 = seconds.parse().map_err(|_| ())?;
+        let centiseconds: u32 = centiseconds.parse().map_err(|_| ())?;
+
+        hours
+            .checked_mul(360000)
+            .and_then(|t| t.checked_add(minutes.checked_mul(6000)?))
+            .and_then(|t| t.checked_add(seconds.checked_mul(100)?))
+            .and_then(|t| t.checked_add(centiseconds))
+            .map(AssTime)
+            .ok_or(())
+    }
+}
+
+impl fmt::Display for AssTime {
+    /// Formats as canonical `H:MM:SS.CC`, keeping every hour digit.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cs = self.0;
+        write!(
+            f,
+            "{}:{:02}:{:02}.{:02}",
+            cs / 360000,
+            cs / 6000 % 60,
+            cs / 100 % 60,
+            cs % 100
+        )
+    }
+}
+
+/// A colour with the alpha convention used by ASS: 0 is opaque, 255 fully transparent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct AssColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl AssColor {
+    /// Unpacks the `0xAABBGGRR` integer every ASS colour notation encodes.
+    pub fn from_packed(value: u32) -> Self {
+        let [r, g, b, a] = value.to_le_bytes();
+        Self { r, g, b, a }
+    }
+
+    pub fn packed(self) -> u32 {
+        u32::from_le_bytes([self.r, self.g, self.b, self.a])
+    }
+
+    pub fn is_transparent(self) -> bool {
+        self.a == 0xFF
+    }
+
+    /// Relative luminance as WCAG defines it, ignoring alpha.
+    pub fn luminance(self) -> f64 {
+        let channel = |value: u8| {
+            let c = f64::from(value) / 255.0;
+            if c <= 0.03928 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        0.2126 * channel(self.r) + 0.7152 * channel(self.g) + 0.0722 * channel(self.b)
+    }
+
+    /// WCAG contrast ratio with `other`, from 1 for the same luminance to 21
+    /// for black on white.
+    pub fn contrast_ratio(self, other: AssColor) -> f64 {
+        let (a, b) = (self.luminance(), other.luminance());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColorError {
+    Empty,
+    /// The characters of a `&H` value that are not hex digits, in order of appearance.
+    InvalidCharacters(String),
+    TooManyDigits,
+    /// A decimal value that is not a number or does not fit in 32 bits.
+    InvalidNumber,
+}
+
+impl fmt::Display for ColorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "colour is empty"),
+            Self::InvalidCharacters(chars) => write!(f, "invalid hex characters: {chars}"),
+            Self::TooManyDigits => write!(f, "more than 8 hex digits"),
+            Self::InvalidNumber => write!(f, "not a 32-bit decimal number"),
+        }
+    }
+}
+
+impl FromStr for AssColor {
+    type Err = ColorError;
+
+    /// Parses `&HAABBGGRR`, `&HBBGGRR&` (opaque), bare decimal, and the signed
+    /// integers SSA writes for colours with the high bit set.
+    fn from_str(color_str: &str) -> Result<Self, Self::Err> {
+        let color_str = color_str.trim();
+        if color_str.is_empty() {
+            return Err(ColorError::Empty);
+        }
+
+        let Some(hex) = strip_prefix_ignore_case(color_str, "&H") else {
+            let value: i64 = color_str.parse().map_err(|_| ColorError::InvalidNumber)?;
+            return match value {
+                0..=0xFFFF_FFFF => Ok(Self::from_packed(value as u32)),
+                v if v >= i64::from(i32::MIN) => Ok(Self::from_packed(v as i32 as u32)),
+                _ => Err(ColorError::InvalidNumber),
+            };
+        };
+
+        let hex = hex.strip_suffix('&').unwrap_or(hex);
+        let mut invalid = String::new();
+        for ch in hex.chars().filter(|ch| !ch.is_ascii_hexdigit()) {
+            if !invalid.contains(ch) {
+                invalid.push(ch);
+            }
+        }
+        if !invalid.is_empty() {
+            return Err(ColorError::InvalidCharacters(invalid));
+        }
+        match hex.len() {
+            0 => Err(ColorError::Empty),
+            1..=8 => Ok(Self::from_packed(
+                u32::from_str_radix(hex, 16).map_err(|_| ColorError::InvalidNumber)?,
+            )),
+            _ => Err(ColorError::TooManyDigits),
+        }
+    }
+}
+
+/// Whether a colour is spelled the way its [`ColorContext`] expects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpelling {
+    Canonical,
+    /// An older spelling renderers still read, e.g. SSA's decimal style colours.
+    Legacy,
+}
+
+impl AssColor {
+    /// Parses a colour as written in `context`, telling the canonical spelling
+    /// from legacy ones. Style fields go through [`FromStr`]. Override tags
+    /// follow the renderers, which skip the `&` and `H` around the digits,
+    /// read what is left as hex and drop the alpha byte.
+    pub(crate) fn parse_in(
+        value: &str,
+        context: ColorContext,
+    ) -> Result<(Self, ColorSpelling), ColorError> {
+        let value = value.trim();
+        let (color, canonical) = match context {
+            ColorContext::StyleField => {
+                let canonical = value.strip_prefix("&H").is_some_and(|hex| {
+                    hex.len() == 8 && hex.bytes().all(|b| b.is_ascii_hexdigit())
+                });
+                (value.parse()?, canonical)
+            }
+            ColorContext::OverrideTag => {
+                let hex = value
+                    .trim_start_matches('&')
+                    .trim_start_matches(['H', 'h'])
+                    .trim_end_matches('&');
+                let packed: Self = format!("&H{hex}").parse()?;
+                let canonical = value
+                    .strip_prefix("&H")
+                    .and_then(|rest| rest.strip_suffix('&'))
+                    .is_some_and(|hex| {
+                        hex.len() == 6 && hex.bytes().all(|b| b.is_ascii_hexdigit())
+                    });
+                (Self { a: 0, ..packed }, canonical)
+            }
+        };
+        let spelling = if canonical {
+            ColorSpelling::Canonical
+        } else {
+            ColorSpelling::Legacy
+        };
+        Ok((color, spelling))
+    }
+
+    /// The colour in the canonical spelling for `context`.
+    pub(crate) fn canonical(self, context: ColorContext) -> String {
+        match context {
+            ColorContext::StyleField => self.to_string(),
+            ColorContext::OverrideTag => format!("&H{:06X}&", self.packed() & 0x00FF_FFFF),
+        }
+    }
+}
+
+impl fmt::Display for AssColor {
+    /// Formats as canonical `&HAABBGGRR`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "&H{:08X}", self.packed())
+    }
+}
+
+/// A file embedded in the [Fonts] or [Graphics] section. The payload is kept
+/// as the raw UU-encoded lines so it can be passed through untouched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    pub section: String,
+    /// Header key as written, `fontname` or `filename`.
+    pub key: String,
+    pub filename: String,
+    /// Where the filename sits on the header line.
+    pub filename_range: Range,
+    pub data_lines: Vec<String>,
+    pub range: Range,
+}
+
+/// A line the parser could not make sense of and skipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseIssue {
+    pub line: u32,
+    pub raw: String,
+    pub reason: ParseIssueReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParseIssueReason {
+    TooFewFields,
+    MissingColon,
+    UnknownLinePrefix,
+}
+
+impl ParseIssue {
+    fn new(line_num: usize, raw: &str, reason: ParseIssueReason) -> Self {
+        Self {
+            line: line_num as u32,
+            raw: raw.to_string(),
+            reason,
+        }
+    }
+
+    /// Classifies a line that does not start with any prefix valid in its section.
+    fn unrecognized(line_num: usize, raw: &str) -> Self {
+        let reason = if raw.contains(':') {
+            ParseIssueReason::UnknownLinePrefix
+        } else {
+            ParseIssueReason::MissingColon
+        };
+        Self::new(line_num, raw, reason)
+    }
+}
+
+/// A key-value pair from Aegisub's `[Aegisub Project Garbage]` or
+/// `[Aegisub Extradata]` section.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AegisubEntry {
+    pub key: String,
+    pub value: String,
     pub range: Range,
 }
 
-#[derive(Debug)]
+/// A key-value line of the `[Script Info]` section, with the key in its
+/// canonical spelling.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptInfoEntry {
+    pub key: String,
+    pub value: String,
+    pub range: Range,
+}
+
+#[derive(Debug, Clone)]
 pub struct AssParser {
-    section_regex: Regex,
+    /// Show comment banners above section headers as the sections' detail in
+    /// document symbols, in place of the item count.
+    pub(crate) banner_details: bool,
 }
 
-impl AssParser {
-    pub fn new() -> Self {
+impl Default for AssParser {
+    fn default() -> Self {
         Self {
-            section_regex: Regex::new(r"^\[([^\]]+)\]").unwrap(),
+            banner_details: true,
         }
     }
+}
+
+impl AssParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
     pub fn parse(&self, text: &str) -> AssDocument {
         let lines: Vec<&str> = text.lines().collect();
+        self.parse_lines(&lines, 0..lines.len())
+    }
+
+    /// Reparses only the sections an edit touched and splices them into
+    /// `previous`, the parse of `old_text`. The result matches `parse(text)`.
+    pub(crate) fn reparse(
+        &self,
+        previous: AssDocument,
+        old_text: &str,
+        text: &str,
+    ) -> (AssDocument, ReparsedSpan) {
+        let old_lines: Vec<&str> = old_text.lines().collect();
+        let lines: Vec<&str> = text.lines().collect();
+        let prefix = old_lines
+            .iter()
+            .zip(&lines)
+            .take_while(|(old, new)| old == new)
+            .count();
+        let suffix = old_lines
+            .iter()
+            .rev()
+            .zip(lines.iter().rev())
+            .take(old_lines.len().min(lines.len()) - prefix)
+            .take_while(|(old, new)| old == new)
+            .count();
+        let headers = previous
+            .sections
+            .iter()
+            .map(|section| section.range.start.line as usize);
+
+        // An edited header may no longer split its section from the one before,
+        // so start at the last header above the first edited line
+        let start = headers.clone().rfind(|&line| line < prefix).unwrap_or(0);
+        // Unknown headers can become attachment data when the section above
+        // changes, so only a known header safely ends the span
+        let mut ends = headers
+            .filter(|&line| line >= old_lines.len() - suffix)
+            .filter(|&line| is_known_section_header(old_lines[line].trim()));
+        let mut old_end = ends.next().unwrap_or(old_lines.len());
+        // An edit just above that header can change its banner, in which case
+        // its section is reparsed too
+        let banner_changed = previous
+            .sections
+            .iter()
+            .find(|section| section.range.start.line as usize == old_end)
+            .is_some_and(|section| {
+                section.banner != section_banner(&lines, old_end + lines.len() - old_lines.len())
+            });
+        if banner_changed {
+            old_end = ends.next().unwrap_or(old_lines.len());
+        }
+        let span = ReparsedSpan {
+            old: start..old_end,
+            new: start..old_end + lines.len() - old_lines.len(),
+            edited: prefix..lines.len() - suffix,
+        };
+
+        let chunk = self.parse_lines(&lines, span.new.clone());
+        let sections = splice(previous.sections, chunk.sections, &span);
+        let script_info_entries = splice(
+            previous.script_info_entries,
+            chunk.script_info_entries,
+            &span,
+        );
+        let script_info = script_info_entries
+            .iter()
+            .map(|entry| (entry.key.clone(), entry.value.clone()))
+            .collect();
+
+        let document = AssDocument {
+            script_info,
+            script_info_entries,
+            styles: splice(previous.styles, chunk.styles, &span),
+            events: splice(previous.events, chunk.events, &span),
+            attachments: splice(previous.attachments, chunk.attachments, &span),
+            aegisub_project: splice(previous.aegisub_project, chunk.aegisub_project, &span),
+            aegisub_extradata: splice(previous.aegisub_extradata, chunk.aegisub_extradata, &span),
+            parse_errors: splice(previous.parse_errors, chunk.parse_errors, &span),
+            sections,
+        };
+        (document, span)
+    }
+
+    /// Parses the lines in `span`, which must start at a section header or at
+    /// the top of the file. Line numbers stay relative to the whole file.
+    fn parse_lines(&self, lines: &[&str], span: std::ops::Range<usize>) -> AssDocument {
         let mut sections = Vec::new();
         let mut script_info = HashMap::new();
+        let mut script_info_entries = Vec::new();
         let mut styles = Vec::new();
         let mut events = Vec::new();
+        let mut attachments: Vec<Attachment> = Vec::new();
+        let mut aegisub_project = Vec::new();
+        let mut aegisub_extradata = Vec::new();
+        let mut parse_errors = Vec::new();
 
         let mut current_section: Option<String> = None;
         let mut current_section_start = 0;
+        let mut current_header_range = Range::default();
+        let mut current_header_problem: Option<HeaderProblem> = None;
+        let mut last_content_line = span.start;
+        let mut current_attachment: Option<usize> = None;
+        let mut style_format: Vec<String> = Vec::new();
+        let mut event_format = default_event_format();
 
-        for (line_num, line) in lines.iter().enumerate() {
-            let line = line.trim();
+        for (line_num, raw_line) in lines.iter().enumerate().take(span.end).skip(span.start) {
+            let line = raw_line.trim();
+
+            // Section ranges end at their last non-empty line, not at the next header
+            let previous_content_line = last_content_line;
+            if !line.is_empty() {
+                last_content_line = line_num;
+            }
+
+            // Attachment payload lines may start with ';' or look like a section header
+            if let Some(index) = current_attachment {
+                if is_attachment_data(line) && !is_known_section_header(line) {
+                    let attachment = &mut attachments[index];
+                    attachment.data_lines.push(raw_line.to_string());
+                    attachment.range.end = Position::new(line_num as u32, raw_line.len() as u32);
+                    continue;
+                }
+            }
 
             // Skip empty lines and comments
             if line.is_empty() || line.starts_with(';') {
@@ -71,46 +687,130 @@ impl AssParser {
             }
 
             // Check for section headers
-            if let Some(captures) = self.section_regex.captures(line) {
+            if let Some(header) = parse_section_header(line) {
                 // Finish previous section
                 if let Some(section_name) = current_section.take() {
-                    sections.push(Section {
-                        name: section_name,
-                        range: Range {
-                            start: Position::new(current_section_start as u32, 0),
-                            end: Position::new(line_num as u32, 0),
-                        },
-                        content: lines[current_section_start..line_num]
-                            .iter()
-                            .map(|s| s.to_string())
-                            .collect(),
-                    });
+                    sections.push(finish_section(
+                        section_name,
+                        lines,
+                        current_section_start,
+                        previous_content_line,
+                        current_header_range,
+                        current_header_problem.take(),
+                    ));
                 }
 
-                current_section = Some(captures[1].to_string());
+                current_section = Some(header.name);
+                current_header_problem = header.problem;
+                style_format = default_style_format(current_section.as_deref().unwrap_or(""));
+                event_format = default_event_format();
                 current_section_start = line_num;
+                let indent = raw_line.len() - raw_line.trim_start().len();
+                current_header_range = Range {
+                    start: Position::new(line_num as u32, indent as u32),
+                    end: Position::new(line_num as u32, (indent + line.len()) as u32),
+                };
+                current_attachment = None;
                 continue;
             }
 
             // Parse content based on current section
             match current_section.as_deref() {
-                Some("Script Info") => {
-                    if let Some((key, value)) = self.parse_key_value(line) {
-                        script_info.insert(key, value);
+                Some("Script Info") => match self.parse_key_value(line) {
+                    Some((key, value)) => {
+                        script_info.insert(key.clone(), value.clone());
+                        script_info_entries.push(ScriptInfoEntry {
+                            key,
+                            value,
+                            range: Range {
+                                start: Position::new(line_num as u32, 0),
+                                end: Position::new(line_num as u32, raw_line.len() as u32),
+                            },
+                        });
+                    }
+                    None => parse_errors.push(ParseIssue::new(
+                        line_num,
+                        raw_line,
+                        ParseIssueReason::MissingColon,
+                    )),
+                },
+                Some("Aegisub Project Garbage") => {
+                    if let Some((key, value)) = line.split_once(':') {
+                        aegisub_project.push(AegisubEntry {
+                            key: key.trim().to_string(),
+                            value: value.trim().to_string(),
+                            range: Range {
+                                start: Position::new(line_num as u32, 0),
+                                end: Position::new(line_num as u32, raw_line.len() as u32),
+                            },
+                        });
+                    }
+                }
+                Some("Aegisub Extradata") => {
+                    if let Some(entry) = parse_extradata(line, line_num, raw_line.len()) {
+                        aegisub_extradata.push(entry);
+                    }
+                }
+                Some(section) if is_attachment_section(section) => {
+                    if let Some((key, filename)) = parse_attachment_header(line) {
+                        let indent = raw_line.len() - raw_line.trim_start().len();
+                        let value = &line[line.find(':').unwrap_or(0) + 1..];
+                        let start = indent + line.len() - value.trim_start().len();
+                        current_attachment = Some(attachments.len());
+                        attachments.push(Attachment {
+                            section: section.to_string(),
+                            key: key.to_string(),
+                            filename: filename.to_string(),
+                            filename_range: Range {
+                                start: Position::new(line_num as u32, start as u32),
+                                end: Position::new(
+                                    line_num as u32,
+                                    (start + filename.len()) as u32,
+                                ),
+                            },
+                            data_lines: Vec::new(),
+                            range: Range {
+                                start: Position::new(line_num as u32, 0),
+                                end: Position::new(line_num as u32, raw_line.len() as u32),
+                            },
+                        });
                     }
                 }
                 Some(section) if section.contains("Styles") => {
-                    if line.starts_with("Style:") {
-                        if let Some(style) = self.parse_style(line, line_num) {
-                            styles.push(style);
+                    if strip_prefix_ignore_case(line, "Style:").is_some() {
+                        match self.parse_style(line, line_num, &style_format) {
+                            Some(style) => styles.push(style),
+                            None => parse_errors.push(ParseIssue::new(
+                                line_num,
+                                raw_line,
+                                ParseIssueReason::TooFewFields,
+                            )),
                         }
+                    } else if let Some(format) = strip_prefix_ignore_case(line, "Format:") {
+                        style_format = parse_format_line(format);
+                    } else {
+                        parse_errors.push(ParseIssue::unrecognized(line_num, raw_line));
                     }
                 }
                 Some("Events") => {
-                    if line.starts_with("Dialogue:") || line.starts_with("Comment:") {
-                        if let Some(event) = self.parse_event(line, line_num) {
-                            events.push(event);
+                    if strip_prefix_ignore_case(line, "Dialogue:").is_some()
+                        || strip_prefix_ignore_case(line, "Comment:").is_some()
+                    {
+                        match self.parse_event(line, line_num, &event_format) {
+                            Some(event) => events.push(event),
+                            None => parse_errors.push(ParseIssue::new(
+                                line_num,
+                                raw_line,
+                                ParseIssueReason::TooFewFields,
+                            )),
                         }
+                    } else if let Some(format) = strip_prefix_ignore_case(line, "Format:") {
+                        event_format = parse_format_line(format);
+                    } else if !OTHER_EVENT_PREFIXES
+                        .iter()
+                        .any(|prefix| strip_prefix_ignore_case(line, prefix).is_some())
+                    {
+                        parse_errors.push(ParseIssue::unrecognized(line_num, raw_line));
                     }
                 }
                 _ => {}
@@ -119,30 +819,32 @@ impl AssParser {
 
         // Finish last section
         if let Some(section_name) = current_section {
-            sections.push(Section {
-                name: section_name,
-                range: Range {
-                    start: Position::new(current_section_start as u32, 0),
-                    end: Position::new(lines.len() as u32, 0),
-                },
-                content: lines[current_section_start..]
-                    .iter()
-                    .map(|s| s.to_string())
-                    .collect(),
-            });
+            sections.push(finish_section(
+                section_name,
+                lines,
+                current_section_start,
+                last_content_line,
+                current_header_range,
+                current_header_problem,
+            ));
         }
 
         AssDocument {
             sections,
             script_info,
+            script_info_entries,
             styles,
             events,
+            attachments,
+            aegisub_project,
+            aegisub_extradata,
+            parse_errors,
         }
     }
 
     fn parse_key_value(&self, line: &str) -> Option<(String, String)> {
         if let Some(colon_pos) = line.find(':') {
-            let key = line[..colon_pos].trim().to_string();
+            let key = canonical_script_info_key(line[..colon_pos].trim());
             let value = line[colon_pos + 1..].trim().to_string();
             Some((key, value))
         } else {
@@ -150,15 +852,41 @@ impl AssParser {
         }
     }
 
-    fn parse_style(&self, line: &str, line_num: usize) -> Option<Style> {
-        let parts: Vec<&str> = line.split_once(':')?.1.split(',').collect();
+    fn parse_style(&self, line: &str, line_num: usize, format: &[String]) -> Option<Style> {
+        let (head, values) = line.split_once(':')?;
+        let parts: Vec<&str> = values.split(',').collect();
         if parts.len() >= 4 {
+            let fields: Vec<(String, String)> = format
+                .iter()
+                .zip(&parts)
+                .map(|(name, value)| (name.clone(), value.trim().to_string()))
+                .collect();
+            let mut part_start = head.len() + 1;
+            let mut field_spans = Vec::new();
+            for part in parts.iter().take(fields.len()) {
+                let start = part_start + part.len() - part.trim_start().len();
+                field_spans.push(start..start + part.trim().len());
+                part_start += part.len() + 1;
+            }
+            let field = |name: &str, default: &str| {
+                fields
+                    .iter()
+                    .find(|(field, _)| field.eq_ignore_ascii_case(name))
+                    .map_or_else(|| default.to_string(), |(_, value)| value.clone())
+            };
             Some(Style {
-                name: parts[0].trim().to_string(),
-                fontname: parts.get(1).unwrap_or(&"Arial").trim().to_string(),
-                fontsize: parts.get(2).unwrap_or(&"20").trim().parse().unwrap_or(20),
-                primary_colour: parts.get(3).unwrap_or(&"&Hffffff").trim().to_string(),
-                secondary_colour: parts.get(4).unwrap_or(&"&Hffffff").trim().to_string(),
+                name: field("Name", ""),
+                fontname: field("Fontname", "Arial"),
+                fontsize: field("Fontsize", "20").parse().unwrap_or(20),
+                primary: field("PrimaryColour", "").parse().ok(),
+                secondary: field("SecondaryColour", "").parse().ok(),
+                outline: field("OutlineColour", "")
+                    .parse()
+                    .or_else(|_| field("TertiaryColour", "").parse())
+                    .ok(),
+                back: field("BackColour", "").parse().ok(),
+                fields,
+                field_spans,
                 range: Range {
                     start: Position::new(line_num as u32, 0),
                     end: Position::new(line_num as u32, line.len() as u32),
@@ -169,44 +897,138 @@ impl AssParser {
         }
     }
 
-    fn parse_event(&self, line: &str, line_num: usize) -> Option<Event> {
-        let event_type = if line.starts_with("Dialogue:") {
+    fn parse_event(&self, line: &str, line_num: usize, format: &[String]) -> Option<Event> {
+        let event_type = if strip_prefix_ignore_case(line, "Dialogue:").is_some() {
             "Dialogue"
         } else {
             "Comment"
         };
-        let parts: Vec<&str> = line.split_once(':')?.1.split(',').collect();
-
-        if parts.len() >= 10 {
-            Some(Event {
-                event_type: event_type.to_string(),
-                start_time: parts.get(1).unwrap_or(&"0:00:00.00").trim().to_string(),
-                end_time: parts.get(2).unwrap_or(&"0:00:00.00").trim().to_string(),
-                style: parts.get(3).unwrap_or(&"Default").trim().to_string(),
-                actor: parts.get(4).unwrap_or(&"").trim().to_string(),
-                text: parts[9..].join(",").trim().to_string(),
-                range: Range {
-                    start: Position::new(line_num as u32, 0),
-                    end: Position::new(line_num as u32, line.len() as u32),
-                },
-            })
+        let (head, fields) = line.split_once(':')?;
+        let parts: Vec<&str> = fields.split(',').collect();
+        let expected = format.len().max(1);
+
+        if parts.len() < 4 {
+            return None;
+        }
+
+        // A line short of fields maps what it has and keeps the rest as Text
+        let mapped = (expected - 1).min(parts.len() - 1);
+        let field_count_mismatch = if parts.len() < expected {
+            Some((expected, parts.len()))
         } else {
-            None
+            extra_field_count(format, &parts).map(|found| (expected, found))
+        };
+        let field = |name: &str| {
+            format[..mapped]
+                .iter()
+                .position(|field| field.eq_ignore_ascii_case(name))
+                .map_or("", |index| parts[index].trim())
+        };
+
+        let mut part_start = head.len() + 1;
+        let mut margins = Vec::new();
+        let (mut start_span, mut end_span) = (0..0, 0..0);
+        let (mut style_span, mut actor_span, mut effect_span) = (0..0, 0..0, 0..0);
+        for (name, part) in format[..mapped].iter().zip(&parts) {
+            let start = part_start + part.len() - part.trim_start().len();
+            let span = start..start + part.trim().len();
+            if name.eq_ignore_ascii_case("Start") {
+                start_span = span.clone();
+            } else if name.eq_ignore_ascii_case("End") {
+                end_span = span.clone();
+            } else if name.eq_ignore_ascii_case("Style") {
+                style_span = span.clone();
+            } else if name.eq_ignore_ascii_case("Name") {
+                actor_span = span.clone();
+            } else if name.eq_ignore_ascii_case("Effect") {
+                effect_span = span.clone();
+            }
+            if let Some(side) = MarginSide::ALL
+                .into_iter()
+                .find(|side| side.field_name().eq_ignore_ascii_case(name))
+            {
+                margins.push(MarginField {
+                    side,
+                    value: part.trim().to_string(),
+                    span,
+                });
+            }
+            part_start += part.len() + 1;
         }
+
+        let raw_text_start = part_start;
+        let raw_text = &line[raw_text_start..];
+        Some(Event {
+            event_type: event_type.to_string(),
+            layer: field("Layer").parse().unwrap_or(0),
+            start_time: field("Start").to_string(),
+            end_time: field("End").to_string(),
+            start: field("Start").parse().ok(),
+            end: field("End").parse().ok(),
+            style: field("Style").to_string(),
+            actor: field("Name").to_string(),
+            effect: field("Effect").to_string(),
+            text: parts[mapped..].join(",").trim().to_string(),
+            text_start: (raw_text_start + raw_text.len() - raw_text.trim_start().len()) as u32,
+            field_count_mismatch,
+            margins,
+            start_span,
+            end_span,
+            style_span,
+            actor_span,
+            effect_span,
+            range: Range {
+                start: Position::new(line_num as u32, 0),
+                end: Position::new(line_num as u32, line.len() as u32),
+            },
+        })
     }
 
     pub fn format(&self, text: &str) -> String {
+        let line_ending = detect_line_ending(text);
         let lines: Vec<&str> = text.lines().collect();
         let mut formatted_lines = Vec::new();
         let mut in_section = false;
+        let mut in_attachments = false;
+        let mut in_aegisub_section = false;
 
         for line in lines {
             let trimmed = line.trim();
 
+            // Aegisub project state is kept byte-for-byte
+            if in_aegisub_section && parse_section_header(trimmed).is_none() {
+                formatted_lines.push(line.to_string());
+                continue;
+            }
+
+            // Attachment payloads are passed through verbatim
+            if in_attachments
+                && (trimmed.is_empty()
+                    || (is_attachment_data(trimmed) && !is_known_section_header(trimmed)))
+            {
+                formatted_lines.push(line.to_string());
+                continue;
+            }
+
             // Section headers
-            if self.section_regex.is_match(trimmed) {
-                if in_section {
-                    formatted_lines.push("".to_string()); // Add blank line before new section
+            if let Some(header) = parse_section_header(trimmed) {
+                let name = header.name;
+                in_attachments = is_attachment_section(&name);
+                in_aegisub_section = is_aegisub_section(&name);
+                // A comment banner right above the header belongs to it, so the
+                // blank line goes above the banner
+                let banner_start = formatted_lines.len()
+                    - formatted_lines
+                        .iter()
+                        .rev()
+                        .take_while(|l| l.trim_start().starts_with(';'))
+                        .count();
+                if in_section
+                    && banner_start > 0
+                    && !formatted_lines[banner_start - 1].trim().is_empty()
+                {
+                    // Add blank line before new section
+                    formatted_lines.insert(banner_start, "".to_string());
                 }
                 formatted_lines.push(trimmed.to_string());
                 in_section = true;
@@ -221,15 +1043,18 @@ impl AssParser {
             }
         }
 
-        formatted_lines.join("\n")
+        let mut formatted = formatted_lines.join(line_ending);
+        if text.ends_with('\n') {
+            formatted.push_str(line_ending);
+        }
+        formatted
     }
 
     #[allow(deprecated)]
-    pub fn extract_symbols(&self, text: &str) -> Vec<DocumentSymbol> {
-        let document = self.parse(text);
+    pub(crate) fn extract_symbols(&self, document: &AssDocument) -> Vec<DocumentSymbol> {
         let mut symbols = Vec::new();
 
-        for section in document.sections {
+        for section in &document.sections {
             let mut children = Vec::new();
 
             match section.name.as_str() {
@@ -261,6 +1086,43 @@ impl AssParser {
                         });
                     }
                 }
+                "Aegisub Project Garbage" | "Aegisub Extradata" => {
+                    let entries = if section.name == "Aegisub Project Garbage" {
+                        &document.aegisub_project
+                    } else {
+                        &document.aegisub_extradata
+                    };
+                    for entry in entries {
+                        children.push(DocumentSymbol {
+                            name: entry.key.clone(),
+                            detail: Some(entry.value.chars().take(50).collect::<String>()),
+                            kind: SymbolKind::PROPERTY,
+                            tags: None,
+                            deprecated: None,
+                            range: entry.range,
+                            selection_range: entry.range,
+                            children: None,
+                        });
+                    }
+                }
+                name if is_attachment_section(name) => {
+                    for attachment in document
+                        .attachments
+                        .iter()
+                        .filter(|attachment| attachment.section == name)
+                    {
+                        children.push(DocumentSymbol {
+                            name: attachment.filename.clone(),
+                            detail: Some(format!("{} lines", attachment.data_lines.len())),
+                            kind: SymbolKind::FILE,
+                            tags: None,
+                            deprecated: None,
+                            range: attachment.range,
+                            selection_range: attachment.range,
+                            children: None,
+                        });
+                    }
+                }
                 "Events" => {
                     for event in &document.events {
                         children.push(DocumentSymbol {
@@ -272,7 +1134,17 @@ impl AssParser {
                                     event.actor, event.start_time, event.end_time
                                 )
                             },
-                            detail: Some(event.text.chars().take(50).collect::<String>()),
+                            detail: Some({
+                                let text = excerpt(
+                                    &event.text,
+                                    document.script_info.get("WrapStyle").map(String::as_str),
+                                    50,
+                                );
+                                match ScrollEffect::parse(&event.effect) {
+                                    Some(effect) => format!("({}) {text}", effect.name()),
+                                    None => text,
+                                }
+                            }),
                             kind: if event.event_type == "Dialogue" {
                                 SymbolKind::FUNCTION
                             } else {
@@ -289,14 +1161,18 @@ impl AssParser {
                 _ => {}
             }
 
+            let detail = match &section.banner {
+                Some(banner) if self.banner_details => banner.clone(),
+                _ => format!("{} items", children.len()),
+            };
             symbols.push(DocumentSymbol {
-                name: section.name,
-                detail: Some(format!("{} items", children.len())),
+                name: section.name.clone(),
+                detail: Some(detail),
                 kind: SymbolKind::NAMESPACE,
                 tags: None,
                 deprecated: None,
                 range: section.range,
-                selection_range: section.range,
+                selection_range: section.header_range,
                 children: if children.is_empty() {
                     None
                 } else {
@@ -308,3 +1184,896 @@ impl AssParser {
         symbols
     }
 }
+
+fn finish_section(
+    name: String,
+    lines: &[&str],
+    start: usize,
+    last_content_line: usize,
+    header_range: Range,
+    header_problem: Option<HeaderProblem>,
+) -> Section {
+    let end = last_content_line.max(start);
+    Section {
+        name,
+        range: Range {
+            start: Position::new(start as u32, 0),
+            end: Position::new(end as u32, lines[end].len() as u32),
+        },
+        header_range,
+        header_problem,
+        content: lines[start..=end].iter().map(|s| s.to_string()).collect(),
+        banner: section_banner(lines, start),
+    }
+}
+
+/// Characters drawn around banner text, as in `;======== SIGNS ========`.
+const BANNER_DECORATION: &[char] = &['=', '-', '*', '#', '~', '_', '+', '/', '\\', '|', '<', '>'];
+
+/// Text of the run of comment lines above the section header on line
+/// `header`, possibly separated from it by blank lines. Decoration is trimmed
+/// and lines that are only decoration are dropped.
+pub fn section_banner(lines: &[&str], header: usize) -> Option<String> {
+    let above = lines[..header]
+        .iter()
+        .rev()
+        .skip_while(|line| line.trim().is_empty());
+    let mut parts: Vec<&str> = above
+        .map_while(|line| line.trim().strip_prefix(';'))
+        .map(|comment| {
+            comment.trim_matches(|c: char| c.is_whitespace() || BANNER_DECORATION.contains(&c))
+        })
+        .filter(|text| !text.is_empty())
+        .collect();
+    parts.reverse();
+    (!parts.is_empty()).then(|| parts.join(" "))
+}
+
+/// Parsed items that know which line they start on, so `AssParser::reparse`
+/// can splice them.
+trait LineItem {
+    fn line(&self) -> usize;
+    fn shift(&mut self, delta: i64);
+}
+
+fn shift_range(range: &mut Range, delta: i64) {
+    range.start.line = (range.start.line as i64 + delta) as u32;
+    range.end.line = (range.end.line as i64 + delta) as u32;
+}
+
+impl LineItem for Style {
+    fn line(&self) -> usize {
+        self.range.start.line as usize
+    }
+
+    fn shift(&mut self, delta: i64) {
+        shift_range(&mut self.range, delta);
+    }
+}
+
+impl LineItem for Event {
+    fn line(&self) -> usize {
+        self.range.start.line as usize
+    }
+
+    fn shift(&mut self, delta: i64) {
+        shift_range(&mut self.range, delta);
+    }
+}
+
+impl LineItem for Attachment {
+    fn line(&self) -> usize {
+        self.range.start.line as usize
+    }
+
+    fn shift(&mut self, delta: i64) {
+        shift_range(&mut self.range, delta);
+        shift_range(&mut self.filename_range, delta);
+    }
+}
+
+impl LineItem for AegisubEntry {
+    fn line(&self) -> usize {
+        self.range.start.line as usize
+    }
+
+    fn shift(&mut self, delta: i64) {
+        shift_range(&mut self.range, delta);
+    }
+}
+
+impl LineItem for ScriptInfoEntry {
+    fn line(&self) -> usize {
+        self.range.start.line as usize
+    }
+
+    fn shift(&mut self, delta: i64) {
+        shift_range(&mut self.range, delta);
+    }
+}
+
+impl LineItem for Section {
+    fn line(&self) -> usize {
+        self.range.start.line as usize
+    }
+
+    fn shift(&mut self, delta: i64) {
+        shift_range(&mut self.range, delta);
+        shift_range(&mut self.header_range, delta);
+    }
+}
+
+impl LineItem for ParseIssue {
+    fn line(&self) -> usize {
+        self.line as usize
+    }
+
+    fn shift(&mut self, delta: i64) {
+        self.line = (self.line as i64 + delta) as u32;
+    }
+}
+
+/// Replaces the items `span` reparsed with `chunk`, moving the ones below it.
+fn splice<T: LineItem>(previous: Vec<T>, chunk: Vec<T>, span: &ReparsedSpan) -> Vec<T> {
+    let mut items = Vec::with_capacity(previous.len() + chunk.len());
+    let mut after = Vec::new();
+    for mut item in previous {
+        if item.line() < span.old.start {
+            items.push(item);
+        } else if item.line() >= span.old.end {
+            item.shift(span.line_delta());
+            after.push(item);
+        }
+    }
+    items.extend(chunk);
+    items.extend(after);
+    items
+}
+
+/// Returns the dominant line ending of `text`, defaulting to `\n`.
+pub fn detect_line_ending(text: &str) -> &'static str {
+    let crlf = text.matches("\r\n").count();
+    let lf = text.matches('\n').count() - crlf;
+    if crlf > lf {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
+
+/// Style columns assumed for `[V4+ Styles]` when no Format line is present.
+pub const V4_PLUS_STYLE_FORMAT: [&str; 23] = [
+    "Name",
+    "Fontname",
+    "Fontsize",
+    "PrimaryColour",
+    "SecondaryColour",
+    "OutlineColour",
+    "BackColour",
+    "Bold",
+    "Italic",
+    "Underline",
+    "StrikeOut",
+    "ScaleX",
+    "ScaleY",
+    "Spacing",
+    "Angle",
+    "BorderStyle",
+    "Outline",
+    "Shadow",
+    "Alignment",
+    "MarginL",
+    "MarginR",
+    "MarginV",
+    "Encoding",
+];
+
+/// Style columns assumed for SSA `[V4 Styles]` when no Format line is present.
+pub const V4_STYLE_FORMAT: [&str; 18] = [
+    "Name",
+    "Fontname",
+    "Fontsize",
+    "PrimaryColour",
+    "SecondaryColour",
+    "TertiaryColour",
+    "BackColour",
+    "Bold",
+    "Italic",
+    "BorderStyle",
+    "Outline",
+    "Shadow",
+    "Alignment",
+    "MarginL",
+    "MarginR",
+    "MarginV",
+    "AlphaLevel",
+    "Encoding",
+];
+
+/// Event columns assumed for `[Events]` when no Format line is present.
+pub const EVENT_FORMAT: [&str; 10] = [
+    "Layer", "Start", "End", "Style", "Name", "MarginL", "MarginR", "MarginV", "Effect", "Text",
+];
+
+pub fn default_event_format() -> Vec<String> {
+    EVENT_FORMAT.iter().map(|name| name.to_string()).collect()
+}
+
+/// Detects commas added before the Text column, which would otherwise shift
+/// every later field silently: the numeric and time fields don't parse where
+/// Format puts them, but do once the fields after some point move right.
+/// Returns the field count the line actually has up to its Text.
+fn extra_field_count(format: &[String], parts: &[&str]) -> Option<usize> {
+    let typed_fields_valid = |at: usize, shift: usize| {
+        format[..format.len().saturating_sub(1)]
+            .iter()
+            .enumerate()
+            .all(|(index, name)| {
+                let value = parts
+                    .get(if index < at { index } else { index + shift })
+                    .map_or("", |value| value.trim());
+                match name.to_ascii_lowercase().as_str() {
+                    "layer" | "marginl" | "marginr" | "marginv" => value.parse::<i64>().is_ok(),
+                    "start" | "end" => value.parse::<AssTime>().is_ok(),
+                    _ => true,
+                }
+            })
+    };
+
+    if typed_fields_valid(0, 0) {
+        return None;
+    }
+    let spare = parts.len().saturating_sub(format.len());
+    (1..=spare).find_map(|shift| {
+        (1..format.len())
+            .any(|at| typed_fields_valid(at, shift))
+            .then_some(format.len() + shift)
+    })
+}
+
+/// Returns the style columns a section uses until a Format line says otherwise.
+pub fn default_style_format(section: &str) -> Vec<String> {
+    let format: &[&str] = if section == "V4 Styles" {
+        &V4_STYLE_FORMAT
+    } else {
+        &V4_PLUS_STYLE_FORMAT
+    };
+    format.iter().map(|name| name.to_string()).collect()
+}
+
+/// Splits the column list of a `Format:` line.
+pub fn parse_format_line(format: &str) -> Vec<String> {
+    format
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .collect()
+}
+
+/// Returns the style columns in effect at `line_idx`, from the nearest Format
+/// line above it in the same section.
+pub fn style_format_at(lines: &[&str], line_idx: usize) -> Vec<String> {
+    for line in lines[..=line_idx.min(lines.len().saturating_sub(1))]
+        .iter()
+        .rev()
+    {
+        let line = line.trim();
+        if let Some(format) = strip_prefix_ignore_case(line, "Format:") {
+            return parse_format_line(format);
+        }
+        if let Some(header) = parse_section_header(line) {
+            return default_style_format(&header.name);
+        }
+    }
+    default_style_format("")
+}
+
+/// Returns the event columns in effect at `line_idx`, from the nearest Format
+/// line above it in the same section.
+pub fn event_format_at(lines: &[&str], line_idx: usize) -> Vec<String> {
+    for line in lines[..=line_idx.min(lines.len().saturating_sub(1))]
+        .iter()
+        .rev()
+    {
+        let line = line.trim();
+        if let Some(format) = strip_prefix_ignore_case(line, "Format:") {
+            return parse_format_line(format);
+        }
+        if parse_section_header(line).is_some() {
+            break;
+        }
+    }
+    default_event_format()
+}
+
+/// Returns the zero-based comma-separated field the byte offset `char_idx`
+/// falls into on a `Key: a,b,c` line.
+pub fn field_index_at(line: &str, char_idx: usize) -> Option<usize> {
+    let colon = line.find(':')?;
+    let end = char_idx.min(line.len());
+    if end <= colon || !line.is_char_boundary(end) {
+        return None;
+    }
+    Some(line[colon + 1..end].matches(',').count())
+}
+
+/// Returns the byte range of the zero-based field `index` on a `Key: a,b,c`
+/// line, with surrounding whitespace excluded.
+pub fn field_range(line: &str, index: usize) -> Option<std::ops::Range<usize>> {
+    let mut start = line.find(':')? + 1;
+    for (i, field) in line[start..].split(',').enumerate() {
+        if i == index {
+            let leading = field.len() - field.trim_start().len();
+            let start = start + leading;
+            return Some(start..start + field.trim().len());
+        }
+        start += field.len() + 1;
+    }
+    None
+}
+
+/// Event line prefixes that are valid but not parsed into events.
+const OTHER_EVENT_PREFIXES: [&str; 5] = ["Format:", "Picture:", "Sound:", "Movie:", "Command:"];
+
+/// Canonical spellings of the standard sections.
+const KNOWN_SECTIONS: [&str; 8] = [
+    "Script Info",
+    "V4+ Styles",
+    "V4 Styles",
+    "Events",
+    "Fonts",
+    "Graphics",
+    "Aegisub Project Garbage",
+    "Aegisub Extradata",
+];
+
+/// Canonical spellings of the standard Script Info keys.
+const KNOWN_SCRIPT_INFO_KEYS: [&str; 19] = [
+    "Title",
+    "Original Script",
+    "Original Translation",
+    "Original Editing",
+    "Original Timing",
+    "Synch Point",
+    "Script Updated By",
+    "Update Details",
+    "ScriptType",
+    "Collisions",
+    "PlayResX",
+    "PlayResY",
+    "LayoutResX",
+    "LayoutResY",
+    "PlayDepth",
+    "Timer",
+    "WrapStyle",
+    "ScaledBorderAndShadow",
+    "YCbCr Matrix",
+];
+
+/// Maps a section name to its canonical spelling, matching known sections
+/// case-insensitively the way libass does. Unknown names are returned as written.
+pub fn canonical_section_name(name: &str) -> String {
+    let name = name.trim();
+    KNOWN_SECTIONS
+        .iter()
+        .find(|known| known.eq_ignore_ascii_case(name))
+        .map_or_else(|| name.to_string(), |known| known.to_string())
+}
+
+/// Maps a Script Info key to its canonical spelling, matching case-insensitively.
+pub fn canonical_script_info_key(key: &str) -> String {
+    KNOWN_SCRIPT_INFO_KEYS
+        .iter()
+        .find(|known| known.eq_ignore_ascii_case(key))
+        .map_or_else(|| key.to_string(), |known| known.to_string())
+}
+
+/// Whether `key` is one of the standard Script Info keys, in any case.
+pub fn is_known_script_info_key(key: &str) -> bool {
+    KNOWN_SCRIPT_INFO_KEYS
+        .iter()
+        .any(|known| known.eq_ignore_ascii_case(key))
+}
+
+pub fn is_known_section_header(line: &str) -> bool {
+    parse_section_header(line).is_some_and(|header| KNOWN_SECTIONS.contains(&header.name.as_str()))
+}
+
+/// Captures the name, closing bracket and any trailing text of a header line
+/// separately so malformed headers can still be recognized.
+static SECTION_HEADER_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^\[([^\]]*)(\])?\s*(.*)$").unwrap());
+
+/// A section header line, possibly malformed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionHeader {
+    /// Canonical section name.
+    pub name: String,
+    pub problem: Option<HeaderProblem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeaderProblem {
+    /// Text after the closing bracket.
+    TrailingText(String),
+    MissingBracket,
+}
+
+/// Recognizes a section header on a trimmed line. `[Name] trailing` is accepted
+/// for any name; a missing closing bracket only for known sections, so that
+/// ordinary lines starting with `[` are not mistaken for headers.
+pub fn parse_section_header(line: &str) -> Option<SectionHeader> {
+    let captures = SECTION_HEADER_REGEX.captures(line)?;
+    let raw_name = captures[1].trim();
+    if raw_name.is_empty() {
+        return None;
+    }
+    let name = canonical_section_name(raw_name);
+    let trailing = captures[3].trim();
+
+    let problem = if captures.get(2).is_none() {
+        if !KNOWN_SECTIONS.contains(&name.as_str()) {
+            return None;
+        }
+        Some(HeaderProblem::MissingBracket)
+    } else if !trailing.is_empty() {
+        Some(HeaderProblem::TrailingText(trailing.to_string()))
+    } else {
+        None
+    };
+
+    Some(SectionHeader { name, problem })
+}
+
+/// Strips `prefix` from the start of `line`, ignoring ASCII case.
+pub fn strip_prefix_ignore_case<'a>(line: &'a str, prefix: &str) -> Option<&'a str> {
+    line.get(..prefix.len())
+        .filter(|head| head.eq_ignore_ascii_case(prefix))
+        .map(|_| &line[prefix.len()..])
+}
+
+/// Returns true for the sections Aegisub uses to store its own project state.
+pub fn is_aegisub_section(name: &str) -> bool {
+    name == "Aegisub Project Garbage" || name == "Aegisub Extradata"
+}
+
+/// Parses an extradata line of the form `Data: id,key,value`.
+fn parse_extradata(line: &str, line_num: usize, line_len: usize) -> Option<AegisubEntry> {
+    let mut fields = strip_prefix_ignore_case(line, "Data:")?.splitn(3, ',');
+    let _id = fields.next()?;
+    let key = fields.next()?.trim().to_string();
+    let value = fields.next().unwrap_or("").trim().to_string();
+    Some(AegisubEntry {
+        key,
+        value,
+        range: Range {
+            start: Position::new(line_num as u32, 0),
+            end: Position::new(line_num as u32, line_len as u32),
+        },
+    })
+}
+
+/// Returns true for the sections that hold embedded files.
+pub fn is_attachment_section(name: &str) -> bool {
+    name.eq_ignore_ascii_case("Fonts") || name.eq_ignore_ascii_case("Graphics")
+}
+
+/// The header key an attachment section expects: `fontname` in [Fonts],
+/// `filename` in [Graphics].
+pub fn attachment_header_key(section: &str) -> &'static str {
+    if section.eq_ignore_ascii_case("Fonts") {
+        "fontname"
+    } else {
+        "filename"
+    }
+}
+
+/// Splits a `fontname:` or `filename:` attachment header into its key and
+/// filename. Either key is accepted in either section so a swapped key can
+/// be reported.
+pub fn parse_attachment_header(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.split_once(':')?;
+    let key = key.trim();
+    matches!(key, "fontname" | "filename").then(|| (key, value.trim()))
+}
+
+/// Attachment payloads are UU-encoded using only the characters `!` through `` ` ``,
+/// so any line containing something else (lowercase letters, spaces) is not payload.
+pub fn is_attachment_data(line: &str) -> bool {
+    !line.is_empty() && line.bytes().all(|b| (b'!'..=b'`').contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = "[Script Info]\nTitle: Example\nScriptType: v4.00+\n\n[V4+ Styles]\nFormat: Name, Fontname, Fontsize\nStyle: Default,Arial,48\n\n[Events]\nFormat: Layer, Start, End, Style, Text\nDialogue: 0,0:00:01.00,0:00:02.00,Default,Hello\n";
+
+    #[test]
+    fn format_round_trips_crlf_byte_for_byte() {
+        let crlf = SCRIPT.replace('\n', "\r\n");
+        let formatted = AssParser::new().format(&crlf);
+        assert_eq!(formatted.as_bytes(), crlf.as_bytes());
+    }
+
+    #[test]
+    fn format_keeps_lf_and_missing_trailing_newline() {
+        let parser = AssParser::new();
+        assert_eq!(parser.format(SCRIPT), SCRIPT);
+        let unterminated = SCRIPT.trim_end_matches('\n');
+        assert_eq!(parser.format(unterminated), unterminated);
+        let crlf = unterminated.replace('\n', "\r\n");
+        assert_eq!(parser.format(&crlf), crlf);
+    }
+
+    #[test]
+    fn format_rejoins_with_dominant_line_ending() {
+        let mixed = "[Script Info]\r\nTitle: Example \r\nScriptType: v4.00+\n";
+        assert_eq!(detect_line_ending(mixed), "\r\n");
+        assert_eq!(
+            AssParser::new().format(mixed),
+            "[Script Info]\r\nTitle: Example\r\nScriptType: v4.00+\r\n"
+        );
+        assert_eq!(detect_line_ending(""), "\n");
+    }
+
+    #[test]
+    fn time_arithmetic_is_checked_near_u32_limits() {
+        let max: AssTime = "11930:27:52.95".parse().unwrap();
+        assert_eq!(max, AssTime(u32::MAX));
+        assert_eq!(max.to_string(), "11930:27:52.95");
+        assert!("11930:27:52.96".parse::<AssTime>().is_err());
+        assert!("4294967295:00:00.00".parse::<AssTime>().is_err());
+        assert_eq!(max.as_millis(), u64::from(u32::MAX) * 10);
+        assert_eq!(AssTime(0).checked_sub(AssTime(1)), None);
+        assert_eq!(AssTime(0).saturating_sub(max), AssTime(0));
+    }
+
+    #[test]
+    fn two_digit_hours_survive_parsing_and_formatting() {
+        let time: AssTime = "12:00:00.00".parse().unwrap();
+        assert_eq!(time.to_string(), "12:00:00.00");
+        assert_eq!(
+            AssTime::parse_lenient("99:59:59.99"),
+            Some(AssTime(35999999))
+        );
+
+        let stream = SCRIPT.replace("0:00:01.00,0:00:02.00", "10:59:58.50,11:00:01.00");
+        let parser = AssParser::new();
+        assert_eq!(parser.format(&stream), stream);
+        let document = parser.parse(&stream);
+        let event = &document.events[0];
+        assert_eq!(event.start.unwrap().to_string(), "10:59:58.50");
+        assert_eq!(event.end.unwrap().to_string(), "11:00:01.00");
+    }
+
+    #[test]
+    fn contrast_ratio_follows_wcag() {
+        let white: AssColor = "&H00FFFFFF".parse().unwrap();
+        let black: AssColor = "&H00000000".parse().unwrap();
+        assert!((white.contrast_ratio(black) - 21.0).abs() < 1e-9);
+        assert!((black.contrast_ratio(white) - 21.0).abs() < 1e-9);
+        assert_eq!(white.contrast_ratio(white), 1.0);
+        let translucent_white: AssColor = "&H80FFFFFF".parse().unwrap();
+        assert_eq!(translucent_white.luminance(), white.luminance());
+    }
+
+    fn contains(outer: Range, inner: Range) -> bool {
+        let pos = |p: Position| (p.line, p.character);
+        pos(outer.start) <= pos(inner.start) && pos(inner.end) <= pos(outer.end)
+    }
+
+    #[test]
+    fn section_ranges_do_not_overlap_and_contain_their_selection() {
+        let text = format!(
+            "{}\n\n[Fonts]\nfontname: a_0.ttf\n!!!!\n\n\n",
+            SCRIPT.replace("\n\n", "\n\n\n")
+        );
+        let parser = AssParser::new();
+        let document = parser.parse(&text);
+        let symbols = parser.extract_symbols(&document);
+        assert_eq!(symbols.len(), 4);
+
+        let lines: Vec<&str> = text.lines().collect();
+        for pair in symbols.windows(2) {
+            assert!(pair[0].range.end.line < pair[1].range.start.line);
+        }
+        for symbol in &symbols {
+            assert!(contains(symbol.range, symbol.selection_range));
+            assert_eq!(symbol.selection_range.start.line, symbol.range.start.line);
+            let last = lines[symbol.range.end.line as usize];
+            assert!(
+                !last.trim().is_empty(),
+                "{} ends on a blank line",
+                symbol.name
+            );
+            assert_eq!(symbol.range.end.character as usize, last.len());
+            for child in symbol.children.iter().flatten() {
+                assert!(contains(symbol.range, child.range), "{}", child.name);
+                assert!(
+                    contains(child.range, child.selection_range),
+                    "{}",
+                    child.name
+                );
+            }
+        }
+        assert_eq!(
+            symbols[0].selection_range.end.character,
+            "[Script Info]".len() as u32
+        );
+    }
+
+    fn generated_script(events: usize) -> String {
+        let mut text = SCRIPT.replace(
+            "Style: Default,Arial,48\n",
+            "Style: Default,Arial,48\nStyle: Sign,Arial,60\n",
+        );
+        text.push_str("Comment: 0,0:00:00.00,0:00:00.00,Default,Generated\n");
+        for i in 0..events {
+            let start = AssTime(i as u32 * 150);
+            let end = AssTime(i as u32 * 150 + 200);
+            let style = if i % 7 == 0 { "Sign" } else { "Default" };
+            text.push_str(&format!(
+                "Dialogue: 0,{start},{end},{style},Line {i} {{\\i1}}text\n"
+            ));
+        }
+        text.push_str("\n[Fonts]\nfontname: a_0.ttf\n!!!!\n");
+        text
+    }
+
+    /// `text` with `lines` starting at line `at` replaced by `with`.
+    fn splice_lines(text: &str, at: usize, lines: usize, with: &[&str]) -> String {
+        let mut all: Vec<&str> = text.lines().collect();
+        all.splice(at..at + lines, with.iter().copied());
+        all.join("\n") + "\n"
+    }
+
+    #[test]
+    fn incremental_reparse_matches_full_parse() {
+        let parser = AssParser::new();
+        let text = generated_script(5_000);
+        let events_at = text.lines().position(|line| line == "[Events]").unwrap();
+        let last = text.lines().count();
+        let edits: Vec<(usize, usize, Vec<&str>)> = vec![
+            // Retime one event
+            (
+                events_at + 500,
+                1,
+                vec!["Dialogue: 0,0:01:00.00,0:01:02.00,Default,Retimed"],
+            ),
+            // Insert and delete events
+            (
+                events_at + 10,
+                0,
+                vec![
+                    "Dialogue: 0,0:00:00.50,0:00:01.00,Default,New",
+                    "Comment: 0,0:00:00.50,0:00:01.00,Default,New",
+                ],
+            ),
+            (events_at + 2_000, 3, vec![]),
+            // Break an event and a style
+            (events_at + 4_000, 1, vec!["Dialogue: 0,broken"]),
+            (6, 1, vec!["Style: Default,Arial"]),
+            // Rename a header, then add a section in the middle
+            (events_at, 1, vec!["[Event]"]),
+            (events_at - 1, 0, vec!["", "[Custom Notes]", "note: kept"]),
+            // Edit the script info and the attachment at either end
+            (1, 1, vec!["Title: Renamed"]),
+            (last - 1, 1, vec!["fontname: b_0.ttf"]),
+        ];
+
+        for (at, lines, with) in edits {
+            let edited = splice_lines(&text, at, lines, &with);
+            let full = parser.parse(&edited);
+            let previous = parser.parse(&text);
+            let (incremental, span) = parser.reparse(previous, &text, &edited);
+            assert!(
+                incremental == full,
+                "reparse at line {at} differs from a full parse"
+            );
+            assert!(span.new.start <= at && at <= span.new.end);
+        }
+    }
+
+    #[test]
+    fn banners_stay_with_their_sections_across_formatting() {
+        let text = include_str!("../tests/fixtures/banners.ass");
+        let parser = AssParser::new();
+        let once = parser.format(text);
+        assert_ne!(once, text);
+        assert_eq!(parser.format(&once), once);
+
+        // The blank line goes above a banner, never between it and its header
+        let lines: Vec<&str> = once.lines().collect();
+        for (i, line) in lines.iter().enumerate().filter(|(_, l)| l.starts_with('[')) {
+            let banner = lines[..i]
+                .iter()
+                .rev()
+                .take_while(|l| l.starts_with(';'))
+                .count();
+            if i > banner {
+                assert_eq!(lines[i - banner - 1], "", "no blank line above {line}");
+            }
+        }
+        assert!(
+            once.starts_with(";==================== HEADER ====================\n[Script Info]")
+        );
+
+        let document = parser.parse(&once);
+        let details: Vec<(String, Option<String>)> = parser
+            .extract_symbols(&document)
+            .into_iter()
+            .map(|symbol| (symbol.name, symbol.detail))
+            .collect();
+        let expected = [
+            ("Script Info", "HEADER"),
+            ("V4+ Styles", "STYLES"),
+            ("Events", "DIALOGUE"),
+            // Banners may stand apart from their header
+            ("Fonts", "SIGNS"),
+        ]
+        .map(|(name, banner)| (name.to_string(), Some(banner.to_string())));
+        assert_eq!(details, expected);
+        let counts = AssParser {
+            banner_details: false,
+        }
+        .extract_symbols(&document);
+        assert_eq!(counts[1].detail.as_deref(), Some("1 items"));
+    }
+
+    #[test]
+    fn lowercase_headers_and_prefixes_are_recognised() {
+        let text = include_str!("../tests/fixtures/lowercase.ass");
+        let document = AssParser::new().parse(text);
+        let names: Vec<&str> = document.sections.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["Script Info", "V4+ Styles", "Events"]);
+        assert!(document.sections.iter().all(|s| s.header_problem.is_none()));
+        assert_eq!(document.script_info["ScriptType"], "v4.00+");
+        assert_eq!(document.script_info["PlayResX"], "1920");
+        let styles: Vec<&str> = document.styles.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(styles, ["Default", "Sign"]);
+        assert_eq!(document.styles[1].fontsize, 60);
+        let events: Vec<(&str, &str)> = document
+            .events
+            .iter()
+            .map(|e| (e.event_type.as_str(), e.style.as_str()))
+            .collect();
+        assert_eq!(events, [("Dialogue", "Default"), ("Comment", "Sign")]);
+        assert!(document.parse_errors.is_empty());
+    }
+
+    #[test]
+    fn attachments_are_listed_under_their_section() {
+        let text = format!(
+            "{SCRIPT}\n[Fonts]\nfontname: title_0.ttf\n!!!!\n!!!!\nfontname: body_0.ttf\n!!!!\n\n[Graphics]\nfilename: logo.png\n!!!!\n"
+        );
+        let parser = AssParser::new();
+        let symbols = parser.extract_symbols(&parser.parse(&text));
+        let attachments = |section: &str| -> Vec<(String, Option<String>, SymbolKind, u32)> {
+            let section = symbols
+                .iter()
+                .find(|symbol| symbol.name == section)
+                .unwrap();
+            section
+                .children
+                .iter()
+                .flatten()
+                .map(|child| {
+                    (
+                        child.name.clone(),
+                        child.detail.clone(),
+                        child.kind,
+                        child.range.start.line,
+                    )
+                })
+                .collect()
+        };
+        let start = SCRIPT.lines().count() as u32 + 1;
+        assert_eq!(
+            attachments("Fonts"),
+            [
+                ("title_0.ttf", "2 lines", start + 1),
+                ("body_0.ttf", "1 lines", start + 4),
+            ]
+            .map(|(name, detail, line)| (
+                name.to_string(),
+                Some(detail.to_string()),
+                SymbolKind::FILE,
+                line
+            ))
+        );
+        assert_eq!(
+            attachments("Graphics"),
+            [(
+                "logo.png".to_string(),
+                Some("1 lines".to_string()),
+                SymbolKind::FILE,
+                start + 8
+            )]
+        );
+    }
+
+    #[test]
+    fn aegisub_sections_are_read_apart_and_kept_verbatim() {
+        let garbage = "[Aegisub Project Garbage]\nAudio File:   ../ep01.mkv\nScroll Position: 12\n\n[Aegisub Extradata]\nData: 1,_aegi_perspective_ambient_plane,e#0;0;1;0;1;1;0;1\n  Data: 2,note,  keep  me\n";
+        let text = format!("{SCRIPT}\n{garbage}");
+        let parser = AssParser::new();
+        let document = parser.parse(&text);
+
+        let entries = |entries: &[AegisubEntry]| -> Vec<(String, String)> {
+            entries
+                .iter()
+                .map(|entry| (entry.key.clone(), entry.value.clone()))
+                .collect()
+        };
+        let pairs = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            entries(&document.aegisub_project),
+            pairs(&[("Audio File", "../ep01.mkv"), ("Scroll Position", "12")])
+        );
+        assert_eq!(
+            entries(&document.aegisub_extradata),
+            pairs(&[
+                ("_aegi_perspective_ambient_plane", "e#0;0;1;0;1;1;0;1"),
+                ("note", "keep  me")
+            ])
+        );
+        // Project state isn't mistaken for Script Info
+        assert!(!document.script_info.contains_key("Audio File"));
+        // Nor read as broken lines of some other section
+        let first = SCRIPT.lines().count() as u32;
+        assert!(document.parse_errors.iter().all(|issue| issue.line < first));
+
+        let formatted = parser.format(&text);
+        assert!(formatted.ends_with(garbage), "{formatted}");
+    }
+
+    #[test]
+    fn colours_parse_in_every_notation() {
+        let orange = AssColor {
+            r: 0xFF,
+            g: 0x80,
+            b: 0x00,
+            a: 0x00,
+        };
+        for written in ["&H000080FF", "&H0080FF&", "&h80ff", "33023"] {
+            assert_eq!(written.parse(), Ok(orange), "{written}");
+        }
+        // SSA writes colours with the high bit set as negative numbers
+        assert_eq!("-1".parse(), Ok(AssColor::from_packed(u32::MAX)));
+        assert_eq!(orange.to_string(), "&H000080FF");
+        assert_eq!(AssColor::from_packed(orange.packed()), orange);
+        assert!("&HFF000000".parse::<AssColor>().unwrap().is_transparent());
+
+        assert_eq!("".parse::<AssColor>(), Err(ColorError::Empty));
+        assert_eq!("&H&".parse::<AssColor>(), Err(ColorError::Empty));
+        assert_eq!(
+            "&H00GG00ZZ".parse::<AssColor>(),
+            Err(ColorError::InvalidCharacters("GZ".to_string()))
+        );
+        assert_eq!(
+            "&H0000000000".parse::<AssColor>(),
+            Err(ColorError::TooManyDigits)
+        );
+        assert_eq!("white".parse::<AssColor>(), Err(ColorError::InvalidNumber));
+
+        let text = "[V4+ Styles]\nFormat: Name, PrimaryColour, SecondaryColour, OutlineColour, BackColour\nStyle: Sign,&H000080FF,&H0080FF&,oops,&H80000000\n";
+        let style = &AssParser::new().parse(text).styles[0];
+        assert_eq!(
+            [style.primary, style.secondary, style.outline, style.back],
+            [
+                Some(orange),
+                Some(orange),
+                None,
+                Some(AssColor {
+                    a: 0x80,
+                    ..AssColor::default()
+                })
+            ]
+        );
+    }
+}
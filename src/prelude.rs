@@ -0,0 +1,14 @@
+//! Everything needed to parse, check and convert a script, for a glob import.
+//!
+//! Positions, ranges and diagnostics are the lsp-types 0.94 types the server
+//! speaks. Columns in ranges taken from a parsed document or the validator
+//! count bytes of the line.
+
+pub use crate::export::{srt_timestamp, to_srt};
+pub use crate::parser::{AssColor, AssDocument, AssParser, AssTime, ColorError, Event, Style};
+pub use crate::render::RenderTarget;
+pub use crate::settings::RuleLevel;
+pub use crate::validation::{
+    DiagnosticCode, UnknownDiagnosticCode, ValidationOptions, ValidationProvider,
+};
+pub use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, Position, Range, Url};
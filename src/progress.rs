@@ -0,0 +1,190 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tower_lsp::lsp_types::*;
+use tower_lsp::Client;
+
+/// How long an analysis runs before its progress is shown.
+pub const DELAY: Duration = Duration::from_millis(500);
+
+/// Documents at least this large, in bytes, show the progress of their
+/// analysis from the start.
+pub const SIZE_THRESHOLD: usize = 1024 * 1024;
+
+/// How often the status is checked for changes to report. Polled rather
+/// than woken, as the work it follows may keep the thread that would wake
+/// it busy.
+const REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A step of a document analysis, with the share of the whole percentage it
+/// takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Parsing,
+    Validating,
+    AdvancedChecks,
+    Overlaps,
+}
+
+impl Phase {
+    fn message(self) -> &'static str {
+        match self {
+            Phase::Parsing => "Parsing",
+            Phase::Validating => "Validating",
+            Phase::AdvancedChecks => "Running advanced checks",
+            Phase::Overlaps => "Checking overlaps",
+        }
+    }
+
+    fn percentages(self) -> (u32, u32) {
+        match self {
+            Phase::Parsing => (0, 10),
+            Phase::Validating => (10, 60),
+            Phase::AdvancedChecks => (60, 70),
+            Phase::Overlaps => (70, 100),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Status {
+    message: Option<String>,
+    percentage: Option<u32>,
+}
+
+/// Where a long piece of work has got, reported from synchronous code on
+/// any thread. A task forwards the latest status to the client as
+/// `$/progress`, and ends the progress once every clone is dropped, so work
+/// that stops early, such as an analysis superseded by a newer change,
+/// still closes it.
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    status: Option<Arc<watch::Sender<Status>>>,
+}
+
+impl Progress {
+    /// Progress that reports nowhere.
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// Progress titled `title`, shown once the work has run for `delay` and
+    /// never if it finishes first. `token` is the one the client sent with
+    /// its request; without one, a token is created when `can_create`, the
+    /// client's `window.workDoneProgress` capability, allows it.
+    pub fn start(
+        client: &Client,
+        title: String,
+        token: Option<NumberOrString>,
+        can_create: bool,
+        delay: Duration,
+    ) -> Self {
+        if token.is_none() && !can_create {
+            return Self::none();
+        }
+        let (sender, receiver) = watch::channel(Status::default());
+        tokio::spawn(forward(client.clone(), title, token, delay, receiver));
+        Self {
+            status: Some(Arc::new(sender)),
+        }
+    }
+
+    pub fn report(&self, message: &str, percentage: Option<u32>) {
+        let Some(status) = &self.status else {
+            return;
+        };
+        status.send_if_modified(|status| {
+            if status.message.as_deref() == Some(message) && status.percentage == percentage {
+                return false;
+            }
+            status.message = Some(message.to_string());
+            status.percentage = percentage;
+            true
+        });
+    }
+
+    /// Reports `done` of the `total` lines or events of an analysis phase.
+    pub fn phase(&self, phase: Phase, done: usize, total: usize) {
+        let (start, end) = phase.percentages();
+        let through = (done.min(total) as u64 * u64::from(end - start))
+            .checked_div(total as u64)
+            .unwrap_or(0);
+        self.report(phase.message(), Some(start + through as u32));
+    }
+
+    /// [`phase`](Self::phase), then yields so the status gets forwarded even
+    /// while the analysis keeps this thread busy.
+    pub async fn step(&self, phase: Phase, done: usize, total: usize) {
+        self.phase(phase, done, total);
+        if self.status.is_some() {
+            tokio::task::yield_now().await;
+        }
+    }
+}
+
+async fn forward(
+    client: Client,
+    title: String,
+    token: Option<NumberOrString>,
+    delay: Duration,
+    mut status: watch::Receiver<Status>,
+) {
+    tokio::time::sleep(delay).await;
+    if status.has_changed().is_err() {
+        return;
+    }
+
+    let token = match token {
+        Some(token) => token,
+        None => {
+            static NEXT: AtomicU64 = AtomicU64::new(0);
+            let token = NumberOrString::String(format!(
+                "ass-lsp-progress-{}",
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            ));
+            let params = WorkDoneProgressCreateParams {
+                token: token.clone(),
+            };
+            if client
+                .send_request::<request::WorkDoneProgressCreate>(params)
+                .await
+                .is_err()
+            {
+                return;
+            }
+            token
+        }
+    };
+    let send = |value| {
+        client.send_notification::<notification::Progress>(ProgressParams {
+            token: token.clone(),
+            value: ProgressParamsValue::WorkDone(value),
+        })
+    };
+
+    let current = status.borrow_and_update().clone();
+    send(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+        title,
+        cancellable: Some(false),
+        message: current.message,
+        percentage: current.percentage,
+    }))
+    .await;
+    loop {
+        tokio::time::sleep(REPORT_INTERVAL).await;
+        match status.has_changed() {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(_) => break,
+        }
+        let current = status.borrow_and_update().clone();
+        send(WorkDoneProgress::Report(WorkDoneProgressReport {
+            cancellable: Some(false),
+            message: current.message,
+            percentage: current.percentage,
+        }))
+        .await;
+    }
+    send(WorkDoneProgress::End(WorkDoneProgressEnd { message: None })).await;
+}
@@ -0,0 +1,272 @@
+use crate::line_index::LineIndex;
+use crate::parser::{AssDocument, Event};
+use crate::text::{row_length, visible_rows};
+use std::collections::HashMap;
+use tower_lsp::lsp_types::*;
+
+/// Command that balances every two-row line in a document. Takes the
+/// document's URI.
+pub const BALANCE_LINE_BREAKS: &str = "ass.balanceLineBreaks";
+
+/// Moves a `\N` break so the two rows of a line come out about as long as
+/// each other.
+#[derive(Debug, Clone)]
+pub struct LineBalancer {
+    /// Lines whose shorter row is at least this share of the longer one are
+    /// left as they are.
+    pub min_ratio: f64,
+    /// Row prefixes that mark a change of speaker; a break before one is
+    /// never moved.
+    pub dashes: Vec<String>,
+}
+
+impl LineBalancer {
+    pub fn new() -> Self {
+        Self {
+            min_ratio: 0.6,
+            dashes: vec!["-".to_string(), "–".to_string(), "—".to_string()],
+        }
+    }
+
+    /// `text` with its one `\N` moved to the space that best evens out the
+    /// rows, or `None` if the rows are balanced enough, no break does
+    /// better, or the line isn't one to reflow: more than two rows, a
+    /// second speaker after the break, or CJK text, which isn't broken at
+    /// spaces.
+    pub fn balance(&self, text: &str) -> Option<String> {
+        let [hard_break] = hard_breaks(text)[..] else {
+            return None;
+        };
+        let (top, bottom) = (&text[..hard_break], &text[hard_break + 2..]);
+        let top_row = visible_row(top);
+        let bottom_row = visible_row(bottom);
+        if self.starts_with_dash(&bottom_row)
+            || top_row.chars().chain(bottom_row.chars()).any(is_cjk)
+        {
+            return None;
+        }
+        let (top_length, bottom_length) = (row_length(&top_row), row_length(&bottom_row));
+        let longer = top_length.max(bottom_length);
+        if longer == 0 || top_length.min(bottom_length) as f64 / longer as f64 >= self.min_ratio {
+            return None;
+        }
+
+        let joined = format!("{} {}", top.trim_end(), bottom.trim_start());
+        let (space, difference) = break_candidates(&joined)
+            .into_iter()
+            .filter_map(|space| {
+                let top = visible_row(&joined[..space]);
+                let bottom = visible_row(&joined[space + 1..]);
+                // A dash alone on the top row, or one starting the bottom
+                // row, would read as a speaker change
+                if top.trim().is_empty()
+                    || self.dashes.iter().any(|dash| top.trim() == dash)
+                    || self.starts_with_dash(&bottom)
+                {
+                    return None;
+                }
+                // Between two equally even breaks, the shorter top row wins
+                let difference = row_length(&bottom) as i64 - row_length(&top) as i64;
+                Some((space, (difference.abs(), -difference.signum())))
+            })
+            .min_by_key(|&(_, difference)| difference)?;
+        if difference.0 >= (top_length as i64 - bottom_length as i64).abs() {
+            return None;
+        }
+        Some(format!("{}\\N{}", &joined[..space], &joined[space + 1..]))
+    }
+
+    /// Edits balancing the events on `lines`, as (event, new text) pairs.
+    pub fn balanced_events<'a>(
+        &self,
+        document: &'a AssDocument,
+        lines: std::ops::RangeInclusive<u32>,
+    ) -> Vec<(&'a Event, String)> {
+        document
+            .events
+            .iter()
+            .filter(|event| event.event_type == "Dialogue")
+            .filter(|event| lines.contains(&event.range.start.line))
+            .filter_map(|event| Some((event, self.balance(&event.text)?)))
+            .collect()
+    }
+
+    fn starts_with_dash(&self, row: &str) -> bool {
+        let row = row.trim_start();
+        self.dashes
+            .iter()
+            .any(|dash| row.starts_with(dash.as_str()))
+    }
+}
+
+impl Default for LineBalancer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A workspace edit replacing the text of each event.
+pub fn balance_edit(uri: &Url, index: &LineIndex, balanced: &[(&Event, String)]) -> WorkspaceEdit {
+    let edits = balanced
+        .iter()
+        .map(|(event, text)| TextEdit {
+            range: index.range(Range {
+                start: Position::new(event.range.start.line, event.text_start),
+                end: event.range.end,
+            }),
+            new_text: text.clone(),
+        })
+        .collect();
+    WorkspaceEdit {
+        changes: Some(HashMap::from([(uri.clone(), edits)])),
+        ..Default::default()
+    }
+}
+
+/// Byte offsets of the `\N` breaks outside override blocks.
+fn hard_breaks(text: &str) -> Vec<usize> {
+    let mut breaks = Vec::new();
+    let mut in_override = false;
+    let mut chars = text.char_indices();
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '{' if !in_override => in_override = true,
+            '}' if in_override => in_override = false,
+            _ if in_override => {}
+            '\\' => {
+                if let Some((_, escaped)) = chars.next() {
+                    if escaped == 'N' {
+                        breaks.push(i);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    breaks
+}
+
+/// Byte offsets of the spaces a line can break at, outside override blocks.
+fn break_candidates(text: &str) -> Vec<usize> {
+    let mut candidates = Vec::new();
+    let mut in_override = false;
+    for (i, ch) in text.char_indices() {
+        match ch {
+            '{' if !in_override => in_override = true,
+            '}' if in_override => in_override = false,
+            ' ' if !in_override => candidates.push(i),
+            _ => {}
+        }
+    }
+    candidates
+}
+
+/// The visible text of a part of an event with no `\N` in it.
+fn visible_row(text: &str) -> String {
+    visible_rows(text, 0).concat()
+}
+
+/// Chinese and Japanese characters, whose text has no spaces between
+/// words.
+fn is_cjk(ch: char) -> bool {
+    matches!(ch,
+        '\u{3000}'..='\u{30FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FF00}'..='\u{FFEF}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_index::PositionEncoding;
+    use crate::parser::AssParser;
+
+    #[test]
+    fn latin_line_moves_its_break_to_the_middle() {
+        let balancer = LineBalancer::new();
+        assert_eq!(
+            balancer
+                .balance("I never thought we would get this far together\\Nand yet")
+                .as_deref(),
+            Some("I never thought we would get\\Nthis far together and yet")
+        );
+        // Override blocks move with their words and are never broken into
+        assert_eq!(
+            balancer
+                .balance("{\\i1}I never thought we would get {\\b1 }this far\\Nand yet")
+                .as_deref(),
+            Some("{\\i1}I never thought we would\\Nget {\\b1 }this far and yet")
+        );
+    }
+
+    #[test]
+    fn balanced_enough_rows_are_left_alone() {
+        let balancer = LineBalancer::new();
+        assert_eq!(
+            balancer.balance("We should go now\\Nbefore it gets dark"),
+            None
+        );
+        assert_eq!(balancer.balance("No break at all"), None);
+        assert_eq!(balancer.balance("Three\\Nrows\\Nhere"), None);
+
+        let strict = LineBalancer {
+            min_ratio: 0.95,
+            ..LineBalancer::new()
+        };
+        assert_eq!(
+            strict.balance("We will go\\Nto the old house").as_deref(),
+            Some("We will go to\\Nthe old house")
+        );
+    }
+
+    #[test]
+    fn dashed_lines_keep_their_speakers() {
+        let balancer = LineBalancer::new();
+        // Two speakers: the break separates them
+        assert_eq!(
+            balancer.balance("- Did you lock the door before we left the house?\\N- Yes."),
+            None
+        );
+        // One speaker whose line starts with a dash keeps it at the start
+        assert_eq!(
+            balancer
+                .balance("- Did you lock the door before we left the house\\Nthis morning?")
+                .as_deref(),
+            Some("- Did you lock the door before\\Nwe left the house this morning?")
+        );
+    }
+
+    #[test]
+    fn cjk_line_is_left_alone() {
+        assert_eq!(
+            LineBalancer::new().balance("今日はとてもいい天気ですね、散歩に行きましょう\\Nはい"),
+            None
+        );
+    }
+
+    #[test]
+    fn edits_replace_only_the_text_of_balanced_dialogue() {
+        let text = "[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+                    Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,I never thought we would get this far together\\Nand yet\n\
+                    Comment: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,I never thought we would get this far together\\Nand yet\n\
+                    Dialogue: 0,0:00:03.00,0:00:04.00,Default,,0,0,0,,Fine\\Nas is\n";
+        let document = AssParser::new().parse(text);
+        let balancer = LineBalancer::new();
+        let balanced = balancer.balanced_events(&document, 0..=10);
+        assert_eq!(balanced.len(), 1);
+        assert!(balancer.balanced_events(&document, 3..=4).is_empty());
+
+        let uri = Url::parse("file:///tmp/balance.ass").unwrap();
+        let index = LineIndex::new(text.to_string(), PositionEncoding::Utf16);
+        let edit = balance_edit(&uri, &index, &balanced);
+        let edits = &edit.changes.unwrap()[&uri];
+        let fixed = crate::line_index::apply_edits(text, PositionEncoding::Utf16, edits);
+        assert_eq!(
+            fixed.lines().nth(2),
+            Some("Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,I never thought we would get\\Nthis far together and yet")
+        );
+        assert_eq!(fixed.lines().nth(3), text.lines().nth(3));
+    }
+}
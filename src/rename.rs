@@ -0,0 +1,373 @@
+use crate::definition::style_references;
+use crate::line_index::LineIndex;
+use crate::parser::{field_range, AssDocument, Event};
+use crate::text::{tokenize, TextToken};
+use std::collections::HashMap;
+use tower_lsp::lsp_types::*;
+
+/// Index of the Style field on Dialogue and Comment lines.
+const EVENT_STYLE_FIELD: usize = 3;
+
+/// Edits that point every reference to style `old`, both event Style fields
+/// and `\r` tags, at `new`.
+pub fn style_reference_edits(
+    document: &AssDocument,
+    lines: &[&str],
+    old: &str,
+    new: &str,
+) -> Vec<TextEdit> {
+    let mut edits = Vec::new();
+
+    for event in &document.events {
+        let line_idx = event.range.start.line;
+        let Some(line) = lines.get(line_idx as usize) else {
+            continue;
+        };
+        let indent = line.len() - line.trim_start().len();
+
+        if event.style == old {
+            if let Some(span) = field_range(line.trim_start(), EVENT_STYLE_FIELD) {
+                edits.push(TextEdit {
+                    range: Range {
+                        start: Position::new(line_idx, (indent + span.start) as u32),
+                        end: Position::new(line_idx, (indent + span.end) as u32),
+                    },
+                    new_text: new.to_string(),
+                });
+            }
+        }
+
+        for (start, end) in reset_references(event, old) {
+            edits.push(TextEdit {
+                range: Range {
+                    start: Position::new(line_idx, (indent + start) as u32),
+                    end: Position::new(line_idx, (indent + end) as u32),
+                },
+                new_text: new.to_string(),
+            });
+        }
+    }
+
+    edits
+}
+
+/// Returns true if any event uses style `name` directly or through `\r`.
+pub fn references_style(document: &AssDocument, name: &str) -> bool {
+    document
+        .events
+        .iter()
+        .any(|event| event.style == name || !reset_references(event, name).is_empty())
+}
+
+/// Column ranges on the event line of the style names in `\r<name>` tags.
+fn reset_references(event: &Event, name: &str) -> Vec<(usize, usize)> {
+    tokenize(&event.text)
+        .into_iter()
+        .filter_map(|token| match token {
+            TextToken::Tag { tag, span } => {
+                let target = tag.strip_prefix('r')?;
+                let leading = target.len() - target.trim_start().len();
+                (target.trim() == name).then(|| {
+                    // Skip the backslash and the `r`
+                    let start = event.text_start as usize + span.start + 2 + leading;
+                    (start, start + name.len())
+                })
+            }
+            TextToken::Text { .. } => None,
+        })
+        .collect()
+}
+
+/// Builds the edit that merges `duplicates` into the style `target`: their
+/// references are renamed to `target` and their Style lines deleted.
+pub fn merge_styles_edit(
+    uri: &Url,
+    index: &LineIndex,
+    document: &AssDocument,
+    target: &str,
+    duplicates: &[String],
+) -> WorkspaceEdit {
+    let lines = index.lines();
+    let mut edits = Vec::new();
+
+    for duplicate in duplicates {
+        edits.extend(style_reference_edits(document, &lines, duplicate, target));
+        for style in document
+            .styles
+            .iter()
+            .filter(|style| &style.name == duplicate)
+        {
+            let line = style.range.start.line;
+            edits.push(TextEdit {
+                range: Range {
+                    start: Position::new(line, 0),
+                    end: Position::new(line + 1, 0),
+                },
+                new_text: String::new(),
+            });
+        }
+    }
+    for edit in &mut edits {
+        edit.range = index.range(edit.range);
+    }
+
+    WorkspaceEdit {
+        changes: Some(HashMap::from([(uri.clone(), edits)])),
+        ..Default::default()
+    }
+}
+
+/// Why `name` can't be a style's name: commas would split the Style and
+/// event lines into extra fields, and braces or backslashes would end a `\r`
+/// tag early. Surrounding spaces are trimmed when the script is read.
+pub fn style_name_problem(name: &str) -> Option<&'static str> {
+    if name.trim().is_empty() {
+        Some("Style names can't be empty")
+    } else if name.contains(',') {
+        Some("Style names can't contain commas, which separate the fields of Style and event lines")
+    } else if name.contains(['{', '}', '\\']) {
+        Some("Style names can't contain braces or backslashes, which would break \\r tags")
+    } else if name.trim() != name {
+        Some(
+            "Style names can't start or end with spaces, which are dropped when the script is read",
+        )
+    } else {
+        None
+    }
+}
+
+/// Renames the style under `position` on its `Style:` line and wherever an
+/// event uses it, in its Style field or a `\r` tag. Dialogue text is left
+/// alone.
+pub fn style_rename_edit(
+    uri: &Url,
+    index: &LineIndex,
+    document: &AssDocument,
+    position: Position,
+    new_name: &str,
+) -> Option<WorkspaceEdit> {
+    let edits = style_references(uri, document, index, position, true)?
+        .into_iter()
+        .map(|location| TextEdit {
+            range: location.range,
+            new_text: new_name.to_string(),
+        })
+        .collect();
+    Some(WorkspaceEdit {
+        changes: Some(HashMap::from([(uri.clone(), edits)])),
+        ..Default::default()
+    })
+}
+
+/// The event whose Name field is under `position`, if it names an actor.
+fn actor_at<'a>(
+    document: &'a AssDocument,
+    index: &LineIndex,
+    position: Position,
+) -> Option<&'a Event> {
+    let (line_idx, char_idx) = index.clamp(position);
+    document.event_at(line_idx as u32).filter(|event| {
+        !event.actor.is_empty()
+            && (event.actor_span.start..=event.actor_span.end).contains(&char_idx)
+    })
+}
+
+/// The client range of the actor name under `position`.
+pub fn actor_range(document: &AssDocument, index: &LineIndex, position: Position) -> Option<Range> {
+    let event = actor_at(document, index, position)?;
+    let line = event.range.start.line;
+    Some(index.range(Range::new(
+        Position::new(line, event.actor_span.start as u32),
+        Position::new(line, event.actor_span.end as u32),
+    )))
+}
+
+/// Why `name` can't be an actor's name: like style names, commas would split
+/// event lines into extra fields. An empty name clears the field.
+pub fn actor_name_problem(name: &str) -> Option<&'static str> {
+    name.contains(',')
+        .then_some("Actor names can't contain commas, which separate the fields of event lines")
+}
+
+/// Renames the actor under `position` on every event with exactly that
+/// Name, as actors are free text rather than references to a definition.
+pub fn actor_rename_edit(
+    uri: &Url,
+    index: &LineIndex,
+    document: &AssDocument,
+    position: Position,
+    new_name: &str,
+) -> Option<WorkspaceEdit> {
+    let actor = &actor_at(document, index, position)?.actor;
+    let edits = document
+        .events
+        .iter()
+        .filter(|event| &event.actor == actor)
+        .map(|event| {
+            let line = event.range.start.line;
+            TextEdit {
+                range: index.range(Range::new(
+                    Position::new(line, event.actor_span.start as u32),
+                    Position::new(line, event.actor_span.end as u32),
+                )),
+                new_text: new_name.to_string(),
+            }
+        })
+        .collect();
+    Some(WorkspaceEdit {
+        changes: Some(HashMap::from([(uri.clone(), edits)])),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_index::{apply_edits, PositionEncoding};
+    use crate::parser::AssParser;
+    use crate::validation::ValidationProvider;
+
+    const SIGN: &str = "Arial,60,&H0000FFFF,&H000000FF,&H00000000,&H00000000,-1,0,0,0,100,100,0,0,1,3,0,8,10,10,10,1";
+
+    fn codes(diagnostics: &[Diagnostic], code: &str) -> Vec<Diagnostic> {
+        diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String(code.to_string())))
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn merging_equivalent_sign_styles_leaves_no_undefined_references() {
+        let text = format!(
+            "[Script Info]\nScriptType: v4.00+\n\n[V4+ Styles]\nFormat: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\nStyle: Sign,{SIGN}\nStyle: Sign Copy,{SIGN}\nStyle: Sign Top,{SIGN}\n\n[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\nDialogue: 0,0:00:01.00,0:00:03.00,Sign,,0,0,0,,Shop\nDialogue: 0,0:00:03.00,0:00:05.00,Sign Copy,,0,0,0,,Bakery\nDialogue: 0,0:00:05.00,0:00:07.00,Sign Top,,0,0,0,,Station {{\\rSign Copy}}exit\n"
+        );
+        let uri = Url::parse("file:///tmp/signs.ass").unwrap();
+        let parser = AssParser::new();
+        let mut validation = ValidationProvider::new();
+        validation.options.check_equivalent_styles = true;
+
+        let document = parser.parse(&text);
+        let equivalent = codes(&validation.validate(&document, &uri), "equivalent_style");
+        assert_eq!(equivalent.len(), 2);
+        let data = equivalent[0].data.as_ref().unwrap();
+        assert_eq!(data["mergeInto"], "Sign");
+        let duplicates: Vec<String> = data["duplicates"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|name| name.as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(duplicates, ["Sign Copy", "Sign Top"]);
+
+        let index = LineIndex::new(text.clone(), PositionEncoding::Utf16);
+        let edit = merge_styles_edit(&uri, &index, &document, "Sign", &duplicates);
+        let edits = &edit.changes.unwrap()[&uri];
+        let merged = apply_edits(&text, PositionEncoding::Utf16, edits);
+
+        let document = parser.parse(&merged);
+        assert_eq!(document.styles.len(), 1);
+        assert!(document.events.iter().all(|event| event.style == "Sign"));
+        assert!(merged.contains("{\\rSign}exit"));
+        let diagnostics = validation.validate(&document, &uri);
+        assert!(codes(&diagnostics, "undefined_style").is_empty());
+        assert!(codes(&diagnostics, "equivalent_style").is_empty());
+    }
+
+    /// A script whose actors differ only in case and spacing from Mio.
+    const ACTORS: &str = "[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+                          Dialogue: 0,0:00:01.00,0:00:02.00,Default,Mio,0,0,0,,Hi\n\
+                          Dialogue: 0,0:00:02.00,0:00:03.00,Default,mio,0,0,0,,Hi\n\
+                          Comment: 0,0:00:03.00,0:00:04.00,Default,Mio,0,0,0,,Note\n\
+                          Dialogue: 0,0:00:04.00,0:00:05.00,Default,Mio Sr,0,0,0,,Hi\n\
+                          Dialogue: 0,0:00:05.00,0:00:06.00,Default,,0,0,0,,Hi\n";
+
+    fn renamed(text: &str, position: Position, new_name: &str) -> Option<String> {
+        let uri = Url::parse("file:///tmp/actors.ass").unwrap();
+        let index = LineIndex::new(text.to_string(), PositionEncoding::Utf16);
+        let document = AssParser::new().parse(text);
+        let edit = actor_rename_edit(&uri, &index, &document, position, new_name)?;
+        Some(apply_edits(
+            text,
+            PositionEncoding::Utf16,
+            &edit.changes.unwrap()[&uri],
+        ))
+    }
+
+    fn actors(text: &str) -> Vec<String> {
+        AssParser::new()
+            .parse(text)
+            .events
+            .into_iter()
+            .map(|event| event.actor)
+            .collect()
+    }
+
+    #[test]
+    fn actor_rename_matches_the_exact_name_on_every_event() {
+        let index = LineIndex::new(ACTORS.to_string(), PositionEncoding::Utf16);
+        let document = AssParser::new().parse(ACTORS);
+        let column = ACTORS.lines().nth(2).unwrap().find("Mio").unwrap() as u32;
+        let range = actor_range(&document, &index, Position::new(2, column + 1)).unwrap();
+        assert_eq!(
+            range,
+            Range::new(Position::new(2, column), Position::new(2, column + 3))
+        );
+        // Not on another field, nor on an empty Name
+        assert_eq!(actor_range(&document, &index, Position::new(2, 2)), None);
+        assert_eq!(actor_range(&document, &index, Position::new(6, 44)), None);
+
+        let text = renamed(ACTORS, Position::new(2, column + 1), "Akane").unwrap();
+        assert_eq!(actors(&text), ["Akane", "mio", "Akane", "Mio Sr", ""]);
+    }
+
+    #[test]
+    fn actor_can_be_cleared_but_not_given_a_comma() {
+        let column = ACTORS.lines().nth(2).unwrap().find("Mio").unwrap() as u32;
+        let text = renamed(ACTORS, Position::new(2, column), "").unwrap();
+        assert_eq!(actors(&text), ["", "mio", "", "Mio Sr", ""]);
+        assert_eq!(actor_name_problem(""), None);
+        assert_eq!(actor_name_problem("Mio (young)"), None);
+        assert!(actor_name_problem("Mio, young").is_some());
+    }
+
+    #[test]
+    fn style_rename_edits_every_reference_but_not_dialogue_text() {
+        let text = format!(
+            "[V4+ Styles]\nFormat: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\nStyle: Default,{SIGN}\nStyle: Sign,{SIGN}\n\n[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\nDialogue: 0,0:00:01.00,0:00:02.00,Sign,,0,0,0,,Sign here\nDialogue: 0,0:00:02.00,0:00:03.00,Default,,0,0,0,,Text{{\\rSign}}sign\n"
+        );
+        let uri = Url::parse("file:///tmp/signs.ass").unwrap();
+        let index = LineIndex::new(text.clone(), PositionEncoding::Utf16);
+        let document = AssParser::new().parse(&text);
+        let position = Position::new(7, 36);
+
+        // prepareRename offers the name under the cursor
+        assert_eq!(
+            crate::definition::style_name_range(&document, &index, position),
+            Some(Range::new(Position::new(7, 34), Position::new(7, 38)))
+        );
+        assert_eq!(style_name_problem("Shop Sign"), None);
+        assert!(style_name_problem("Shop, Sign").is_some());
+
+        let edit = style_rename_edit(&uri, &index, &document, position, "Shop Sign").unwrap();
+        let edits = &edit.changes.as_ref().unwrap()[&uri];
+        let ranges: Vec<(u32, u32, u32)> = edits
+            .iter()
+            .map(|edit| {
+                (
+                    edit.range.start.line,
+                    edit.range.start.character,
+                    edit.range.end.character,
+                )
+            })
+            .collect();
+        assert_eq!(ranges, [(3, 7, 11), (7, 34, 38), (8, 57, 61)]);
+        assert!(edits.iter().all(|edit| edit.new_text == "Shop Sign"));
+
+        let renamed = apply_edits(&text, PositionEncoding::Utf16, edits);
+        let lines: Vec<&str> = renamed.lines().collect();
+        assert!(lines[3].starts_with("Style: Shop Sign,"));
+        assert!(lines[7].ends_with(",Shop Sign,,0,0,0,,Sign here"));
+        assert!(lines[8].ends_with("Text{\\rShop Sign}sign"));
+    }
+}
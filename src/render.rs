@@ -0,0 +1,695 @@
+use crate::karaoke::karaoke_syllables;
+use crate::metadata::override_tag_name;
+use crate::parser::{canonical_script_info_key, AssDocument, Event, MarginSide, Style};
+use crate::text::{tokenize, TextToken};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::str::FromStr;
+
+/// Margin used when an event has no style to inherit one from.
+const DEFAULT_MARGIN: u32 = 10;
+
+/// The renderer assumed where scripts leave behaviour to the player.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderTarget {
+    #[default]
+    Libass,
+    VsFilter,
+}
+
+impl RenderTarget {
+    pub fn name(self) -> &'static str {
+        match self {
+            RenderTarget::Libass => "libass",
+            RenderTarget::VsFilter => "VSFilter",
+        }
+    }
+}
+
+impl FromStr for RenderTarget {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "libass" => Ok(RenderTarget::Libass),
+            "vsfilter" => Ok(RenderTarget::VsFilter),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Whether Outline, Shadow and blur sizes scale from script to video pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BorderScaling {
+    pub scaled: bool,
+    /// False when `ScaledBorderAndShadow` is missing or unreadable and
+    /// `scaled` is the render target's default.
+    pub explicit: bool,
+}
+
+/// Resolves `ScaledBorderAndShadow` for a script. Without it, libass scales
+/// borders and VSFilter keeps them in video pixels.
+pub fn border_scaling(
+    script_info: &HashMap<String, String>,
+    target: RenderTarget,
+) -> BorderScaling {
+    let value = script_info
+        .get("ScaledBorderAndShadow")
+        .map(|value| value.to_ascii_lowercase());
+    match value.as_deref() {
+        Some("yes") => BorderScaling {
+            scaled: true,
+            explicit: true,
+        },
+        Some("no") => BorderScaling {
+            scaled: false,
+            explicit: true,
+        },
+        _ => BorderScaling {
+            scaled: target == RenderTarget::Libass,
+            explicit: false,
+        },
+    }
+}
+
+/// `PlayResX`x`PlayResY` when both are declared.
+pub fn play_res(script_info: &HashMap<String, String>) -> Option<(u32, u32)> {
+    let x = script_info.get("PlayResX")?.trim().parse().ok()?;
+    let y = script_info.get("PlayResY")?.trim().parse().ok()?;
+    Some((x, y))
+}
+
+/// The script resolution renderers lay text out in. A missing dimension is
+/// derived from the other as libass does, at 4:3 except for 1280x1024, and
+/// [`DEFAULT_PLAY_RES`] applies when both are missing.
+pub fn effective_play_res(script_info: &HashMap<String, String>) -> (u32, u32) {
+    let dimension = |key: &str| {
+        script_info
+            .get(key)
+            .and_then(|value| value.trim().parse::<u32>().ok())
+            .filter(|value| *value > 0)
+    };
+    match (dimension("PlayResX"), dimension("PlayResY")) {
+        (Some(x), Some(y)) => (x, y),
+        (Some(x), None) => (x, if x == 1280 { 1024 } else { x * 3 / 4 }),
+        (None, Some(y)) => (if y == 1024 { 1280 } else { y * 4 / 3 }, y),
+        (None, None) => DEFAULT_PLAY_RES,
+    }
+}
+
+/// A PlayResX or PlayResY line in Script Info.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayResEntry {
+    /// `PlayResX` or `PlayResY`.
+    pub key: &'static str,
+    /// The value as written, trimmed.
+    pub value: String,
+    pub line: u32,
+    /// Byte span of the value on its line.
+    pub span: Range<usize>,
+}
+
+impl PlayResEntry {
+    /// The value renderers take, `None` if it isn't a positive number.
+    pub fn resolution(&self) -> Option<u32> {
+        self.value.parse().ok().filter(|value| *value > 0)
+    }
+}
+
+/// The PlayResX and PlayResY lines of a document, in the order written.
+pub fn play_res_entries(document: &AssDocument) -> Vec<PlayResEntry> {
+    let mut entries = Vec::new();
+    for section in document
+        .sections
+        .iter()
+        .filter(|section| section.name == "Script Info")
+    {
+        for (offset, text) in section.content.iter().enumerate() {
+            let Some((key, rest)) = text.split_once(':') else {
+                continue;
+            };
+            let key = match canonical_script_info_key(key.trim()).as_str() {
+                "PlayResX" => "PlayResX",
+                "PlayResY" => "PlayResY",
+                _ => continue,
+            };
+            let value = rest.trim();
+            let start = text.len() - rest.trim_start().len();
+            entries.push(PlayResEntry {
+                key,
+                value: value.to_string(),
+                line: section.range.start.line + offset as u32,
+                span: start..start + value.len(),
+            });
+        }
+    }
+    entries
+}
+
+/// The height renderers pair with a width when PlayResY is missing: 4:3,
+/// except that 1280 wide is taken as 1280x1024.
+fn derived_height(width: u32) -> u32 {
+    if width == 1280 {
+        1024
+    } else {
+        width * 3 / 4
+    }
+}
+
+/// The width renderers pair with a height when PlayResX is missing.
+fn derived_width(height: u32) -> u32 {
+    if height == 1024 {
+        1280
+    } else {
+        height * 4 / 3
+    }
+}
+
+/// The resolution `target` lays the script out in, reading the keys in
+/// order the way it does.
+pub fn resolved_play_res(entries: &[PlayResEntry], target: RenderTarget) -> (u32, u32) {
+    let (mut x, mut y) = (0, 0);
+    match target {
+        // libass (ass.c) stores each value as it reads it, so the last line
+        // wins, and only derives a missing dimension once the whole header
+        // is read, in ass_lazy_track_init, never going below 1
+        RenderTarget::Libass => {
+            for entry in entries {
+                let Some(value) = entry.resolution() else {
+                    continue;
+                };
+                match entry.key {
+                    "PlayResX" => x = value,
+                    _ => y = value,
+                }
+            }
+            match (x, y) {
+                (0, 0) => return DEFAULT_PLAY_RES,
+                (x, 0) => y = derived_height(x).max(1),
+                (0, y) => x = derived_width(y).max(1),
+                _ => {}
+            }
+        }
+        // VSFilter (STS.cpp, OpenSubStationAlpha) derives the other
+        // dimension as soon as it reads one that is still unset, so a value
+        // derived from an earlier line sticks when the key appears again
+        RenderTarget::VsFilter => {
+            for entry in entries {
+                let Some(value) = entry.resolution() else {
+                    continue;
+                };
+                match entry.key {
+                    "PlayResX" => {
+                        x = value;
+                        if y == 0 {
+                            y = derived_height(x);
+                        }
+                    }
+                    _ => {
+                        y = value;
+                        if x == 0 {
+                            x = derived_width(y);
+                        }
+                    }
+                }
+            }
+            if (x, y) == (0, 0) {
+                return DEFAULT_PLAY_RES;
+            }
+        }
+    }
+    (x, y)
+}
+
+/// How renderers fill in the dimension a script leaves out when it sets
+/// only one of PlayResX and PlayResY, as the missing key and a note such as
+/// "libass and VSFilter assume 4:3, giving PlayResY=288". `None` unless
+/// exactly one is set.
+pub fn missing_play_res_note(entries: &[PlayResEntry]) -> Option<(&'static str, String)> {
+    let has = |key: &str| {
+        entries
+            .iter()
+            .any(|entry| entry.key == key && entry.resolution().is_some())
+    };
+    let (missing, present) = match (has("PlayResX"), has("PlayResY")) {
+        (true, false) => ("PlayResY", "PlayResX"),
+        (false, true) => ("PlayResX", "PlayResY"),
+        _ => return None,
+    };
+    let derived = |target| {
+        let (x, y) = resolved_play_res(entries, target);
+        if missing == "PlayResY" {
+            (x, y)
+        } else {
+            (y, x)
+        }
+    };
+
+    let (given, libass) = derived(RenderTarget::Libass);
+    let (_, vsfilter) = derived(RenderTarget::VsFilter);
+    let special = if missing == "PlayResY" { 1280 } else { 1024 };
+    let ratio = if given == special { "1280x1024" } else { "4:3" };
+    let note = if libass == vsfilter {
+        format!("libass and VSFilter assume {ratio}, giving {missing}={libass}")
+    } else {
+        // Only a repeated key sets them apart, as VSFilter derives the
+        // missing value from the first one
+        format!(
+            "libass assumes {ratio}, giving {missing}={libass}; VSFilter derives it from the first {present}, giving {missing}={vsfilter}"
+        )
+    };
+    Some((missing, note))
+}
+
+/// What a style is used for, judged by its name and its events. Signs and
+/// lyrics are placed deliberately, so placement heuristics leave them alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StyleRole {
+    Dialogue,
+    Sign,
+    Lyrics,
+}
+
+/// Name parts that mark a style as a sign, matched anywhere in the name.
+const SIGN_NAME_PARTS: &[&str] = &["sign", "typeset", "title", "eyecatch", "note"];
+/// Name parts that mark a style as song lyrics, matched anywhere in the name.
+const LYRICS_NAME_PARTS: &[&str] = &["song", "lyric", "kara", "romaji", "kanji"];
+/// Short words that mark lyrics or signs only as whole words of the name,
+/// so `OP_Eng` and `TS_Top` match but `Speed` and `Tsubasa` do not.
+const ROLE_NAME_WORDS: &[(&str, StyleRole)] = &[
+    ("op", StyleRole::Lyrics),
+    ("ed", StyleRole::Lyrics),
+    ("ts", StyleRole::Sign),
+];
+
+/// Classifies the style `name`. Its name decides first; otherwise a style
+/// whose dialogue is mostly positioned with `\pos` or `\move` is a sign, and
+/// one whose dialogue is mostly karaoke is lyrics.
+pub fn style_role(document: &AssDocument, name: &str) -> StyleRole {
+    let lowercase = name.to_lowercase();
+    if SIGN_NAME_PARTS.iter().any(|part| lowercase.contains(part)) {
+        return StyleRole::Sign;
+    }
+    if LYRICS_NAME_PARTS
+        .iter()
+        .any(|part| lowercase.contains(part))
+    {
+        return StyleRole::Lyrics;
+    }
+    let word_role = lowercase
+        .split(|c: char| !c.is_alphanumeric())
+        .find_map(|word| {
+            ROLE_NAME_WORDS
+                .iter()
+                .find(|(role_word, _)| *role_word == word)
+                .map(|(_, role)| *role)
+        });
+    if let Some(role) = word_role {
+        return role;
+    }
+
+    let events: Vec<&Event> = document
+        .events
+        .iter()
+        .filter(|event| event.event_type == "Dialogue" && event.style == name)
+        .collect();
+    let positioned = events
+        .iter()
+        .filter(|event| {
+            tokenize(&event.text).into_iter().any(|token| {
+                matches!(token, TextToken::Tag { tag, .. }
+                    if matches!(override_tag_name(tag), Some("pos" | "move")))
+            })
+        })
+        .count();
+    let karaoke = events
+        .iter()
+        .filter(|event| !karaoke_syllables(event).is_empty())
+        .count();
+    if positioned * 2 > events.len() {
+        StyleRole::Sign
+    } else if karaoke * 2 > events.len() {
+        StyleRole::Lyrics
+    } else {
+        StyleRole::Dialogue
+    }
+}
+
+/// Where an event's margin on one side comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarginSource {
+    Event,
+    Style,
+    Default,
+}
+
+impl MarginSource {
+    pub fn name(self) -> &'static str {
+        match self {
+            MarginSource::Event => "event",
+            MarginSource::Style => "style",
+            MarginSource::Default => "default",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EffectiveMargin {
+    pub value: u32,
+    pub source: MarginSource,
+}
+
+/// The margin an event renders with on one side. An event value of 0 means
+/// "use the style's margin" rather than no margin, so only a nonzero value
+/// overrides; without a style the margin is 10.
+pub fn effective_margin(event: &Event, style: Option<&Style>, side: MarginSide) -> EffectiveMargin {
+    let event_value = event
+        .margin(side)
+        .and_then(|margin| margin.value.parse::<u32>().ok())
+        .filter(|&value| value != 0);
+    if let Some(value) = event_value {
+        return EffectiveMargin {
+            value,
+            source: MarginSource::Event,
+        };
+    }
+    match style.and_then(|style| style.field(side.field_name())?.trim().parse().ok()) {
+        Some(value) => EffectiveMargin {
+            value,
+            source: MarginSource::Style,
+        },
+        None => EffectiveMargin {
+            value: DEFAULT_MARGIN,
+            source: MarginSource::Default,
+        },
+    }
+}
+
+/// Script resolution renderers assume when `PlayResX`/`PlayResY` are missing.
+pub const DEFAULT_PLAY_RES: (u32, u32) = (384, 288);
+
+/// Motion an event's Effect field gives its text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrollEffect {
+    /// `Banner;delay[;lefttoright[;fadeawaywidth]]`: the text crosses the
+    /// screen horizontally, right to left unless `lefttoright` is 1.
+    Banner { delay: i32, left_to_right: bool },
+    /// `Scroll up;y1;y2;delay[;fadeawayheight]` or `Scroll down;...`: the
+    /// text moves vertically through the band between `y1` and `y2`.
+    Scroll {
+        up: bool,
+        y1: i32,
+        y2: i32,
+        delay: i32,
+    },
+}
+
+impl ScrollEffect {
+    /// Parses an Effect field, or `None` for other effects and for effects
+    /// missing the parameters VSFilter requires.
+    pub fn parse(effect: &str) -> Option<Self> {
+        let mut parts = effect.split(';');
+        let kind = parts.next()?.trim().to_ascii_lowercase();
+        let params: Vec<i32> = parts.map_while(|part| part.trim().parse().ok()).collect();
+        match (kind.as_str(), params.as_slice()) {
+            ("banner", [delay, rest @ ..]) => Some(ScrollEffect::Banner {
+                delay: *delay,
+                left_to_right: rest.first() == Some(&1),
+            }),
+            ("scroll up" | "scroll down", [y1, y2, delay, ..]) => Some(ScrollEffect::Scroll {
+                up: kind == "scroll up",
+                y1: *y1,
+                y2: *y2,
+                delay: *delay,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ScrollEffect::Banner { .. } => "banner",
+            ScrollEffect::Scroll { .. } => "scroll",
+        }
+    }
+
+    /// Milliseconds the text takes to move one script pixel. VSFilter divides
+    /// the delay by the script-to-video scale and moves one video pixel per
+    /// resulting millisecond, which is one script pixel every `delay` ms. The
+    /// scaled delay is floored at 1, so 0 and negative delays move as fast as 1.
+    pub fn delay(self) -> u32 {
+        let delay = match self {
+            ScrollEffect::Banner { delay, .. } | ScrollEffect::Scroll { delay, .. } => delay,
+        };
+        delay.max(1) as u32
+    }
+
+    /// Speed in script pixels per second, `1000 / delay`: delay 1 is
+    /// 1000 px/s, delay 10 is 100 px/s and delay 40 is 25 px/s.
+    pub fn speed(self) -> f64 {
+        1000.0 / f64::from(self.delay())
+    }
+
+    /// Top and bottom of the band a scroll moves through. The values may come
+    /// in either order, and `0;0` (or a bottom of 0) means the whole screen
+    /// height, as in libass.
+    pub fn band(self, play_res_y: u32) -> Option<(u32, u32)> {
+        let ScrollEffect::Scroll { y1, y2, .. } = self else {
+            return None;
+        };
+        let (top, bottom) = (y1.min(y2).max(0) as u32, y1.max(y2).max(0) as u32);
+        Some((top, if bottom == 0 { play_res_y } else { bottom }))
+    }
+
+    /// How far the text travels, not counting its own width or height: the
+    /// screen width for a banner, the band height for a scroll.
+    pub fn travel(self, (play_res_x, play_res_y): (u32, u32)) -> u32 {
+        match self.band(play_res_y) {
+            Some((top, bottom)) => bottom - top,
+            None => play_res_x,
+        }
+    }
+
+    /// Milliseconds the text takes to travel `distance` script pixels.
+    pub fn travel_time(self, distance: u32) -> u64 {
+        u64::from(distance) * u64::from(self.delay())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::AssParser;
+
+    const SCRIPT: &str = "[V4+ Styles]\nFormat: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\nStyle: Wide,Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,120,0,45,1\n\n[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n";
+
+    /// Effective (left, right, vertical) margins of an event with the given
+    /// margin fields, in the Wide style or one that doesn't exist.
+    fn margins(style: &str, fields: &str) -> [(u32, MarginSource); 3] {
+        let text = format!("{SCRIPT}Dialogue: 0,0:00:01.00,0:00:02.00,{style},,{fields},,Text\n");
+        let document = AssParser::new().parse(&text);
+        let event = &document.events[0];
+        MarginSide::ALL.map(|side| {
+            let margin = effective_margin(event, document.style(&event.style), side);
+            (margin.value, margin.source)
+        })
+    }
+
+    #[test]
+    fn zero_event_margin_inherits_the_style() {
+        use MarginSource::*;
+        assert_eq!(
+            margins("Wide", "0,0,0"),
+            [(120, Style), (0, Style), (45, Style)]
+        );
+        // However zero is written
+        assert_eq!(
+            margins("Wide", "0000,00,0"),
+            [(120, Style), (0, Style), (45, Style)]
+        );
+        // Only nonzero values override, each side on its own
+        assert_eq!(
+            margins("Wide", "30,0,1"),
+            [(30, Event), (0, Style), (1, Event)]
+        );
+        // A style margin of 0 is a margin of 0, not the default
+        assert_eq!(margins("Wide", "0,5,0")[1], (5, Event));
+        // Without the style there is nothing to inherit
+        assert_eq!(
+            margins("Missing", "0,7,0"),
+            [(10, Default), (7, Event), (10, Default)]
+        );
+        // Values that aren't margins are ignored rather than read as 0
+        assert_eq!(margins("Wide", "-20,abc,")[0], (120, Style));
+        assert_eq!(margins("Wide", "-20,abc,")[2], (45, Style));
+    }
+
+    #[test]
+    fn scroll_delay_converts_to_reference_speeds() {
+        let banner = |delay| ScrollEffect::Banner {
+            delay,
+            left_to_right: false,
+        };
+        // One script pixel every `delay` ms
+        for (delay, speed) in [
+            (1, 1000.0),
+            (2, 500.0),
+            (10, 100.0),
+            (25, 40.0),
+            (40, 25.0),
+            (100, 10.0),
+        ] {
+            assert_eq!(banner(delay).speed(), speed, "delay {delay}");
+        }
+        assert!((banner(3).speed() - 333.333).abs() < 0.001);
+        // Zero and negative delays are as fast as 1
+        assert_eq!(banner(0).speed(), 1000.0);
+        assert_eq!(banner(-5).delay(), 1);
+
+        // Crossing the screen, not counting the text's own width
+        assert_eq!(
+            banner(10).travel_time(banner(10).travel(DEFAULT_PLAY_RES)),
+            3840
+        );
+        assert_eq!(
+            banner(10).travel_time(banner(10).travel((1920, 1080))),
+            19200
+        );
+        let scroll = ScrollEffect::parse("Scroll up;0;0;20").unwrap();
+        assert_eq!(scroll.travel((1920, 1080)), 1080);
+        assert_eq!(scroll.travel_time(1080), 21600);
+        let scroll = ScrollEffect::parse("scroll down;900;100;5;40").unwrap();
+        assert_eq!(scroll.band(1080), Some((100, 900)));
+        assert_eq!(scroll.travel_time(scroll.travel((1920, 1080))), 4000);
+    }
+
+    #[test]
+    fn scroll_effects_need_their_parameters() {
+        assert_eq!(
+            ScrollEffect::parse("Banner;15;1"),
+            Some(ScrollEffect::Banner {
+                delay: 15,
+                left_to_right: true
+            })
+        );
+        assert_eq!(
+            ScrollEffect::parse("Scroll down;300;0;8"),
+            Some(ScrollEffect::Scroll {
+                up: false,
+                y1: 300,
+                y2: 0,
+                delay: 8
+            })
+        );
+        assert_eq!(
+            ScrollEffect::parse("Scroll down;300;0;8")
+                .unwrap()
+                .band(720),
+            Some((0, 300))
+        );
+        for effect in ["Banner;", "Banner", "Scroll up;10;20", "Karaoke", ""] {
+            assert_eq!(ScrollEffect::parse(effect), None, "{effect}");
+        }
+    }
+
+    fn script_info_entries(script_info: &str) -> Vec<PlayResEntry> {
+        play_res_entries(
+            &AssParser::new().parse(&format!("[Script Info]\nScriptType: v4.00+\n{script_info}")),
+        )
+    }
+
+    #[test]
+    fn single_play_res_key_is_completed_by_both_targets() {
+        for (script_info, resolution, note) in [
+            (
+                "PlayResX: 384\n",
+                (384, 288),
+                "libass and VSFilter assume 4:3, giving PlayResY=288",
+            ),
+            (
+                "PlayResX: 1280\n",
+                (1280, 1024),
+                "libass and VSFilter assume 1280x1024, giving PlayResY=1024",
+            ),
+            (
+                "PlayResY: 720\n",
+                (960, 720),
+                "libass and VSFilter assume 4:3, giving PlayResX=960",
+            ),
+            (
+                "PlayResY: 1024\n",
+                (1280, 1024),
+                "libass and VSFilter assume 1280x1024, giving PlayResX=1280",
+            ),
+        ] {
+            let entries = script_info_entries(script_info);
+            for target in [RenderTarget::Libass, RenderTarget::VsFilter] {
+                assert_eq!(
+                    resolved_play_res(&entries, target),
+                    resolution,
+                    "{script_info:?} under {}",
+                    target.name()
+                );
+            }
+            let missing = if script_info.starts_with("PlayResX") {
+                "PlayResY"
+            } else {
+                "PlayResX"
+            };
+            assert_eq!(
+                missing_play_res_note(&entries),
+                Some((missing, note.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn repeated_single_key_is_completed_differently_per_target() {
+        // libass derives the height once, from the last width; VSFilter
+        // derives it from the first width and keeps it
+        let entries = script_info_entries("PlayResX: 640\nPlayResX: 1280\n");
+        assert_eq!(
+            resolved_play_res(&entries, RenderTarget::Libass),
+            (1280, 1024)
+        );
+        assert_eq!(
+            resolved_play_res(&entries, RenderTarget::VsFilter),
+            (1280, 480)
+        );
+        assert_eq!(
+            missing_play_res_note(&entries),
+            Some((
+                "PlayResY",
+                "libass assumes 1280x1024, giving PlayResY=1024; VSFilter derives it from the first PlayResX, giving PlayResY=480"
+                    .to_string()
+            ))
+        );
+
+        let entries = script_info_entries("PlayResY: 480\nPlayResY: 1024\n");
+        assert_eq!(
+            resolved_play_res(&entries, RenderTarget::Libass),
+            (1280, 1024)
+        );
+        assert_eq!(
+            resolved_play_res(&entries, RenderTarget::VsFilter),
+            (640, 1024)
+        );
+    }
+
+    #[test]
+    fn both_keys_or_neither_need_no_derivation() {
+        let both = script_info_entries("PlayResX: 1920\nPlayResY: 1080\n");
+        let none = script_info_entries("");
+        let unreadable = script_info_entries("PlayResX: wide\nPlayResY: 0\n");
+        for target in [RenderTarget::Libass, RenderTarget::VsFilter] {
+            assert_eq!(resolved_play_res(&both, target), (1920, 1080));
+            assert_eq!(resolved_play_res(&none, target), DEFAULT_PLAY_RES);
+            assert_eq!(resolved_play_res(&unreadable, target), DEFAULT_PLAY_RES);
+        }
+        assert_eq!(missing_play_res_note(&both), None);
+        assert_eq!(missing_play_res_note(&none), None);
+        assert_eq!(missing_play_res_note(&unreadable), None);
+    }
+}
@@ -0,0 +1,89 @@
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tower_lsp::lsp_types::{TextDocumentIdentifier, Url};
+
+/// Client notification naming the document the user is looking at, whose
+/// deep pass then runs next.
+pub const ACTIVE_DOCUMENT: &str = "ass-lsp/activeDocument";
+
+/// Deep passes allowed to run at once.
+pub const DEEP_PASS_CONCURRENCY: usize = 2;
+
+/// How long the queue must stop growing before deep passes start, so a burst
+/// of restored documents all get their fast pass first.
+const SETTLE_TIME: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActiveDocumentParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+/// Documents that have had their fast pass but still await the deep one,
+/// served first in, first out apart from the active document.
+#[derive(Debug, Default)]
+pub struct DeepPassQueue {
+    state: Mutex<QueueState>,
+    ready: Notify,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    queue: VecDeque<Url>,
+    /// Last document reported active; it may be named before its fast pass
+    /// has queued it.
+    active: Option<Url>,
+}
+
+impl DeepPassQueue {
+    pub fn push(&self, uri: Url) {
+        let mut state = self.state.lock().unwrap();
+        if !state.queue.contains(&uri) {
+            if state.active.as_ref() == Some(&uri) {
+                state.queue.push_front(uri);
+            } else {
+                state.queue.push_back(uri);
+            }
+        }
+        drop(state);
+        self.ready.notify_one();
+    }
+
+    /// Makes `uri` the active document, moving it to the front if it is waiting.
+    pub fn prioritize(&self, uri: &Url) {
+        let mut state = self.state.lock().unwrap();
+        state.active = Some(uri.clone());
+        if let Some(position) = state.queue.iter().position(|queued| queued == uri) {
+            let uri = state.queue.remove(position).unwrap();
+            state.queue.push_front(uri);
+        }
+    }
+
+    /// Drops `uri`, e.g. because a change analysed it fully already.
+    pub fn remove(&self, uri: &Url) {
+        self.state
+            .lock()
+            .unwrap()
+            .queue
+            .retain(|queued| queued != uri);
+    }
+
+    pub fn pop(&self) -> Option<Url> {
+        self.state.lock().unwrap().queue.pop_front()
+    }
+
+    /// Waits until something is queued and the queue has stopped growing.
+    pub async fn settled(&self) {
+        self.ready.notified().await;
+        loop {
+            let len = self.state.lock().unwrap().queue.len();
+            tokio::time::sleep(SETTLE_TIME).await;
+            if self.state.lock().unwrap().queue.len() <= len {
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,623 @@
+use crate::drawing::command_letters;
+use crate::line_index::LineIndex;
+use crate::metadata::{
+    override_tag_name, written_tag_name, COLOR_TAGS, KARAOKE_TAGS, OVERRIDE_TAGS,
+};
+use crate::parser::{is_attachment_section, AssDocument, Event, Section};
+use crate::text::{argument_span, parse_transform, tag_arguments, tokenize, TextToken};
+use std::collections::HashMap;
+use std::ops::{Range as Span, RangeInclusive};
+use tower_lsp::lsp_types::*;
+
+/// What a semantic token marks. The order is the legend's, so a kind's
+/// index is its token type; each maps to a standard type so themes color
+/// it without configuration.
+#[derive(Debug, Clone, Copy)]
+enum TokenKind {
+    SectionHeader,
+    ScriptInfoKey,
+    /// The descriptor of a Format, Style, Dialogue or Comment line.
+    LineKind,
+    Time,
+    StyleName,
+    Actor,
+    Effect,
+    TagName,
+    TagArgument,
+    Color,
+    /// Text after a karaoke tag.
+    Syllable,
+    DrawingCommand,
+    Comment,
+}
+
+impl TokenKind {
+    const ALL: [TokenKind; 13] = [
+        TokenKind::SectionHeader,
+        TokenKind::ScriptInfoKey,
+        TokenKind::LineKind,
+        TokenKind::Time,
+        TokenKind::StyleName,
+        TokenKind::Actor,
+        TokenKind::Effect,
+        TokenKind::TagName,
+        TokenKind::TagArgument,
+        TokenKind::Color,
+        TokenKind::Syllable,
+        TokenKind::DrawingCommand,
+        TokenKind::Comment,
+    ];
+
+    fn token_type(self) -> SemanticTokenType {
+        match self {
+            TokenKind::SectionHeader => SemanticTokenType::NAMESPACE,
+            TokenKind::ScriptInfoKey => SemanticTokenType::PROPERTY,
+            TokenKind::LineKind => SemanticTokenType::KEYWORD,
+            TokenKind::Time => SemanticTokenType::NUMBER,
+            TokenKind::StyleName => SemanticTokenType::CLASS,
+            TokenKind::Actor => SemanticTokenType::VARIABLE,
+            TokenKind::Effect => SemanticTokenType::ENUM_MEMBER,
+            TokenKind::TagName => SemanticTokenType::FUNCTION,
+            TokenKind::TagArgument => SemanticTokenType::PARAMETER,
+            TokenKind::Color => SemanticTokenType::STRING,
+            TokenKind::Syllable => SemanticTokenType::EVENT,
+            TokenKind::DrawingCommand => SemanticTokenType::MACRO,
+            TokenKind::Comment => SemanticTokenType::COMMENT,
+        }
+    }
+}
+
+/// Set on a style's name where its `Style:` line defines it.
+const DECLARATION: u32 = 1;
+
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: TokenKind::ALL.map(TokenKind::token_type).to_vec(),
+        token_modifiers: vec![SemanticTokenModifier::DECLARATION],
+    }
+}
+
+/// A token before encoding, in byte columns.
+struct RawToken {
+    line: u32,
+    span: Span<usize>,
+    kind: TokenKind,
+    modifiers: u32,
+}
+
+#[derive(Default)]
+struct Tokens(Vec<RawToken>);
+
+impl Tokens {
+    fn push(&mut self, line: u32, span: Span<usize>, kind: TokenKind) {
+        self.push_with(line, span, kind, 0);
+    }
+
+    fn push_with(&mut self, line: u32, span: Span<usize>, kind: TokenKind, modifiers: u32) {
+        if !span.is_empty() {
+            self.0.push(RawToken {
+                line,
+                span,
+                kind,
+                modifiers,
+            });
+        }
+    }
+}
+
+/// Every token in the document, in the LSP's relative encoding with columns
+/// in the client's position encoding.
+pub fn semantic_tokens(document: &AssDocument, index: &LineIndex) -> Vec<SemanticToken> {
+    semantic_tokens_in(document, index, 0..=u32::MAX)
+}
+
+/// The tokens on `lines`, encoded as [`semantic_tokens`] encodes them. Only
+/// the styles and events on those lines are tokenized.
+pub fn semantic_tokens_in(
+    document: &AssDocument,
+    index: &LineIndex,
+    lines: RangeInclusive<u32>,
+) -> Vec<SemanticToken> {
+    let mut tokens = Tokens::default();
+    for section in &document.sections {
+        if section.range.start.line <= *lines.end() && section.range.end.line >= *lines.start() {
+            section_tokens(&mut tokens, section);
+        }
+    }
+    for style in &document.styles {
+        let line = style.range.start.line;
+        if !lines.contains(&line) {
+            continue;
+        }
+        for ((name, _), span) in style.fields.iter().zip(&style.field_spans) {
+            if name.eq_ignore_ascii_case("Name") {
+                tokens.push_with(line, span.clone(), TokenKind::StyleName, DECLARATION);
+            } else if name.to_ascii_lowercase().ends_with("colour") {
+                tokens.push(line, span.clone(), TokenKind::Color);
+            }
+        }
+    }
+    for event in &document.events {
+        if lines.contains(&event.range.start.line) {
+            event_tokens(&mut tokens, event);
+        }
+    }
+    tokens.0.retain(|token| lines.contains(&token.line));
+    encode(tokens.0, index)
+}
+
+/// The token array last sent for each open document, so `full/delta`
+/// requests can answer with what changed since.
+#[derive(Debug, Default)]
+pub struct TokenCache {
+    documents: HashMap<Url, CachedTokens>,
+    /// Makes result ids unique across reopened documents.
+    next_id: u64,
+}
+
+#[derive(Debug)]
+struct CachedTokens {
+    version: i32,
+    result_id: String,
+    data: Vec<SemanticToken>,
+}
+
+impl TokenCache {
+    /// The tokens of `version`, computed with `tokens` unless that version's
+    /// are cached.
+    pub fn full(
+        &mut self,
+        uri: &Url,
+        version: i32,
+        tokens: impl FnOnce() -> Vec<SemanticToken>,
+    ) -> SemanticTokens {
+        let cached = self.update(uri, version, tokens);
+        SemanticTokens {
+            result_id: Some(cached.result_id.clone()),
+            data: cached.data.clone(),
+        }
+    }
+
+    /// The edits from the array sent as `previous_result_id` to the tokens of
+    /// `version`, or all the tokens when that array is no longer cached.
+    pub fn delta(
+        &mut self,
+        uri: &Url,
+        version: i32,
+        previous_result_id: &str,
+        tokens: impl FnOnce() -> Vec<SemanticToken>,
+    ) -> SemanticTokensFullDeltaResult {
+        let previous = self
+            .documents
+            .get(uri)
+            .filter(|cached| cached.result_id == previous_result_id)
+            .map(|cached| cached.data.clone());
+        let cached = self.update(uri, version, tokens);
+        match previous {
+            Some(previous) => SemanticTokensFullDeltaResult::TokensDelta(SemanticTokensDelta {
+                result_id: Some(cached.result_id.clone()),
+                edits: token_edits(&previous, &cached.data),
+            }),
+            None => SemanticTokensFullDeltaResult::Tokens(SemanticTokens {
+                result_id: Some(cached.result_id.clone()),
+                data: cached.data.clone(),
+            }),
+        }
+    }
+
+    pub fn remove(&mut self, uri: &Url) {
+        self.documents.remove(uri);
+    }
+
+    fn update(
+        &mut self,
+        uri: &Url,
+        version: i32,
+        tokens: impl FnOnce() -> Vec<SemanticToken>,
+    ) -> &CachedTokens {
+        if self
+            .documents
+            .get(uri)
+            .is_none_or(|cached| cached.version != version)
+        {
+            self.next_id += 1;
+            let cached = CachedTokens {
+                version,
+                result_id: format!("{version}.{}", self.next_id),
+                data: tokens(),
+            };
+            self.documents.insert(uri.clone(), cached);
+        }
+        &self.documents[uri]
+    }
+}
+
+/// The single edit turning `old` into `new`: whatever lies between their
+/// common prefix and suffix. Positions count integers, five per token.
+fn token_edits(old: &[SemanticToken], new: &[SemanticToken]) -> Vec<SemanticTokensEdit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let deleted = old.len() - prefix - suffix;
+    let inserted = &new[prefix..new.len() - suffix];
+    if deleted == 0 && inserted.is_empty() {
+        return Vec::new();
+    }
+    vec![SemanticTokensEdit {
+        start: 5 * prefix as u32,
+        delete_count: 5 * deleted as u32,
+        data: (!inserted.is_empty()).then(|| inserted.to_vec()),
+    }]
+}
+
+/// Section headers, comment lines, and the keys and descriptors that start
+/// the lines of a section.
+fn section_tokens(tokens: &mut Tokens, section: &Section) {
+    let header = section.header_range;
+    tokens.push(
+        header.start.line,
+        header.start.character as usize..header.end.character as usize,
+        TokenKind::SectionHeader,
+    );
+    // Attachment data is encoded text that may start with anything
+    if is_attachment_section(&section.name) {
+        return;
+    }
+    let kind = match section.name.as_str() {
+        "Script Info" | "Aegisub Project Garbage" => TokenKind::ScriptInfoKey,
+        "V4+ Styles" | "V4 Styles" | "Events" => TokenKind::LineKind,
+        _ => return,
+    };
+    for (offset, text) in section.content.iter().enumerate().skip(1) {
+        let line = section.range.start.line + offset as u32;
+        let indent = text.len() - text.trim_start().len();
+        let trimmed = text.trim();
+        if trimmed.starts_with(';') {
+            tokens.push(line, indent..indent + trimmed.len(), TokenKind::Comment);
+        } else if let Some((key, _)) = trimmed.split_once(':') {
+            tokens.push(line, indent..indent + key.trim_end().len(), kind);
+        }
+    }
+}
+
+/// An event's fields, and the tags, comments, karaoke syllables and drawing
+/// commands of its text.
+fn event_tokens(tokens: &mut Tokens, event: &Event) {
+    let line = event.range.start.line;
+    tokens.push(line, event.start_span.clone(), TokenKind::Time);
+    tokens.push(line, event.end_span.clone(), TokenKind::Time);
+    tokens.push(line, event.style_span.clone(), TokenKind::StyleName);
+    tokens.push(line, event.actor_span.clone(), TokenKind::Actor);
+    tokens.push(line, event.effect_span.clone(), TokenKind::Effect);
+
+    let base = event.text_start as usize;
+    let text = &event.text;
+    // Text before the first tag of a block is a comment
+    let mut pos = 0;
+    while let Some(open) = text[pos..].find('{').map(|i| pos + i + 1) {
+        let close = text[open..].find('}').map_or(text.len(), |i| open + i);
+        let block = &text[open..close];
+        let comment = block.find('\\').map_or(block, |tags| &block[..tags]);
+        let leading = comment.len() - comment.trim_start().len();
+        let start = base + open + leading;
+        tokens.push(
+            line,
+            start..start + comment.trim().len(),
+            TokenKind::Comment,
+        );
+        pos = close;
+    }
+
+    let mut drawing = false;
+    let mut karaoke = false;
+    for token in tokenize(text) {
+        match token {
+            TextToken::Tag { tag, span } => {
+                let name = tag_tokens(tokens, line, base, tag, span);
+                if name == Some("p") {
+                    drawing = tag[1..].trim().parse::<u32>().is_ok_and(|scale| scale > 0);
+                }
+                karaoke |= name.is_some_and(|name| KARAOKE_TAGS.contains(&name));
+            }
+            TextToken::Text { text, span } if drawing => {
+                for letter in command_letters(text) {
+                    let start = base + span.start + letter;
+                    tokens.push(line, start..start + 1, TokenKind::DrawingCommand);
+                }
+            }
+            TextToken::Text { text, span } if karaoke => {
+                let leading = text.len() - text.trim_start().len();
+                let start = base + span.start + leading;
+                tokens.push(line, start..start + text.trim().len(), TokenKind::Syllable);
+            }
+            TextToken::Text { .. } => {}
+        }
+    }
+}
+
+/// A tag's name and arguments, and those of the tags a `\t` animates.
+/// Returns the tag's name, if it's one renderers know.
+fn tag_tokens(
+    tokens: &mut Tokens,
+    line: u32,
+    base: usize,
+    tag: &str,
+    span: Span<usize>,
+) -> Option<&'static str> {
+    let start = base + span.start;
+    let Some(name) = override_tag_name(tag) else {
+        // A name in the wrong case, like `\AN8`, is still marked as a tag so
+        // the quick fix for it is easy to find
+        let written = written_tag_name(tag);
+        if OVERRIDE_TAGS
+            .iter()
+            .any(|known| known.eq_ignore_ascii_case(written))
+        {
+            tokens.push(line, start..start + 1 + written.len(), TokenKind::TagName);
+        }
+        return None;
+    };
+    // The backslash and the name
+    tokens.push(line, start..start + 1 + name.len(), TokenKind::TagName);
+
+    if let Some(Ok(transform)) = parse_transform(tag, span.clone()) {
+        for (inner, inner_span) in transform.tags {
+            tag_tokens(tokens, line, base, inner, inner_span);
+        }
+        return Some(name);
+    }
+    let kind = if COLOR_TAGS.contains(&name) {
+        TokenKind::Color
+    } else {
+        TokenKind::TagArgument
+    };
+    for arg in tag_arguments(tag, name).args {
+        let arg_span = argument_span(tag, arg);
+        let arg_start = start + 1 + arg_span.start;
+        tokens.push(line, arg_start..arg_start + arg.len(), kind);
+    }
+    Some(name)
+}
+
+/// Sorts the tokens and encodes each relative to the one before it, dropping
+/// any that overlap an earlier token.
+fn encode(mut raw: Vec<RawToken>, index: &LineIndex) -> Vec<SemanticToken> {
+    raw.sort_by_key(|token| (token.line, token.span.start));
+    let mut encoded = Vec::with_capacity(raw.len());
+    let (mut previous_line, mut previous_start) = (0, 0);
+    let mut covered_to: Option<(u32, usize)> = None;
+    for token in raw {
+        if covered_to.is_some_and(|(line, end)| line == token.line && token.span.start < end) {
+            continue;
+        }
+        covered_to = Some((token.line, token.span.end));
+
+        let line = token.line as usize;
+        let start = index.position(line, token.span.start).character;
+        let end = index.position(line, token.span.end).character;
+        let delta_line = token.line - previous_line;
+        encoded.push(SemanticToken {
+            delta_line,
+            delta_start: if delta_line == 0 {
+                start - previous_start
+            } else {
+                start
+            },
+            length: end - start,
+            token_type: token.kind as u32,
+            token_modifiers_bitset: token.modifiers,
+        });
+        (previous_line, previous_start) = (token.line, start);
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_index::PositionEncoding;
+    use crate::parser::AssParser;
+
+    /// Absolute (line, start, length, kind) of each token.
+    fn decode(tokens: &[SemanticToken]) -> Vec<(u32, u32, u32, u32)> {
+        let (mut line, mut start) = (0, 0);
+        tokens
+            .iter()
+            .map(|token| {
+                if token.delta_line > 0 {
+                    start = 0;
+                }
+                line += token.delta_line;
+                start += token.delta_start;
+                (line, start, token.length, token.token_type)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn wrong_case_tag_names_are_still_tags() {
+        let text = "[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\nDialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,{\\AN8\\Pos(1,2)\\K20\\Blurr}Text\n";
+        let document = AssParser::new().parse(text);
+        let index = LineIndex::new(text.to_string(), PositionEncoding::Utf16);
+        let line = index.line_text(2);
+        let tag_name = TokenKind::TagName as u32;
+        let names: Vec<&str> = decode(&semantic_tokens(&document, &index))
+            .into_iter()
+            .filter(|&(token_line, _, _, kind)| token_line == 2 && kind == tag_name)
+            .map(|(_, start, length, _)| &line[start as usize..(start + length) as usize])
+            .collect();
+        assert_eq!(names, ["\\AN", "\\Pos", "\\K"]);
+    }
+
+    /// Each token of the highlighting fixture as `line:column text kind`,
+    /// with `modifiers` when set. Columns are UTF-16, as clients count them.
+    fn listing(text: &str, tokens: &[SemanticToken]) -> String {
+        let lines: Vec<Vec<u16>> = text
+            .lines()
+            .map(|line| line.encode_utf16().collect())
+            .collect();
+        let mut listing = String::new();
+        let modifiers = tokens.iter().map(|token| token.token_modifiers_bitset);
+        for ((line, start, length, kind), modifiers) in decode(tokens).into_iter().zip(modifiers) {
+            let units = &lines[line as usize][start as usize..(start + length) as usize];
+            let kind = format!("{:?}", TokenKind::ALL[kind as usize]);
+            listing.push_str(&format!(
+                "{line}:{start} {:?} {kind}{}\n",
+                String::from_utf16(units).unwrap(),
+                if modifiers == DECLARATION {
+                    " declaration"
+                } else {
+                    ""
+                }
+            ));
+        }
+        listing
+    }
+
+    /// Compares with `tests/semantic_tokens.txt`; rerun with
+    /// `UPDATE_GOLDEN=1` to accept a change.
+    #[test]
+    fn fixture_tokens_match_golden_listing() {
+        let text = include_str!("../tests/fixtures/highlight.ass");
+        let document = AssParser::new().parse(text);
+        let index = LineIndex::new(text.to_string(), PositionEncoding::Utf16);
+        let listing = listing(text, &semantic_tokens(&document, &index));
+
+        let golden =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/semantic_tokens.txt");
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::write(&golden, &listing).unwrap();
+        }
+        let expected = std::fs::read_to_string(&golden).unwrap_or_default();
+        assert!(
+            listing == expected,
+            "semantic tokens changed; rerun with UPDATE_GOLDEN=1 to accept:\n{listing}"
+        );
+        assert_eq!(legend().token_types.len(), TokenKind::ALL.len());
+    }
+
+    fn flatten(tokens: &[SemanticToken]) -> Vec<u32> {
+        tokens
+            .iter()
+            .flat_map(|token| {
+                [
+                    token.delta_line,
+                    token.delta_start,
+                    token.length,
+                    token.token_type,
+                    token.token_modifiers_bitset,
+                ]
+            })
+            .collect()
+    }
+
+    /// `previous` with the edits of a delta applied, as a client does: in
+    /// reverse order, each against the original array.
+    fn apply_delta(previous: &[SemanticToken], edits: &[SemanticTokensEdit]) -> Vec<u32> {
+        let mut data = flatten(previous);
+        let mut edits = edits.to_vec();
+        edits.sort_by_key(|edit| std::cmp::Reverse(edit.start));
+        for edit in edits {
+            let start = edit.start as usize;
+            let inserted = flatten(edit.data.as_deref().unwrap_or_default());
+            data.splice(start..start + edit.delete_count as usize, inserted);
+        }
+        data
+    }
+
+    #[test]
+    fn deltas_applied_to_the_previous_array_give_the_full_result() {
+        let uri = Url::parse("file:///tmp/highlight.ass").unwrap();
+        let original = include_str!("../tests/fixtures/highlight.ass");
+        let tokens = |text: &str| {
+            let document = AssParser::new().parse(text);
+            semantic_tokens(
+                &document,
+                &LineIndex::new(text.to_string(), PositionEncoding::Utf16),
+            )
+        };
+        let edits = [
+            // A tag added mid-line, a line removed, one added at the end,
+            // an actor renamed and nothing changed at all
+            original.replace("Hello {", "Hello {\\b1}{"),
+            original.replace("Title: 字幕 test\n", ""),
+            format!("{original}Dialogue: 0,0:00:07.00,0:00:08.00,Default,,0,0,0,,{{\\i1}}End\n"),
+            original.replace(",Mio,", ",Akane,"),
+            original.to_string(),
+        ];
+        let last = edits.len() as i32 + 1;
+
+        let mut cache = TokenCache::default();
+        let mut previous = cache.full(&uri, 1, || tokens(original));
+        for (version, text) in (2..).zip(&edits) {
+            let fresh = tokens(text);
+            let previous_id = previous.result_id.clone().unwrap();
+            let result = cache.delta(&uri, version, &previous_id, || fresh.clone());
+            let SemanticTokensFullDeltaResult::TokensDelta(delta) = result else {
+                panic!("no delta from a cached result");
+            };
+            assert_eq!(apply_delta(&previous.data, &delta.edits), flatten(&fresh));
+            assert!(delta.edits.len() <= 1);
+            previous = SemanticTokens {
+                result_id: delta.result_id,
+                data: fresh,
+            };
+        }
+
+        // Asked again for the same version, nothing changed
+        let previous_id = previous.result_id.unwrap();
+        let result = cache.delta(&uri, last, &previous_id, || unreachable!());
+        assert!(
+            matches!(result, SemanticTokensFullDeltaResult::TokensDelta(delta) if delta.edits.is_empty())
+        );
+    }
+
+    #[test]
+    fn cache_is_keyed_by_version_and_dropped_on_close() {
+        let uri = Url::parse("file:///tmp/highlight.ass").unwrap();
+        let mut cache = TokenCache::default();
+        let token = |length| SemanticToken {
+            delta_line: 0,
+            delta_start: 0,
+            length,
+            token_type: 0,
+            token_modifiers_bitset: 0,
+        };
+        let first = cache.full(&uri, 1, || vec![token(1)]);
+        // The same version isn't computed again
+        let again = cache.full(&uri, 1, || unreachable!());
+        assert_eq!(again, first);
+
+        // An unknown result id gets the whole array
+        let result = cache.delta(&uri, 2, "stale", || vec![token(2)]);
+        assert!(
+            matches!(result, SemanticTokensFullDeltaResult::Tokens(tokens) if tokens.data == [token(2)])
+        );
+
+        cache.remove(&uri);
+        let reopened = cache.full(&uri, 1, || vec![token(3)]);
+        assert_eq!(reopened.data, [token(3)]);
+        assert_ne!(reopened.result_id, first.result_id);
+    }
+
+    #[test]
+    fn range_tokens_are_the_full_tokens_on_those_lines() {
+        let text = include_str!("../tests/fixtures/highlight.ass");
+        let document = AssParser::new().parse(text);
+        let index = LineIndex::new(text.to_string(), PositionEncoding::Utf16);
+        let full = decode(&semantic_tokens(&document, &index));
+        for lines in [0..=3, 7..=7, 11..=12, 12..=100] {
+            let expected: Vec<_> = full
+                .iter()
+                .copied()
+                .filter(|(line, ..)| lines.contains(line))
+                .collect();
+            let range = decode(&semantic_tokens_in(&document, &index, lines.clone()));
+            assert_eq!(range, expected, "lines {lines:?}");
+        }
+    }
+}
@@ -0,0 +1,2837 @@
+use std::collections::HashMap;
+use tower_lsp::jsonrpc::Result;
+use tower_lsp::lsp_types::*;
+use tower_lsp::{Client, LanguageServer, LspService, Server};
+
+use crate::advanced::{AdvancedFeatures, PerformanceMetrics};
+use crate::completion::CompletionProvider;
+use crate::history::{DiagnosticHistory, DiagnosticsDeltaParams, DiagnosticsDeltaResponse};
+use crate::hover::HoverProvider;
+use crate::inlay::InlayHintProvider;
+use crate::line_index::{apply_changes, LineIndex, PositionEncoding};
+use crate::parser::{AssDocument, AssParser, AssTime};
+use crate::progress::{Phase, Progress};
+use crate::reflow::LineBalancer;
+use crate::scheduler::{ActiveDocumentParams, DeepPassQueue, DEEP_PASS_CONCURRENCY};
+use crate::settings::Settings;
+use crate::suppression::SuppressionProvider;
+use crate::timeline::{EventsInTimeOrderParams, EventsInTimeOrderResponse};
+use crate::validation::ValidationProvider;
+use crate::workspace::WorkspaceIndex;
+use crate::workspace_check::WorkspaceCheck;
+use crate::{
+    colors, definition, fix_all, folding, fonts, history, lens, links, on_type, parser, progress,
+    reflow, rename, scheduler, semantic, timeline, validation, video, workspace, workspace_check,
+};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+use tokio::sync::Semaphore;
+
+#[derive(Clone)]
+pub struct AssLanguageServer {
+    client: Client,
+    parser: AssParser,
+    completion: Arc<std::sync::RwLock<CompletionProvider>>,
+    hover: Arc<std::sync::RwLock<HoverProvider>>,
+    /// Replaced as a whole when the settings change, so a pass keeps the
+    /// validator it started with.
+    validation: Arc<std::sync::RwLock<Arc<ValidationProvider>>>,
+    suppression: SuppressionProvider,
+    line_balancer: Arc<std::sync::RwLock<LineBalancer>>,
+    inlay_hints: Arc<std::sync::RwLock<InlayHintProvider>>,
+    document_map: Arc<tokio::sync::RwLock<HashMap<Url, DocumentState>>>,
+    /// Text and version of each open document as the client has it. Changes
+    /// are applied here, under a lock held across no await, so they land in
+    /// the order they arrive even while earlier ones are still validating.
+    texts: Arc<std::sync::Mutex<HashMap<Url, (i32, String)>>>,
+    advanced_features: Arc<tokio::sync::RwLock<HashMap<String, AdvancedFeatures>>>,
+    diagnostic_history: Arc<tokio::sync::RwLock<HashMap<Url, DiagnosticHistory>>>,
+    deep_passes: Arc<DeepPassQueue>,
+    /// Dialogue of the scripts next to open documents.
+    workspace: Arc<std::sync::Mutex<WorkspaceIndex>>,
+    /// Paths of the client's workspace folders.
+    workspace_folders: Arc<std::sync::RwLock<Vec<PathBuf>>>,
+    workspace_check: Arc<std::sync::RwLock<WorkspaceCheck>>,
+    /// Scripts the last workspace check found problems in, reported clean
+    /// by the next one once fixed.
+    workspace_findings: Arc<std::sync::Mutex<HashSet<Url>>>,
+    /// Counts changes to scripts, settings and workspace folders, so a
+    /// workspace pull can wait until there is something new to check.
+    workspace_changes: Arc<tokio::sync::watch::Sender<u64>>,
+    /// The count of changes the last workspace pull checked.
+    workspace_pulled: Arc<std::sync::Mutex<Option<u64>>>,
+    /// Negotiated at initialize.
+    position_encoding: Arc<OnceLock<PositionEncoding>>,
+    /// Set at initialize from the client's capabilities.
+    diagnostic_mode: Arc<OnceLock<DiagnosticMode>>,
+    /// Set at initialize: whether the client lets the server register file
+    /// watchers.
+    watches_files: Arc<OnceLock<bool>>,
+    /// Set at initialize: whether the client accepts progress the server
+    /// starts.
+    work_done_progress: Arc<OnceLock<bool>>,
+    semantic_tokens: Arc<std::sync::Mutex<semantic::TokenCache>>,
+    /// Length of each video file scripts were timed against, probed once.
+    video_durations: Arc<std::sync::Mutex<HashMap<PathBuf, Option<AssTime>>>>,
+    /// Format documents on `textDocument/willSaveWaitUntil`.
+    format_on_save: Arc<std::sync::atomic::AtomicBool>,
+    /// Report overlaps between layers, not just within one.
+    strict_overlaps: Arc<std::sync::atomic::AtomicBool>,
+}
+
+/// How diagnostics reach the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum DiagnosticMode {
+    /// Published after every pass.
+    #[default]
+    Push,
+    /// Returned from `textDocument/diagnostic` only, as a client that pulls
+    /// would show pushed ones twice. With `refresh`, the client is asked to
+    /// pull again whenever a deep pass completes, so passes queued by the
+    /// server, such as folder rechecks, reach it too.
+    Pull { refresh: bool },
+}
+
+/// Documents at least this large, in bytes, publish their line diagnostics
+/// section by section when first parsed instead of all at once.
+const STREAMING_THRESHOLD: usize = 1024 * 1024;
+
+/// Lines validated between progress reports.
+const PROGRESS_LINES: usize = 2000;
+
+/// How far analysis of a document version has got.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Analysis {
+    /// Parsed and validated; the advanced checks have not run yet, so the
+    /// published diagnostics are incomplete.
+    Partial,
+    Complete,
+}
+
+/// A script a workspace check read, with what it found.
+struct CheckedScript {
+    uri: Url,
+    /// Client version, for scripts that are open.
+    version: Option<i32>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// An open document, parsed once per change. The parse is also what the next
+/// change splices its reparsed sections into.
+struct DocumentState {
+    index: LineIndex,
+    parsed: AssDocument,
+    /// Client version of the text.
+    version: i32,
+    /// Style and event diagnostics, which depend only on their own line.
+    line_diagnostics: Vec<Diagnostic>,
+    /// Everything the fast pass reports, before suppression.
+    core_diagnostics: Vec<Diagnostic>,
+    /// Fast pass timings, completed by the deep pass.
+    metrics: PerformanceMetrics,
+    analysis: Analysis,
+}
+
+impl AssLanguageServer {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            parser: AssParser::new(),
+            completion: Arc::new(std::sync::RwLock::new(CompletionProvider::new())),
+            hover: Arc::new(std::sync::RwLock::new(HoverProvider::new())),
+            validation: Arc::new(std::sync::RwLock::new(Arc::new(ValidationProvider::new()))),
+            suppression: SuppressionProvider::new(),
+            line_balancer: Arc::new(std::sync::RwLock::new(LineBalancer::new())),
+            inlay_hints: Arc::new(std::sync::RwLock::new(InlayHintProvider::new())),
+            document_map: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+            texts: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            advanced_features: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+            diagnostic_history: Arc::new(tokio::sync::RwLock::new(HashMap::new())),
+            deep_passes: Arc::new(DeepPassQueue::default()),
+            workspace: Arc::new(std::sync::Mutex::new(WorkspaceIndex::default())),
+            workspace_folders: Arc::new(std::sync::RwLock::new(Vec::new())),
+            workspace_check: Arc::new(std::sync::RwLock::new(WorkspaceCheck::new())),
+            workspace_findings: Arc::new(std::sync::Mutex::new(HashSet::new())),
+            workspace_changes: Arc::new(tokio::sync::watch::Sender::new(0)),
+            workspace_pulled: Arc::new(std::sync::Mutex::new(None)),
+            position_encoding: Arc::new(OnceLock::new()),
+            diagnostic_mode: Arc::new(OnceLock::new()),
+            watches_files: Arc::new(OnceLock::new()),
+            work_done_progress: Arc::new(OnceLock::new()),
+            semantic_tokens: Arc::new(std::sync::Mutex::new(semantic::TokenCache::default())),
+            video_durations: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            format_on_save: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            strict_overlaps: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+        }
+    }
+
+    fn position_encoding(&self) -> PositionEncoding {
+        self.position_encoding.get().copied().unwrap_or_default()
+    }
+
+    fn diagnostic_mode(&self) -> DiagnosticMode {
+        self.diagnostic_mode.get().copied().unwrap_or_default()
+    }
+
+    fn validation(&self) -> Arc<ValidationProvider> {
+        self.validation.read().unwrap().clone()
+    }
+
+    /// Builds the providers from client settings. Settings that don't parse
+    /// are reported and leave the current ones in place.
+    async fn configure(&self, value: &serde_json::Value) {
+        let settings = match Settings::from_value(value) {
+            Ok(settings) => settings,
+            Err(error) => {
+                self.client
+                    .show_message(
+                        MessageType::WARNING,
+                        format!("Invalid ass-lsp settings: {error}"),
+                    )
+                    .await;
+                return;
+            }
+        };
+        let unknown = settings.unknown_rules();
+        if !unknown.is_empty() {
+            self.client
+                .log_message(
+                    MessageType::WARNING,
+                    format!("Unknown rules in settings: {}", unknown.join(", ")),
+                )
+                .await;
+        }
+        let validation = settings.validation_provider();
+        if validation.options.check_missing_fonts {
+            // Enumerated off the async threads, so validation only reads the list
+            let _ = tokio::task::spawn_blocking(|| {
+                once_cell::sync::Lazy::force(&fonts::SYSTEM_FONTS);
+            })
+            .await;
+        }
+        *self.validation.write().unwrap() = Arc::new(validation);
+        *self.hover.write().unwrap() = settings.hover();
+        *self.line_balancer.write().unwrap() = settings.line_balancer();
+        *self.inlay_hints.write().unwrap() = settings.inlay_hints();
+        *self.completion.write().unwrap() = settings.completion();
+        *self.workspace_check.write().unwrap() = settings.workspace_check();
+        self.workspace_changed();
+        self.format_on_save.store(
+            settings.format_on_save,
+            std::sync::atomic::Ordering::Relaxed,
+        );
+        self.strict_overlaps.store(
+            settings.strict_overlaps,
+            std::sync::atomic::Ordering::Relaxed,
+        );
+    }
+
+    /// Asks the client to report changes to scripts on disk, through
+    /// `workspace/didChangeWatchedFiles`.
+    async fn watch_scripts(&self) {
+        let watchers = ["**/*.ass", "**/*.ssa"]
+            .into_iter()
+            .map(|pattern| FileSystemWatcher {
+                glob_pattern: GlobPattern::String(pattern.to_string()),
+                kind: None,
+            })
+            .collect();
+        let registration = Registration {
+            id: "ass-lsp-watched-files".to_string(),
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
+                watchers,
+            })
+            .ok(),
+        };
+        if let Err(error) = self.client.register_capability(vec![registration]).await {
+            self.client
+                .log_message(
+                    MessageType::WARNING,
+                    format!("Could not watch script files: {error}"),
+                )
+                .await;
+        }
+    }
+
+    /// Validates every open document again from scratch, the way it is
+    /// validated when opened.
+    async fn revalidate_all(&self) {
+        let documents: Vec<(Url, String, i32)> = self
+            .document_map
+            .read()
+            .await
+            .iter()
+            .map(|(uri, state)| (uri.clone(), state.index.text().to_string(), state.version))
+            .collect();
+        for (uri, text, version) in documents {
+            let progress = self.analysis_progress(&uri);
+            let Some((index, diagnostics)) = self.fast_pass(&uri, text, version, &progress).await
+            else {
+                continue;
+            };
+            self.publish(&uri, version, &index, diagnostics, Analysis::Partial)
+                .await;
+            self.deep_passes.push(uri);
+        }
+    }
+
+    /// Analyses a changed document in full. The change makes it the document
+    /// being worked on, so it doesn't wait behind the queued deep passes.
+    async fn on_change(&self, uri: Url, text: String, version: i32) {
+        self.deep_passes.remove(&uri);
+        let progress = self.analysis_progress(&uri);
+        self.fast_pass(&uri, text, version, &progress).await;
+        self.deep_pass(&uri, &progress).await;
+    }
+
+    /// Progress for analysing the document, shown once the analysis has
+    /// taken [`progress::DELAY`], or from the start for large documents.
+    fn analysis_progress(&self, uri: &Url) -> Progress {
+        let size = self
+            .texts
+            .lock()
+            .unwrap()
+            .get(uri)
+            .map_or(0, |(_, text)| text.len());
+        let delay = if size >= progress::SIZE_THRESHOLD {
+            std::time::Duration::ZERO
+        } else {
+            progress::DELAY
+        };
+        let name = uri
+            .path_segments()
+            .and_then(|mut segments| segments.next_back())
+            .unwrap_or(uri.as_str());
+        Progress::start(
+            &self.client,
+            format!("Analysing {name}"),
+            None,
+            self.work_done_progress.get().copied().unwrap_or(false),
+            delay,
+        )
+    }
+
+    /// Parses the document and runs the core validation, storing the result as
+    /// a partial analysis. Returns the new index and the core diagnostics, or
+    /// `None` if a newer version of the document is already stored.
+    async fn fast_pass(
+        &self,
+        uri: &Url,
+        text: String,
+        version: i32,
+        progress: &Progress,
+    ) -> Option<(LineIndex, Vec<Diagnostic>)> {
+        let validation = self.validation();
+        let index = LineIndex::new(text, self.position_encoding());
+        let text = index.text();
+        let start_time = Instant::now();
+        // Only what the reparse needs is copied out, so requests keep reading
+        // the stored document while this pass parses and streams
+        let previous = {
+            let document_map = self.document_map.read().await;
+            match document_map.get(uri) {
+                Some(state) if state.version > version => return None,
+                // Unchanged text is validated again from scratch, as after a
+                // settings change, so nothing carries over
+                Some(state) if state.index.text() != text => Some((
+                    state.parsed.clone(),
+                    state.index.text().to_string(),
+                    state.line_diagnostics.clone(),
+                )),
+                _ => None,
+            }
+        };
+
+        // Performance tracking
+        let parse_start = Instant::now();
+        progress.step(Phase::Parsing, 0, 1).await;
+        let (parsed, reparsed) = match previous {
+            Some((parsed, previous_text, line_diagnostics)) => {
+                let (parsed, span) = self.parser.reparse(parsed, &previous_text, text);
+                (parsed, Some((span, line_diagnostics)))
+            }
+            None => (self.parser.parse(text), None),
+        };
+        let parse_time = parse_start.elapsed();
+
+        let validation_start = Instant::now();
+        progress.step(Phase::Validating, 0, 1).await;
+        let edited = reparsed.as_ref().map(|(span, _)| span.edited.clone());
+        let line_diagnostics = match reparsed {
+            Some((span, previous)) => {
+                // Keep diagnostics outside the reparsed lines, moved along with them
+                let mut line_diagnostics: Vec<Diagnostic> = previous
+                    .into_iter()
+                    .filter_map(|mut diagnostic| {
+                        let line = diagnostic.range.start.line as usize;
+                        if line >= span.old.end {
+                            let delta = span.line_delta();
+                            diagnostic.range.start.line = (line as i64 + delta) as u32;
+                            diagnostic.range.end.line =
+                                (diagnostic.range.end.line as i64 + delta) as u32;
+                        } else if line >= span.old.start {
+                            return None;
+                        }
+                        Some(diagnostic)
+                    })
+                    .collect();
+                line_diagnostics.extend(validation.validate_lines(&parsed, span.new));
+                line_diagnostics
+            }
+            None if text.len() >= STREAMING_THRESHOLD => {
+                self.stream_line_diagnostics(uri, version, &index, &parsed, progress)
+                    .await
+            }
+            None => {
+                let lines = index.lines().len();
+                validate_lines_stepped(&validation, &parsed, 0..usize::MAX, lines, progress).await
+            }
+        };
+        let mut diagnostics = validation.validate_document(&parsed, uri);
+        diagnostics.extend(line_diagnostics.iter().cloned());
+        // Only reported for the change that wrote the lines
+        if let Some(edited) = edited.filter(|_| validation.options.check_pasted_timing) {
+            diagnostics.extend(validation.validate_pasted_timing(&parsed, edited));
+        }
+        let validation_time = validation_start.elapsed();
+
+        let metrics = PerformanceMetrics {
+            parse_time,
+            validation_time,
+            completion_time: std::time::Duration::default(),
+            total_time: start_time.elapsed(),
+            file_size: text.len(),
+            lines_count: text.lines().count(),
+        };
+        // A newer version may have been stored while this one was validated
+        let mut document_map = self.document_map.write().await;
+        if document_map
+            .get(uri)
+            .is_some_and(|state| state.version > version)
+        {
+            return None;
+        }
+        document_map.insert(
+            uri.clone(),
+            DocumentState {
+                index: index.clone(),
+                parsed,
+                version,
+                line_diagnostics,
+                core_diagnostics: diagnostics.clone(),
+                metrics,
+                analysis: Analysis::Partial,
+            },
+        );
+
+        Some((index, diagnostics))
+    }
+
+    /// Runs the advanced checks on a partially analysed document and publishes
+    /// its complete diagnostics. Does nothing if the document was closed or is
+    /// already complete.
+    async fn deep_pass(&self, uri: &Url, progress: &Progress) {
+        let start_time = Instant::now();
+        let (index, parsed, mut diagnostics, version, mut metrics) = {
+            let document_map = self.document_map.read().await;
+            match document_map.get(uri) {
+                Some(state) if state.analysis == Analysis::Partial => (
+                    state.index.clone(),
+                    state.parsed.clone(),
+                    state.core_diagnostics.clone(),
+                    state.version,
+                    state.metrics.clone(),
+                ),
+                _ => return,
+            }
+        };
+
+        // Advanced features, taken out of the map so deep passes of other
+        // documents can run alongside
+        let file_path = uri.to_string();
+        let mut advanced = self
+            .advanced_features
+            .write()
+            .await
+            .remove(&file_path)
+            .unwrap_or_else(|| AdvancedFeatures::new(file_path.clone()));
+        advanced.strict_overlaps = self
+            .strict_overlaps
+            .load(std::sync::atomic::Ordering::Relaxed);
+
+        // Advanced validation
+        progress.step(Phase::AdvancedChecks, 0, 1).await;
+        let style_warnings = advanced.analyze_style_inheritance(&index);
+        let advanced_warnings = advanced.validate_advanced(&index);
+        diagnostics.extend(
+            advanced.detect_timing_overlaps(&parsed, uri, &mut |swept, total| {
+                progress.phase(Phase::Overlaps, swept, total)
+            }),
+        );
+        if self.validation().options.check_cross_file_duplicates {
+            diagnostics.extend(self.cross_file_duplicates(uri, &parsed).await);
+        }
+        if let Some(duration) = self.video_duration(uri, &parsed).await {
+            diagnostics.extend(self.validation().validate_video_duration(&parsed, duration));
+        }
+
+        // Add advanced warnings as diagnostics, covering their line's text
+        let lines = index.lines();
+        for warning in style_warnings.iter().chain(advanced_warnings.iter()) {
+            let text = lines.get(warning.line as usize).copied().unwrap_or("");
+            let start = text.len() - text.trim_start().len();
+            let end = text.trim_end().len().max(start);
+            diagnostics.push(Diagnostic {
+                range: Range {
+                    start: Position::new(warning.line, start as u32),
+                    end: Position::new(warning.line, end as u32),
+                },
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: None,
+                code_description: None,
+                source: Some("ass-lsp-advanced".to_string()),
+                message: warning.message.clone(),
+                related_information: None,
+                tags: None,
+                data: None,
+            });
+        }
+
+        // Record performance metrics
+        metrics.total_time += start_time.elapsed();
+        advanced.record_performance_metrics(metrics);
+
+        // Log performance suggestions
+        let suggestions = advanced.get_performance_suggestions();
+        for suggestion in suggestions {
+            self.client.log_message(MessageType::INFO, suggestion).await;
+        }
+
+        self.advanced_features
+            .write()
+            .await
+            .insert(file_path, advanced);
+
+        self.publish(uri, version, &index, diagnostics, Analysis::Complete)
+            .await;
+    }
+
+    /// Diagnostics for lines another script in the document's folder also
+    /// has. The folder is read from disk the first time; after that only
+    /// this document's entry is refreshed, and if it changed, the other open
+    /// scripts in the folder are checked again.
+    async fn cross_file_duplicates(&self, uri: &Url, parsed: &AssDocument) -> Vec<Diagnostic> {
+        let Ok(path) = uri.to_file_path() else {
+            return Vec::new();
+        };
+        let Some(folder) = path.parent().map(std::path::Path::to_path_buf) else {
+            return Vec::new();
+        };
+
+        if self.workspace.lock().unwrap().needs_folder(&folder) {
+            let open = self
+                .document_map
+                .read()
+                .await
+                .keys()
+                .filter_map(|uri| uri.to_file_path().ok())
+                .collect();
+            let parser = self.parser.clone();
+            let reading = folder.clone();
+            let scripts = tokio::task::spawn_blocking(move || {
+                workspace::read_folder(&reading, &open, &parser)
+            })
+            .await
+            .unwrap_or_default();
+            self.workspace.lock().unwrap().add_folder(&folder, scripts);
+        }
+
+        let (changed, duplicates) = {
+            let mut workspace = self.workspace.lock().unwrap();
+            let changed = workspace.update_open(&path, parsed);
+            (changed, workspace.duplicates(&path))
+        };
+        if changed {
+            self.recheck_folder(&folder, uri).await;
+        }
+
+        let document_map = self.document_map.read().await;
+        duplicates
+            .iter()
+            .map(|duplicate| {
+                let related =
+                    Url::from_file_path(&duplicate.other_path)
+                        .ok()
+                        .and_then(|other_uri| {
+                            let range = document_map
+                                .get(&other_uri)?
+                                .index
+                                .range(duplicate.other.range);
+                            Some(Location::new(other_uri, range))
+                        });
+                workspace::duplicate_diagnostic(duplicate, related)
+            })
+            .collect()
+    }
+
+    /// Length of the video the document names, probed off the async
+    /// threads the first time it is asked for.
+    async fn video_duration(&self, uri: &Url, parsed: &AssDocument) -> Option<AssTime> {
+        let path = links::video_path(uri, parsed)?;
+        if let Some(duration) = self.video_durations.lock().unwrap().get(&path) {
+            return *duration;
+        }
+        let probing = path.clone();
+        let duration = tokio::task::spawn_blocking(move || video::probe_duration(&probing))
+            .await
+            .ok()
+            .flatten();
+        self.video_durations.lock().unwrap().insert(path, duration);
+        duration
+    }
+
+    /// Queues a deep pass for the open scripts in `folder` other than `uri`,
+    /// whose cross-file duplicates may have changed.
+    async fn recheck_folder(&self, folder: &std::path::Path, uri: &Url) {
+        let mut document_map = self.document_map.write().await;
+        for (other, state) in document_map.iter_mut() {
+            let in_folder = other
+                .to_file_path()
+                .is_ok_and(|path| path.parent() == Some(folder));
+            if other != uri && in_folder {
+                state.analysis = Analysis::Partial;
+                self.deep_passes.push(other.clone());
+            }
+        }
+    }
+
+    /// Applies suppressions, records the set in the diagnostics history and
+    /// sends it to the client, unless a newer version has replaced the one
+    /// it was computed for.
+    async fn publish(
+        &self,
+        uri: &Url,
+        version: i32,
+        index: &LineIndex,
+        diagnostics: Vec<Diagnostic>,
+        analysis: Analysis,
+    ) {
+        // Held while publishing, so a newer version's diagnostics can't be
+        // published before these
+        let mut document_map = self.document_map.write().await;
+        match document_map.get_mut(uri) {
+            Some(state) if state.version == version => state.analysis = analysis,
+            _ => return,
+        }
+
+        let diagnostics = self.send_diagnostics(uri, index, diagnostics).await;
+        self.diagnostic_history
+            .write()
+            .await
+            .entry(uri.clone())
+            .or_default()
+            .record(version, index.text(), diagnostics);
+        drop(document_map);
+        if analysis == Analysis::Complete
+            && self.diagnostic_mode() == (DiagnosticMode::Pull { refresh: true })
+        {
+            let _ = self.client.workspace_diagnostic_refresh().await;
+        }
+    }
+
+    /// Applies suppressions and converts diagnostics to the client's position
+    /// encoding, sending them unless the client pulls. Returns the result.
+    async fn send_diagnostics(
+        &self,
+        uri: &Url,
+        index: &LineIndex,
+        diagnostics: Vec<Diagnostic>,
+    ) -> Vec<Diagnostic> {
+        let diagnostics = self.client_diagnostics(uri, index, diagnostics);
+        if self.diagnostic_mode() == DiagnosticMode::Push {
+            self.client
+                .publish_diagnostics(uri.clone(), diagnostics.clone(), None)
+                .await;
+        }
+        self.workspace_changed();
+        diagnostics
+    }
+
+    /// Applies suppressions and converts diagnostics to the client's position
+    /// encoding.
+    fn client_diagnostics(
+        &self,
+        uri: &Url,
+        index: &LineIndex,
+        diagnostics: Vec<Diagnostic>,
+    ) -> Vec<Diagnostic> {
+        let validation = self.validation();
+        let diagnostics = validation.apply_rule_levels(diagnostics);
+        let diagnostics = self.suppression.apply(index.text(), diagnostics);
+        let mut diagnostics = validation.limit_diagnostics(diagnostics);
+        for diagnostic in &mut diagnostics {
+            diagnostic.range = index.range(diagnostic.range);
+            // Locations in other documents are already in client positions
+            for related in diagnostic
+                .related_information
+                .iter_mut()
+                .flatten()
+                .filter(|related| related.location.uri == *uri)
+            {
+                related.location.range = index.range(related.location.range);
+            }
+        }
+        diagnostics
+    }
+
+    /// Diagnostics for the script at `uri` holding `text`, from the checks
+    /// the fast pass runs and in client positions.
+    fn script_diagnostics(&self, uri: &Url, text: String) -> Vec<Diagnostic> {
+        let index = LineIndex::new(text, self.position_encoding());
+        let document = self.parser.parse(index.text());
+        let diagnostics = self.validation().validate(&document, uri);
+        self.client_diagnostics(uri, &index, diagnostics)
+    }
+
+    fn workspace_changed(&self) {
+        self.workspace_changes.send_modify(|changes| *changes += 1);
+    }
+
+    /// Checks the scripts under the workspace folders, open ones as the
+    /// editor has them and the rest from disk, reporting progress on
+    /// `token`, or on one created for it. Returns the scripts with findings,
+    /// along with those the previous check found problems in that are clean
+    /// or gone now, which come back with no findings.
+    async fn check_workspace(&self, token: Option<NumberOrString>) -> Vec<CheckedScript> {
+        let folders = self.workspace_folders.read().unwrap().clone();
+        let check = self.workspace_check.read().unwrap().clone();
+        let progress = Progress::start(
+            &self.client,
+            "Checking scripts".to_string(),
+            token,
+            self.work_done_progress.get().copied().unwrap_or(false),
+            std::time::Duration::ZERO,
+        );
+        let listing = check.clone();
+        let finding = progress.clone();
+        let paths = tokio::task::spawn_blocking(move || {
+            listing.scripts(&folders, &mut |found| {
+                finding.report(&format!("Found {found} scripts"), Some(0))
+            })
+        })
+        .await
+        .unwrap_or_default();
+
+        let total = paths.len();
+        let mut checked = Vec::new();
+        for (done, path) in paths.into_iter().enumerate() {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            progress.report(&name, Some((done * 100 / total) as u32));
+            let open = self
+                .texts
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|(uri, _)| uri.to_file_path().is_ok_and(|open| open == path))
+                .map(|(uri, (version, text))| (uri.clone(), Some(*version), text.clone()));
+            let (uri, version, text) = match open {
+                Some(open) => open,
+                None => {
+                    let Ok(uri) = Url::from_file_path(&path) else {
+                        continue;
+                    };
+                    let reading = check.clone();
+                    let text = tokio::task::spawn_blocking(move || reading.read(&path))
+                        .await
+                        .ok()
+                        .flatten();
+                    let Some(text) = text else {
+                        continue;
+                    };
+                    (uri, None, text)
+                }
+            };
+            let diagnostics = self.script_diagnostics(&uri, text);
+            checked.push(CheckedScript {
+                uri,
+                version,
+                diagnostics,
+            });
+        }
+
+        let found: HashSet<Url> = checked
+            .iter()
+            .filter(|script| !script.diagnostics.is_empty())
+            .map(|script| script.uri.clone())
+            .collect();
+        let with_problems = found.len();
+        let previous = std::mem::replace(&mut *self.workspace_findings.lock().unwrap(), found);
+        checked.retain(|script| !script.diagnostics.is_empty() || previous.contains(&script.uri));
+        for uri in previous {
+            if !checked.iter().any(|script| script.uri == uri) {
+                checked.push(CheckedScript {
+                    uri,
+                    version: None,
+                    diagnostics: Vec::new(),
+                });
+            }
+        }
+        progress.report(
+            &format!("Checked {total} scripts, {with_problems} with problems"),
+            Some(100),
+        );
+        checked
+    }
+
+    /// Validates the lines of a newly parsed large document a section at a
+    /// time, sending the diagnostics found so far after each section that adds
+    /// any. Every send is the cumulative set, so the client never sees results
+    /// disappear; the caller publishes the complete set at the end. Nothing is
+    /// sent once a newer `version` is stored.
+    async fn stream_line_diagnostics(
+        &self,
+        uri: &Url,
+        version: i32,
+        index: &LineIndex,
+        parsed: &AssDocument,
+        progress: &Progress,
+    ) -> Vec<Diagnostic> {
+        let lines = index.lines().len();
+        let mut boundaries: Vec<usize> = parsed
+            .sections
+            .iter()
+            .map(|section| section.range.start.line as usize)
+            .collect();
+        boundaries.insert(0, 0);
+        boundaries.push(usize::MAX);
+
+        let validation = self.validation();
+        let mut diagnostics = Vec::new();
+        for chunk in boundaries.windows(2) {
+            let found =
+                validate_lines_stepped(&validation, parsed, chunk[0]..chunk[1], lines, progress)
+                    .await;
+            if found.is_empty() {
+                continue;
+            }
+            diagnostics.extend(found);
+            let superseded = self
+                .document_map
+                .read()
+                .await
+                .get(uri)
+                .is_some_and(|state| state.version > version);
+            if !superseded {
+                self.send_diagnostics(uri, index, diagnostics.clone()).await;
+            }
+        }
+        diagnostics
+    }
+
+    /// Runs queued deep passes, a few at a time, for the life of the server.
+    /// Each burst of opens is left to settle first so every document gets its
+    /// fast pass before any deep one starts.
+    async fn run_deep_passes(self) {
+        let permits = Arc::new(Semaphore::new(DEEP_PASS_CONCURRENCY));
+        loop {
+            self.deep_passes.settled().await;
+            loop {
+                // Taken before popping, so a document prioritized while every
+                // pass is busy still goes next
+                let permit = permits.clone().acquire_owned().await.unwrap();
+                let Some(uri) = self.deep_passes.pop() else {
+                    break;
+                };
+                let server = self.clone();
+                tokio::spawn(async move {
+                    let progress = server.analysis_progress(&uri);
+                    server.deep_pass(&uri, &progress).await;
+                    drop(permit);
+                });
+            }
+        }
+    }
+}
+
+impl AssLanguageServer {
+    async fn events_in_time_order(
+        &self,
+        params: EventsInTimeOrderParams,
+    ) -> Result<EventsInTimeOrderResponse> {
+        let uri = &params.text_document.uri;
+
+        let document_map = self.document_map.read().await;
+        let Some(state) = document_map.get(uri) else {
+            return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                "Document not open: {uri}"
+            )));
+        };
+
+        Ok(timeline::events_in_time_order(
+            &state.parsed,
+            Some(state.version),
+            &params,
+        ))
+    }
+
+    async fn diagnostics_delta(
+        &self,
+        params: DiagnosticsDeltaParams,
+    ) -> Result<DiagnosticsDeltaResponse> {
+        let uri = &params.text_document.uri;
+        let history = self.diagnostic_history.read().await;
+        let Some(history) = history.get(uri) else {
+            return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                "Document not open: {uri}"
+            )));
+        };
+
+        history
+            .delta(params.from, params.to)
+            .map_err(tower_lsp::jsonrpc::Error::invalid_params)
+    }
+
+    async fn active_document(&self, params: ActiveDocumentParams) {
+        self.deep_passes.prioritize(&params.text_document.uri);
+    }
+
+    /// "Balance line breaks" for each event in `range` whose rows could be
+    /// evened out, and one for the whole document when it has several.
+    fn balance_actions(
+        &self,
+        uri: &Url,
+        state: &DocumentState,
+        range: Range,
+    ) -> Vec<CodeActionOrCommand> {
+        let balancer = self.line_balancer.read().unwrap();
+        let (start, _) = state.index.clamp(range.start);
+        let (end, _) = state.index.clamp(range.end);
+        let mut actions: Vec<CodeActionOrCommand> = balancer
+            .balanced_events(&state.parsed, start as u32..=end as u32)
+            .into_iter()
+            .map(|balanced| {
+                CodeActionOrCommand::CodeAction(CodeAction {
+                    title: "Balance line breaks".to_string(),
+                    kind: Some(CodeActionKind::REFACTOR_REWRITE),
+                    edit: Some(reflow::balance_edit(uri, &state.index, &[balanced])),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        let count = balancer.balanced_events(&state.parsed, 0..=u32::MAX).len();
+        if count > 1 {
+            actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: format!("Balance line breaks in all {count} unbalanced lines"),
+                kind: Some(CodeActionKind::SOURCE),
+                command: Some(Command {
+                    title: "Balance line breaks".to_string(),
+                    command: reflow::BALANCE_LINE_BREAKS.to_string(),
+                    arguments: Some(vec![serde_json::json!(uri)]),
+                }),
+                ..Default::default()
+            }));
+        }
+        actions
+    }
+
+    /// The combined fix of [`fix_all::fix_all`] as a `source.fixAll.assLsp`
+    /// action. It is taken against the text the client has, which the parsed
+    /// document may lag behind, as it runs on save.
+    fn fix_all_action(&self, uri: &Url) -> Option<CodeActionOrCommand> {
+        let text = self.texts.lock().unwrap().get(uri)?.1.clone();
+        let fixed = fix_all::fix_all(&text, &self.parser, &self.validation());
+        if fixed == text {
+            return None;
+        }
+        let index = LineIndex::new(text, self.position_encoding());
+        Some(CodeActionOrCommand::CodeAction(CodeAction {
+            title: "Fix all auto-fixable problems".to_string(),
+            kind: Some(CodeActionKind::new(fix_all::FIX_ALL)),
+            edit: Some(WorkspaceEdit {
+                changes: Some(HashMap::from([(uri.clone(), index.edits_to(&fixed))])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }))
+    }
+
+    /// "Merge into <style>" actions for `equivalent_style` diagnostics. The
+    /// merge is disabled when another open document uses one of the duplicates,
+    /// since renaming them here would break that file.
+    async fn merge_style_actions(
+        &self,
+        uri: &Url,
+        diagnostics: &[Diagnostic],
+    ) -> Vec<CodeActionOrCommand> {
+        let document_map = self.document_map.read().await;
+        let Some(state) = document_map.get(uri) else {
+            return Vec::new();
+        };
+        let mut actions = Vec::new();
+
+        for diagnostic in diagnostics.iter().filter(|diagnostic| {
+            diagnostic.code == Some(NumberOrString::String("equivalent_style".to_string()))
+        }) {
+            let Some(data) = &diagnostic.data else {
+                continue;
+            };
+            let Some(target) = data["mergeInto"].as_str() else {
+                continue;
+            };
+            let duplicates: Vec<String> = data["duplicates"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|name| name.as_str().map(str::to_string))
+                .collect();
+
+            let external_user = document_map
+                .iter()
+                .filter(|(other, _)| *other != uri)
+                .find(|(_, other)| {
+                    duplicates
+                        .iter()
+                        .any(|name| rename::references_style(&other.parsed, name))
+                })
+                .map(|(other, _)| other);
+
+            let mut action = CodeAction {
+                title: format!("Merge into {target}"),
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![diagnostic.clone()]),
+                ..Default::default()
+            };
+            match external_user {
+                Some(other) => {
+                    action.disabled = Some(CodeActionDisabled {
+                        reason: format!("A merged style is used by {other}"),
+                    });
+                }
+                None => {
+                    action.edit = Some(rename::merge_styles_edit(
+                        uri,
+                        &state.index,
+                        &state.parsed,
+                        target,
+                        &duplicates,
+                    ));
+                }
+            }
+            actions.push(CodeActionOrCommand::CodeAction(action));
+        }
+
+        actions
+    }
+}
+
+#[tower_lsp::async_trait]
+impl LanguageServer for AssLanguageServer {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        let encoding = PositionEncoding::negotiate(
+            params
+                .capabilities
+                .general
+                .as_ref()
+                .and_then(|general| general.position_encodings.as_deref()),
+        );
+        let _ = self.position_encoding.set(encoding);
+        let pulls = params
+            .capabilities
+            .text_document
+            .as_ref()
+            .is_some_and(|text_document| text_document.diagnostic.is_some());
+        let _ = self.diagnostic_mode.set(if pulls {
+            let refresh = params
+                .capabilities
+                .workspace
+                .as_ref()
+                .and_then(|workspace| workspace.diagnostic.as_ref())
+                .and_then(|diagnostic| diagnostic.refresh_support)
+                .unwrap_or(false);
+            DiagnosticMode::Pull { refresh }
+        } else {
+            DiagnosticMode::Push
+        });
+        let watches_files = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.did_change_watched_files.as_ref())
+            .and_then(|watched| watched.dynamic_registration)
+            .unwrap_or(false);
+        let _ = self.watches_files.set(watches_files);
+        let work_done_progress = params
+            .capabilities
+            .window
+            .as_ref()
+            .and_then(|window| window.work_done_progress)
+            .unwrap_or(false);
+        let _ = self.work_done_progress.set(work_done_progress);
+        #[allow(deprecated)]
+        let folders: Vec<Url> = match &params.workspace_folders {
+            Some(folders) => folders.iter().map(|folder| folder.uri.clone()).collect(),
+            None => params.root_uri.iter().cloned().collect(),
+        };
+        *self.workspace_folders.write().unwrap() = folders
+            .iter()
+            .filter_map(|uri| uri.to_file_path().ok())
+            .collect();
+        if let Some(options) = &params.initialization_options {
+            self.configure(options).await;
+        }
+
+        Ok(InitializeResult {
+            capabilities: ServerCapabilities {
+                position_encoding: Some(encoding.kind()),
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        change: Some(TextDocumentSyncKind::INCREMENTAL),
+                        will_save_wait_until: Some(true),
+                        ..Default::default()
+                    },
+                )),
+                completion_provider: Some(CompletionOptions {
+                    resolve_provider: Some(true),
+                    trigger_characters: Some(vec![
+                        "\\".to_string(),
+                        "{".to_string(),
+                        ",".to_string(),
+                        ":".to_string(),
+                    ]),
+                    work_done_progress_options: Default::default(),
+                    all_commit_characters: None,
+                    completion_item: None,
+                }),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                color_provider: Some(ColorProviderCapability::Simple(true)),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: Some(true),
+                }),
+                inlay_hint_provider: Some(OneOf::Left(true)),
+                definition_provider: Some(OneOf::Left(true)),
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+                references_provider: Some(OneOf::Left(true)),
+                semantic_tokens_provider: Some(
+                    SemanticTokensServerCapabilities::SemanticTokensOptions(
+                        SemanticTokensOptions {
+                            legend: semantic::legend(),
+                            range: Some(true),
+                            full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
+                            work_done_progress_options: Default::default(),
+                        },
+                    ),
+                ),
+                rename_provider: Some(OneOf::Right(RenameOptions {
+                    prepare_provider: Some(true),
+                    work_done_progress_options: Default::default(),
+                })),
+                diagnostic_provider: Some(DiagnosticServerCapabilities::Options(
+                    DiagnosticOptions {
+                        identifier: Some("ass-lsp".to_string()),
+                        inter_file_dependencies: false,
+                        workspace_diagnostics: true,
+                        work_done_progress_options: WorkDoneProgressOptions {
+                            work_done_progress: Some(true),
+                        },
+                    },
+                )),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![
+                            CodeActionKind::QUICKFIX,
+                            CodeActionKind::REFACTOR_REWRITE,
+                            CodeActionKind::SOURCE,
+                            CodeActionKind::new(fix_all::FIX_ALL),
+                        ]),
+                        ..Default::default()
+                    },
+                )),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![
+                        reflow::BALANCE_LINE_BREAKS.to_string(),
+                        timeline::SORT_EVENTS.to_string(),
+                        validation::CONVERT_ALL_LEGACY_ALIGNMENT.to_string(),
+                        validation::REMOVE_UNUSED_STYLES.to_string(),
+                        workspace_check::CHECK_WORKSPACE.to_string(),
+                    ],
+                    work_done_progress_options: WorkDoneProgressOptions {
+                        work_done_progress: Some(true),
+                    },
+                }),
+                document_formatting_provider: Some(OneOf::Left(true)),
+                document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+                    first_trigger_character: on_type::OPEN_BRACE.to_string(),
+                    more_trigger_character: Some(vec![on_type::CLOSE_BRACE.to_string()]),
+                }),
+                document_symbol_provider: Some(OneOf::Left(true)),
+                document_link_provider: Some(DocumentLinkOptions {
+                    resolve_provider: Some(true),
+                    work_done_progress_options: Default::default(),
+                }),
+                workspace: Some(WorkspaceServerCapabilities {
+                    workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+                        supported: Some(true),
+                        change_notifications: Some(OneOf::Left(true)),
+                    }),
+                    file_operations: None,
+                }),
+                ..ServerCapabilities::default()
+            },
+            server_info: Some(ServerInfo {
+                name: "ass-lsp".to_string(),
+                version: Some("0.1.0".to_string()),
+            }),
+        })
+    }
+
+    async fn initialized(&self, _: InitializedParams) {
+        self.client
+            .log_message(MessageType::INFO, "ASS Language Server initialized!")
+            .await;
+        if self.watches_files.get().copied().unwrap_or(false) {
+            self.watch_scripts().await;
+        }
+        tokio::spawn(self.clone().run_deep_passes());
+    }
+
+    async fn shutdown(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        self.texts.lock().unwrap().insert(
+            params.text_document.uri.clone(),
+            (
+                params.text_document.version,
+                params.text_document.text.clone(),
+            ),
+        );
+        self.client
+            .log_message(MessageType::INFO, "file opened!")
+            .await;
+        // Opens often come in bursts when an editor restores a session, so
+        // only the fast pass runs here and the deep pass is queued
+        let uri = params.text_document.uri;
+        let version = params.text_document.version;
+        let progress = self.analysis_progress(&uri);
+        let Some((index, diagnostics)) = self
+            .fast_pass(&uri, params.text_document.text, version, &progress)
+            .await
+        else {
+            return;
+        };
+        self.publish(&uri, version, &index, diagnostics, Analysis::Partial)
+            .await;
+        self.deep_passes.push(uri);
+    }
+
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        let uri = params.text_document.uri;
+        let version = params.text_document.version;
+        let text = {
+            let mut texts = self.texts.lock().unwrap();
+            match texts.get_mut(&uri) {
+                Some((current, text)) if version > *current => {
+                    *text = apply_changes(
+                        std::mem::take(text),
+                        &params.content_changes,
+                        self.position_encoding(),
+                    );
+                    *current = version;
+                    Some(text.clone())
+                }
+                _ => None,
+            }
+        };
+        let Some(text) = text else {
+            // Applying it to text it wasn't made against would garble the
+            // document, so a stale change or one for an unopened document is
+            // dropped
+            self.client
+                .log_message(
+                    MessageType::WARNING,
+                    format!("Ignored change to {uri} at version {version}"),
+                )
+                .await;
+            return;
+        };
+        self.on_change(uri, text, version).await;
+    }
+
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        self.configure(&params.settings).await;
+        self.revalidate_all().await;
+    }
+
+    async fn did_save(&self, _: DidSaveTextDocumentParams) {
+        self.client
+            .log_message(MessageType::INFO, "file saved!")
+            .await;
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        self.texts.lock().unwrap().remove(&params.text_document.uri);
+        let mut document_map = self.document_map.write().await;
+        document_map.remove(&params.text_document.uri);
+        drop(document_map);
+        if let Ok(path) = params.text_document.uri.to_file_path() {
+            let changed = self.workspace.lock().unwrap().close(&path, &self.parser);
+            if let Some(folder) = path.parent().filter(|_| changed) {
+                self.recheck_folder(folder, &params.text_document.uri).await;
+            }
+        }
+        self.deep_passes.remove(&params.text_document.uri);
+        self.advanced_features
+            .write()
+            .await
+            .remove(params.text_document.uri.as_str());
+        self.semantic_tokens
+            .lock()
+            .unwrap()
+            .remove(&params.text_document.uri);
+        self.diagnostic_history
+            .write()
+            .await
+            .remove(&params.text_document.uri);
+        self.workspace_changed();
+        self.client
+            .log_message(MessageType::INFO, "file closed!")
+            .await;
+    }
+
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        let paths = |folders: Vec<WorkspaceFolder>| -> Vec<PathBuf> {
+            folders
+                .into_iter()
+                .filter_map(|folder| folder.uri.to_file_path().ok())
+                .collect()
+        };
+        let removed = paths(params.event.removed);
+        let added = paths(params.event.added);
+        {
+            let mut folders = self.workspace_folders.write().unwrap();
+            folders.retain(|folder| !removed.contains(folder));
+            folders.extend(added);
+        }
+        self.workspace_changed();
+    }
+
+    /// Scripts changed outside the editor, e.g. saved by Aegisub. An open
+    /// document keeps the editor's text, which the client will reconcile;
+    /// for the rest, state built from the old copy is dropped and open
+    /// documents in the same folder are checked against the new one.
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        self.workspace_changed();
+        for change in params.changes {
+            let open = self.texts.lock().unwrap().contains_key(&change.uri);
+            if open {
+                if change.typ != FileChangeType::CREATED {
+                    self.client
+                        .log_message(
+                            MessageType::INFO,
+                            format!(
+                                "{} changed on disk while open; keeping the editor's text",
+                                change.uri
+                            ),
+                        )
+                        .await;
+                }
+                continue;
+            }
+            self.advanced_features
+                .write()
+                .await
+                .remove(change.uri.as_str());
+            let Ok(path) = change.uri.to_file_path() else {
+                continue;
+            };
+            let changed = self.workspace.lock().unwrap().reload(&path, &self.parser);
+            if let Some(folder) = path.parent().filter(|_| changed) {
+                self.recheck_folder(folder, &change.uri).await;
+            }
+        }
+    }
+
+    /// Checks the scripts under the workspace folders. Clients pull again
+    /// as soon as a report arrives, so a pull after one that saw the current
+    /// state waits for a change first.
+    async fn workspace_diagnostic(
+        &self,
+        params: WorkspaceDiagnosticParams,
+    ) -> Result<WorkspaceDiagnosticReportResult> {
+        let mut changes = self.workspace_changes.subscribe();
+        let pulled = *self.workspace_pulled.lock().unwrap();
+        if let Some(pulled) = pulled {
+            let _ = changes.wait_for(|changes| *changes != pulled).await;
+        }
+        let seen = *changes.borrow_and_update();
+
+        let token = params.work_done_progress_params.work_done_token;
+        let items = self
+            .check_workspace(token)
+            .await
+            .into_iter()
+            .map(|script| {
+                WorkspaceDocumentDiagnosticReport::Full(WorkspaceFullDocumentDiagnosticReport {
+                    uri: script.uri,
+                    version: script.version.map(i64::from),
+                    full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                        result_id: None,
+                        items: script.diagnostics,
+                    },
+                })
+            })
+            .collect();
+        *self.workspace_pulled.lock().unwrap() = Some(seen);
+        Ok(WorkspaceDiagnosticReportResult::Report(
+            WorkspaceDiagnosticReport { items },
+        ))
+    }
+
+    /// Finishes the analysis of the document's latest version and returns
+    /// what it found, or just the result id if the client already
+    /// has that set.
+    async fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> Result<DocumentDiagnosticReportResult> {
+        let uri = params.text_document.uri;
+        // The pull can arrive before the fast pass of the latest change has
+        // stored its result
+        let text = self.texts.lock().unwrap().get(&uri).cloned();
+        let progress = self.analysis_progress(&uri);
+        if let Some((version, text)) = text {
+            let stored = self
+                .document_map
+                .read()
+                .await
+                .get(&uri)
+                .map(|state| state.version);
+            if stored.is_none_or(|stored| stored < version) {
+                self.fast_pass(&uri, text, version, &progress).await;
+            }
+        }
+        self.deep_passes.remove(&uri);
+        self.deep_pass(&uri, &progress).await;
+
+        let history = self.diagnostic_history.read().await;
+        let latest = history.get(&uri).and_then(DiagnosticHistory::latest);
+        let report = match latest {
+            Some((result_id, _)) if params.previous_result_id.as_deref() == Some(result_id) => {
+                DocumentDiagnosticReport::Unchanged(RelatedUnchangedDocumentDiagnosticReport {
+                    related_documents: None,
+                    unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                        result_id: result_id.to_string(),
+                    },
+                })
+            }
+            _ => DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+                related_documents: None,
+                full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                    result_id: latest.map(|(result_id, _)| result_id.to_string()),
+                    items: latest.map_or_else(Vec::new, |(_, items)| items.to_vec()),
+                },
+            }),
+        };
+        Ok(DocumentDiagnosticReportResult::Report(report))
+    }
+
+    async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        let uri = &params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
+
+        let document_map = self.document_map.read().await;
+        if let Some(state) = document_map.get(uri) {
+            let completions = self.completion.read().unwrap().provide_completions(
+                &state.parsed,
+                &state.index,
+                position,
+            );
+            return Ok(completions.map(CompletionResponse::List));
+        }
+
+        Ok(None)
+    }
+
+    async fn completion_resolve(&self, params: CompletionItem) -> Result<CompletionItem> {
+        Ok(self.completion.read().unwrap().resolve(params))
+    }
+
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        let uri = &params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+
+        let document_map = self.document_map.read().await;
+        Ok(document_map.get(uri).and_then(|state| {
+            let link = definition::style_definition(uri, &state.parsed, &state.index, position)?;
+            Some(GotoDefinitionResponse::Link(vec![link]))
+        }))
+    }
+
+    async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+        let uri = &params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
+
+        let document_map = self.document_map.read().await;
+        Ok(document_map.get(uri).and_then(|state| {
+            definition::style_references(
+                uri,
+                &state.parsed,
+                &state.index,
+                position,
+                params.context.include_declaration,
+            )
+        }))
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>> {
+        let document_map = self.document_map.read().await;
+        Ok(document_map
+            .get(&params.text_document.uri)
+            .and_then(|state| {
+                let (parsed, index) = (&state.parsed, &state.index);
+                definition::style_name_range(parsed, index, params.position)
+                    .or_else(|| rename::actor_range(parsed, index, params.position))
+            })
+            .map(PrepareRenameResponse::Range))
+    }
+
+    /// Renames the style or actor under the cursor.
+    async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        let uri = &params.text_document_position.text_document.uri;
+        let position = params.text_document_position.position;
+        let new_name = &params.new_name;
+
+        let document_map = self.document_map.read().await;
+        let Some(state) = document_map.get(uri) else {
+            return Ok(None);
+        };
+        let (parsed, index) = (&state.parsed, &state.index);
+        let (problem, edit) = if rename::actor_range(parsed, index, position).is_some() {
+            (
+                rename::actor_name_problem(new_name),
+                rename::actor_rename_edit(uri, index, parsed, position, new_name),
+            )
+        } else {
+            (
+                rename::style_name_problem(new_name),
+                rename::style_rename_edit(uri, index, parsed, position, new_name),
+            )
+        };
+        match problem {
+            Some(problem) => Err(tower_lsp::jsonrpc::Error::invalid_params(problem)),
+            None => Ok(edit),
+        }
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> Result<Option<SemanticTokensResult>> {
+        let uri = &params.text_document.uri;
+        let document_map = self.document_map.read().await;
+        Ok(document_map.get(uri).map(|state| {
+            let mut cache = self.semantic_tokens.lock().unwrap();
+            SemanticTokensResult::Tokens(cache.full(uri, state.version, || {
+                semantic::semantic_tokens(&state.parsed, &state.index)
+            }))
+        }))
+    }
+
+    async fn semantic_tokens_full_delta(
+        &self,
+        params: SemanticTokensDeltaParams,
+    ) -> Result<Option<SemanticTokensFullDeltaResult>> {
+        let uri = &params.text_document.uri;
+        let document_map = self.document_map.read().await;
+        Ok(document_map.get(uri).map(|state| {
+            let mut cache = self.semantic_tokens.lock().unwrap();
+            cache.delta(uri, state.version, &params.previous_result_id, || {
+                semantic::semantic_tokens(&state.parsed, &state.index)
+            })
+        }))
+    }
+
+    async fn semantic_tokens_range(
+        &self,
+        params: SemanticTokensRangeParams,
+    ) -> Result<Option<SemanticTokensRangeResult>> {
+        let document_map = self.document_map.read().await;
+        Ok(document_map.get(&params.text_document.uri).map(|state| {
+            let lines = params.range.start.line..=params.range.end.line;
+            SemanticTokensRangeResult::Tokens(SemanticTokens {
+                result_id: None,
+                data: semantic::semantic_tokens_in(&state.parsed, &state.index, lines),
+            })
+        }))
+    }
+
+    async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        let document_map = self.document_map.read().await;
+        let Some(state) = document_map.get(&params.text_document.uri) else {
+            return Ok(None);
+        };
+        let (start, _) = state.index.clamp(params.range.start);
+        let (end, _) = state.index.clamp(params.range.end);
+        let hints = self.inlay_hints.read().unwrap().inlay_hints(
+            &state.parsed,
+            &state.index,
+            start as u32..=end as u32,
+        );
+        Ok(Some(hints))
+    }
+
+    async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let uri = &params.text_document_position_params.text_document.uri;
+        let position = params.text_document_position_params.position;
+
+        let document_map = self.document_map.read().await;
+        if let Some(state) = document_map.get(uri) {
+            return Ok(self.hover.read().unwrap().provide_hover(
+                &state.parsed,
+                &state.index,
+                position,
+            ));
+        }
+
+        Ok(None)
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let uri = &params.text_document.uri;
+        let mut actions = match self.document_map.read().await.get(uri) {
+            Some(state) => {
+                let mut actions =
+                    self.validation()
+                        .quick_fixes(uri, &state.index, &params.context.diagnostics);
+                actions.extend(self.balance_actions(uri, state, params.range));
+                actions.extend(sort_events_actions(uri, &params.context.diagnostics));
+                actions.extend(legacy_alignment_actions(uri, state));
+                actions.extend(unused_styles_actions(uri, state));
+                actions
+            }
+            None => Vec::new(),
+        };
+        actions.extend(
+            self.merge_style_actions(uri, &params.context.diagnostics)
+                .await,
+        );
+        if fix_all::requested(params.context.only.as_deref()) {
+            actions.extend(self.fix_all_action(uri));
+        }
+        if actions.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(actions))
+    }
+
+    async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        let uri = &params.text_document.uri;
+
+        let document_map = self.document_map.read().await;
+        if let Some(state) = document_map.get(uri) {
+            let text = state.index.text();
+            let mut formatted = self.parser.format(text);
+            if params.options.insert_final_newline == Some(true) && !formatted.ends_with('\n') {
+                formatted.push_str(parser::detect_line_ending(text));
+            }
+            if formatted != text {
+                return Ok(Some(state.index.edits_to(&formatted)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn will_save_wait_until(
+        &self,
+        params: WillSaveTextDocumentParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        if !self
+            .format_on_save
+            .load(std::sync::atomic::Ordering::Relaxed)
+        {
+            return Ok(None);
+        }
+        let text = self
+            .texts
+            .lock()
+            .unwrap()
+            .get(&params.text_document.uri)
+            .map(|(_, text)| text.clone());
+        let Some(text) = text else {
+            return Ok(None);
+        };
+        let formatted = self.parser.format(&text);
+        if formatted == text {
+            return Ok(None);
+        }
+        let index = LineIndex::new(text, self.position_encoding());
+        Ok(Some(index.edits_to(&formatted)))
+    }
+
+    async fn on_type_formatting(
+        &self,
+        params: DocumentOnTypeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        let uri = &params.text_document_position.text_document.uri;
+        // The stored document may not have the typed character yet
+        let texts = self.texts.lock().unwrap();
+        Ok(texts.get(uri).and_then(|(_, text)| {
+            on_type::on_type_edits(
+                text,
+                self.position_encoding(),
+                params.text_document_position.position,
+                &params.ch,
+            )
+        }))
+    }
+
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> Result<Option<DocumentSymbolResponse>> {
+        let uri = &params.text_document.uri;
+
+        let document_map = self.document_map.read().await;
+        if let Some(state) = document_map.get(uri) {
+            let mut symbols = self.parser.extract_symbols(&state.parsed);
+            client_symbol_ranges(&state.index, &mut symbols);
+            return Ok(Some(DocumentSymbolResponse::Nested(symbols)));
+        }
+
+        Ok(None)
+    }
+
+    async fn folding_range(&self, params: FoldingRangeParams) -> Result<Option<Vec<FoldingRange>>> {
+        let document_map = self.document_map.read().await;
+        Ok(document_map
+            .get(&params.text_document.uri)
+            .map(|state| folding::folding_ranges(&state.parsed, &state.index)))
+    }
+
+    async fn document_color(&self, params: DocumentColorParams) -> Result<Vec<ColorInformation>> {
+        let document_map = self.document_map.read().await;
+        Ok(document_map
+            .get(&params.text_document.uri)
+            .map(|state| colors::document_colors(&state.parsed, &state.index))
+            .unwrap_or_default())
+    }
+
+    async fn color_presentation(
+        &self,
+        params: ColorPresentationParams,
+    ) -> Result<Vec<ColorPresentation>> {
+        let document_map = self.document_map.read().await;
+        Ok(document_map
+            .get(&params.text_document.uri)
+            .map(|state| {
+                colors::color_presentations(&state.parsed, &state.index, params.range, params.color)
+            })
+            .unwrap_or_default())
+    }
+
+    async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+        let uri = &params.text_document.uri;
+        let document_map = self.document_map.read().await;
+        Ok(document_map
+            .get(uri)
+            .map(|state| lens::code_lenses(uri, &state.parsed, &state.index)))
+    }
+
+    async fn code_lens_resolve(&self, params: CodeLens) -> Result<CodeLens> {
+        let Some(data) = lens::LensData::from_lens(&params) else {
+            return Ok(params);
+        };
+        let document_map = self.document_map.read().await;
+        let Some(state) = document_map.get(&data.uri) else {
+            return Ok(params);
+        };
+        Ok(lens::resolve_code_lens(
+            params,
+            data,
+            &state.parsed,
+            &state.index,
+        ))
+    }
+
+    async fn document_link(&self, params: DocumentLinkParams) -> Result<Option<Vec<DocumentLink>>> {
+        let uri = &params.text_document.uri;
+
+        let document_map = self.document_map.read().await;
+        if let Some(state) = document_map.get(uri) {
+            let links = links::document_links(uri, &state.parsed, &state.index);
+            return Ok(Some(links));
+        }
+
+        Ok(None)
+    }
+
+    async fn execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> Result<Option<serde_json::Value>> {
+        let command = params.command.as_str();
+        if command == workspace_check::CHECK_WORKSPACE {
+            let token = params.work_done_progress_params.work_done_token;
+            let checked = self.check_workspace(token).await;
+            let problems: usize = checked.iter().map(|script| script.diagnostics.len()).sum();
+            for script in &checked {
+                // Open documents publish their own diagnostics
+                if !self.texts.lock().unwrap().contains_key(&script.uri) {
+                    self.client
+                        .publish_diagnostics(script.uri.clone(), script.diagnostics.clone(), None)
+                        .await;
+                }
+            }
+            return Ok(Some(serde_json::json!({
+                "scripts": checked.iter().filter(|script| !script.diagnostics.is_empty()).count(),
+                "diagnostics": problems,
+            })));
+        }
+        if ![
+            reflow::BALANCE_LINE_BREAKS,
+            timeline::SORT_EVENTS,
+            validation::CONVERT_ALL_LEGACY_ALIGNMENT,
+            validation::REMOVE_UNUSED_STYLES,
+        ]
+        .contains(&command)
+        {
+            return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                "Unknown command: {command}"
+            )));
+        }
+        let Some(uri) = params
+            .arguments
+            .first()
+            .and_then(|argument| serde_json::from_value::<Url>(argument.clone()).ok())
+        else {
+            return Err(tower_lsp::jsonrpc::Error::invalid_params(
+                "Expected the document URI",
+            ));
+        };
+
+        let edit = {
+            let document_map = self.document_map.read().await;
+            let Some(state) = document_map.get(&uri) else {
+                return Err(tower_lsp::jsonrpc::Error::invalid_params(format!(
+                    "Document not open: {uri}"
+                )));
+            };
+            match command {
+                timeline::SORT_EVENTS => {
+                    timeline::sort_events_edit(&uri, &state.parsed, &state.index)
+                }
+                validation::CONVERT_ALL_LEGACY_ALIGNMENT => {
+                    validation::legacy_alignment_edit(&uri, &state.parsed, &state.index)
+                }
+                validation::REMOVE_UNUSED_STYLES => {
+                    validation::unused_styles_edit(&uri, &state.parsed, &state.index)
+                }
+                _ => {
+                    let balanced = self
+                        .line_balancer
+                        .read()
+                        .unwrap()
+                        .balanced_events(&state.parsed, 0..=u32::MAX);
+                    (!balanced.is_empty())
+                        .then(|| reflow::balance_edit(&uri, &state.index, &balanced))
+                }
+            }
+        };
+        let Some(edit) = edit else {
+            return Ok(None);
+        };
+        if let Err(error) = self.client.apply_edit(edit).await {
+            let action = match command {
+                timeline::SORT_EVENTS => "sort events",
+                validation::CONVERT_ALL_LEGACY_ALIGNMENT => "convert legacy alignment",
+                validation::REMOVE_UNUSED_STYLES => "remove unused styles",
+                _ => "balance line breaks",
+            };
+            self.client
+                .log_message(MessageType::WARNING, format!("Could not {action}: {error}"))
+                .await;
+        }
+        Ok(None)
+    }
+
+    async fn document_link_resolve(&self, params: DocumentLink) -> Result<DocumentLink> {
+        Ok(links::resolve_link(params))
+    }
+}
+
+/// Validates `lines` of the document [`PROGRESS_LINES`] at a time, reporting
+/// how far through its `total` lines each piece ends.
+async fn validate_lines_stepped(
+    validation: &ValidationProvider,
+    parsed: &AssDocument,
+    lines: std::ops::Range<usize>,
+    total: usize,
+    progress: &Progress,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut start = lines.start;
+    while start < lines.end {
+        // The last piece runs to the end of the range, past the lines counted
+        let end = match start.saturating_add(PROGRESS_LINES) {
+            end if end >= total => lines.end,
+            end => end.min(lines.end),
+        };
+        diagnostics.extend(validation.validate_lines(parsed, start..end));
+        progress.step(Phase::Validating, end, total).await;
+        start = end;
+    }
+    diagnostics
+}
+
+/// "Sort events by start time" for an `unsorted_events` diagnostic, running
+/// the command so the edit is worked out against the latest text.
+fn sort_events_actions(uri: &Url, diagnostics: &[Diagnostic]) -> Vec<CodeActionOrCommand> {
+    diagnostics
+        .iter()
+        .filter(|diagnostic| {
+            diagnostic.code == Some(NumberOrString::String("unsorted_events".to_string()))
+        })
+        .map(|diagnostic| {
+            CodeActionOrCommand::CodeAction(CodeAction {
+                title: "Sort events by start time".to_string(),
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![diagnostic.clone()]),
+                command: Some(Command {
+                    title: "Sort events by start time".to_string(),
+                    command: timeline::SORT_EVENTS.to_string(),
+                    arguments: Some(vec![serde_json::json!(uri)]),
+                }),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// "Convert all legacy alignment tags" as a source action running the
+/// command, like sorting, offered when the document has a `\a` to convert.
+fn legacy_alignment_actions(uri: &Url, state: &DocumentState) -> Vec<CodeActionOrCommand> {
+    if validation::legacy_alignment_edit(uri, &state.parsed, &state.index).is_none() {
+        return Vec::new();
+    }
+    let title = "Convert all legacy alignment tags to \\an".to_string();
+    vec![CodeActionOrCommand::CodeAction(CodeAction {
+        title: title.clone(),
+        kind: Some(CodeActionKind::SOURCE),
+        command: Some(Command {
+            title,
+            command: validation::CONVERT_ALL_LEGACY_ALIGNMENT.to_string(),
+            arguments: Some(vec![serde_json::json!(uri)]),
+        }),
+        ..Default::default()
+    })]
+}
+
+/// "Remove all unused styles" as a source action running the command, offered
+/// when the document has a style no event uses.
+fn unused_styles_actions(uri: &Url, state: &DocumentState) -> Vec<CodeActionOrCommand> {
+    if validation::unused_styles_edit(uri, &state.parsed, &state.index).is_none() {
+        return Vec::new();
+    }
+    let title = "Remove all unused styles".to_string();
+    vec![CodeActionOrCommand::CodeAction(CodeAction {
+        title: title.clone(),
+        kind: Some(CodeActionKind::SOURCE),
+        command: Some(Command {
+            title,
+            command: validation::REMOVE_UNUSED_STYLES.to_string(),
+            arguments: Some(vec![serde_json::json!(uri)]),
+        }),
+        ..Default::default()
+    })]
+}
+
+/// Converts symbol ranges, which the parser keeps in byte columns, for the client.
+fn client_symbol_ranges(index: &LineIndex, symbols: &mut [DocumentSymbol]) {
+    for symbol in symbols {
+        symbol.range = index.range(symbol.range);
+        symbol.selection_range = index.range(symbol.selection_range);
+        if let Some(children) = &mut symbol.children {
+            client_symbol_ranges(index, children);
+        }
+    }
+}
+
+/// Serves the language server over `input` and `output` until the client
+/// exits, as the binary does over stdin and stdout.
+pub async fn serve(input: impl tokio::io::AsyncRead + Unpin, output: impl tokio::io::AsyncWrite) {
+    let (service, socket) = service();
+    Server::new(input, output, socket).serve(service).await;
+}
+
+/// The server with its custom methods, ready to serve.
+fn service() -> (LspService<AssLanguageServer>, tower_lsp::ClientSocket) {
+    LspService::build(AssLanguageServer::new)
+        .custom_method(
+            timeline::EVENTS_IN_TIME_ORDER,
+            AssLanguageServer::events_in_time_order,
+        )
+        .custom_method(
+            history::DIAGNOSTICS_DELTA,
+            AssLanguageServer::diagnostics_delta,
+        )
+        .custom_method(
+            scheduler::ACTIVE_DOCUMENT,
+            AssLanguageServer::active_document,
+        )
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::collections::VecDeque;
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::io::{DuplexStream, ReadHalf, WriteHalf};
+
+    /// A client talking JSON-RPC to a served [`AssLanguageServer`]. Requests
+    /// from the server are answered with `null` and kept with the
+    /// notifications.
+    struct TestClient {
+        reader: BufReader<ReadHalf<DuplexStream>>,
+        writer: WriteHalf<DuplexStream>,
+        next_id: i64,
+        notifications: VecDeque<Value>,
+        responses: HashMap<i64, Value>,
+    }
+
+    impl TestClient {
+        async fn start() -> Self {
+            Self::start_with(Value::Null).await
+        }
+
+        /// Starts a server configured with `settings` as its initialization
+        /// options.
+        async fn start_with(settings: Value) -> Self {
+            Self::start_as(json!({}), settings).await
+        }
+
+        /// Starts a server for a client with `capabilities`.
+        async fn start_as(capabilities: Value, settings: Value) -> Self {
+            let (client, server) = tokio::io::duplex(1 << 20);
+            let (server_read, server_write) = tokio::io::split(server);
+            let (service, socket) = service();
+            tokio::spawn(Server::new(server_read, server_write, socket).serve(service));
+            let (reader, writer) = tokio::io::split(client);
+            let mut client = Self {
+                reader: BufReader::new(reader),
+                writer,
+                next_id: 0,
+                notifications: VecDeque::new(),
+                responses: HashMap::new(),
+            };
+            client
+                .request(
+                    "initialize",
+                    json!({ "capabilities": capabilities, "initializationOptions": settings }),
+                )
+                .await;
+            client.notify("initialized", json!({})).await;
+            client
+        }
+
+        async fn write(&mut self, message: Value) {
+            let body = message.to_string();
+            let frame = format!("Content-Length: {}\r\n\r\n{body}", body.len());
+            self.writer.write_all(frame.as_bytes()).await.unwrap();
+        }
+
+        async fn read(&mut self) -> Value {
+            let mut length = 0;
+            loop {
+                let mut header = String::new();
+                self.reader.read_line(&mut header).await.unwrap();
+                let header = header.trim_end();
+                if header.is_empty() {
+                    break;
+                }
+                if let Some(value) = header.strip_prefix("Content-Length: ") {
+                    length = value.parse().unwrap();
+                }
+            }
+            let mut body = vec![0; length];
+            self.reader.read_exact(&mut body).await.unwrap();
+            serde_json::from_slice(&body).unwrap()
+        }
+
+        /// Reads one message, filing it as a response or a notification.
+        async fn pump(&mut self) {
+            let message = tokio::time::timeout(Duration::from_secs(10), self.read())
+                .await
+                .expect("server went quiet");
+            match (message.get("id").cloned(), message.get("method")) {
+                (Some(id), Some(_)) => {
+                    self.write(json!({ "jsonrpc": "2.0", "id": id, "result": null }))
+                        .await;
+                    self.notifications.push_back(message);
+                }
+                (Some(id), None) => {
+                    self.responses.insert(id.as_i64().unwrap(), message);
+                }
+                (None, _) => self.notifications.push_back(message),
+            }
+        }
+
+        async fn notify(&mut self, method: &str, params: Value) {
+            self.write(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+                .await;
+        }
+
+        /// Sends a request without waiting for its response.
+        async fn send(&mut self, method: &str, params: Value) -> i64 {
+            self.next_id += 1;
+            let id = self.next_id;
+            self.write(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))
+                .await;
+            id
+        }
+
+        async fn response(&mut self, id: i64) -> Value {
+            loop {
+                if let Some(response) = self.responses.remove(&id) {
+                    return response;
+                }
+                self.pump().await;
+            }
+        }
+
+        async fn request(&mut self, method: &str, params: Value) -> Value {
+            let id = self.send(method, params).await;
+            let response = self.response(id).await;
+            assert!(response.get("error").is_none(), "{response}");
+            response["result"].clone()
+        }
+
+        async fn next_notification(&mut self, method: &str) -> Value {
+            loop {
+                let position = self
+                    .notifications
+                    .iter()
+                    .position(|notification| notification["method"] == method);
+                if let Some(position) = position {
+                    return self.notifications.remove(position).unwrap()["params"].take();
+                }
+                self.pump().await;
+            }
+        }
+
+        /// The first diagnostics published for `uri` that satisfy `done`,
+        /// dropping earlier ones.
+        async fn diagnostics(&mut self, uri: &str, done: impl Fn(&[Value]) -> bool) -> Vec<Value> {
+            loop {
+                let params = self
+                    .next_notification("textDocument/publishDiagnostics")
+                    .await;
+                let diagnostics = params["diagnostics"].as_array().unwrap();
+                if params["uri"] == uri && done(diagnostics) {
+                    return diagnostics.clone();
+                }
+            }
+        }
+
+        async fn open(&mut self, uri: &str, text: &str) {
+            let document = json!({ "uri": uri, "languageId": "ass", "version": 1, "text": text });
+            self.notify("textDocument/didOpen", json!({ "textDocument": document }))
+                .await;
+        }
+
+        async fn replace(&mut self, uri: &str, version: i32, text: &str) {
+            let params = json!({
+                "textDocument": { "uri": uri, "version": version },
+                "contentChanges": [{ "text": text }],
+            });
+            self.notify("textDocument/didChange", params).await;
+        }
+    }
+
+    const URI: &str = "file:///tmp/server-test.ass";
+
+    fn script(events: impl IntoIterator<Item = String>) -> String {
+        let mut text = String::from(
+            "[Script Info]\nScriptType: v4.00+\nPlayResX: 1920\nPlayResY: 1080\n\n[V4+ Styles]\nFormat: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\nStyle: Default,Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1\n\n[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n",
+        );
+        for event in events {
+            text.push_str(&event);
+            text.push('\n');
+        }
+        text
+    }
+
+    fn dialogue(index: usize, style: &str, text: &str) -> String {
+        let start = AssTime((index as u32) * 300);
+        let end = AssTime((index as u32) * 300 + 200);
+        format!("Dialogue: 0,{start},{end},{style},,0,0,0,,{text}")
+    }
+
+    /// The first line of a hover result, if any.
+    fn hover_title(result: &Value) -> Option<String> {
+        let contents = result.get("contents")?.as_str()?;
+        Some(contents.lines().next()?.to_string())
+    }
+
+    #[tokio::test]
+    async fn hover_on_a_stale_version_survives_rapid_changes() {
+        let mut client = TestClient::start().await;
+        let long = script((0..200).map(|i| dialogue(i, "Default", "{\\fad(100,200)}Line")));
+        client.open(URI, &long).await;
+        let tag_line = 11 + 199;
+        let tag_column = long.lines().nth(tag_line).unwrap().find("\\fad").unwrap() + 1;
+
+        // Each change shrinks or grows the script under hovers made against
+        // the first version, some past the end of the newer text
+        let mut hovers = Vec::new();
+        for version in 2..=30 {
+            let count = if version % 2 == 0 { 3 } else { 150 };
+            let text = script((0..count).map(|i| dialogue(i, "Default", "Plain {\\b1}text")));
+            client.replace(URI, version, &text).await;
+            let position = json!({ "line": tag_line, "character": tag_column });
+            let params = json!({ "textDocument": { "uri": URI }, "position": position });
+            hovers.push(client.send("textDocument/hover", params).await);
+        }
+        // Out of order, so ignored
+        client.replace(URI, 7, &long).await;
+        let last = script([dialogue(0, "Final", "Plain {\\fad(100,200)}text")]);
+        client.replace(URI, 31, &last).await;
+
+        for id in hovers {
+            let response = client.response(id).await;
+            assert!(response.get("error").is_none(), "{response}");
+            let result = &response["result"];
+            assert!(
+                result.is_null() || hover_title(result).is_some(),
+                "{response}"
+            );
+        }
+        let diagnostics = client
+            .diagnostics(URI, |diagnostics| {
+                diagnostics
+                    .iter()
+                    .any(|diagnostic| diagnostic["code"] == "undefined_style")
+            })
+            .await;
+        assert!(!diagnostics.is_empty());
+
+        let hover = |character: u32| {
+            json!({
+                "textDocument": { "uri": URI },
+                "position": { "line": 11, "character": character },
+            })
+        };
+        let column = last.lines().nth(11).unwrap().find("\\fad").unwrap() as u32 + 1;
+        let result = client.request("textDocument/hover", hover(column)).await;
+        assert_eq!(hover_title(&result).as_deref(), Some("**Simple Fade**"));
+        let result = client.request("textDocument/hover", hover(200)).await;
+        assert!(hover_title(&result).is_none());
+    }
+
+    fn has_code(diagnostics: &Value, code: &str) -> bool {
+        diagnostics
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|diagnostic| diagnostic["code"] == code)
+    }
+
+    #[tokio::test]
+    async fn restored_session_gets_every_fast_pass_before_deep_passes() {
+        let mut client = TestClient::start().await;
+        // Overlaps are only found by the deep pass
+        let text = script((0..400).map(|i| {
+            let (start, end) = (AssTime(i * 100), AssTime(i * 100 + 250));
+            format!("Dialogue: 0,{start},{end},Default,,0,0,0,,Line {i}")
+        }));
+        let uris: Vec<String> = (0..8)
+            .map(|i| format!("file:///tmp/session-{i}.ass"))
+            .collect();
+        for uri in &uris {
+            client.open(uri, &text).await;
+        }
+
+        let mut partial = HashSet::new();
+        let mut complete = HashSet::new();
+        while complete.len() < uris.len() {
+            let params = client
+                .next_notification("textDocument/publishDiagnostics")
+                .await;
+            let uri = params["uri"].as_str().unwrap().to_string();
+            if has_code(&params["diagnostics"], "timing_overlap") {
+                assert_eq!(
+                    partial.len(),
+                    uris.len(),
+                    "deep pass before every fast pass"
+                );
+                complete.insert(uri);
+            } else {
+                assert!(!complete.contains(&uri), "partial after complete");
+                partial.insert(uri);
+            }
+        }
+        assert_eq!(partial, uris.iter().cloned().collect());
+    }
+
+    /// Every diagnostics publish for `uri` up to and including the deep
+    /// pass's, which alone reports overlaps.
+    async fn publishes_through_deep_pass(client: &mut TestClient, uri: &str) -> Vec<Value> {
+        let mut publishes = Vec::new();
+        loop {
+            let params = client
+                .next_notification("textDocument/publishDiagnostics")
+                .await;
+            if params["uri"] != uri {
+                continue;
+            }
+            let deep = has_code(&params["diagnostics"], "timing_overlap");
+            publishes.push(params["diagnostics"].clone());
+            if deep {
+                return publishes;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn large_script_streams_sections_and_ends_as_a_whole_run_would() {
+        let uri = "file:///tmp/large.ass";
+        let mut client = TestClient::start().await;
+        let event = |i: u32, style: &str| {
+            let (start, end) = (AssTime(i * 60), AssTime(i * 60 + 55));
+            format!("Dialogue: 0,{start},{end},{style},,0,0,0,,Hi there")
+        };
+        let mut events: Vec<String> = (0..18000)
+            .map(|i| event(i, if i % 6000 == 5 { "Missing" } else { "Default" }))
+            .collect();
+        events.push("Dialogue: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,Overlapping".into());
+        let default = "Style: Default,Arial,48,";
+        let text = script(events).replacen(
+            default,
+            &format!("Style: Broken,Arial,0,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1\n{default}"),
+            1,
+        );
+        assert!(text.len() >= STREAMING_THRESHOLD);
+
+        client.open(uri, &text).await;
+        let streamed = publishes_through_deep_pass(&mut client, uri).await;
+        // The styles section is sent before the events are validated
+        assert!(streamed.len() >= 3, "{} publishes", streamed.len());
+        assert!(has_code(&streamed[0], "zero_font_size"));
+        assert!(!has_code(&streamed[0], "undefined_style"));
+        assert!(has_code(&streamed[1], "undefined_style"));
+
+        // An edit and its undo take the incremental path back to the same
+        // text, which validates it in one go
+        let edited = text.replacen("Overlapping", "Edited", 1);
+        client.replace(uri, 2, &edited).await;
+        publishes_through_deep_pass(&mut client, uri).await;
+        client.replace(uri, 3, &text).await;
+        let whole = publishes_through_deep_pass(&mut client, uri).await;
+        assert_eq!(
+            whole.len(),
+            1,
+            "a change publishes once, after the deep pass"
+        );
+        assert_eq!(streamed.last(), whole.last());
+    }
+
+    #[tokio::test]
+    async fn lines_pasted_fourteen_minutes_late_are_shifted_back() {
+        const SAMPLE: &str = include_str!("../tests/fixtures/sample.ass");
+        let mut client = TestClient::start_with(json!({ "checkPastedTiming": true })).await;
+        client.open(URI, SAMPLE).await;
+        // Only an edit of a stored version tells which lines it wrote
+        client.diagnostics(URI, |_| true).await;
+
+        // Ten lines timed to follow Bob's, but against video 14 minutes longer
+        let bob = SAMPLE.find("Dialogue: 1,").unwrap();
+        let pasted: String = (0..10)
+            .map(|i| {
+                let start = AssTime(14 * 6000 + 700 + i * 100);
+                let end = AssTime(start.0 + 90);
+                format!("Dialogue: 0,{start},{end},Default,,0,0,0,,Pasted line {i}\n")
+            })
+            .collect();
+        let text = format!("{}{pasted}{}", &SAMPLE[..bob], &SAMPLE[bob..]);
+        client.replace(URI, 2, &text).await;
+
+        let diagnostics = client
+            .diagnostics(URI, |diagnostics| {
+                diagnostics
+                    .iter()
+                    .any(|diagnostic| diagnostic["code"] == "pasted_timing")
+            })
+            .await;
+        let pasted_timing: Vec<Value> = diagnostics
+            .into_iter()
+            .filter(|diagnostic| diagnostic["code"] == "pasted_timing")
+            .collect();
+        assert_eq!(pasted_timing.len(), 1);
+        let first_line = SAMPLE[..bob].lines().count() as u64;
+        assert_eq!(pasted_timing[0]["range"]["start"]["line"], first_line);
+        assert_eq!(pasted_timing[0]["range"]["end"]["line"], first_line + 9);
+        assert_eq!(pasted_timing[0]["data"]["offset"], -14 * 6000);
+
+        let params = json!({
+            "textDocument": { "uri": URI },
+            "range": pasted_timing[0]["range"],
+            "context": { "diagnostics": pasted_timing },
+        });
+        let actions = client.request("textDocument/codeAction", params).await;
+        let shift = actions
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|action| action["title"] == "Shift pasted lines by -0:14:00.00")
+            .expect("shift action");
+        let edits: Vec<TextEdit> =
+            serde_json::from_value(shift["edit"]["changes"][URI].clone()).unwrap();
+        assert_eq!(edits.len(), 20);
+        let shifted = crate::line_index::apply_edits(&text, PositionEncoding::Utf16, &edits);
+        let expected: String = (0..10)
+            .map(|i| {
+                let start = AssTime(700 + i * 100);
+                let end = AssTime(start.0 + 90);
+                format!("Dialogue: 0,{start},{end},Default,,0,0,0,,Pasted line {i}\n")
+            })
+            .collect();
+        assert_eq!(
+            shifted,
+            format!("{}{expected}{}", &SAMPLE[..bob], &SAMPLE[bob..])
+        );
+    }
+
+    /// The part of `line` between two UTF-16 columns.
+    fn utf16_slice(line: &str, start: &Value, end: &Value) -> String {
+        let units: Vec<u16> = line.encode_utf16().collect();
+        let (start, end) = (start.as_u64().unwrap(), end.as_u64().unwrap());
+        String::from_utf16(&units[start as usize..end as usize]).unwrap()
+    }
+
+    #[tokio::test]
+    async fn cjk_hovers_and_squiggles_line_up_in_utf16() {
+        const CJK: &str = include_str!("../tests/fixtures/cjk.ass");
+        let uri = "file:///tmp/cjk.ass";
+        let mut client = TestClient::start().await;
+        client.open(uri, CJK).await;
+        let lines: Vec<&str> = CJK.lines().collect();
+
+        let diagnostics = client
+            .diagnostics(uri, |diagnostics| {
+                diagnostics
+                    .iter()
+                    .any(|diagnostic| diagnostic["code"] == "unknown_tag")
+            })
+            .await;
+        let mut squiggled = Vec::new();
+        for diagnostic in &diagnostics {
+            let range = &diagnostic["range"];
+            let line = lines[range["start"]["line"].as_u64().unwrap() as usize];
+            let text = utf16_slice(
+                line,
+                &range["start"]["character"],
+                &range["end"]["character"],
+            );
+            match diagnostic["code"].as_str() {
+                Some("unknown_tag") => squiggled.push(text),
+                Some("unclosed_override") => assert_eq!(text, "{\\bord"),
+                _ => {}
+            }
+        }
+        assert_eq!(squiggled, ["\\blurr", "\\blurr", "\\bordd"]);
+
+        // Every tag after CJK, emoji and Hangul text hovers as itself
+        let mut hovered = 0;
+        for (line, text) in lines.iter().enumerate() {
+            for (byte, tag) in [("\\bord", "**Border**"), ("\\fs", "**Font Size**")]
+                .iter()
+                .flat_map(|&(tag, title)| text.match_indices(tag).map(move |(i, _)| (i, title)))
+            {
+                let character = text[..byte].encode_utf16().count() + 1;
+                let params = json!({
+                    "textDocument": { "uri": uri },
+                    "position": { "line": line, "character": character },
+                });
+                let result = client.request("textDocument/hover", params).await;
+                let title = hover_title(&result);
+                if text[byte..].starts_with("\\bordd") {
+                    assert_ne!(title.as_deref(), Some(tag), "{line}:{character}");
+                } else {
+                    assert_eq!(title.as_deref(), Some(tag), "{line}:{character}");
+                    hovered += 1;
+                }
+            }
+        }
+        assert_eq!(hovered, 4);
+    }
+
+    #[tokio::test]
+    async fn line_shared_by_split_scripts_is_reported_in_each_when_opened() {
+        let mut client = TestClient::start_with(json!({ "crossFileDuplicates": true })).await;
+        let folder = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/split");
+        let dialogue = Url::from_file_path(folder.join("dialogue.ass")).unwrap();
+        let signs = Url::from_file_path(folder.join("signs.ass")).unwrap();
+        let shared = |diagnostics: &[Value]| {
+            diagnostics
+                .iter()
+                .find(|diagnostic| diagnostic["code"] == "cross_file_duplicate")
+                .cloned()
+        };
+
+        let text = std::fs::read_to_string(folder.join("dialogue.ass")).unwrap();
+        client.open(dialogue.as_str(), &text).await;
+        let diagnostics = client
+            .diagnostics(dialogue.as_str(), |d| shared(d).is_some())
+            .await;
+        let found = shared(&diagnostics).unwrap();
+        assert_eq!(found["range"]["start"]["line"], 13);
+        assert_eq!(found["severity"], 3);
+        assert!(found["message"]
+            .as_str()
+            .unwrap()
+            .contains("signs.ass on line 13"));
+        // The signs aren't open, so there is nowhere in the editor to point
+        assert!(found.get("relatedInformation").is_none());
+
+        let text = std::fs::read_to_string(folder.join("signs.ass")).unwrap();
+        client.open(signs.as_str(), &text).await;
+        let diagnostics = client
+            .diagnostics(signs.as_str(), |d| shared(d).is_some())
+            .await;
+        let found = shared(&diagnostics).unwrap();
+        assert_eq!(found["range"]["start"]["line"], 12);
+        assert!(found["message"]
+            .as_str()
+            .unwrap()
+            .contains("dialogue.ass on line 14"));
+        let related = &found["relatedInformation"][0]["location"];
+        assert_eq!(related["uri"], dialogue.as_str());
+        assert_eq!(related["range"]["start"]["line"], 13);
+    }
+
+    #[tokio::test]
+    async fn change_batches_apply_in_order_and_stale_versions_are_dropped() {
+        let mut client = TestClient::start().await;
+        let text = script((0..3).map(|i| dialogue(i, "Default", "Line")));
+        client.open(URI, &text).await;
+        client.diagnostics(URI, |_| true).await;
+
+        // Line 14 only exists once the first change pushes the last line down
+        let added = dialogue(5, "Default", "Added");
+        let last = dialogue(2, "Default", "Line").len();
+        let params = json!({
+            "textDocument": { "uri": URI, "version": 2 },
+            "contentChanges": [
+                { "range": { "start": { "line": 13, "character": 0 }, "end": { "line": 13, "character": 0 } },
+                  "text": format!("{added}\n") },
+                { "range": { "start": { "line": 14, "character": last }, "end": { "line": 14, "character": last } },
+                  "text": "{\\b1" },
+                { "range": { "start": { "line": 11, "character": 0 }, "end": { "line": 12, "character": 0 } },
+                  "text": "" },
+            ],
+        });
+        client.notify("textDocument/didChange", params).await;
+        let diagnostics = client
+            .diagnostics(URI, |d| d.iter().any(|d| d["code"] == "unclosed_override"))
+            .await;
+        let unclosed: Vec<&Value> = diagnostics
+            .iter()
+            .filter(|d| d["code"] == "unclosed_override")
+            .collect();
+        assert_eq!(unclosed.len(), 1);
+        assert_eq!(unclosed[0]["range"]["start"]["line"], 13);
+
+        // A change made against version 1 would garble version 2
+        client.replace(URI, 2, "").await;
+        let ignored = format!("Ignored change to {URI} at version 2");
+        while client.next_notification("window/logMessage").await["message"] != ignored {}
+        let id = client
+            .send(
+                "textDocument/foldingRange",
+                json!({ "textDocument": { "uri": URI } }),
+            )
+            .await;
+        let folds = client.response(id).await["result"].clone();
+        assert_eq!(folds.as_array().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn pulled_diagnostics_are_full_then_unchanged_and_never_pushed() {
+        let capabilities = json!({ "textDocument": { "diagnostic": {} } });
+        let mut client = TestClient::start_as(capabilities, Value::Null).await;
+        let overlapping = [
+            dialogue(0, "Default", "One"),
+            "Dialogue: 0,0:00:00.10,0:00:01.00,Default,,0,0,0,,Two".to_string(),
+        ];
+        client.open(URI, &script(overlapping.clone())).await;
+
+        let pull = |previous: Option<&str>| json!({ "textDocument": { "uri": URI }, "previousResultId": previous });
+        // The pull runs the deep pass itself rather than waiting for it
+        let report = client.request("textDocument/diagnostic", pull(None)).await;
+        assert_eq!(report["kind"], "full");
+        assert!(has_code(&report["items"], "timing_overlap"));
+        let first = report["resultId"].as_str().unwrap().to_string();
+
+        let report = client
+            .request("textDocument/diagnostic", pull(Some(&first)))
+            .await;
+        assert_eq!(report["kind"], "unchanged");
+        assert_eq!(report["resultId"], first.as_str());
+
+        // An edit makes the old result stale, even pulled straight away
+        client
+            .replace(URI, 2, &script([dialogue(0, "Default", "One")]))
+            .await;
+        let report = client
+            .request("textDocument/diagnostic", pull(Some(&first)))
+            .await;
+        assert_eq!(report["kind"], "full");
+        assert_ne!(report["resultId"], first.as_str());
+        assert!(!has_code(&report["items"], "timing_overlap"));
+
+        // A client that pulls would show pushed diagnostics twice
+        client
+            .request(
+                "textDocument/foldingRange",
+                json!({ "textDocument": { "uri": URI } }),
+            )
+            .await;
+        assert!(client
+            .notifications
+            .iter()
+            .all(|notification| notification["method"] != "textDocument/publishDiagnostics"));
+    }
+
+    #[tokio::test]
+    async fn actor_rename_is_prepared_on_the_name_field_and_rejects_commas() {
+        let mut client = TestClient::start().await;
+        let text = script([
+            "Dialogue: 0,0:00:01.00,0:00:02.00,Default,Mio,0,0,0,,Hi".to_string(),
+            "Dialogue: 0,0:00:02.00,0:00:03.00,Default,Mio,0,0,0,,Bye".to_string(),
+        ]);
+        client.open(URI, &text).await;
+        client.diagnostics(URI, |_| true).await;
+
+        let at =
+            json!({ "textDocument": { "uri": URI }, "position": { "line": 11, "character": 44 } });
+        let range = client
+            .request("textDocument/prepareRename", at.clone())
+            .await;
+        assert_eq!(range["start"]["character"], 42);
+        assert_eq!(range["end"]["character"], 45);
+
+        let mut rename = at.clone();
+        rename["newName"] = json!("Akane");
+        let edit = client.request("textDocument/rename", rename.clone()).await;
+        assert_eq!(edit["changes"][URI].as_array().unwrap().len(), 2);
+
+        rename["newName"] = json!("Akane, Mio");
+        let id = client.send("textDocument/rename", rename).await;
+        let response = client.response(id).await;
+        assert_eq!(response["error"]["code"], -32602);
+    }
+
+    #[tokio::test]
+    async fn formatting_one_mis_trimmed_line_edits_only_that_line() {
+        let mut client = TestClient::start().await;
+        let events = (0..40).map(|i| dialogue(i, "Default", "Line"));
+        let text = script(events);
+        client.open(URI, &text).await;
+        let params = json!({
+            "textDocument": { "uri": URI },
+            "options": { "tabSize": 4, "insertSpaces": true },
+        });
+        let edits = client
+            .request("textDocument/formatting", params.clone())
+            .await;
+        assert_eq!(edits, Value::Null);
+
+        let line = dialogue(20, "Default", "Line");
+        client
+            .replace(URI, 2, &text.replacen(&line, &format!("  {line}  "), 1))
+            .await;
+        let edits = client.request("textDocument/formatting", params).await;
+        assert_eq!(
+            edits,
+            json!([{
+                "range": {
+                    "start": { "line": 31, "character": 0 },
+                    "end": { "line": 32, "character": 0 },
+                },
+                "newText": format!("{line}\n"),
+            }])
+        );
+    }
+
+    #[tokio::test]
+    async fn unsorted_events_offer_a_sort_that_applies_through_the_client() {
+        let mut client = TestClient::start_with(json!({ "checkEventOrder": true })).await;
+        let text = script([
+            dialogue(2, "Default", "Third"),
+            dialogue(0, "Default", "First"),
+            dialogue(1, "Default", "Second"),
+        ]);
+        client.open(URI, &text).await;
+        let diagnostics = client
+            .diagnostics(URI, |diagnostics| {
+                has_code(&json!(diagnostics), "unsorted_events")
+            })
+            .await;
+
+        let params = json!({
+            "textDocument": { "uri": URI },
+            "range": diagnostics[0]["range"],
+            "context": { "diagnostics": diagnostics },
+        });
+        let actions = client.request("textDocument/codeAction", params).await;
+        let sort = actions
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|action| action["title"] == "Sort events by start time")
+            .expect("sort action");
+        assert_eq!(sort["command"]["command"], timeline::SORT_EVENTS);
+
+        let result = client
+            .request("workspace/executeCommand", sort["command"].clone())
+            .await;
+        assert_eq!(result, Value::Null);
+        let apply = client.next_notification("workspace/applyEdit").await;
+        let edits = &apply["edit"]["changes"][URI];
+        let lines: Vec<(u64, &str)> = edits
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|edit| {
+                let line = edit["range"]["start"]["line"].as_u64().unwrap();
+                (line, edit["newText"].as_str().unwrap())
+            })
+            .collect();
+        let (first, second, third) = (
+            dialogue(0, "Default", "First"),
+            dialogue(1, "Default", "Second"),
+            dialogue(2, "Default", "Third"),
+        );
+        assert_eq!(
+            lines,
+            vec![
+                (11, first.as_str()),
+                (12, second.as_str()),
+                (13, third.as_str())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn save_round_applies_fix_all_then_finds_nothing_to_format() {
+        let mut client = TestClient::start_with(json!({ "formatOnSave": true })).await;
+        let moved = dialogue(1, "Default", "Moved");
+        let text = script([
+            dialogue(0, "Default", "{\\a6}Top"),
+            format!("  {moved}  "),
+            "Dialogue: 0,0:0:09.00,0:00:10.00,Default,,0,0,0,,{}Empty".to_string(),
+        ]);
+        client.open(URI, &text).await;
+        client.diagnostics(URI, |_| true).await;
+
+        let save = json!({ "textDocument": { "uri": URI }, "reason": 1 });
+        let edits = client
+            .request("textDocument/willSaveWaitUntil", save.clone())
+            .await;
+        assert_eq!(edits[0]["newText"], format!("{moved}\n"));
+
+        // The editor asks for fixAll first, then for the formatting edits
+        let params = json!({
+            "textDocument": { "uri": URI },
+            "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 0 } },
+            "context": { "diagnostics": [], "only": [fix_all::FIX_ALL] },
+        });
+        let actions = client.request("textDocument/codeAction", params).await;
+        let action = actions
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|action| action["kind"] == fix_all::FIX_ALL)
+            .expect("fixAll action");
+        let edits: Vec<TextEdit> =
+            serde_json::from_value(action["edit"]["changes"][URI].clone()).unwrap();
+        let fixed = crate::line_index::apply_edits(&text, PositionEncoding::Utf16, &edits);
+        assert_eq!(
+            fixed,
+            script([
+                dialogue(0, "Default", "{\\an8}Top"),
+                moved,
+                "Dialogue: 0,0:00:09.00,0:00:10.00,Default,,0,0,0,,Empty".to_string(),
+            ])
+        );
+
+        client.replace(URI, 2, &fixed).await;
+        let edits = client.request("textDocument/willSaveWaitUntil", save).await;
+        assert_eq!(edits, Value::Null);
+    }
+
+    #[tokio::test]
+    async fn watched_file_events_drop_the_cache_of_closed_scripts_only() {
+        let (service, _socket) = service();
+        let server = service.inner();
+        let closed = Url::parse("file:///tmp/server-test-closed.ass").unwrap();
+        let open = Url::parse(URI).unwrap();
+        for uri in [&closed, &open] {
+            server
+                .advanced_features
+                .write()
+                .await
+                .insert(uri.to_string(), AdvancedFeatures::new(uri.to_string()));
+        }
+        server
+            .texts
+            .lock()
+            .unwrap()
+            .insert(open.clone(), (1, script([])));
+
+        let changes = [&closed, &open]
+            .into_iter()
+            .map(|uri| FileEvent::new(uri.clone(), FileChangeType::DELETED))
+            .collect();
+        server
+            .did_change_watched_files(DidChangeWatchedFilesParams { changes })
+            .await;
+        let cached = server.advanced_features.read().await;
+        assert!(!cached.contains_key(closed.as_str()));
+        assert!(cached.contains_key(open.as_str()));
+    }
+
+    #[tokio::test]
+    async fn closing_a_script_drops_its_cache() {
+        let (service, _socket) = service();
+        let server = service.inner();
+        let uri = Url::parse(URI).unwrap();
+        server
+            .advanced_features
+            .write()
+            .await
+            .insert(uri.to_string(), AdvancedFeatures::new(uri.to_string()));
+        server
+            .did_close(DidCloseTextDocumentParams {
+                text_document: TextDocumentIdentifier::new(uri.clone()),
+            })
+            .await;
+        assert!(server.advanced_features.read().await.is_empty());
+    }
+}
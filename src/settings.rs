@@ -0,0 +1,385 @@
+use crate::completion::{CompletionProvider, CompletionSource};
+use crate::hover::HoverProvider;
+use crate::inlay::InlayHintProvider;
+use crate::reflow::LineBalancer;
+use crate::render::RenderTarget;
+use crate::validation::{ValidationOptions, ValidationProvider, DIAGNOSTIC_CODES};
+use crate::workspace_check::WorkspaceCheck;
+use serde::Deserialize;
+use std::collections::HashMap;
+use tower_lsp::lsp_types::DiagnosticSeverity;
+
+/// Section of the client settings the server reads.
+pub const SETTINGS_SECTION: &str = "assLsp";
+
+/// User settings, from `initializationOptions` and
+/// `workspace/didChangeConfiguration`. Anything left out keeps its default.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Settings {
+    /// Severity per diagnostic code.
+    pub rules: HashMap<String, RuleLevel>,
+    pub cps_soft_limit: Option<f64>,
+    pub cps_hard_limit: Option<f64>,
+    pub min_duration_ms: Option<u64>,
+    pub max_line_length: Option<usize>,
+    /// Milliseconds past which a timestamp is reported as implausible.
+    pub timestamp_ceiling_ms: Option<u64>,
+    /// Report styles that differ only in name.
+    pub check_equivalent_styles: bool,
+    /// Centiseconds the karaoke of a line may end before or after the line.
+    pub karaoke_tolerance_cs: Option<u32>,
+    /// Report dialogue an edit pastes far from the times around it.
+    pub check_pasted_timing: bool,
+    /// Milliseconds pasted dialogue must be from the lines on both sides of
+    /// it to be reported.
+    pub pasted_timing_gap_ms: Option<u64>,
+    /// Report right-to-left lines whose left-to-right runs start or end
+    /// with punctuation.
+    pub check_bidi_punctuation: bool,
+    /// Report dialogue listed out of start time order.
+    pub check_event_order: bool,
+    /// Report runs of spaces or `\h` used to line text up.
+    pub check_padding: bool,
+    /// Row prefixes after which a run of spaces is indentation, not padding,
+    /// such as a dialogue dash.
+    pub padding_prefixes: Option<Vec<String>>,
+    /// Report dialogue styles aligned to one edge with a margin meant for
+    /// the other; on unless turned off.
+    pub check_alignment_margins: Option<bool>,
+    /// Share of PlayResY above which a top-aligned style's MarginV looks
+    /// meant for the bottom.
+    pub top_margin_fraction: Option<f64>,
+    /// Also report tags that still work but have a clearer equivalent.
+    pub strict_compat: bool,
+    /// Renderer whose defaults are assumed, `libass` or `vsfilter`.
+    pub render_target: Option<RenderTarget>,
+    /// Compare dialogue with the other scripts in each document's folder.
+    pub cross_file_duplicates: bool,
+    /// Report styles whose font is neither installed nor embedded.
+    pub check_missing_fonts: bool,
+    /// Shorter row to longer row ratio below which line breaks are
+    /// offered for balancing.
+    pub line_balance_ratio: Option<f64>,
+    /// Diagnostics published per document at most.
+    pub max_diagnostics: Option<usize>,
+    /// Completion sources in priority order; any left out are disabled.
+    pub completion_sources: Option<Vec<CompletionSource>>,
+    /// Completion items returned at most; a longer list is marked incomplete.
+    pub max_completion_items: Option<usize>,
+    /// Show each Dialogue line's duration after its End time.
+    pub duration_hints: Option<bool>,
+    /// Show each Dialogue line's characters per second after its text.
+    pub cps_hints: Option<bool>,
+    /// Format documents as they are saved.
+    pub format_on_save: bool,
+    /// Report overlapping dialogue across layers too, not just within one.
+    pub strict_overlaps: bool,
+    /// Scripts a workspace check reads at most.
+    pub workspace_max_files: Option<usize>,
+    /// Size in bytes above which a workspace check skips a script.
+    pub workspace_max_file_size: Option<u64>,
+}
+
+/// What a rule reports as, or `Off` to silence it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleLevel {
+    Error,
+    Warning,
+    Info,
+    Hint,
+    Off,
+}
+
+impl RuleLevel {
+    pub fn severity(self) -> Option<DiagnosticSeverity> {
+        match self {
+            RuleLevel::Error => Some(DiagnosticSeverity::ERROR),
+            RuleLevel::Warning => Some(DiagnosticSeverity::WARNING),
+            RuleLevel::Info => Some(DiagnosticSeverity::INFORMATION),
+            RuleLevel::Hint => Some(DiagnosticSeverity::HINT),
+            RuleLevel::Off => None,
+        }
+    }
+}
+
+impl Settings {
+    /// Reads settings sent either whole or wrapped in their section, as
+    /// clients differ in which they send.
+    pub fn from_value(value: &serde_json::Value) -> Result<Self, serde_json::Error> {
+        let value = value.get(SETTINGS_SECTION).unwrap_or(value);
+        if value.is_null() {
+            return Ok(Self::default());
+        }
+        Settings::deserialize(value)
+    }
+
+    /// Rule names that aren't diagnostic codes, likely typos.
+    pub fn unknown_rules(&self) -> Vec<&str> {
+        let mut unknown: Vec<&str> = self
+            .rules
+            .keys()
+            .map(String::as_str)
+            .filter(|code| !DIAGNOSTIC_CODES.contains(code))
+            .collect();
+        unknown.sort_unstable();
+        unknown
+    }
+
+    /// A validator with these settings over the defaults.
+    pub fn validation_provider(&self) -> ValidationProvider {
+        let defaults = ValidationOptions::default();
+        ValidationProvider::with_options(ValidationOptions {
+            rule_levels: self.rules.clone(),
+            cps_soft_limit: self.cps_soft_limit.unwrap_or(defaults.cps_soft_limit),
+            cps_hard_limit: self.cps_hard_limit.unwrap_or(defaults.cps_hard_limit),
+            min_duration_ms: self.min_duration_ms.unwrap_or(defaults.min_duration_ms),
+            max_line_length: self.max_line_length,
+            timestamp_ceiling: self
+                .timestamp_ceiling_ms
+                .map_or(defaults.timestamp_ceiling, |ceiling| {
+                    u32::try_from(ceiling / 10).unwrap_or(u32::MAX)
+                }),
+            check_equivalent_styles: self.check_equivalent_styles,
+            karaoke_tolerance_cs: self
+                .karaoke_tolerance_cs
+                .unwrap_or(defaults.karaoke_tolerance_cs),
+            check_pasted_timing: self.check_pasted_timing,
+            pasted_timing_gap_ms: self
+                .pasted_timing_gap_ms
+                .unwrap_or(defaults.pasted_timing_gap_ms),
+            check_bidi_punctuation: self.check_bidi_punctuation,
+            check_event_order: self.check_event_order,
+            check_padding: self.check_padding,
+            padding_prefixes: self
+                .padding_prefixes
+                .clone()
+                .unwrap_or(defaults.padding_prefixes),
+            check_alignment_margins: self
+                .check_alignment_margins
+                .unwrap_or(defaults.check_alignment_margins),
+            top_margin_fraction: self
+                .top_margin_fraction
+                .unwrap_or(defaults.top_margin_fraction),
+            strict_compat: self.strict_compat,
+            render_target: self.render_target.unwrap_or(defaults.render_target),
+            check_cross_file_duplicates: self.cross_file_duplicates,
+            check_missing_fonts: self.check_missing_fonts,
+            max_diagnostics: self.max_diagnostics.unwrap_or(defaults.max_diagnostics),
+        })
+    }
+
+    /// Hover with these settings over the defaults.
+    pub fn hover(&self) -> HoverProvider {
+        let mut hover = HoverProvider::new();
+        if let Some(target) = self.render_target {
+            hover.render_target = target;
+        }
+        hover
+    }
+
+    /// A line balancer with these settings over the defaults.
+    pub fn line_balancer(&self) -> LineBalancer {
+        let mut balancer = LineBalancer::new();
+        if let Some(ratio) = self.line_balance_ratio {
+            balancer.min_ratio = ratio;
+        }
+        balancer
+    }
+
+    /// Completion with these settings over the defaults.
+    pub fn completion(&self) -> CompletionProvider {
+        let mut completion = CompletionProvider::new();
+        if let Some(sources) = &self.completion_sources {
+            completion.sources = sources.clone();
+        }
+        completion.max_items = self.max_completion_items;
+        completion
+    }
+
+    /// Inlay hints with these settings over the defaults.
+    pub fn inlay_hints(&self) -> InlayHintProvider {
+        let mut hints = InlayHintProvider::new();
+        if let Some(show) = self.duration_hints {
+            hints.show_duration = show;
+        }
+        if let Some(show) = self.cps_hints {
+            hints.show_cps = show;
+        }
+        hints
+    }
+
+    /// Workspace check bounds with these settings over the defaults.
+    pub fn workspace_check(&self) -> WorkspaceCheck {
+        let mut check = WorkspaceCheck::new();
+        if let Some(max) = self.workspace_max_files {
+            check.max_files = max;
+        }
+        if let Some(max) = self.workspace_max_file_size {
+            check.max_file_size = max;
+        }
+        check
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn validation(value: serde_json::Value) -> ValidationProvider {
+        Settings::from_value(&json!({ "assLsp": value }))
+            .unwrap()
+            .validation_provider()
+    }
+
+    #[test]
+    fn timestamp_ceiling_is_read_in_milliseconds() {
+        assert_eq!(validation(json!({})).options.timestamp_ceiling, 10 * 360000);
+        let ceiling = validation(json!({ "timestampCeilingMs": 3_600_000 }))
+            .options
+            .timestamp_ceiling;
+        assert_eq!(ceiling, 360000);
+        let ceiling = validation(json!({ "timestampCeilingMs": u64::MAX }))
+            .options
+            .timestamp_ceiling;
+        assert_eq!(ceiling, u32::MAX);
+    }
+
+    #[test]
+    fn missing_fonts_check_is_opt_in() {
+        assert!(!validation(json!({})).options.check_missing_fonts);
+        assert!(
+            validation(json!({ "checkMissingFonts": true }))
+                .options
+                .check_missing_fonts
+        );
+    }
+
+    #[test]
+    fn equivalent_styles_check_is_opt_in() {
+        assert!(!validation(json!({})).options.check_equivalent_styles);
+        let settings = json!({ "checkEquivalentStyles": true });
+        assert!(validation(settings).options.check_equivalent_styles);
+    }
+
+    #[test]
+    fn karaoke_tolerance_is_read_in_centiseconds() {
+        assert_eq!(validation(json!({})).options.karaoke_tolerance_cs, 10);
+        let settings = json!({ "karaokeToleranceCs": 25 });
+        assert_eq!(validation(settings).options.karaoke_tolerance_cs, 25);
+    }
+
+    #[test]
+    fn pasted_timing_check_is_opt_in_with_a_configurable_gap() {
+        let options = validation(json!({})).options;
+        assert!(!options.check_pasted_timing);
+        assert_eq!(options.pasted_timing_gap_ms, 5 * 60 * 1000);
+
+        let options =
+            validation(json!({ "checkPastedTiming": true, "pastedTimingGapMs": 60000 })).options;
+        assert!(options.check_pasted_timing);
+        assert_eq!(options.pasted_timing_gap_ms, 60000);
+    }
+
+    #[test]
+    fn bidi_punctuation_check_is_opt_in() {
+        assert!(!validation(json!({})).options.check_bidi_punctuation);
+        let settings = json!({ "checkBidiPunctuation": true });
+        assert!(validation(settings).options.check_bidi_punctuation);
+    }
+
+    #[test]
+    fn event_order_check_is_opt_in() {
+        assert!(!validation(json!({})).options.check_event_order);
+        assert!(
+            validation(json!({ "checkEventOrder": true }))
+                .options
+                .check_event_order
+        );
+    }
+
+    #[test]
+    fn padding_check_is_opt_in_with_configurable_prefixes() {
+        let options = validation(json!({})).options;
+        assert!(!options.check_padding);
+        assert_eq!(options.padding_prefixes, ["-", "–", "—"]);
+
+        let options = validation(json!({ "checkPadding": true, "paddingPrefixes": ["*"] })).options;
+        assert!(options.check_padding);
+        assert_eq!(options.padding_prefixes, ["*"]);
+    }
+
+    #[test]
+    fn alignment_margin_check_can_be_tuned_or_turned_off() {
+        let options = validation(json!({})).options;
+        assert!(options.check_alignment_margins);
+        assert_eq!(options.top_margin_fraction, 0.6);
+
+        let settings = json!({ "checkAlignmentMargins": false, "topMarginFraction": 0.75 });
+        let options = validation(settings).options;
+        assert!(!options.check_alignment_margins);
+        assert_eq!(options.top_margin_fraction, 0.75);
+    }
+
+    #[test]
+    fn strict_compat_is_opt_in() {
+        assert!(!validation(json!({})).options.strict_compat);
+        assert!(
+            validation(json!({ "strictCompat": true }))
+                .options
+                .strict_compat
+        );
+    }
+
+    #[test]
+    fn render_target_reaches_validation_and_hover() {
+        assert_eq!(
+            validation(json!({})).options.render_target,
+            RenderTarget::Libass
+        );
+        let settings = json!({ "assLsp": { "renderTarget": "vsfilter" } });
+        let settings = Settings::from_value(&settings).unwrap();
+        assert_eq!(
+            settings.validation_provider().options.render_target,
+            RenderTarget::VsFilter
+        );
+        assert_eq!(settings.hover().render_target, RenderTarget::VsFilter);
+        assert!(Settings::from_value(&json!({ "renderTarget": "mpv" })).is_err());
+    }
+
+    #[test]
+    fn strict_overlaps_is_opt_in() {
+        assert!(!Settings::default().strict_overlaps);
+        let settings = json!({ "strictOverlaps": true });
+        assert!(Settings::from_value(&settings).unwrap().strict_overlaps);
+    }
+
+    #[test]
+    fn completion_sources_can_be_reordered_disabled_and_capped() {
+        let settings = json!({
+            "completionSources": ["sections", "eventTypes"],
+            "maxCompletionItems": 20
+        });
+        let completion = Settings::from_value(&settings).unwrap().completion();
+        assert_eq!(
+            completion.sources,
+            vec![CompletionSource::Sections, CompletionSource::EventTypes]
+        );
+        assert_eq!(completion.max_items, Some(20));
+        let defaults = Settings::default().completion();
+        assert_eq!(defaults.sources.len(), 10);
+        assert_eq!(defaults.max_items, None);
+    }
+
+    #[test]
+    fn rules_are_read_by_code_and_typos_are_flagged() {
+        let settings = json!({ "rules": { "long_line": "error", "hig_cps": "off" } });
+        let settings = Settings::from_value(&settings).unwrap();
+        assert_eq!(settings.rules["long_line"], RuleLevel::Error);
+        assert_eq!(settings.unknown_rules(), ["hig_cps"]);
+        assert_eq!(RuleLevel::Off.severity(), None);
+        assert!(Settings::from_value(&json!({ "rules": { "long_line": "loud" } })).is_err());
+    }
+}
@@ -0,0 +1,279 @@
+use crate::parser::{parse_section_header, strip_prefix_ignore_case};
+use crate::validation::DIAGNOSTIC_CODES;
+use regex::Regex;
+use std::collections::HashSet;
+use tower_lsp::lsp_types::*;
+
+/// A single `ass-lsp-ignore` directive found in the document.
+#[derive(Debug)]
+struct Suppression {
+    /// The event line the directive applies to, or `None` for file-wide directives.
+    target_line: Option<u32>,
+    codes: Vec<String>,
+    used: Vec<bool>,
+    range: Range,
+}
+
+#[derive(Debug, Clone)]
+pub struct SuppressionProvider {
+    comment_regex: Regex,
+    file_regex: Regex,
+    trailing_regex: Regex,
+}
+
+impl SuppressionProvider {
+    pub fn new() -> Self {
+        Self {
+            comment_regex: Regex::new(r"^;\s*ass-lsp-ignore:(.*)$").unwrap(),
+            file_regex: Regex::new(r"^;\s*ass-lsp-ignore-file:(.*)$").unwrap(),
+            trailing_regex: Regex::new(r"\{ass-lsp-ignore:([^}]*)\}\s*$").unwrap(),
+        }
+    }
+
+    /// Drops diagnostics silenced by suppression comments and reports
+    /// unknown or unused suppressions as hints.
+    pub fn apply(&self, text: &str, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        let diagnostics = Self::dedup(diagnostics);
+        let mut suppressions = self.collect_suppressions(text);
+        if suppressions.is_empty() {
+            return diagnostics;
+        }
+
+        let mut kept: Vec<Diagnostic> = diagnostics
+            .into_iter()
+            .filter(|diagnostic| !Self::suppress(&mut suppressions, diagnostic))
+            .collect();
+
+        for suppression in &suppressions {
+            for (code, used) in suppression.codes.iter().zip(&suppression.used) {
+                if !DIAGNOSTIC_CODES.contains(&code.as_str()) {
+                    kept.push(Diagnostic {
+                        range: suppression.range,
+                        severity: Some(DiagnosticSeverity::HINT),
+                        code: Some(NumberOrString::String("unknown_suppression".to_string())),
+                        code_description: None,
+                        source: Some("ass-lsp".to_string()),
+                        message: format!("Unknown diagnostic code in suppression: {code}"),
+                        related_information: None,
+                        tags: None,
+                        data: None,
+                    });
+                } else if !used {
+                    kept.push(Diagnostic {
+                        range: suppression.range,
+                        severity: Some(DiagnosticSeverity::HINT),
+                        code: Some(NumberOrString::String("unused_suppression".to_string())),
+                        code_description: None,
+                        source: Some("ass-lsp".to_string()),
+                        message: format!("Suppression for {code} is unused"),
+                        related_information: None,
+                        tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+                        data: None,
+                    });
+                }
+            }
+        }
+
+        kept
+    }
+
+    /// Drops diagnostics whose data names a `coveredBy` code that was also
+    /// reported on the same line, so one mistake isn't reported twice.
+    fn dedup(diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        let reported: HashSet<(u32, String)> = diagnostics
+            .iter()
+            .filter_map(|diagnostic| match &diagnostic.code {
+                Some(NumberOrString::String(code)) => {
+                    Some((diagnostic.range.start.line, code.clone()))
+                }
+                _ => None,
+            })
+            .collect();
+
+        diagnostics
+            .into_iter()
+            .filter(|diagnostic| {
+                let covered_by = diagnostic
+                    .data
+                    .as_ref()
+                    .and_then(|data| data["coveredBy"].as_str());
+                !covered_by.is_some_and(|code| {
+                    reported.contains(&(diagnostic.range.start.line, code.to_string()))
+                })
+            })
+            .collect()
+    }
+
+    fn suppress(suppressions: &mut [Suppression], diagnostic: &Diagnostic) -> bool {
+        let Some(NumberOrString::String(code)) = &diagnostic.code else {
+            return false;
+        };
+
+        let mut suppressed = false;
+        for suppression in suppressions.iter_mut() {
+            if suppression
+                .target_line
+                .is_some_and(|line| line != diagnostic.range.start.line)
+            {
+                continue;
+            }
+            if let Some(index) = suppression.codes.iter().position(|c| c == code) {
+                suppression.used[index] = true;
+                suppressed = true;
+            }
+        }
+        suppressed
+    }
+
+    /// File-wide directives only count in the preamble: above the first
+    /// header or in [Script Info], not partway down the file.
+    fn collect_suppressions(&self, text: &str) -> Vec<Suppression> {
+        let lines: Vec<&str> = text.lines().collect();
+        let mut suppressions = Vec::new();
+        let mut in_preamble = true;
+
+        for (line_num, line) in lines.iter().enumerate() {
+            let trimmed = line.trim();
+            let line_range = Range {
+                start: Position::new(line_num as u32, 0),
+                end: Position::new(line_num as u32, line.len() as u32),
+            };
+            if let Some(header) = parse_section_header(trimmed) {
+                in_preamble &= header.name == "Script Info";
+            }
+
+            if let Some(captures) = self.file_regex.captures(trimmed) {
+                if in_preamble {
+                    suppressions.push(Suppression::new(None, &captures[1], line_range));
+                }
+            } else if let Some(captures) = self.comment_regex.captures(trimmed) {
+                let next_is_event = lines
+                    .get(line_num + 1)
+                    .is_some_and(|next| is_event_line(next.trim()));
+                if next_is_event {
+                    suppressions.push(Suppression::new(
+                        Some(line_num as u32 + 1),
+                        &captures[1],
+                        line_range,
+                    ));
+                }
+            } else if is_event_line(trimmed) {
+                if let Some(captures) = self.trailing_regex.captures(line) {
+                    let whole = captures.get(0).unwrap();
+                    let range = Range {
+                        start: Position::new(line_num as u32, whole.start() as u32),
+                        end: Position::new(line_num as u32, whole.end() as u32),
+                    };
+                    suppressions.push(Suppression::new(Some(line_num as u32), &captures[1], range));
+                }
+            }
+        }
+
+        suppressions
+    }
+}
+
+impl Suppression {
+    fn new(target_line: Option<u32>, codes: &str, range: Range) -> Self {
+        let codes: Vec<String> = codes
+            .split(',')
+            .map(|code| code.trim().to_string())
+            .filter(|code| !code.is_empty())
+            .collect();
+        Self {
+            target_line,
+            used: vec![false; codes.len()],
+            codes,
+            range,
+        }
+    }
+}
+
+fn is_event_line(line: &str) -> bool {
+    strip_prefix_ignore_case(line, "Dialogue:").is_some()
+        || strip_prefix_ignore_case(line, "Comment:").is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(line: u32, code: &str) -> Diagnostic {
+        Diagnostic {
+            range: Range::new(Position::new(line, 0), Position::new(line, 10)),
+            code: Some(NumberOrString::String(code.to_string())),
+            ..Diagnostic::default()
+        }
+    }
+
+    fn codes(diagnostics: &[Diagnostic]) -> Vec<(u32, &str)> {
+        diagnostics
+            .iter()
+            .map(|diagnostic| match &diagnostic.code {
+                Some(NumberOrString::String(code)) => (diagnostic.range.start.line, code.as_str()),
+                _ => (diagnostic.range.start.line, ""),
+            })
+            .collect()
+    }
+
+    const EVENTS: &str = "[Events]\n; ass-lsp-ignore: short_duration\nDialogue: 0,0:00:00.00,0:00:00.10,Default,,0,0,0,,Hi\nDialogue: 0,0:00:01.00,0:00:01.10,Default,,0,0,0,,Again {ass-lsp-ignore: short_duration}\nDialogue: 0,0:00:02.00,0:00:02.10,Default,,0,0,0,,Reported\n";
+
+    #[test]
+    fn event_level_suppressions_cover_only_their_line() {
+        let diagnostics = vec![
+            diagnostic(2, "short_duration"),
+            diagnostic(3, "short_duration"),
+            diagnostic(4, "short_duration"),
+            diagnostic(2, "high_cps"),
+        ];
+        let kept = SuppressionProvider::new().apply(EVENTS, diagnostics);
+        assert_eq!(codes(&kept), vec![(4, "short_duration"), (2, "high_cps")]);
+    }
+
+    #[test]
+    fn file_level_suppression_covers_every_line() {
+        let text = format!("; ass-lsp-ignore-file: short_duration, high_cps\n{EVENTS}");
+        let diagnostics = vec![
+            diagnostic(3, "short_duration"),
+            diagnostic(4, "short_duration"),
+            diagnostic(5, "short_duration"),
+            diagnostic(3, "high_cps"),
+            diagnostic(3, "unknown_style"),
+        ];
+        let kept = SuppressionProvider::new().apply(&text, diagnostics);
+        assert_eq!(codes(&kept), vec![(3, "unknown_style")]);
+    }
+
+    #[test]
+    fn file_level_suppression_past_the_preamble_is_ignored() {
+        let text = "[Script Info]\n; ass-lsp-ignore-file: high_cps\nTitle: Demo\n\n[Events]\n; ass-lsp-ignore-file: short_duration\nDialogue: 0,0:00:00.00,0:00:00.10,Default,,0,0,0,,Hi\n";
+        let diagnostics = vec![diagnostic(6, "short_duration"), diagnostic(6, "high_cps")];
+        let kept = SuppressionProvider::new().apply(text, diagnostics);
+        assert_eq!(codes(&kept), vec![(6, "short_duration")]);
+    }
+
+    #[test]
+    fn unknown_codes_are_reported() {
+        let text = "[Events]\n; ass-lsp-ignore: no_such_rule\nDialogue: 0,0:00:00.00,0:00:01.00,Default,,0,0,0,,Hi\n";
+        let kept = SuppressionProvider::new().apply(text, Vec::new());
+        assert_eq!(codes(&kept), vec![(1, "unknown_suppression")]);
+        assert!(kept[0].message.contains("no_such_rule"));
+    }
+
+    #[test]
+    fn unused_suppressions_are_reported_as_unnecessary() {
+        let kept = SuppressionProvider::new().apply(EVENTS, vec![diagnostic(2, "short_duration")]);
+        assert_eq!(codes(&kept), vec![(3, "unused_suppression")]);
+        assert_eq!(kept[0].tags, Some(vec![DiagnosticTag::UNNECESSARY]));
+        let line = EVENTS.lines().nth(3).unwrap();
+        let column = line.find('{').unwrap() as u32;
+        assert_eq!(kept[0].range.start.character, column);
+    }
+
+    #[test]
+    fn directive_not_above_an_event_is_ignored() {
+        let text = "[Events]\n; ass-lsp-ignore: short_duration\n\nDialogue: 0,0:00:00.00,0:00:00.10,Default,,0,0,0,,Hi\n";
+        let kept = SuppressionProvider::new().apply(text, vec![diagnostic(3, "short_duration")]);
+        assert_eq!(codes(&kept), vec![(3, "short_duration")]);
+    }
+}
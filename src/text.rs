@@ -0,0 +1,656 @@
+use crate::metadata::{known_tag_name, override_tag_name, HEX_ARGUMENT_TAGS, NAME_ARGUMENT_TAGS};
+use std::ops::Range;
+
+/// A piece of event text as seen by the override tokenizer. Spans are byte
+/// ranges into the tokenized text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextToken<'a> {
+    /// A single tag from an override block, without its leading backslash,
+    /// e.g. `k25` or `t(0,100,\fs30)`.
+    Tag { tag: &'a str, span: Range<usize> },
+    /// Text outside override blocks.
+    Text { text: &'a str, span: Range<usize> },
+}
+
+/// Splits event text into plain text runs and override tags. Comments inside
+/// override blocks are dropped, and backslashes inside parentheses belong to
+/// the enclosing tag so `\t(...)` stays whole. An unclosed block runs to the
+/// end of the text.
+pub fn tokenize(text: &str) -> Vec<TextToken<'_>> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < text.len() {
+        let Some(open) = text[pos..].find('{').map(|i| pos + i) else {
+            tokens.push(TextToken::Text {
+                text: &text[pos..],
+                span: pos..text.len(),
+            });
+            break;
+        };
+        if open > pos {
+            tokens.push(TextToken::Text {
+                text: &text[pos..open],
+                span: pos..open,
+            });
+        }
+
+        let close = text[open..].find('}').map_or(text.len(), |i| open + i);
+        tokens.extend(
+            split_tags(&text[open + 1..close], open + 1)
+                .into_iter()
+                .map(|(tag, span)| TextToken::Tag { tag, span }),
+        );
+
+        pos = (close + 1).min(text.len());
+    }
+
+    tokens
+}
+
+/// Override blocks after the last rendered character of `text`, as spans
+/// covering their braces. Blocks without tags (comments, extradata markers)
+/// are left out, as are a `{\p0}` ending a drawing, unclosed blocks and
+/// lines with no rendered text at all.
+pub fn trailing_override_blocks(text: &str) -> Vec<Range<usize>> {
+    let rendered_end = tokenize(text)
+        .into_iter()
+        .rev()
+        .find_map(|token| match token {
+            TextToken::Text { text, span } => {
+                let visible = text
+                    .replace("\\N", "")
+                    .replace("\\n", "")
+                    .replace("\\h", "");
+                (!visible.trim().is_empty()).then_some(span.end)
+            }
+            TextToken::Tag { .. } => None,
+        });
+    let Some(mut pos) = rendered_end else {
+        return Vec::new();
+    };
+
+    let mut blocks = Vec::new();
+    while let Some(open) = text[pos..].find('{').map(|i| pos + i) {
+        // Reported as unclosed instead
+        let Some(close) = text[open..].find('}').map(|i| open + i) else {
+            break;
+        };
+        let tags = split_tags(&text[open + 1..close], 0);
+        let ends_drawing = tags.iter().all(|(tag, _)| tag.trim() == "p0");
+        if !tags.is_empty() && !ends_drawing {
+            blocks.push(open..close + 1);
+        }
+        pos = close + 1;
+    }
+    blocks
+}
+
+/// Closed override blocks with nothing but whitespace between the braces,
+/// as spans covering the braces. Comment blocks and markers such as `{*}`
+/// aren't empty.
+pub fn empty_override_blocks(text: &str) -> Vec<Range<usize>> {
+    let mut blocks = Vec::new();
+    let mut pos = 0;
+    while let Some(open) = text[pos..].find('{').map(|i| pos + i) {
+        let Some(close) = text[open..].find('}').map(|i| open + i) else {
+            break;
+        };
+        if text[open + 1..close].trim().is_empty() {
+            blocks.push(open..close + 1);
+        }
+        pos = close + 1;
+    }
+    blocks
+}
+
+/// Where to close the block opened at `open` that lacks its `}`: after its
+/// last tag, before the first character no tag can take, such as text, a
+/// `\N` or another `{`. `None` when the block starts with something other
+/// than a tag, or when a name tag's argument can't be told from the text
+/// after it, as in `{\fnTimes New Roman hello`.
+pub fn unclosed_block_end(text: &str, open: usize) -> Option<usize> {
+    let mut end = None;
+    let mut pos = open + 1;
+    loop {
+        let rest = &text[pos..];
+        let Some(tag) = rest.trim_start().strip_prefix('\\') else {
+            break;
+        };
+        let Some(name) = known_tag_name(tag) else {
+            break;
+        };
+        let tag_start = pos + rest.len() - tag.len();
+        pos = tag_start + name.len() + argument_len(&tag[name.len()..], name)?;
+        end = Some(pos);
+    }
+    end
+}
+
+/// The length of the argument at the start of `rest`, which follows a tag
+/// named `name` in an unclosed block.
+fn argument_len(rest: &str, name: &str) -> Option<usize> {
+    let stop = |c: char| matches!(c, '\\' | '{' | '}');
+    if rest.starts_with('(') {
+        let mut depth = 0;
+        for (i, ch) in rest.char_indices() {
+            match ch {
+                '(' => depth += 1,
+                ')' if depth == 1 => return Some(i + 1),
+                ')' => depth -= 1,
+                '{' | '}' => return Some(i),
+                _ => {}
+            }
+        }
+        return Some(rest.len());
+    }
+    if NAME_ARGUMENT_TAGS.contains(&name) {
+        let len = rest.find(stop).unwrap_or(rest.len());
+        let spaced = rest[..len].trim().contains(char::is_whitespace);
+        return (!spaced || rest[len..].starts_with('\\')).then_some(len);
+    }
+    let len = |rest: &str, take: fn(char) -> bool| rest.find(|c| !take(c)).unwrap_or(rest.len());
+    if HEX_ARGUMENT_TAGS.contains(&name) {
+        let value = rest.strip_prefix('&').unwrap_or(rest);
+        let value = value.strip_prefix(['H', 'h']).unwrap_or(value);
+        let value = &value[len(value, |c| c.is_ascii_hexdigit())..];
+        let value = value.strip_prefix('&').unwrap_or(value);
+        return Some(rest.len() - value.len());
+    }
+    let value = rest.strip_prefix(['-', '+']).unwrap_or(rest);
+    let digits = len(value, |c| c.is_ascii_digit() || c == '.');
+    Some(rest.len() - value.len() + digits)
+}
+
+/// Splits the inside of an override block into tags, ignoring text before the
+/// first backslash. Spans start at the backslash and are offset by `base`.
+fn split_tags(block: &str, base: usize) -> Vec<(&str, Range<usize>)> {
+    let mut tags = Vec::new();
+    let mut tag_start = None;
+    let mut depth = 0usize;
+    for (i, ch) in block.char_indices() {
+        match ch {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            '\\' if depth == 0 => {
+                if let Some(start) = tag_start.replace(i + 1) {
+                    tags.push((&block[start..i], base + start - 1..base + i));
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = tag_start {
+        tags.push((&block[start..], base + start - 1..base + block.len()));
+    }
+    tags
+}
+
+/// The arguments of a `\t` tag: `\t([t1,t2,][accel,]tags)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transform<'a> {
+    /// Animation start in milliseconds from the event start.
+    pub t1: Option<i64>,
+    /// Animation end in milliseconds from the event start.
+    pub t2: Option<i64>,
+    pub accel: Option<f64>,
+    /// Animated tags with their spans, as in [`TextToken::Tag`]. A nested
+    /// `\t` can be passed to [`parse_transform`] again.
+    pub tags: Vec<(&'a str, Range<usize>)>,
+}
+
+/// Why a `\t` tag could not be parsed, with the span to report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransformError {
+    MissingParen(Range<usize>),
+    /// A timing or acceleration argument that is not a number.
+    InvalidNumber(Range<usize>),
+    TooManyArguments(Range<usize>),
+    NoTags(Range<usize>),
+}
+
+impl TransformError {
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            Self::MissingParen(span)
+            | Self::InvalidNumber(span)
+            | Self::TooManyArguments(span)
+            | Self::NoTags(span) => span.clone(),
+        }
+    }
+}
+
+/// Parses a tag from [`tokenize`] as a transform. Returns `None` if the tag is
+/// not `\t(...)`.
+pub fn parse_transform(
+    tag: &str,
+    span: Range<usize>,
+) -> Option<Result<Transform<'_>, TransformError>> {
+    let rest = tag.strip_prefix('t')?;
+    if !rest.trim_start().starts_with('(') {
+        return None;
+    }
+    // Offset of `tag` within the tokenized text, just past the backslash
+    let base = span.start + 1;
+    let open = tag.find('(')?;
+
+    let mut depth = 0usize;
+    let mut close = None;
+    for (i, ch) in tag[open..].char_indices() {
+        match ch {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(open + i);
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    let Some(close) = close else {
+        return Some(Err(TransformError::MissingParen(span)));
+    };
+
+    let content = &tag[open + 1..close];
+    let content_base = base + open + 1;
+    let mut depth = 0usize;
+    let tags_start = content.char_indices().find_map(|(i, ch)| match ch {
+        '(' => {
+            depth += 1;
+            None
+        }
+        ')' => {
+            depth = depth.saturating_sub(1);
+            None
+        }
+        '\\' if depth == 0 => Some(i),
+        _ => None,
+    });
+    let Some(tags_start) = tags_start else {
+        return Some(Err(TransformError::NoTags(span)));
+    };
+
+    let mut args = Vec::new();
+    let mut arg_start = 0;
+    for piece in content[..tags_start].split(',') {
+        let leading = piece.len() - piece.trim_start().len();
+        let value = piece.trim();
+        if !value.is_empty() {
+            let start = content_base + arg_start + leading;
+            args.push((value, start..start + value.len()));
+        }
+        arg_start += piece.len() + 1;
+    }
+
+    let time = |(value, span): &(&str, Range<usize>)| {
+        value
+            .parse::<i64>()
+            .map_err(|_| TransformError::InvalidNumber(span.clone()))
+    };
+    let accel = |(value, span): &(&str, Range<usize>)| {
+        value
+            .parse::<f64>()
+            .map_err(|_| TransformError::InvalidNumber(span.clone()))
+    };
+    let parsed = match args.as_slice() {
+        [] => Ok((None, None, None)),
+        [a] => accel(a).map(|a| (None, None, Some(a))),
+        [t1, t2] => time(t1).and_then(|t1| Ok((Some(t1), Some(time(t2)?), None))),
+        [t1, t2, a] => time(t1).and_then(|t1| Ok((Some(t1), Some(time(t2)?), Some(accel(a)?)))),
+        [.., extra] => Err(TransformError::TooManyArguments(
+            args[3].1.start..extra.1.end,
+        )),
+    };
+
+    Some(parsed.map(|(t1, t2, accel)| Transform {
+        t1,
+        t2,
+        accel,
+        tags: split_tags(&content[tags_start..], content_base + tags_start),
+    }))
+}
+
+/// What follows a tag's name: a parenthesized argument list, or a bare value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagArguments<'a> {
+    pub parenthesized: bool,
+    /// False when the parentheses are never closed.
+    pub closed: bool,
+    /// Trimmed arguments. A bare value is a single argument, empty when the
+    /// tag has none; empty parentheses have no arguments.
+    pub args: Vec<&'a str>,
+}
+
+impl TagArguments<'_> {
+    /// The arguments as written, e.g. `(10,20,abc)` or `120`.
+    pub fn display(&self) -> String {
+        let args = self.args.join(",");
+        match (self.parenthesized, self.closed) {
+            (true, true) => format!("({args})"),
+            (true, false) => format!("({args}"),
+            (false, _) => args,
+        }
+    }
+}
+
+/// Byte range of `arg`, one of the arguments [`tag_arguments`] split off,
+/// within the tag it came from.
+pub fn argument_span(tag: &str, arg: &str) -> Range<usize> {
+    let start = arg.as_ptr() as usize - tag.as_ptr() as usize;
+    start..start + arg.len()
+}
+
+/// Splits off the arguments of a tag from [`tokenize`] whose name is `name`.
+pub fn tag_arguments<'a>(tag: &'a str, name: &str) -> TagArguments<'a> {
+    let rest = tag.get(name.len()..).unwrap_or("").trim();
+    let Some(inner) = rest.strip_prefix('(') else {
+        return TagArguments {
+            parenthesized: false,
+            closed: true,
+            args: vec![rest],
+        };
+    };
+    let (inner, closed) = match inner.rfind(')') {
+        Some(close) => (&inner[..close], true),
+        None => (inner, false),
+    };
+    let args = if inner.trim().is_empty() {
+        Vec::new()
+    } else {
+        inner.split(',').map(str::trim).collect()
+    };
+    TagArguments {
+        parenthesized: true,
+        closed,
+        args,
+    }
+}
+
+/// Resolves the wrap style in effect for an event: the script's `WrapStyle`,
+/// overridden by the last `\q` tag in the event text.
+pub fn effective_wrap_style(script_wrap_style: Option<&str>, text: &str) -> u8 {
+    let mut wrap_style = script_wrap_style
+        .and_then(|value| value.trim().parse().ok())
+        .filter(|style| *style <= 3)
+        .unwrap_or(0);
+
+    for token in tokenize(text) {
+        let TextToken::Tag { tag, .. } = token else {
+            continue;
+        };
+        let style = tag
+            .strip_prefix('q')
+            .and_then(|value| value.trim().chars().next())
+            .and_then(|digit| digit.to_digit(10));
+        if let Some(style) = style.filter(|style| *style <= 3) {
+            wrap_style = style as u8;
+        }
+    }
+
+    wrap_style
+}
+
+/// Splits event text into the rows a renderer shows, before automatic wrapping.
+///
+/// Override blocks are removed, `\N` always breaks, and `\h` becomes a
+/// non-breaking space. A soft `\n` only breaks under wrap style 2, matching
+/// libass; every other wrap style renders it as a space.
+pub fn visible_rows(text: &str, wrap_style: u8) -> Vec<String> {
+    let mut rows = vec![String::new()];
+    let mut chars = text.chars().peekable();
+    let mut in_override = false;
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' if !in_override => in_override = true,
+            '}' if in_override => in_override = false,
+            _ if in_override => {}
+            '\\' => match chars.peek() {
+                Some('N') => {
+                    chars.next();
+                    rows.push(String::new());
+                }
+                Some('n') => {
+                    chars.next();
+                    if wrap_style == 2 {
+                        rows.push(String::new());
+                    } else {
+                        rows.last_mut().unwrap().push(' ');
+                    }
+                }
+                Some('h') => {
+                    chars.next();
+                    rows.last_mut().unwrap().push('\u{a0}');
+                }
+                _ => rows.last_mut().unwrap().push(ch),
+            },
+            _ => rows.last_mut().unwrap().push(ch),
+        }
+    }
+
+    rows
+}
+
+/// How long a row reads, in characters without surrounding spaces. The
+/// line length check and line break balancing both measure rows this way.
+pub fn row_length(row: &str) -> usize {
+    row.trim().chars().count()
+}
+
+/// A one-line preview of event text: visible rows joined with ` / `, cut to
+/// `max_chars` characters.
+pub fn excerpt(text: &str, script_wrap_style: Option<&str>, max_chars: usize) -> String {
+    let wrap_style = effective_wrap_style(script_wrap_style, text);
+    visible_rows(text, wrap_style)
+        .join(" / ")
+        .chars()
+        .take(max_chars)
+        .collect()
+}
+
+/// Event text divided in two for splitting an event, with the leading
+/// override blocks repeated on both halves so the styling carries over.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextSplit {
+    pub first: String,
+    pub second: String,
+    /// Share of the visible characters that ends up in the first half.
+    pub share: f64,
+    /// Whether the text was split at a `\N` rather than a sentence end.
+    pub at_line_break: bool,
+}
+
+/// Characters that end a sentence, as a split point when there is no `\N`.
+const SENTENCE_ENDS: &[char] = &['.', '!', '?', '…', '。', '！', '？'];
+
+/// Splits event text at a natural break: the `\N` nearest the middle, or
+/// else just after the last sentence-ending punctuation before the middle,
+/// falling back to the first one after it.
+/// Returns `None` when neither exists or a half would have no visible text.
+pub fn split_text(text: &str) -> Option<TextSplit> {
+    let mut lead_end = 0;
+    while text[lead_end..].starts_with('{') {
+        let Some(close) = text[lead_end..].find('}') else {
+            break;
+        };
+        lead_end += close + 1;
+    }
+    let (lead, body) = text.split_at(lead_end);
+
+    // Candidate cuts as (cut start, cut end, visible characters before)
+    let mut breaks = Vec::new();
+    let mut sentence_ends = Vec::new();
+    let mut visible = 0usize;
+    let mut in_override = false;
+    let mut chars = body.char_indices().peekable();
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '{' if !in_override => in_override = true,
+            '}' if in_override => in_override = false,
+            _ if in_override => {}
+            '\\' if chars.peek().is_some_and(|&(_, next)| next == 'N') => {
+                chars.next();
+                breaks.push((i, i + 2, visible));
+            }
+            _ => {
+                visible += 1;
+                let at_word_end = chars
+                    .peek()
+                    .is_some_and(|&(_, next)| next.is_whitespace() || next == '\\');
+                if SENTENCE_ENDS.contains(&ch) && at_word_end {
+                    let end = i + ch.len_utf8();
+                    sentence_ends.push((end, end, visible));
+                }
+            }
+        }
+    }
+
+    let middle = visible / 2;
+    let at_line_break = !breaks.is_empty();
+    let (cut_start, cut_end, before) = if !at_line_break {
+        let after_middle = sentence_ends.partition_point(|&(_, _, before)| before <= middle);
+        match after_middle.checked_sub(1) {
+            Some(last_before) => sentence_ends[last_before],
+            None => *sentence_ends.first()?,
+        }
+    } else {
+        breaks
+            .into_iter()
+            .min_by_key(|&(_, _, before)| before.abs_diff(middle))?
+    };
+    if before == 0 || before == visible {
+        return None;
+    }
+
+    Some(TextSplit {
+        first: format!("{lead}{}", body[..cut_start].trim_end()),
+        second: format!("{lead}{}", body[cut_end..].trim_start()),
+        share: before as f64 / visible as f64,
+        at_line_break,
+    })
+}
+
+/// A character the renderer draws, with the byte span it was written as:
+/// `\h` is one non-breaking space spanning two bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderedChar {
+    pub ch: char,
+    pub span: Range<usize>,
+}
+
+/// The characters event text renders as, split into rows at `\N`. Override
+/// blocks and drawings (`\p1` and up) are left out. A soft `\n` is a space,
+/// as every wrap style but 2 renders it.
+pub fn rendered_rows(text: &str) -> Vec<Vec<RenderedChar>> {
+    let mut rows = vec![Vec::new()];
+    let mut drawing = false;
+
+    for token in tokenize(text) {
+        let (text, span) = match token {
+            TextToken::Tag { tag, .. } => {
+                if override_tag_name(tag) == Some("p") {
+                    drawing = tag[1..].trim().parse::<u32>().is_ok_and(|scale| scale > 0);
+                }
+                continue;
+            }
+            TextToken::Text { text, span } => (text, span),
+        };
+        if drawing {
+            continue;
+        }
+
+        let mut chars = text.char_indices().peekable();
+        while let Some((i, ch)) = chars.next() {
+            let start = span.start + i;
+            let escaped = match (ch, chars.peek()) {
+                ('\\', Some(&(_, 'N'))) => {
+                    chars.next();
+                    rows.push(Vec::new());
+                    continue;
+                }
+                ('\\', Some(&(_, 'n'))) => Some(' '),
+                ('\\', Some(&(_, 'h'))) => Some('\u{a0}'),
+                _ => None,
+            };
+            let (ch, len) = match escaped {
+                Some(escaped) => {
+                    chars.next();
+                    (escaped, 2)
+                }
+                None => (ch, ch.len_utf8()),
+            };
+            rows.last_mut().unwrap().push(RenderedChar {
+                ch,
+                span: start..start + len,
+            });
+        }
+    }
+
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hard_break_splits_under_every_wrap_style() {
+        for wrap_style in 0..=3 {
+            assert_eq!(
+                visible_rows("{\\b1}One\\NTwo", wrap_style),
+                vec!["One", "Two"],
+                "wrap style {wrap_style}"
+            );
+        }
+    }
+
+    #[test]
+    fn soft_break_splits_only_under_wrap_style_two() {
+        for wrap_style in 0..=3 {
+            let rows = visible_rows("One\\nTwo", wrap_style);
+            if wrap_style == 2 {
+                assert_eq!(rows, vec!["One", "Two"]);
+            } else {
+                assert_eq!(rows, vec!["One Two"], "wrap style {wrap_style}");
+            }
+        }
+    }
+
+    #[test]
+    fn wrap_style_comes_from_script_then_last_q_tag() {
+        assert_eq!(effective_wrap_style(None, "Text"), 0);
+        for script in ["0", "1", "2", "3"] {
+            let expected = script.parse::<u8>().unwrap();
+            assert_eq!(effective_wrap_style(Some(script), "Text"), expected);
+        }
+        assert_eq!(effective_wrap_style(Some("9"), "Text"), 0);
+        assert_eq!(effective_wrap_style(Some(" 1 "), "Text"), 1);
+        for q in 0..=3u8 {
+            let text = format!("{{\\q{q}}}Text");
+            assert_eq!(effective_wrap_style(Some("1"), &text), q);
+        }
+        assert_eq!(effective_wrap_style(Some("1"), "{\\q2}A{\\q3}B"), 3);
+        assert_eq!(effective_wrap_style(Some("1"), "{\\q7}Text"), 1);
+    }
+
+    #[test]
+    fn soft_break_under_q2_breaks_in_previews_and_row_counts() {
+        let text = "{\\q2}First row\\nsecond row";
+        let wrap_style = effective_wrap_style(Some("0"), text);
+        let rows = visible_rows(text, wrap_style);
+        assert_eq!(rows, vec!["First row", "second row"]);
+        assert_eq!(excerpt(text, Some("0"), 80), rows.join(" / "));
+        assert_eq!(rows.iter().map(|row| row_length(row)).max(), Some(10));
+
+        let unwrapped = "First row\\nsecond row";
+        assert_eq!(excerpt(unwrapped, Some("0"), 80), "First row second row");
+        assert_eq!(excerpt(unwrapped, Some("2"), 80), "First row / second row");
+    }
+
+    #[test]
+    fn hard_space_is_kept_as_no_break_space() {
+        assert_eq!(visible_rows("A\\hB", 0), vec!["A\u{a0}B"]);
+    }
+}
@@ -0,0 +1,288 @@
+use crate::line_index::LineIndex;
+use crate::parser::{AssDocument, Event};
+use crate::text::excerpt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tower_lsp::lsp_types::{Position, Range, TextDocumentIdentifier, TextEdit, Url, WorkspaceEdit};
+
+/// Custom request listing a document's events in time order.
+pub const EVENTS_IN_TIME_ORDER: &str = "ass-lsp/eventsInTimeOrder";
+
+/// Command that sorts a document's events by start time. Takes the
+/// document's URI.
+pub const SORT_EVENTS: &str = "assLsp.sortEvents";
+
+const DEFAULT_PAGE_SIZE: usize = 200;
+const MAX_PAGE_SIZE: usize = 5000;
+const EXCERPT_CHARS: usize = 80;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventsInTimeOrderParams {
+    pub text_document: TextDocumentIdentifier,
+    /// Only events still showing at or after this time, in centiseconds.
+    pub window_start: Option<u32>,
+    /// Only events starting before this time, in centiseconds.
+    pub window_end: Option<u32>,
+    #[serde(default)]
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventsInTimeOrderResponse {
+    /// Version of the document snapshot the events were read from.
+    pub version: Option<i32>,
+    /// Number of events matching the window, across all pages.
+    pub total: usize,
+    pub events: Vec<TimelineEvent>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimelineEvent {
+    /// Zero-based line of the event in the document.
+    pub line: u32,
+    pub event_type: String,
+    pub start: String,
+    pub end: String,
+    /// Duration in centiseconds, 0 if the times are malformed or reversed.
+    pub duration: u32,
+    pub style: String,
+    pub actor: String,
+    pub excerpt: String,
+}
+
+/// Events sorted by start time, ties broken by line number so the order is
+/// stable across identical snapshots. Events with malformed start times are
+/// left out.
+#[derive(Debug)]
+pub struct EventIndex<'a> {
+    events: Vec<&'a Event>,
+}
+
+impl<'a> EventIndex<'a> {
+    pub fn new(document: &'a AssDocument) -> Self {
+        let mut events: Vec<&Event> = document
+            .events
+            .iter()
+            .filter(|event| event.start.is_some())
+            .collect();
+        events.sort_by_key(|event| (event.start, event.range.start.line));
+        Self { events }
+    }
+
+    /// Events overlapping `[window_start, window_end)`, in time order.
+    pub fn in_window(
+        &self,
+        window_start: Option<u32>,
+        window_end: Option<u32>,
+    ) -> impl Iterator<Item = &'a Event> + '_ {
+        // Events are sorted by start, so everything from the first event
+        // starting at or after the window end can be cut off
+        let cutoff = window_end.map_or(self.events.len(), |window_end| {
+            self.events
+                .partition_point(|event| event.start.is_some_and(|s| s.centiseconds() < window_end))
+        });
+        self.events[..cutoff].iter().copied().filter(move |event| {
+            window_start.is_none_or(|window_start| {
+                event
+                    .end
+                    .is_none_or(|end| end.centiseconds() > window_start)
+            })
+        })
+    }
+}
+
+pub fn events_in_time_order(
+    document: &AssDocument,
+    version: Option<i32>,
+    params: &EventsInTimeOrderParams,
+) -> EventsInTimeOrderResponse {
+    let index = EventIndex::new(document);
+    let matching: Vec<&Event> = index
+        .in_window(params.window_start, params.window_end)
+        .collect();
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+    let wrap_style = document.script_info.get("WrapStyle").map(String::as_str);
+
+    EventsInTimeOrderResponse {
+        version,
+        total: matching.len(),
+        events: matching
+            .iter()
+            .skip(params.offset)
+            .take(limit)
+            .map(|event| TimelineEvent {
+                line: event.range.start.line,
+                event_type: event.event_type.clone(),
+                start: event.start_time.clone(),
+                end: event.end_time.clone(),
+                duration: event.duration().map_or(0, |d| d.centiseconds()),
+                style: event.style.clone(),
+                actor: event.actor.clone(),
+                excerpt: excerpt(&event.text, wrap_style, EXCERPT_CHARS),
+            })
+            .collect(),
+    }
+}
+
+/// A workspace edit sorting the events by start time, rewriting only the
+/// lines that change. Comment lines sort with the dialogue, and ties keep
+/// their order. Events whose start time doesn't parse stay on their line,
+/// as do the Format line and comments. `None` when already sorted.
+pub fn sort_events_edit(
+    uri: &Url,
+    document: &AssDocument,
+    index: &LineIndex,
+) -> Option<WorkspaceEdit> {
+    let timed: Vec<&Event> = document
+        .events
+        .iter()
+        .filter(|event| event.start.is_some())
+        .collect();
+    let mut sorted = timed.clone();
+    sorted.sort_by_key(|event| event.start);
+
+    let edits: Vec<TextEdit> = timed
+        .iter()
+        .zip(&sorted)
+        .filter(|(slot, event)| slot.range.start.line != event.range.start.line)
+        .map(|(slot, event)| {
+            let line = slot.range.start.line as usize;
+            TextEdit {
+                range: index.range(Range::new(
+                    Position::new(line as u32, 0),
+                    Position::new(line as u32, index.line_text(line).len() as u32),
+                )),
+                new_text: index.line_text(event.range.start.line as usize).to_string(),
+            }
+        })
+        .collect();
+    (!edits.is_empty()).then(|| WorkspaceEdit {
+        changes: Some(HashMap::from([(uri.clone(), edits)])),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_index::{apply_edits, PositionEncoding};
+    use crate::parser::AssParser;
+
+    const SCRIPT: &str = "[Script Info]\nScriptType: v4.00+\n\n[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\nDialogue: 0,0:00:05.00,0:00:07.00,Default,,0,0,0,,Third\nDialogue: 0,0:00:01.00,0:00:03.00,Default,,0,0,0,,First\nDialogue: 0,0:00:10.00,0:00:12.00,Default,,0,0,0,,Fifth\nComment: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,Second, tied with the first\nDialogue: 0,x:00:00.00,0:00:01.00,Default,,0,0,0,,Malformed\nDialogue: 0,0:00:08.00,0:00:09.00,Default,,0,0,0,,Fourth\n";
+
+    fn params(
+        window: (Option<u32>, Option<u32>),
+        offset: usize,
+        limit: Option<usize>,
+    ) -> EventsInTimeOrderParams {
+        EventsInTimeOrderParams {
+            text_document: TextDocumentIdentifier::new(Url::parse("file:///a.ass").unwrap()),
+            window_start: window.0,
+            window_end: window.1,
+            offset,
+            limit,
+        }
+    }
+
+    fn page(
+        window: (Option<u32>, Option<u32>),
+        offset: usize,
+        limit: Option<usize>,
+    ) -> (usize, Vec<String>) {
+        let document = AssParser::new().parse(SCRIPT);
+        let response = events_in_time_order(&document, Some(3), &params(window, offset, limit));
+        assert_eq!(response.version, Some(3));
+        let excerpts = response
+            .events
+            .into_iter()
+            .map(|event| event.excerpt)
+            .collect();
+        (response.total, excerpts)
+    }
+
+    #[test]
+    fn events_are_sorted_with_ties_in_line_order() {
+        let (total, excerpts) = page((None, None), 0, None);
+        assert_eq!(total, 5);
+        assert_eq!(
+            excerpts,
+            [
+                "First",
+                "Second, tied with the first",
+                "Third",
+                "Fourth",
+                "Fifth"
+            ]
+        );
+    }
+
+    #[test]
+    fn pages_split_at_their_boundaries() {
+        assert_eq!(
+            page((None, None), 0, Some(2)).1,
+            ["First", "Second, tied with the first"]
+        );
+        assert_eq!(page((None, None), 2, Some(2)).1, ["Third", "Fourth"]);
+        assert_eq!(page((None, None), 4, Some(2)).1, ["Fifth"]);
+        assert_eq!(page((None, None), 5, Some(2)), (5, Vec::new()));
+        assert_eq!(page((None, None), 99, None), (5, Vec::new()));
+        assert_eq!(page((None, None), 0, Some(0)), (5, Vec::new()));
+    }
+
+    #[test]
+    fn window_keeps_events_showing_inside_it() {
+        // Ending exactly at the window start is outside it
+        assert_eq!(
+            page((Some(300), None), 0, None).1,
+            ["Third", "Fourth", "Fifth"]
+        );
+        // Starting exactly at the window end is outside it
+        assert_eq!(
+            page((None, Some(800)), 0, None).1,
+            ["First", "Second, tied with the first", "Third"]
+        );
+        assert_eq!(
+            page((Some(600), Some(1000)), 0, None),
+            (2, vec!["Third".to_string(), "Fourth".to_string()])
+        );
+        assert_eq!(
+            page((Some(600), Some(1000)), 1, Some(1)),
+            (2, vec!["Fourth".to_string()])
+        );
+        assert_eq!(page((Some(1300), None), 0, None), (0, Vec::new()));
+    }
+
+    #[test]
+    fn sorting_moves_timed_events_around_the_ones_that_stay() {
+        let uri = Url::parse("file:///a.ass").unwrap();
+        let parser = AssParser::new();
+        let index = LineIndex::new(SCRIPT.to_string(), PositionEncoding::Utf16);
+        let edit = sort_events_edit(&uri, &parser.parse(SCRIPT), &index).unwrap();
+        let edits = &edit.changes.unwrap()[&uri];
+        let sorted = apply_edits(SCRIPT, PositionEncoding::Utf16, edits);
+        let texts: Vec<&str> = sorted
+            .lines()
+            .skip(4)
+            .map(|line| line.rsplit(",,").next().unwrap())
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                "Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text",
+                "First",
+                "Second, tied with the first",
+                "Third",
+                "Fourth",
+                "Malformed",
+                "Fifth",
+            ]
+        );
+
+        let index = LineIndex::new(sorted.clone(), PositionEncoding::Utf16);
+        assert!(sort_events_edit(&uri, &parser.parse(&sorted), &index).is_none());
+    }
+}
@@ -1,40 +1,483 @@
-use crate::parser::{AssDocument, Event, Style};
+use crate::bidi::{bidi_class, directional_control, BidiClass, RLM};
+use crate::drawing::{
+    bounding_box, drawing_coordinates, drawing_modes, drawing_problem, scale_divisor,
+    MAX_DRAWING_SCALE,
+};
+use crate::fonts::{self, FontCatalog, SYSTEM_FONTS};
+use crate::karaoke::{karaoke_end, karaoke_syllables};
+use crate::line_index::LineIndex;
+use crate::metadata::{
+    closest_name, closest_override_tag, event_effect, known_tag_name, override_tag_name,
+    written_tag_name, ColorContext, ANIMATABLE_TAGS, COLOR_TAGS, EVENT_EFFECTS, KARAOKE_TAGS,
+    POSITIONING_TAGS, TEMPLATER_EFFECTS,
+};
+use crate::metadata::{text_backdrop, BORDER_STYLE_OPAQUE_BOX};
+use crate::parser::{
+    attachment_header_key, canonical_script_info_key, default_event_format, default_style_format,
+    detect_line_ending, field_range, is_known_script_info_key, parse_format_line,
+    parse_section_header, strip_prefix_ignore_case, style_format_at, AssColor, AssDocument,
+    AssTime, ColorSpelling, Event, HeaderProblem, MarginSide, ParseIssueReason, Style,
+    EVENT_FORMAT, V4_PLUS_STYLE_FORMAT, V4_STYLE_FORMAT,
+};
+use crate::render::{
+    border_scaling, effective_margin, effective_play_res, missing_play_res_note, play_res,
+    play_res_entries, resolved_play_res, style_role, MarginSource, PlayResEntry, RenderTarget,
+    StyleRole,
+};
+use crate::settings::RuleLevel;
+use crate::text::{
+    argument_span, empty_override_blocks, parse_transform, rendered_rows, row_length, split_text,
+    tag_arguments, tokenize, trailing_override_blocks, unclosed_block_end, visible_rows,
+    TagArguments, TextToken, TransformError,
+};
 use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::ops::Range as Span;
 use tower_lsp::lsp_types::*;
 
-#[derive(Debug)]
+/// Command that rewrites every legacy `\a` tag of a document to `\an`,
+/// taking its URI.
+pub const CONVERT_ALL_LEGACY_ALIGNMENT: &str = "assLsp.convertAllLegacyAlignment";
+
+/// Command that deletes every unused style of a document, taking its URI.
+pub const REMOVE_UNUSED_STYLES: &str = "assLsp.removeUnusedStyles";
+
+/// Every diagnostic code the server can emit, used to check suppression comments.
+pub const DIAGNOSTIC_CODES: &[&str] = &[
+    "missing_section",
+    "empty_style_name",
+    "zero_font_size",
+    "invalid_color",
+    "legacy_color",
+    "invalid_time_format",
+    "implausible_timestamp",
+    "invalid_time_order",
+    "unmatched_brace",
+    "unclosed_override",
+    "undefined_style",
+    "too_few_fields",
+    "missing_colon",
+    "unknown_line_prefix",
+    "missing_font",
+    "unverified_font",
+    "invisible_box_shadow",
+    "low_contrast",
+    "malformed_section_header",
+    "karaoke_timing_mismatch",
+    "malformed_transform",
+    "invalid_transform_timing",
+    "non_animatable_tag",
+    "equivalent_style",
+    "field_count_mismatch",
+    "trailing_override_tags",
+    "empty_override_block",
+    "unscaled_border_and_shadow",
+    "missing_scaled_border_and_shadow",
+    "invalid_script_info_value",
+    "invalid_margin",
+    "margin_exceeds_play_res",
+    "attachment_key_mismatch",
+    "unsafe_attachment_filename",
+    "duplicate_attachment",
+    "invalid_tag_arguments",
+    "unknown_tag",
+    "wrong_case_tag",
+    "karaoke_tag_case",
+    "high_cps",
+    "zero_duration",
+    "short_duration",
+    "duplicate_style",
+    "padding_run",
+    "unused_style",
+    "unsorted_events",
+    "pasted_timing",
+    "unknown_effect",
+    "invalid_effect",
+    "directional_control",
+    "bidi_punctuation",
+    "deprecated_tag",
+    "alignment_margin_mismatch",
+    "long_line",
+    "timing_overlap",
+    "unused_drawing_precision",
+    "drawing_as_text",
+    "drawing_scale_out_of_range",
+    "invalid_fade_timing",
+    "invalid_fade_alpha",
+    "duplicate_event",
+    "conflicting_play_res",
+    "duplicate_script_info_key",
+    "too_many_diagnostics",
+    "missing_play_res",
+    "inverted_clip",
+    "invalid_clip_drawing",
+    "clip_outside_play_res",
+    "position_outside_play_res",
+    "cross_file_duplicate",
+    "missing_format_line",
+    "unknown_format_field",
+    "duplicate_format_field",
+    "misplaced_text_field",
+    "script_type_mismatch",
+    "missing_script_type",
+];
+
+/// Contrast ratio between a style's text and its outline or box below which
+/// the two are hard to tell apart.
+const MIN_CONTRAST_RATIO: f64 = 1.5;
+
+/// One of the [`DIAGNOSTIC_CODES`], as set on every diagnostic the validator
+/// reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DiagnosticCode(&'static str);
+
+impl DiagnosticCode {
+    /// Every code, in the order of [`DIAGNOSTIC_CODES`].
+    pub fn all() -> impl Iterator<Item = DiagnosticCode> {
+        DIAGNOSTIC_CODES.iter().map(|code| DiagnosticCode(code))
+    }
+
+    /// The code of a diagnostic, `None` if it has none the validator knows.
+    pub fn of(diagnostic: &Diagnostic) -> Option<DiagnosticCode> {
+        match &diagnostic.code {
+            Some(NumberOrString::String(code)) => code.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        self.0
+    }
+}
+
+impl std::str::FromStr for DiagnosticCode {
+    type Err = UnknownDiagnosticCode;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        DIAGNOSTIC_CODES
+            .iter()
+            .find(|known| **known == code)
+            .map(|known| DiagnosticCode(known))
+            .ok_or_else(|| UnknownDiagnosticCode(code.to_string()))
+    }
+}
+
+impl std::fmt::Display for DiagnosticCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+/// A code that isn't one of the [`DIAGNOSTIC_CODES`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownDiagnosticCode(pub String);
+
+impl std::fmt::Display for UnknownDiagnosticCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown diagnostic code `{}`", self.0)
+    }
+}
+
+impl std::error::Error for UnknownDiagnosticCode {}
+
+/// What validation checks and the limits it checks against. Opt-in checks
+/// are off by default.
+#[derive(Debug, Clone)]
+pub struct ValidationOptions {
+    /// Timestamps beyond this many centiseconds are reported as implausible.
+    pub timestamp_ceiling: u32,
+    /// Opt-in check that style fonts are installed or embedded.
+    pub check_missing_fonts: bool,
+    /// Opt-in check for styles that differ only in name.
+    pub check_equivalent_styles: bool,
+    /// Opt-in check that dialogue is listed in start time order.
+    pub check_event_order: bool,
+    /// Opt-in check for runs of spaces or `\h` used to line text up.
+    pub check_padding: bool,
+    /// Opt-in check for right-to-left lines with left-to-right runs whose
+    /// edge punctuation renderers place differently.
+    pub check_bidi_punctuation: bool,
+    /// Check for dialogue styles aligned to the top with a margin meant for
+    /// the bottom, or the other way round. Sign and lyrics styles are skipped.
+    pub check_alignment_margins: bool,
+    /// Fraction of PlayResY above which a top-aligned style's MarginV looks
+    /// meant for bottom placement.
+    pub top_margin_fraction: f64,
+    /// Opt-in check, on each edit, for dialogue pasted from a script timed
+    /// against different video.
+    pub check_pasted_timing: bool,
+    /// How far, in milliseconds, pasted dialogue must be from the events on
+    /// both sides of it to be taken as timed differently.
+    pub pasted_timing_gap_ms: u64,
+    /// Opt-in check, run by the server's deep pass, for dialogue that
+    /// another script in the same folder also has at an overlapping time.
+    pub check_cross_file_duplicates: bool,
+    /// Row prefixes after which a run of spaces is indentation, not padding,
+    /// as in dash-led dialogue.
+    pub padding_prefixes: Vec<String>,
+    /// Renderer whose defaults are assumed for settings a script leaves out.
+    pub render_target: RenderTarget,
+    /// Opt-in profile that also flags tags that still work but have a
+    /// clearer equivalent, such as `\K` for `\kf`.
+    pub strict_compat: bool,
+    /// Dialogue shown for less than this many milliseconds is flagged as a flash.
+    pub min_duration_ms: u64,
+    /// How many centiseconds the karaoke of a line may end before or after
+    /// the line itself.
+    pub karaoke_tolerance_cs: u32,
+    /// Characters per second above which dialogue gets a reading speed note.
+    pub cps_soft_limit: f64,
+    /// Characters per second above which dialogue is too fast to read.
+    pub cps_hard_limit: f64,
+    /// Rows of dialogue longer than this many characters are reported; off
+    /// when unset.
+    pub max_line_length: Option<usize>,
+    /// Severity overrides by diagnostic code, applied to everything reported.
+    pub rule_levels: HashMap<String, RuleLevel>,
+    /// Diagnostics published per document at most, so a broken file doesn't
+    /// bury the editor; the rest are counted in one summary.
+    pub max_diagnostics: usize,
+}
+
+impl Default for ValidationOptions {
+    fn default() -> Self {
+        Self {
+            timestamp_ceiling: 10 * 360000,
+            check_missing_fonts: false,
+            check_equivalent_styles: false,
+            check_event_order: false,
+            check_padding: false,
+            check_bidi_punctuation: false,
+            check_alignment_margins: true,
+            top_margin_fraction: 0.6,
+            check_pasted_timing: false,
+            pasted_timing_gap_ms: 5 * 60 * 1000,
+            check_cross_file_duplicates: false,
+            padding_prefixes: vec!["-".to_string(), "–".to_string(), "—".to_string()],
+            render_target: RenderTarget::default(),
+            strict_compat: false,
+            min_duration_ms: 500,
+            karaoke_tolerance_cs: 10,
+            cps_soft_limit: 18.0,
+            cps_hard_limit: 25.0,
+            max_line_length: None,
+            rule_levels: HashMap::new(),
+            max_diagnostics: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ValidationProvider {
     time_regex: Regex,
-    color_regex: Regex,
+    pub(crate) options: ValidationOptions,
+}
+
+impl Default for ValidationProvider {
+    fn default() -> Self {
+        Self::with_options(ValidationOptions::default())
+    }
 }
 
 impl ValidationProvider {
     pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(options: ValidationOptions) -> Self {
         Self {
             time_regex: Regex::new(r"^\d{1,2}:\d{2}:\d{2}\.\d{2}$").unwrap(),
-            color_regex: Regex::new(r"^&H[0-9A-Fa-f]{6,8}$|^\d+$").unwrap(),
+            options,
+        }
+    }
+
+    pub fn options(&self) -> &ValidationOptions {
+        &self.options
+    }
+
+    /// Gives diagnostics the severity configured for their code, dropping
+    /// those whose rule is turned off.
+    pub(crate) fn apply_rule_levels(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        if self.options.rule_levels.is_empty() {
+            return diagnostics;
+        }
+        diagnostics
+            .into_iter()
+            .filter_map(|mut diagnostic| {
+                let level = match &diagnostic.code {
+                    Some(NumberOrString::String(code)) => self.options.rule_levels.get(code),
+                    _ => None,
+                };
+                if let Some(level) = level {
+                    diagnostic.severity = Some(level.severity()?);
+                }
+                Some(diagnostic)
+            })
+            .collect()
+    }
+
+    /// Drops repeats of a diagnostic with the same range, code and message,
+    /// then keeps the [`max_diagnostics`](Self::max_diagnostics) most severe,
+    /// in their original order, plus a summary of how many were left out.
+    pub(crate) fn limit_diagnostics(&self, mut diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        let mut seen = HashSet::new();
+        diagnostics.retain(|diagnostic| {
+            let code = match &diagnostic.code {
+                Some(NumberOrString::String(code)) => code.clone(),
+                Some(NumberOrString::Number(code)) => code.to_string(),
+                None => String::new(),
+            };
+            let Range { start, end } = diagnostic.range;
+            let span = (start.line, start.character, end.line, end.character);
+            seen.insert((span, code, diagnostic.message.clone()))
+        });
+        if diagnostics.len() <= self.options.max_diagnostics {
+            return diagnostics;
         }
+
+        let mut order: Vec<usize> = (0..diagnostics.len()).collect();
+        // A missing severity is the client's to pick, so it ranks last
+        order.sort_by_key(|&index| {
+            let severity = diagnostics[index].severity;
+            (severity.is_none(), severity)
+        });
+        let mut kept = order[..self.options.max_diagnostics].to_vec();
+        kept.sort_unstable();
+        let left_out = diagnostics.len() - kept.len();
+        let mut limited: Vec<Diagnostic> = kept
+            .into_iter()
+            .map(|index| diagnostics[index].clone())
+            .collect();
+        limited.extend(self.apply_rule_levels(vec![Diagnostic {
+            range: Range::default(),
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String("too_many_diagnostics".to_string())),
+            code_description: None,
+            source: Some("ass-lsp".to_string()),
+            message: format!(
+                "{left_out} more diagnostics not shown; only the {} most severe are listed",
+                self.options.max_diagnostics
+            ),
+            related_information: None,
+            tags: None,
+            data: None,
+        }]));
+        limited
     }
 
-    pub fn validate(&self, document: &AssDocument) -> Vec<Diagnostic> {
+    pub fn validate(&self, document: &AssDocument, uri: &Url) -> Vec<Diagnostic> {
+        let mut diagnostics = self.validate_document(document, uri);
+        diagnostics.extend(self.validate_lines(document, 0..usize::MAX));
+        diagnostics
+    }
+
+    /// Checks that look across the whole document and must rerun on every
+    /// change. `uri` is the document's, for pointing at other lines in it.
+    pub(crate) fn validate_document(&self, document: &AssDocument, uri: &Url) -> Vec<Diagnostic> {
         let mut diagnostics = Vec::new();
 
         // Validate required sections
         diagnostics.extend(self.validate_required_sections(document));
 
+        // Report headers that were recognized despite being malformed
+        diagnostics.extend(self.validate_section_headers(document));
+
+        // Format lines that don't map fields the way they were meant to
+        diagnostics.extend(self.validate_format_lines(document, uri));
+
+        // Report lines the parser had to skip
+        diagnostics.extend(self.validate_parse_errors(document));
+
+        // Check for style references
+        diagnostics.extend(self.validate_style_references(document));
+
+        // Styles that share a name, of which renderers only use one
+        diagnostics.extend(self.validate_duplicate_styles(document, uri));
+
+        // Styles nothing renders with
+        diagnostics.extend(self.validate_unused_styles(document));
+
+        // Dialogue pasted twice, which renders twice
+        diagnostics.extend(self.validate_duplicate_events(document, uri));
+
+        // Check values of the Script Info keys renderers interpret
+        diagnostics.extend(self.validate_script_info_values(document));
+
+        // ScriptType that disagrees with the styles section's version
+        diagnostics.extend(self.validate_script_type(document, uri));
+
+        // Script Info keys set more than once, of which renderers keep the last
+        diagnostics.extend(self.validate_duplicate_script_info_keys(document, uri));
+
+        // Resolution keys set twice, or only one of the pair set
+        diagnostics.extend(self.validate_play_res(document, uri));
+
+        // Clip rectangles the script's resolution never shows
+        diagnostics.extend(self.validate_clip_bounds(document));
+
+        // Positions pasted from a script with a larger resolution
+        diagnostics.extend(self.validate_positions(document));
+
+        // Explain how borders and shadows will scale
+        diagnostics.extend(self.validate_border_scaling(document));
+
+        // Margins that leave no room on screen
+        diagnostics.extend(self.validate_margin_overflow(document));
+
+        // Styles whose alignment and vertical margin disagree
+        if self.options.check_alignment_margins {
+            diagnostics.extend(self.validate_alignment_margins(document, uri));
+        }
+
+        // Tags with a newer equivalent, which depends on the script type
+        diagnostics.extend(self.validate_deprecated_tags(document));
+
+        if self.options.check_event_order {
+            diagnostics.extend(self.validate_event_order(document));
+        }
+
+        if self.options.check_missing_fonts {
+            diagnostics.extend(self.validate_fonts(document, &SYSTEM_FONTS));
+        }
+
+        if self.options.check_equivalent_styles {
+            diagnostics.extend(self.validate_equivalent_styles(document));
+        }
+
+        // Check attachment headers
+        diagnostics.extend(self.validate_attachments(document));
+
+        diagnostics
+    }
+
+    /// Checks of the styles and events starting within `lines`. Each depends only
+    /// on its own line, so results for lines an edit didn't touch stay valid.
+    pub(crate) fn validate_lines(
+        &self,
+        document: &AssDocument,
+        lines: std::ops::Range<usize>,
+    ) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let in_lines = |range: &Range| lines.contains(&(range.start.line as usize));
+
         // Validate styles
-        for style in &document.styles {
+        for style in document
+            .styles
+            .iter()
+            .filter(|style| in_lines(&style.range))
+        {
             diagnostics.extend(self.validate_style(style));
         }
 
         // Validate events
-        for event in &document.events {
+        for event in document
+            .events
+            .iter()
+            .filter(|event| in_lines(&event.range))
+        {
             diagnostics.extend(self.validate_event(event));
         }
 
-        // Check for style references
-        diagnostics.extend(self.validate_style_references(document));
-
         diagnostics
     }
 
@@ -57,7 +500,7 @@ impl ValidationProvider {
                     message: format!("Missing required section: [{required}]"),
                     related_information: None,
                     tags: None,
-                    data: None,
+                    data: Some(serde_json::json!({ "section": required })),
                 });
             }
         }
@@ -65,49 +508,349 @@ impl ValidationProvider {
         diagnostics
     }
 
-    fn validate_style(&self, style: &Style) -> Vec<Diagnostic> {
+    /// The Format lines of the styles and events sections: every field must
+    /// be one renderers know, named once, with Text last among event fields
+    /// as it takes the rest of the line. A section with style or event
+    /// lines but no Format line is an error, as several renderers reject
+    /// it.
+    fn validate_format_lines(&self, document: &AssDocument, uri: &Url) -> Vec<Diagnostic> {
         let mut diagnostics = Vec::new();
+        let diagnostic = |range: Range, severity, code: &str, message: String| Diagnostic {
+            range,
+            severity: Some(severity),
+            code: Some(NumberOrString::String(code.to_string())),
+            code_description: None,
+            source: Some("ass-lsp".to_string()),
+            message,
+            related_information: None,
+            tags: None,
+            data: None,
+        };
 
-        // Validate style name
-        if style.name.is_empty() {
+        for section in &document.sections {
+            let (known, entry_prefixes): (Vec<&'static str>, &[&str]) = if section.name == "Events"
+            {
+                // SSA calls the Layer column Marked
+                let fields = EVENT_FORMAT.iter().copied().chain(["Marked"]).collect();
+                (fields, &["Dialogue:", "Comment:"])
+            } else if section.name.contains("Styles") {
+                let mut fields = V4_PLUS_STYLE_FORMAT.to_vec();
+                fields.extend(
+                    V4_STYLE_FORMAT
+                        .iter()
+                        .filter(|field| !V4_PLUS_STYLE_FORMAT.contains(field)),
+                );
+                (fields, &["Style:"])
+            } else {
+                continue;
+            };
+
+            let mut has_format = false;
+            for (offset, text) in section.content.iter().enumerate() {
+                let line = section.range.start.line + offset as u32;
+                let Some(format) = strip_prefix_ignore_case(text.trim(), "Format:") else {
+                    continue;
+                };
+                has_format = true;
+                let range_of = |index: usize| {
+                    let span = field_range(text, index).unwrap_or(0..0);
+                    Range {
+                        start: Position::new(line, span.start as u32),
+                        end: Position::new(line, span.end as u32),
+                    }
+                };
+
+                let fields = parse_format_line(format);
+                let mut seen: HashMap<String, usize> = HashMap::new();
+                for (index, field) in fields.iter().enumerate() {
+                    if field.is_empty() {
+                        continue;
+                    }
+                    if !known.iter().any(|name| name.eq_ignore_ascii_case(field)) {
+                        let suggestion = closest_name(field, &known);
+                        let mut unknown = diagnostic(
+                            range_of(index),
+                            DiagnosticSeverity::ERROR,
+                            "unknown_format_field",
+                            format!(
+                                "Unknown {} field '{field}'{}; renderers ignore its column",
+                                section.name,
+                                suggestion
+                                    .map(|name| format!(" (did you mean '{name}'?)"))
+                                    .unwrap_or_default()
+                            ),
+                        );
+                        unknown.data =
+                            suggestion.map(|name| serde_json::json!({ "suggestion": name }));
+                        diagnostics.push(unknown);
+                        continue;
+                    }
+                    let Some(&first) = seen.get(&field.to_ascii_lowercase()) else {
+                        seen.insert(field.to_ascii_lowercase(), index);
+                        continue;
+                    };
+                    let mut duplicate = diagnostic(
+                        range_of(index),
+                        DiagnosticSeverity::WARNING,
+                        "duplicate_format_field",
+                        format!("Field '{field}' is listed twice, so its columns conflict"),
+                    );
+                    duplicate.related_information = Some(vec![DiagnosticRelatedInformation {
+                        location: Location::new(uri.clone(), range_of(first)),
+                        message: "First listed here".to_string(),
+                    }]);
+                    diagnostics.push(duplicate);
+                }
+
+                if section.name == "Events" {
+                    let text_index = fields
+                        .iter()
+                        .position(|field| field.eq_ignore_ascii_case("Text"));
+                    if let Some(index) = text_index.filter(|&index| index + 1 < fields.len()) {
+                        diagnostics.push(diagnostic(
+                            range_of(index),
+                            DiagnosticSeverity::ERROR,
+                            "misplaced_text_field",
+                            format!(
+                                "Text must be the last field: it takes the rest of the line, so {} never get a value",
+                                fields[index + 1..].join(", ")
+                            ),
+                        ));
+                    }
+                }
+            }
+
+            let has_entries = section.content.iter().any(|text| {
+                entry_prefixes
+                    .iter()
+                    .any(|prefix| strip_prefix_ignore_case(text.trim(), prefix).is_some())
+            });
+            if has_entries && !has_format {
+                let mut missing = diagnostic(
+                    section.header_range,
+                    DiagnosticSeverity::ERROR,
+                    "missing_format_line",
+                    format!(
+                        "[{}] has no Format line; several renderers reject its lines without one",
+                        section.name
+                    ),
+                );
+                missing.data = Some(serde_json::json!({ "section": section.name }));
+                diagnostics.push(missing);
+            }
+        }
+
+        diagnostics
+    }
+
+    fn validate_section_headers(&self, document: &AssDocument) -> Vec<Diagnostic> {
+        document
+            .sections
+            .iter()
+            .filter_map(|section| {
+                let message = match section.header_problem.as_ref()? {
+                    HeaderProblem::TrailingText(text) => format!(
+                        "Unexpected text after section header [{}]: '{text}'",
+                        section.name
+                    ),
+                    HeaderProblem::MissingBracket => {
+                        format!(
+                            "Section header [{}] is missing its closing ']'",
+                            section.name
+                        )
+                    }
+                };
+                Some(Diagnostic {
+                    range: section.header_range,
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    code: Some(NumberOrString::String(
+                        "malformed_section_header".to_string(),
+                    )),
+                    code_description: None,
+                    source: Some("ass-lsp".to_string()),
+                    message,
+                    related_information: None,
+                    tags: None,
+                    // The clean header, used by the quick fix
+                    data: Some(serde_json::Value::String(format!("[{}]", section.name))),
+                })
+            })
+            .collect()
+    }
+
+    /// Values of well-known Script Info keys that renderers can't read. Unknown
+    /// keys are left alone.
+    fn validate_script_info_values(&self, document: &AssDocument) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        for (line, text) in script_info_lines(document) {
+            let Some((key, rest)) = text.split_once(':') else {
+                continue;
+            };
+            let key = canonical_script_info_key(key.trim());
+            let value = rest.trim();
+            let Some((severity, expected)) = script_info_value_problem(&key, value) else {
+                continue;
+            };
+
+            let start = text.len() - rest.trim_start().len();
             diagnostics.push(Diagnostic {
-                range: style.range,
-                severity: Some(DiagnosticSeverity::ERROR),
-                code: Some(NumberOrString::String("empty_style_name".to_string())),
+                range: Range {
+                    start: Position::new(line, start as u32),
+                    end: Position::new(line, (start + value.len()) as u32),
+                },
+                severity: Some(severity),
+                code: Some(NumberOrString::String(
+                    "invalid_script_info_value".to_string(),
+                )),
                 code_description: None,
                 source: Some("ass-lsp".to_string()),
-                message: "Style name cannot be empty".to_string(),
+                message: format!("Invalid {key} '{value}': expected {expected}"),
                 related_information: None,
                 tags: None,
                 data: None,
             });
         }
 
-        // Validate font size
-        if style.fontsize == 0 {
-            diagnostics.push(Diagnostic {
-                range: style.range,
+        diagnostics
+    }
+
+    /// A ScriptType naming one format over a styles section of the other.
+    /// Renderers that go by the section header then read style alignments
+    /// as one numbering and those that go by ScriptType as the other. A
+    /// V4+ script without a ScriptType gets a suggestion to add one.
+    fn validate_script_type(&self, document: &AssDocument, uri: &Url) -> Option<Diagnostic> {
+        let styles = document
+            .sections
+            .iter()
+            .find(|section| matches!(section.name.as_str(), "V4+ Styles" | "V4 Styles"))?;
+        let styles_ass = styles.name == "V4+ Styles";
+        let related = Some(vec![DiagnosticRelatedInformation {
+            location: Location::new(uri.clone(), styles.header_range),
+            message: format!("[{}] section", styles.name),
+        }]);
+
+        // Renderers keep the last ScriptType they read
+        let script_type = script_info_lines(document).rev().find_map(|(line, text)| {
+            let (key, rest) = text.split_once(':')?;
+            (canonical_script_info_key(key.trim()) == "ScriptType").then_some((line, text, rest))
+        });
+        let Some((line, text, rest)) = script_type else {
+            if !styles_ass {
+                return None;
+            }
+            let script_info = document
+                .sections
+                .iter()
+                .find(|section| section.name == "Script Info")?;
+            return Some(Diagnostic {
+                range: script_info.header_range,
                 severity: Some(DiagnosticSeverity::WARNING),
-                code: Some(NumberOrString::String("zero_font_size".to_string())),
+                code: Some(NumberOrString::String("missing_script_type".to_string())),
                 code_description: None,
                 source: Some("ass-lsp".to_string()),
-                message: "Font size should not be zero".to_string(),
-                related_information: None,
+                message: "ScriptType is not set for a script with [V4+ Styles]; add ScriptType: v4.00+ so every renderer reads it as ASS".to_string(),
+                related_information: related,
                 tags: None,
-                data: None,
+                data: Some(serde_json::json!({ "insertAt": script_info.header_range.end })),
             });
+        };
+
+        let value = rest.trim();
+        let declared_ass = match value.to_ascii_lowercase().as_str() {
+            "v4.00+" | "v4.00++" => true,
+            "v4.00" => false,
+            // Reported by validate_script_info_values
+            _ => return None,
+        };
+        if declared_ass == styles_ass {
+            return None;
         }
+        let (declared, found) = if declared_ass {
+            ("ASS", "SSA")
+        } else {
+            ("SSA", "ASS")
+        };
+        let start = text.len() - rest.trim_start().len();
+        Some(Diagnostic {
+            range: Range {
+                start: Position::new(line, start as u32),
+                end: Position::new(line, (start + value.len()) as u32),
+            },
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String("script_type_mismatch".to_string())),
+            code_description: None,
+            source: Some("ass-lsp".to_string()),
+            message: format!(
+                "ScriptType {value} declares {declared}, but the styles are in [{}], an {found} section; renderers that go by the header read style Alignment as {found} and those that go by ScriptType as {declared}, so lines can land in different places",
+                styles.name
+            ),
+            related_information: related,
+            tags: None,
+            data: None,
+        })
+    }
 
-        // Validate colors
-        if !self.color_regex.is_match(&style.primary_colour) {
+    /// Script Info keys set more than once. Renderers keep the last value, so
+    /// an earlier one is dead text that reads as if it applied. Standard keys
+    /// set to a different value are warnings; repeats of the same value and
+    /// of custom keys, which only tools read, are information.
+    fn validate_duplicate_script_info_keys(
+        &self,
+        document: &AssDocument,
+        uri: &Url,
+    ) -> Vec<Diagnostic> {
+        let entries = &document.script_info_entries;
+        let mut diagnostics = Vec::new();
+
+        for (index, entry) in entries.iter().enumerate() {
+            let Some(previous) = entries[..index]
+                .iter()
+                .rfind(|other| other.key == entry.key)
+            else {
+                continue;
+            };
+            let changed = previous.value != entry.value;
+            // Reported as conflicting_play_res, with the resolution it leads to
+            if changed && matches!(entry.key.as_str(), "PlayResX" | "PlayResY") {
+                continue;
+            }
+            let Some(winner) = document.script_info.get(&entry.key) else {
+                continue;
+            };
+            let known = is_known_script_info_key(&entry.key);
+            let message = if changed {
+                format!(
+                    "{} is set again, to '{}' after '{}' on line {}; the last value, '{winner}', is the one that applies",
+                    entry.key,
+                    entry.value,
+                    previous.value,
+                    previous.range.start.line + 1
+                )
+            } else {
+                format!(
+                    "{} is set again to the same value as on line {}; the repeat can be removed",
+                    entry.key,
+                    previous.range.start.line + 1
+                )
+            };
             diagnostics.push(Diagnostic {
-                range: style.range,
-                severity: Some(DiagnosticSeverity::ERROR),
-                code: Some(NumberOrString::String("invalid_color".to_string())),
+                range: entry.range,
+                severity: Some(if known && changed {
+                    DiagnosticSeverity::WARNING
+                } else {
+                    DiagnosticSeverity::INFORMATION
+                }),
+                code: Some(NumberOrString::String(
+                    "duplicate_script_info_key".to_string(),
+                )),
                 code_description: None,
                 source: Some("ass-lsp".to_string()),
-                message: format!("Invalid color format: {}", style.primary_colour),
-                related_information: None,
+                message,
+                related_information: Some(vec![DiagnosticRelatedInformation {
+                    location: Location::new(uri.clone(), previous.range),
+                    message: format!("Earlier {}", previous.key),
+                }]),
                 tags: None,
                 data: None,
             });
@@ -116,137 +859,303 @@ impl ValidationProvider {
         diagnostics
     }
 
-    fn validate_event(&self, event: &Event) -> Vec<Diagnostic> {
+    /// PlayResX or PlayResY set again to a different value, which renderers
+    /// resolve by reading order, and a pair with one key missing, which they
+    /// fill in from the other.
+    fn validate_play_res(&self, document: &AssDocument, uri: &Url) -> Vec<Diagnostic> {
+        let entries = play_res_entries(document);
         let mut diagnostics = Vec::new();
+        let range_of = |entry: &PlayResEntry| Range {
+            start: Position::new(entry.line, entry.span.start as u32),
+            end: Position::new(entry.line, entry.span.end as u32),
+        };
 
-        // Validate time format
-        if !self.time_regex.is_match(&event.start_time) {
+        for (index, entry) in entries.iter().enumerate() {
+            let Some(previous) = entries[..index]
+                .iter()
+                .rfind(|other| other.key == entry.key)
+                .filter(|other| other.value != entry.value)
+            else {
+                continue;
+            };
+            let (x, y) = resolved_play_res(&entries, self.options.render_target);
             diagnostics.push(Diagnostic {
-                range: event.range,
+                range: range_of(entry),
                 severity: Some(DiagnosticSeverity::ERROR),
-                code: Some(NumberOrString::String("invalid_time_format".to_string())),
+                code: Some(NumberOrString::String("conflicting_play_res".to_string())),
                 code_description: None,
                 source: Some("ass-lsp".to_string()),
                 message: format!(
-                    "Invalid time format: {} (expected H:MM:SS.CC)",
-                    event.start_time
+                    "{} is set again, to {} after {} on line {}; {} lays the script out at {x}x{y}",
+                    entry.key,
+                    entry.value,
+                    previous.value,
+                    previous.line + 1,
+                    self.options.render_target.name()
                 ),
-                related_information: None,
+                related_information: Some(vec![DiagnosticRelatedInformation {
+                    location: Location::new(uri.clone(), range_of(previous)),
+                    message: format!("Earlier {}", previous.key),
+                }]),
                 tags: None,
                 data: None,
             });
         }
 
-        if !self.time_regex.is_match(&event.end_time) {
-            diagnostics.push(Diagnostic {
-                range: event.range,
-                severity: Some(DiagnosticSeverity::ERROR),
-                code: Some(NumberOrString::String("invalid_time_format".to_string())),
-                code_description: None,
-                source: Some("ass-lsp".to_string()),
-                message: format!(
-                    "Invalid time format: {} (expected H:MM:SS.CC)",
-                    event.end_time
-                ),
-                related_information: None,
-                tags: None,
-                data: None,
-            });
+        if let Some((missing, note)) = missing_play_res_note(&entries) {
+            let present = entries
+                .iter()
+                .rfind(|entry| entry.key != missing && entry.resolution().is_some());
+            if let Some(present) = present {
+                diagnostics.push(Diagnostic {
+                    range: range_of(present),
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(NumberOrString::String("missing_play_res".to_string())),
+                    code_description: None,
+                    source: Some("ass-lsp".to_string()),
+                    message: format!("{missing} is not set; {note}"),
+                    related_information: None,
+                    tags: None,
+                    data: None,
+                });
+            }
         }
 
-        // Validate time order
-        if self.parse_time(&event.start_time) >= self.parse_time(&event.end_time) {
-            diagnostics.push(Diagnostic {
-                range: event.range,
-                severity: Some(DiagnosticSeverity::WARNING),
-                code: Some(NumberOrString::String("invalid_time_order".to_string())),
-                code_description: None,
-                source: Some("ass-lsp".to_string()),
-                message: "Start time should be before end time".to_string(),
-                related_information: None,
-                tags: None,
-                data: None,
-            });
-        }
+        diagnostics
+    }
 
-        // Validate override tags in dialogue text
-        diagnostics.extend(self.validate_override_tags(&event.text, event.range));
+    /// Clip rectangles lying wholly outside the PlayRes frame, which clip
+    /// everything for `\clip` and nothing for `\iclip`.
+    fn validate_clip_bounds(&self, document: &AssDocument) -> Vec<Diagnostic> {
+        let (width, height) =
+            resolved_play_res(&play_res_entries(document), self.options.render_target);
+        let (width, height) = (f64::from(width), f64::from(height));
+        let mut diagnostics = Vec::new();
+
+        for event in document
+            .events
+            .iter()
+            .filter(|event| event.event_type == "Dialogue")
+        {
+            for clip in event_clips(event) {
+                let ClipShape::Rectangle([x1, y1, x2, y2]) = clip.shape else {
+                    continue;
+                };
+                let (left, right) = (x1.min(x2), x1.max(x2));
+                let (top, bottom) = (y1.min(y2), y1.max(y2));
+                if right >= 0.0 && bottom >= 0.0 && left <= width && top <= height {
+                    continue;
+                }
+                let effect = if clip.name == "clip" {
+                    "hides the whole line"
+                } else {
+                    "has no effect"
+                };
+                diagnostics.push(Diagnostic {
+                    range: Range {
+                        start: Position::new(event.range.start.line, clip.columns.start as u32),
+                        end: Position::new(event.range.start.line, clip.columns.end as u32),
+                    },
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(NumberOrString::String("clip_outside_play_res".to_string())),
+                    code_description: None,
+                    source: Some("ass-lsp".to_string()),
+                    message: format!(
+                        "\\{} rectangle lies outside the {width}x{height} PlayRes frame, so it {effect}",
+                        clip.name
+                    ),
+                    related_information: None,
+                    tags: None,
+                    data: None,
+                });
+            }
+        }
 
         diagnostics
     }
 
-    fn validate_override_tags(&self, text: &str, range: Range) -> Vec<Diagnostic> {
+    /// `\pos`, `\move` and `\org` coordinates more than a little outside
+    /// the PlayRes frame, typically pasted from a script laid out at a
+    /// larger resolution. Skipped when the script sets no resolution, which
+    /// is reported on its own.
+    fn validate_positions(&self, document: &AssDocument) -> Vec<Diagnostic> {
+        let entries = play_res_entries(document);
+        if entries.iter().all(|entry| entry.resolution().is_none()) {
+            return Vec::new();
+        }
+        let (width, height) = resolved_play_res(&entries, self.options.render_target);
         let mut diagnostics = Vec::new();
-        let mut brace_count = 0;
-        let mut _in_override = false;
-
-        for (i, ch) in text.chars().enumerate() {
-            match ch {
-                '{' => {
-                    brace_count += 1;
-                    _in_override = true;
-                }
-                '}' => {
-                    if brace_count == 0 {
-                        diagnostics.push(Diagnostic {
-                            range: Range {
-                                start: Position::new(
-                                    range.start.line,
-                                    range.start.character + i as u32,
-                                ),
-                                end: Position::new(
-                                    range.start.line,
-                                    range.start.character + i as u32 + 1,
-                                ),
-                            },
-                            severity: Some(DiagnosticSeverity::ERROR),
-                            code: Some(NumberOrString::String("unmatched_brace".to_string())),
-                            code_description: None,
-                            source: Some("ass-lsp".to_string()),
-                            message: "Unmatched closing brace".to_string(),
-                            related_information: None,
-                            tags: None,
-                            data: None,
-                        });
+
+        for event in document
+            .events
+            .iter()
+            .filter(|event| event.event_type == "Dialogue")
+        {
+            for (tag, span) in event_tags(event) {
+                let Some(name @ ("pos" | "move" | "org")) = known_tag_name(tag) else {
+                    continue;
+                };
+                let arguments = tag_arguments(tag, name);
+                if tag_argument_problem(name, &arguments).is_some() {
+                    continue;
+                }
+                // \move's trailing arguments are times
+                for (index, arg) in arguments.args.iter().take(4).enumerate() {
+                    let value: f64 = arg.parse().unwrap_or_default();
+                    let (axis, limit) = if index % 2 == 0 {
+                        ("x", width)
                     } else {
-                        brace_count -= 1;
-                        _in_override = false;
+                        ("y", height)
+                    };
+                    let slack = f64::from(limit) * POSITION_SLACK;
+                    if (-slack..=f64::from(limit) + slack).contains(&value) {
+                        continue;
                     }
+                    let point = match (name, index) {
+                        ("move", 0 | 1) => "start ",
+                        ("move", _) => "end ",
+                        _ => "",
+                    };
+                    // A far-off rotation origin is a common way to bend text
+                    let (severity, note) = if name == "org" {
+                        (
+                            DiagnosticSeverity::INFORMATION,
+                            "; fine if the rotation is meant to pivot far away",
+                        )
+                    } else {
+                        (DiagnosticSeverity::WARNING, "")
+                    };
+                    // The tag's span starts at its backslash
+                    let start = event.text_start as usize + span.start + 1;
+                    let arg = argument_span(tag, arg);
+                    diagnostics.push(Diagnostic {
+                        range: Range {
+                            start: Position::new(
+                                event.range.start.line,
+                                (start + arg.start) as u32,
+                            ),
+                            end: Position::new(event.range.start.line, (start + arg.end) as u32),
+                        },
+                        severity: Some(severity),
+                        code: Some(NumberOrString::String(
+                            "position_outside_play_res".to_string(),
+                        )),
+                        code_description: None,
+                        source: Some("ass-lsp".to_string()),
+                        message: format!(
+                            "\\{name} {point}{axis} {value} is outside the {width}x{height} PlayRes frame{note}"
+                        ),
+                        related_information: None,
+                        tags: None,
+                        data: None,
+                    });
                 }
-                _ => {}
             }
         }
 
-        if brace_count > 0 {
-            diagnostics.push(Diagnostic {
-                range,
-                severity: Some(DiagnosticSeverity::ERROR),
-                code: Some(NumberOrString::String("unclosed_override".to_string())),
-                code_description: None,
-                source: Some("ass-lsp".to_string()),
-                message: "Unclosed override tag".to_string(),
-                related_information: None,
-                tags: None,
-                data: None,
+        diagnostics
+    }
+
+    fn validate_border_scaling(&self, document: &AssDocument) -> Option<Diagnostic> {
+        let script_info = document
+            .sections
+            .iter()
+            .find(|section| section.name == "Script Info")?;
+        let scaling = border_scaling(&document.script_info, self.options.render_target);
+        let resolution = play_res(&document.script_info)
+            .map(|(x, y)| format!(" authored for {x}x{y}"))
+            .unwrap_or_default();
+
+        let (range, code, message, data) = if !scaling.explicit {
+            // An unreadable value is reported by validate_script_info_values
+            if document.script_info.contains_key("ScaledBorderAndShadow") {
+                return None;
+            }
+            let default = if scaling.scaled { "yes" } else { "no" };
+            (
+                script_info.header_range,
+                "missing_scaled_border_and_shadow",
+                format!(
+                    "ScaledBorderAndShadow is not set, so {} assumes {default} and other renderers may not; set it to yes so borders{resolution} scale with the video",
+                    self.options.render_target.name()
+                ),
+                Some(serde_json::json!({ "insertAt": script_info.range.end })),
+            )
+        } else if !scaling.scaled {
+            let line = script_info_lines(document).rfind(|(_, line)| {
+                line.split_once(':').is_some_and(|(key, _)| {
+                    canonical_script_info_key(key.trim()) == "ScaledBorderAndShadow"
+                })
             });
-        }
+            let (line, text) = line?;
+            let indent = text.len() - text.trim_start().len();
+            (
+                Range {
+                    start: Position::new(line, indent as u32),
+                    end: Position::new(line, text.trim_end().len() as u32),
+                },
+                "unscaled_border_and_shadow",
+                format!(
+                    "Outline, Shadow and blur are in video pixels, so borders{resolution} look thicker on smaller video and thinner on larger"
+                ),
+                None,
+            )
+        } else {
+            return None;
+        };
 
-        diagnostics
+        Some(Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::INFORMATION),
+            code: Some(NumberOrString::String(code.to_string())),
+            code_description: None,
+            source: Some("ass-lsp".to_string()),
+            message,
+            related_information: None,
+            tags: None,
+            data,
+        })
     }
 
-    fn validate_style_references(&self, document: &AssDocument) -> Vec<Diagnostic> {
+    /// Event margins above half the play resolution on their axis, which push
+    /// the text off-screen. Only event overrides are reported, on their field.
+    fn validate_margin_overflow(&self, document: &AssDocument) -> Vec<Diagnostic> {
         let mut diagnostics = Vec::new();
-        let style_names: Vec<&str> = document.styles.iter().map(|s| s.name.as_str()).collect();
+        let Some((play_res_x, play_res_y)) = play_res(&document.script_info) else {
+            return diagnostics;
+        };
 
         for event in &document.events {
-            if !style_names.contains(&event.style.as_str()) && event.style != "Default" {
+            let style = document.style(&event.style);
+            for side in MarginSide::ALL {
+                let margin = effective_margin(event, style, side);
+                let (axis, play_res) = match side {
+                    MarginSide::Vertical => ("PlayResY", play_res_y),
+                    _ => ("PlayResX", play_res_x),
+                };
+                if margin.source != MarginSource::Event || margin.value <= play_res / 2 {
+                    continue;
+                }
+                let Some(field) = event.margin(side) else {
+                    continue;
+                };
+                let line = event.range.start.line;
                 diagnostics.push(Diagnostic {
-                    range: event.range,
+                    range: Range {
+                        start: Position::new(line, field.span.start as u32),
+                        end: Position::new(line, field.span.end as u32),
+                    },
                     severity: Some(DiagnosticSeverity::WARNING),
-                    code: Some(NumberOrString::String("undefined_style".to_string())),
+                    code: Some(NumberOrString::String(
+                        "margin_exceeds_play_res".to_string(),
+                    )),
                     code_description: None,
                     source: Some("ass-lsp".to_string()),
-                    message: format!("Reference to undefined style: {}", event.style),
+                    message: format!(
+                        "{} {} is more than half of {axis} {play_res}, pushing the text off-screen",
+                        side.field_name(),
+                        margin.value
+                    ),
                     related_information: None,
                     tags: None,
                     data: None,
@@ -257,22 +1166,5197 @@ impl ValidationProvider {
         diagnostics
     }
 
-    fn parse_time(&self, time_str: &str) -> u32 {
-        let parts: Vec<&str> = time_str.split(':').collect();
-        if parts.len() != 3 {
-            return 0;
+    /// Attachment headers with the other section's key, filenames older
+    /// renderers may not handle, and filenames used twice in one section.
+    fn validate_attachments(&self, document: &AssDocument) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let diagnostic = |range, code: &str, message| Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String(code.to_string())),
+            code_description: None,
+            source: Some("ass-lsp".to_string()),
+            message,
+            related_information: None,
+            tags: None,
+            data: None,
+        };
+
+        for (index, attachment) in document.attachments.iter().enumerate() {
+            let expected = attachment_header_key(&attachment.section);
+            if attachment.key != expected {
+                diagnostics.push(diagnostic(
+                    Range {
+                        start: attachment.range.start,
+                        end: attachment.filename_range.start,
+                    },
+                    "attachment_key_mismatch",
+                    format!(
+                        "[{}] attachments start with `{expected}:`, so renderers skip this `{}:` one",
+                        attachment.section, attachment.key
+                    ),
+                ));
+            }
+
+            let mut unsafe_chars: Vec<char> = attachment
+                .filename
+                .chars()
+                .filter(|ch| !(ch.is_ascii_alphanumeric() || matches!(ch, '_' | '-' | '.')))
+                .collect();
+            unsafe_chars.sort_unstable();
+            unsafe_chars.dedup();
+            if !unsafe_chars.is_empty() {
+                diagnostics.push(diagnostic(
+                    attachment.filename_range,
+                    "unsafe_attachment_filename",
+                    format!(
+                        "Attachment filename contains '{}'; older renderers only handle letters, digits, '_', '-' and '.'",
+                        unsafe_chars.into_iter().collect::<String>()
+                    ),
+                ));
+            }
+
+            let earlier = document.attachments[..index].iter().find(|other| {
+                other.section == attachment.section
+                    && other.filename.eq_ignore_ascii_case(&attachment.filename)
+            });
+            if let Some(earlier) = earlier {
+                diagnostics.push(diagnostic(
+                    attachment.filename_range,
+                    "duplicate_attachment",
+                    format!(
+                        "[{}] already has an attachment named {} (line {})",
+                        attachment.section,
+                        earlier.filename,
+                        earlier.range.start.line + 1
+                    ),
+                ));
+            }
         }
 
-        let hours: u32 = parts[0].parse().unwrap_or(0);
-        let minutes: u32 = parts[1].parse().unwrap_or(0);
-        let seconds_parts: Vec<&str> = parts[2].split('.').collect();
-        let seconds: u32 = seconds_parts[0].parse().unwrap_or(0);
-        let centiseconds: u32 = if seconds_parts.len() > 1 {
-            seconds_parts[1].parse().unwrap_or(0)
+        diagnostics
+    }
+
+    fn validate_parse_errors(&self, document: &AssDocument) -> Vec<Diagnostic> {
+        document
+            .parse_errors
+            .iter()
+            .map(|issue| {
+                let (code, message) = match issue.reason {
+                    ParseIssueReason::TooFewFields => {
+                        ("too_few_fields", "Line has too few fields and was skipped")
+                    }
+                    ParseIssueReason::MissingColon => (
+                        "missing_colon",
+                        "Line is missing a ':' separator and was skipped",
+                    ),
+                    ParseIssueReason::UnknownLinePrefix => (
+                        "unknown_line_prefix",
+                        "Unknown line type for this section; the line was skipped",
+                    ),
+                };
+                Diagnostic {
+                    range: Range {
+                        start: Position::new(issue.line, 0),
+                        end: Position::new(issue.line, issue.raw.len() as u32),
+                    },
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    code: Some(NumberOrString::String(code.to_string())),
+                    code_description: None,
+                    source: Some("ass-lsp".to_string()),
+                    message: message.to_string(),
+                    related_information: None,
+                    tags: None,
+                    data: None,
+                }
+            })
+            .collect()
+    }
+
+    fn validate_style(&self, style: &Style) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        // Validate style name
+        if style.name.is_empty() {
+            diagnostics.push(Diagnostic {
+                range: style.range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String("empty_style_name".to_string())),
+                code_description: None,
+                source: Some("ass-lsp".to_string()),
+                message: "Style name cannot be empty".to_string(),
+                related_information: None,
+                tags: None,
+                data: None,
+            });
+        }
+
+        // Validate font size
+        if style.fontsize == 0 {
+            diagnostics.push(Diagnostic {
+                range: style.range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String("zero_font_size".to_string())),
+                code_description: None,
+                source: Some("ass-lsp".to_string()),
+                message: "Font size should not be zero".to_string(),
+                related_information: None,
+                tags: None,
+                data: None,
+            });
+        }
+
+        // Validate colors
+        let line = style.range.start.line;
+        for ((name, value), span) in style.fields.iter().zip(&style.field_spans) {
+            if !name.to_ascii_lowercase().ends_with("colour") {
+                continue;
+            }
+            let range = Range {
+                start: Position::new(line, span.start as u32),
+                end: Position::new(line, span.end as u32),
+            };
+            diagnostics.extend(color_diagnostic(
+                range,
+                name,
+                value,
+                ColorContext::StyleField,
+            ));
+        }
+
+        // An opaque box's shadow is drawn in BackColour; fully transparent hides it
+        let shadow = style
+            .field("Shadow")
+            .and_then(|shadow| shadow.parse::<f64>().ok());
+        if style.field("BorderStyle") == Some(BORDER_STYLE_OPAQUE_BOX)
+            && shadow.is_some_and(|shadow| shadow > 0.0)
+            && style.back.is_some_and(AssColor::is_transparent)
+        {
+            diagnostics.push(Diagnostic {
+                range: style.range,
+                severity: Some(DiagnosticSeverity::HINT),
+                code: Some(NumberOrString::String("invisible_box_shadow".to_string())),
+                code_description: None,
+                source: Some("ass-lsp".to_string()),
+                message: "BorderStyle 3 box shadow is drawn in BackColour, which is fully transparent; the shadow will be invisible".to_string(),
+                related_information: None,
+                tags: None,
+                data: None,
+            });
+        }
+
+        // Text that barely stands out from its outline, or from its box
+        let outline = style
+            .field("Outline")
+            .and_then(|outline| outline.parse::<f64>().ok())
+            .unwrap_or(0.0);
+        if let Some((backdrop, field)) = text_backdrop(style.field("BorderStyle"), outline) {
+            let colour = if field == "BackColour" {
+                style.back
+            } else {
+                style.outline
+            };
+            let ratio = style
+                .primary
+                .zip(colour)
+                .filter(|(fill, colour)| !fill.is_transparent() && !colour.is_transparent())
+                .map(|(fill, colour)| fill.contrast_ratio(colour));
+            if let Some(ratio) = ratio.filter(|ratio| *ratio < MIN_CONTRAST_RATIO) {
+                diagnostics.push(Diagnostic {
+                    range: style.range,
+                    severity: Some(DiagnosticSeverity::HINT),
+                    code: Some(NumberOrString::String("low_contrast".to_string())),
+                    code_description: None,
+                    source: Some("ass-lsp".to_string()),
+                    message: format!(
+                        "PrimaryColour has a contrast ratio of {ratio:.1}:1 with the {backdrop}, drawn in {field}; the text may be hard to read"
+                    ),
+                    related_information: None,
+                    tags: None,
+                    data: None,
+                });
+            }
+        }
+
+        diagnostics
+    }
+
+    fn validate_event(&self, event: &Event) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+
+        // Fields were mapped on a best-effort basis
+        if let Some((expected, found)) = event.field_count_mismatch {
+            diagnostics.push(Diagnostic {
+                range: event.range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String("field_count_mismatch".to_string())),
+                code_description: None,
+                source: Some("ass-lsp".to_string()),
+                message: format!(
+                    "Event has {found} fields but the Events Format declares {expected}"
+                ),
+                related_information: None,
+                tags: None,
+                data: None,
+            });
+        }
+
+        // Margins are non-negative integers, with 0 meaning the style's margin
+        for margin in &event.margins {
+            if margin.value.parse::<u32>().is_ok() {
+                continue;
+            }
+            let name = margin.side.field_name();
+            let message = if margin.value.parse::<i64>().is_ok() {
+                format!("{name} cannot be negative; use 0 for the style's margin")
+            } else {
+                format!(
+                    "Invalid {name} '{}': expected a non-negative integer",
+                    margin.value
+                )
+            };
+            let line = event.range.start.line;
+            diagnostics.push(Diagnostic {
+                range: Range {
+                    start: Position::new(line, margin.span.start as u32),
+                    end: Position::new(line, margin.span.end as u32),
+                },
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String("invalid_margin".to_string())),
+                code_description: None,
+                source: Some("ass-lsp".to_string()),
+                message,
+                related_information: None,
+                tags: None,
+                data: None,
+            });
+        }
+
+        // Validate time format
+        let line = event.range.start.line;
+        for (time, span) in [
+            (&event.start_time, &event.start_span),
+            (&event.end_time, &event.end_span),
+        ] {
+            if self.time_regex.is_match(time) {
+                continue;
+            }
+            let range = Range {
+                start: Position::new(line, span.start as u32),
+                end: Position::new(line, span.end as u32),
+            };
+            diagnostics.push(Diagnostic {
+                range: event.range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String("invalid_time_format".to_string())),
+                code_description: None,
+                source: Some("ass-lsp".to_string()),
+                message: format!("Invalid time format: {time} (expected H:MM:SS.CC)"),
+                related_information: None,
+                tags: None,
+                data: Some(serde_json::json!({ "range": range })),
+            });
+        }
+
+        let start = event.start.map(|time| time.centiseconds());
+        let end = event.end.map(|time| time.centiseconds());
+
+        // Flag timestamps that are valid but almost certainly typos
+        for time in [start, end].into_iter().flatten() {
+            if time > self.options.timestamp_ceiling {
+                diagnostics.push(Diagnostic {
+                    range: event.range,
+                    severity: Some(DiagnosticSeverity::INFORMATION),
+                    code: Some(NumberOrString::String("implausible_timestamp".to_string())),
+                    code_description: None,
+                    source: Some("ass-lsp".to_string()),
+                    message: format!(
+                        "Timestamp exceeds {}; is this a typo?",
+                        AssTime(self.options.timestamp_ceiling)
+                    ),
+                    related_information: None,
+                    tags: None,
+                    data: None,
+                });
+                break;
+            }
+        }
+
+        // Validate time order
+        if let (Some(start), Some(end), None) = (event.start, event.end, event.duration()) {
+            let field = |span: &Span<usize>| Range {
+                start: Position::new(line, span.start as u32),
+                end: Position::new(line, span.end as u32),
+            };
+            diagnostics.push(Diagnostic {
+                range: event.range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String("invalid_time_order".to_string())),
+                code_description: None,
+                source: Some("ass-lsp".to_string()),
+                message: "Start time should be before end time".to_string(),
+                related_information: None,
+                tags: None,
+                data: Some(serde_json::json!({
+                    "start": start.to_string(),
+                    "end": end.to_string(),
+                    "startRange": field(&event.start_span),
+                    "endRange": field(&event.end_span),
+                })),
+            });
+        }
+
+        // Lines too short to read, or not shown at all
+        let duration = event.duration().map(|duration| duration.as_millis());
+        if let (Some(duration), "Dialogue") = (duration, event.event_type.as_str()) {
+            let problem = if duration == 0 {
+                Some((
+                    DiagnosticSeverity::WARNING,
+                    "zero_duration",
+                    "Event has zero duration and is never shown".to_string(),
+                ))
+            } else if duration < self.options.min_duration_ms {
+                Some((
+                    DiagnosticSeverity::INFORMATION,
+                    "short_duration",
+                    format!(
+                        "Event is shown for only {duration}ms, under the {}ms minimum to be readable",
+                        self.options.min_duration_ms
+                    ),
+                ))
+            } else {
+                None
+            };
+            if let Some((severity, code, message)) = problem {
+                let line = event.range.start.line;
+                let span = event.timing_span();
+                diagnostics.push(Diagnostic {
+                    range: Range {
+                        start: Position::new(line, span.start as u32),
+                        end: Position::new(line, span.end as u32),
+                    },
+                    severity: Some(severity),
+                    code: Some(NumberOrString::String(code.to_string())),
+                    code_description: None,
+                    source: Some("ass-lsp".to_string()),
+                    message,
+                    related_information: None,
+                    tags: None,
+                    data: None,
+                });
+            }
+        }
+
+        // Karaoke syllables should end with the event
+        let syllables = karaoke_syllables(event);
+        if let (Some(duration), Some(karaoke_total), Some(last)) =
+            (event.duration(), karaoke_end(&syllables), syllables.last())
+        {
+            let duration = duration.centiseconds();
+            let message = if karaoke_total
+                > duration.saturating_add(self.options.karaoke_tolerance_cs)
+            {
+                Some(format!(
+                    "Karaoke syllables last {karaoke_total}cs but the event only lasts {duration}cs, so the last syllable is cut off"
+                ))
+            } else if duration > karaoke_total.saturating_add(self.options.karaoke_tolerance_cs) {
+                Some(format!(
+                    "Karaoke syllables last {karaoke_total}cs but the event lasts {duration}cs, so the last syllable holds for {}cs",
+                    duration - karaoke_total
+                ))
+            } else {
+                None
+            };
+            if let Some(message) = message {
+                let line = event.range.start.line;
+                diagnostics.push(Diagnostic {
+                    range: Range {
+                        start: Position::new(line, last.tag_span.start as u32),
+                        end: Position::new(line, last.tag_span.end as u32),
+                    },
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(NumberOrString::String(
+                        "karaoke_timing_mismatch".to_string(),
+                    )),
+                    code_description: None,
+                    source: Some("ass-lsp".to_string()),
+                    message,
+                    related_information: None,
+                    tags: None,
+                    data: None,
+                });
+            }
+        }
+
+        // Validate override tags in dialogue text
+        diagnostics.extend(self.validate_override_tags(event));
+        diagnostics.extend(self.validate_transforms(event));
+        diagnostics.extend(self.validate_fades(event));
+        diagnostics.extend(self.validate_clips(event));
+        diagnostics.extend(self.validate_reading_speed(event));
+        if let Some(limit) = self.options.max_line_length {
+            diagnostics.extend(self.validate_line_length(event, limit));
+        }
+        if self.options.check_padding {
+            diagnostics.extend(self.validate_padding(event));
+        }
+        diagnostics.extend(self.validate_drawing_scales(event));
+        diagnostics.extend(self.validate_directional_controls(event));
+        if self.options.check_bidi_punctuation {
+            diagnostics.extend(self.validate_bidi_punctuation(event));
+        }
+        diagnostics.extend(self.validate_tag_arguments(event));
+        diagnostics.extend(self.validate_tag_colors(event));
+        diagnostics.extend(self.validate_effect(event));
+        diagnostics.extend(self.validate_unknown_tags(event));
+        diagnostics.extend(self.validate_karaoke_case(event));
+        diagnostics.extend(self.validate_trailing_tags(event));
+        diagnostics.extend(self.validate_empty_blocks(event));
+
+        diagnostics
+    }
+
+    /// `{}` and `{ }` left behind by editing, which render nothing. The data
+    /// holds the block for the quick fix that removes it.
+    fn validate_empty_blocks(&self, event: &Event) -> Vec<Diagnostic> {
+        let line = event.range.start.line;
+        empty_override_blocks(&event.text)
+            .into_iter()
+            .map(|span| Diagnostic {
+                range: Range {
+                    start: Position::new(line, event.text_start + span.start as u32),
+                    end: Position::new(line, event.text_start + span.end as u32),
+                },
+                severity: Some(DiagnosticSeverity::HINT),
+                code: Some(NumberOrString::String("empty_override_block".to_string())),
+                code_description: None,
+                source: Some("ass-lsp".to_string()),
+                message: "Empty override block".to_string(),
+                related_information: None,
+                tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+                data: Some(serde_json::json!({ "block": &event.text[span] })),
+            })
+            .collect()
+    }
+
+    /// Tags only affect the text after them, so a block after the last
+    /// rendered character is a no-op. The data carries what the quick fixes
+    /// need to move or delete the block.
+    fn validate_trailing_tags(&self, event: &Event) -> Vec<Diagnostic> {
+        // Comment events aren't rendered, so nothing in them trails
+        if event.event_type == "Comment" {
+            return Vec::new();
+        }
+        let line = event.range.start.line;
+        trailing_override_blocks(&event.text)
+            .into_iter()
+            .map(|span| {
+                let block = &event.text[span.clone()];
+                let names: Vec<&str> = tokenize(block)
+                    .into_iter()
+                    .filter_map(|token| match token {
+                        TextToken::Tag { tag, .. } => override_tag_name(tag),
+                        TextToken::Text { .. } => None,
+                    })
+                    .collect();
+                let positioning = names.iter().any(|name| POSITIONING_TAGS.contains(name));
+                let mut data = serde_json::json!({
+                    "block": block,
+                    "textStart": event.text_start,
+                    "moveToStart": positioning,
+                });
+                // A trailing \k is an empty syllable, which the karaoke timing
+                // rule reports on its own when it throws the timing off
+                if !names.is_empty() && names.iter().all(|name| KARAOKE_TAGS.contains(name)) {
+                    data["coveredBy"] = "karaoke_timing_mismatch".into();
+                }
+
+                Diagnostic {
+                    range: Range {
+                        start: Position::new(line, event.text_start + span.start as u32),
+                        end: Position::new(line, event.text_start + span.end as u32),
+                    },
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(NumberOrString::String("trailing_override_tags".to_string())),
+                    code_description: None,
+                    source: Some("ass-lsp".to_string()),
+                    message: "Override tags after the last visible character have no effect"
+                        .to_string(),
+                    related_information: None,
+                    tags: None,
+                    data: Some(data),
+                }
+            })
+            .collect()
+    }
+
+    fn validate_override_tags(&self, event: &Event) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut open_braces = Vec::new();
+        let line = event.range.start.line;
+
+        for (i, brace) in event.text.match_indices(['{', '}']) {
+            if brace == "{" {
+                open_braces.push(i);
+                continue;
+            }
+            if open_braces.pop().is_some() {
+                continue;
+            }
+            diagnostics.push(Diagnostic {
+                range: Range {
+                    start: Position::new(line, event.text_start + i as u32),
+                    end: Position::new(line, event.text_start + i as u32 + 1),
+                },
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String("unmatched_brace".to_string())),
+                code_description: None,
+                source: Some("ass-lsp".to_string()),
+                message: "Unmatched closing brace".to_string(),
+                related_information: None,
+                tags: None,
+                data: None,
+            });
+        }
+
+        if !open_braces.is_empty() {
+            // Where each unclosed block's tags end, if all of them can tell
+            let closes: Option<Vec<u32>> = open_braces
+                .iter()
+                .map(|&open| {
+                    let end = unclosed_block_end(&event.text, open)?;
+                    Some(event.text_start + end as u32)
+                })
+                .collect();
+            diagnostics.push(Diagnostic {
+                range: Range {
+                    start: Position::new(line, event.text_start + open_braces[0] as u32),
+                    end: event.range.end,
+                },
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String("unclosed_override".to_string())),
+                code_description: None,
+                source: Some("ass-lsp".to_string()),
+                message: "Unclosed override tag".to_string(),
+                related_information: None,
+                tags: None,
+                data: closes.map(|closes| serde_json::json!({ "closeAt": closes })),
+            });
+        }
+
+        diagnostics
+    }
+
+    /// Dialogue shown too briefly for its length, in characters per second of
+    /// visible text. Events without a positive duration are reported by the
+    /// timing checks, and drawings have no text to read.
+    fn validate_reading_speed(&self, event: &Event) -> Option<Diagnostic> {
+        let (characters, cps) = reading_speed(event)?;
+        let duration = event.duration()?.as_millis();
+        let (severity, limit) = if cps > self.options.cps_hard_limit {
+            (DiagnosticSeverity::WARNING, self.options.cps_hard_limit)
+        } else if cps > self.options.cps_soft_limit {
+            (DiagnosticSeverity::INFORMATION, self.options.cps_soft_limit)
         } else {
+            return None;
+        };
+
+        // What the split quick fix needs: the two texts and where the event's
+        // time share of the first one ends
+        let start = event.start?.centiseconds();
+        let data = split_text(&event.text).map(|split| {
+            let split_at = start + (duration as f64 / 10.0 * split.share).round() as u32;
+            serde_json::json!({
+                "split": {
+                    "first": split.first,
+                    "second": split.second,
+                    "atLineBreak": split.at_line_break,
+                    "time": AssTime(split_at).to_string(),
+                    "startSpan": event.start_span,
+                    "endSpan": event.end_span,
+                    "textStart": event.text_start,
+                }
+            })
+        });
+
+        let line = event.range.start.line;
+        Some(Diagnostic {
+            range: Range {
+                start: Position::new(line, event.text_start),
+                end: event.range.end,
+            },
+            severity: Some(severity),
+            code: Some(NumberOrString::String("high_cps".to_string())),
+            code_description: None,
+            source: Some("ass-lsp".to_string()),
+            message: format!(
+                "{cps:.1} characters per second is above the limit of {limit}: {characters} characters in {:.2}s",
+                duration as f64 / 1000.0
+            ),
+            related_information: None,
+            tags: None,
+            data,
+        })
+    }
+
+    /// Dialogue whose longest row, counted before automatic wrapping, has
+    /// more than `limit` characters.
+    fn validate_line_length(&self, event: &Event, limit: usize) -> Option<Diagnostic> {
+        if event.event_type != "Dialogue" {
+            return None;
+        }
+        let (row, length) = visible_rows(&event.text, 0)
+            .iter()
+            .map(|row| row_length(row))
+            .enumerate()
+            .max_by_key(|&(row, length)| (length, std::cmp::Reverse(row)))?;
+        if length <= limit {
+            return None;
+        }
+
+        let line = event.range.start.line;
+        Some(Diagnostic {
+            range: Range {
+                start: Position::new(line, event.text_start),
+                end: event.range.end,
+            },
+            severity: Some(DiagnosticSeverity::INFORMATION),
+            code: Some(NumberOrString::String("long_line".to_string())),
+            code_description: None,
+            source: Some("ass-lsp".to_string()),
+            message: format!(
+                "Row {} has {length} characters, above the limit of {limit}",
+                row + 1
+            ),
+            related_information: None,
+            tags: None,
+            data: None,
+        })
+    }
+
+    /// `\p` tags that look like a misreading of the scale: an exponent above
+    /// 4 on whole-number coordinates, which usually means it was taken for a
+    /// linear scale, `\p0` followed by drawing commands, which then render
+    /// as text, and a scale too large to mean anything.
+    fn validate_drawing_scales(&self, event: &Event) -> Vec<Diagnostic> {
+        let line = event.range.start.line;
+        let mut diagnostics = Vec::new();
+        for mode in drawing_modes(&event.text) {
+            let coordinates = drawing_coordinates(&mode.commands());
+            let (code, message) = match scale_divisor(mode.scale) {
+                Some(_) if mode.scale > MAX_DRAWING_SCALE => (
+                    "drawing_scale_out_of_range",
+                    format!(
+                        "\\p{} is out of range: the scale is an exponent, so coordinates would be divided by 2^{} and nothing is visible. Scales above \\p{MAX_DRAWING_SCALE} aren't meaningful",
+                        mode.scale,
+                        mode.scale - 1
+                    ),
+                ),
+                _ if coordinates.is_none() => continue,
+                None => (
+                    "drawing_as_text",
+                    "\\p0 ends drawing mode, so these drawing commands render as text".to_string(),
+                ),
+                Some(divisor)
+                    if mode.scale > 4
+                        && coordinates
+                            .iter()
+                            .flatten()
+                            .all(|(x, y)| x.fract() == 0.0 && y.fract() == 0.0) =>
+                {
+                    let Some(bounds) = coordinates
+                        .as_deref()
+                        .and_then(|coordinates| bounding_box(coordinates, mode.scale))
+                    else {
+                        continue;
+                    };
+                    (
+                        "unused_drawing_precision",
+                        format!(
+                            "\\p{} is an exponent, not a linear scale: coordinates are divided by {divisor}, so this drawing is {}×{} pixels",
+                            mode.scale,
+                            bounds.width(),
+                            bounds.height()
+                        ),
+                    )
+                }
+                Some(_) => continue,
+            };
+            let start = event.text_start as usize + mode.tag_span.start;
+            let end = event.text_start as usize + mode.tag_span.end;
+            let severity = if code == "drawing_scale_out_of_range" {
+                DiagnosticSeverity::WARNING
+            } else {
+                DiagnosticSeverity::HINT
+            };
+            diagnostics.push(Diagnostic {
+                range: Range {
+                    start: Position::new(line, start as u32),
+                    end: Position::new(line, end as u32),
+                },
+                severity: Some(severity),
+                code: Some(NumberOrString::String(code.to_string())),
+                code_description: None,
+                source: Some("ass-lsp".to_string()),
+                message,
+                related_information: None,
+                tags: None,
+                data: None,
+            });
+        }
+        diagnostics
+    }
+
+    /// Runs of three or more spaces or `\h` in rendered text, which fake
+    /// centring or indentation and fall apart when the font changes.
+    fn validate_padding(&self, event: &Event) -> Vec<Diagnostic> {
+        let line = event.range.start.line;
+        let mut diagnostics = Vec::new();
+        for row in rendered_rows(&event.text) {
+            let mut index = 0;
+            while index < row.len() {
+                let run = row[index..]
+                    .iter()
+                    .take_while(|rendered| matches!(rendered.ch, ' ' | '\u{a0}'))
+                    .count();
+                if run == 0 {
+                    index += 1;
+                    continue;
+                }
+                let before: String = row[..index].iter().map(|rendered| rendered.ch).collect();
+                let indentation = self
+                    .options
+                    .padding_prefixes
+                    .iter()
+                    .any(|prefix| before.trim() == prefix);
+                if run >= 3 && !indentation {
+                    let (start, end) = (row[index].span.start, row[index + run - 1].span.end);
+                    diagnostics.push(Diagnostic {
+                        range: Range {
+                            start: Position::new(line, event.text_start + start as u32),
+                            end: Position::new(line, event.text_start + end as u32),
+                        },
+                        severity: Some(DiagnosticSeverity::INFORMATION),
+                        code: Some(NumberOrString::String("padding_run".to_string())),
+                        code_description: None,
+                        source: Some("ass-lsp".to_string()),
+                        message: format!(
+                            "{run} spaces in a row line text up only in this font; position it with \\an, \\pos or margins instead"
+                        ),
+                        related_information: None,
+                        tags: None,
+                        data: None,
+                    });
+                }
+                index += run;
+            }
+        }
+        diagnostics
+    }
+
+    /// Directional control characters in rendered text. Embeddings,
+    /// overrides and isolates are always reported, since renderers differ in
+    /// whether they honour them. A direction mark is reported when its row
+    /// has no letters of that direction, where it can only be a leftover.
+    fn validate_directional_controls(&self, event: &Event) -> Vec<Diagnostic> {
+        let line = event.range.start.line;
+        let mut diagnostics = Vec::new();
+        for row in rendered_rows(&event.text) {
+            let has_letters = |class| {
+                row.iter().any(|rendered| {
+                    directional_control(rendered.ch).is_none() && bidi_class(rendered.ch) == class
+                })
+            };
+            for rendered in &row {
+                let Some(name) = directional_control(rendered.ch) else {
+                    continue;
+                };
+                let message = match bidi_class(rendered.ch) {
+                    BidiClass::Neutral => format!(
+                        "U+{:04X} {name} in the text; renderers differ in whether they honour explicit embeddings, overrides and isolates",
+                        u32::from(rendered.ch)
+                    ),
+                    class if !has_letters(class) => {
+                        let (direction, other) = match class {
+                            BidiClass::Rtl => ("right-to-left", "left-to-right"),
+                            _ => ("left-to-right", "right-to-left"),
+                        };
+                        format!(
+                            "Stray U+{:04X} {name} in {other} text: the line has no {direction} letters for it to act on",
+                            u32::from(rendered.ch)
+                        )
+                    }
+                    _ => continue,
+                };
+                diagnostics.push(Diagnostic {
+                    range: Range {
+                        start: Position::new(line, event.text_start + rendered.span.start as u32),
+                        end: Position::new(line, event.text_start + rendered.span.end as u32),
+                    },
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(NumberOrString::String("directional_control".to_string())),
+                    code_description: None,
+                    source: Some("ass-lsp".to_string()),
+                    message,
+                    related_information: None,
+                    tags: None,
+                    data: Some(serde_json::json!({
+                        "name": format!("U+{:04X} {name}", u32::from(rendered.ch)),
+                    })),
+                });
+            }
+        }
+        diagnostics
+    }
+
+    /// Right-to-left rows with left-to-right runs that start or end with
+    /// punctuation. Renderers with and without full bidi support put that
+    /// punctuation on different sides; a right-to-left mark next to it pins
+    /// it. The data holds where the marks go.
+    fn validate_bidi_punctuation(&self, event: &Event) -> Vec<Diagnostic> {
+        let line = event.range.start.line;
+        let mut diagnostics = Vec::new();
+        for row in rendered_rows(&event.text) {
+            let visible: Vec<_> = row
+                .iter()
+                .filter(|rendered| !rendered.ch.is_whitespace())
+                .collect();
+            let classes: Vec<BidiClass> = visible
+                .iter()
+                .map(|rendered| bidi_class(rendered.ch))
+                .collect();
+            let first_strong = classes.iter().find(|class| **class != BidiClass::Neutral);
+            if first_strong != Some(&BidiClass::Rtl) || !classes.contains(&BidiClass::Ltr) {
+                continue;
+            }
+            let is_punctuation =
+                |ch: char| bidi_class(ch) == BidiClass::Neutral && !ch.is_alphanumeric();
+            let (Some(first), Some(last)) = (visible.first(), visible.last()) else {
+                continue;
+            };
+            let mut insert_at = Vec::new();
+            let mut edges = Vec::new();
+            if is_punctuation(first.ch) {
+                insert_at.push(event.text_start as usize + first.span.start);
+                edges.push(format!("starts with `{}`", first.ch));
+            }
+            if is_punctuation(last.ch) {
+                insert_at.push(event.text_start as usize + last.span.end);
+                edges.push(format!("ends with `{}`", last.ch));
+            }
+            if edges.is_empty() {
+                continue;
+            }
+            diagnostics.push(Diagnostic {
+                range: Range {
+                    start: Position::new(line, event.text_start + first.span.start as u32),
+                    end: Position::new(line, event.text_start + last.span.end as u32),
+                },
+                severity: Some(DiagnosticSeverity::INFORMATION),
+                code: Some(NumberOrString::String("bidi_punctuation".to_string())),
+                code_description: None,
+                source: Some("ass-lsp".to_string()),
+                message: format!(
+                    "Right-to-left line with left-to-right text {}; renderers may put the punctuation on different sides, so pin it with a right-to-left mark (U+200F)",
+                    edges.join(" and ")
+                ),
+                related_information: None,
+                tags: None,
+                data: Some(serde_json::json!({ "insertAt": insert_at })),
+            });
+        }
+        diagnostics
+    }
+
+    /// Styles used by dialogue whose Alignment and MarginV point at opposite
+    /// edges, usually a style copied from another and only half edited:
+    /// top-aligned with a MarginV beyond `top_margin_fraction` of the height,
+    /// or bottom-aligned with one of 90% or more.
+    fn validate_alignment_margins(&self, document: &AssDocument, uri: &Url) -> Vec<Diagnostic> {
+        let (_, play_res_y) = effective_play_res(&document.script_info);
+        let referenced = referenced_styles(document);
+        let mut diagnostics = Vec::new();
+
+        for style in &document.styles {
+            let (Some(alignment), Some(margin_v)) = (
+                style
+                    .field("Alignment")
+                    .and_then(|value| value.parse::<u8>().ok()),
+                style
+                    .field("MarginV")
+                    .and_then(|value| value.parse::<u32>().ok()),
+            ) else {
+                continue;
+            };
+            let share = f64::from(margin_v) / f64::from(play_res_y);
+            let message = match alignment {
+                7..=9 if share > self.options.top_margin_fraction => format!(
+                    "Alignment {alignment} puts text at the top, but MarginV {margin_v} is {:.0}% of PlayResY {play_res_y}, a margin meant for the bottom; the text renders {margin_v}px below the top edge, near the bottom of the screen",
+                    share * 100.0
+                ),
+                1..=3 if share >= 0.9 => format!(
+                    "Alignment {alignment} puts text at the bottom, but MarginV {margin_v} is {:.0}% of PlayResY {play_res_y}; the text renders {margin_v}px above the bottom edge, near the top of the screen",
+                    share * 100.0
+                ),
+                _ => continue,
+            };
+            if !referenced.contains(style.name.as_str())
+                || style_role(document, &style.name) != StyleRole::Dialogue
+            {
+                continue;
+            }
+            let (Some(margin_range), Some(alignment_range)) =
+                (style.field_range("MarginV"), style.field_range("Alignment"))
+            else {
+                continue;
+            };
+            diagnostics.push(Diagnostic {
+                range: margin_range,
+                severity: Some(DiagnosticSeverity::INFORMATION),
+                code: Some(NumberOrString::String(
+                    "alignment_margin_mismatch".to_string(),
+                )),
+                code_description: None,
+                source: Some("ass-lsp".to_string()),
+                message,
+                related_information: Some(vec![DiagnosticRelatedInformation {
+                    location: Location {
+                        uri: uri.clone(),
+                        range: alignment_range,
+                    },
+                    message: format!("Alignment {alignment}"),
+                }]),
+                tags: None,
+                data: None,
+            });
+        }
+        diagnostics
+    }
+
+    /// Legacy `\a` alignments, and `\K` under the strict profile, with the
+    /// tag to use instead in the data. SSA scripts are exempt, as `\an` is
+    /// an ASS tag. This looks at the script type, so it runs with the
+    /// document checks rather than per line.
+    fn validate_deprecated_tags(&self, document: &AssDocument) -> Vec<Diagnostic> {
+        if is_ssa_script(document) {
+            return Vec::new();
+        }
+
+        let mut diagnostics = Vec::new();
+        for event in document
+            .events
+            .iter()
+            .filter(|event| event.event_type == "Dialogue")
+        {
+            let line = event.range.start.line;
+            for (tag, span) in event_tags(event) {
+                let (replacement, message) = match known_tag_name(tag) {
+                    Some("a") => {
+                        let Some(an) = legacy_alignment(tag) else {
+                            continue;
+                        };
+                        (
+                            format!("\\an{an}"),
+                            format!("\\{tag} is legacy SSA alignment; write \\an{an}"),
+                        )
+                    }
+                    Some("K") if self.options.strict_compat => {
+                        let duration = tag[1..].trim();
+                        (
+                            format!("\\kf{duration}"),
+                            format!("\\K is an older name for \\kf; write \\kf{duration}"),
+                        )
+                    }
+                    _ => continue,
+                };
+                diagnostics.push(Diagnostic {
+                    range: Range {
+                        start: Position::new(line, event.text_start + span.start as u32),
+                        end: Position::new(line, event.text_start + span.end as u32),
+                    },
+                    severity: Some(DiagnosticSeverity::INFORMATION),
+                    code: Some(NumberOrString::String("deprecated_tag".to_string())),
+                    code_description: None,
+                    source: Some("ass-lsp".to_string()),
+                    message,
+                    related_information: None,
+                    tags: Some(vec![DiagnosticTag::DEPRECATED]),
+                    data: Some(serde_json::json!({ "replacement": replacement })),
+                });
+            }
+        }
+        diagnostics
+    }
+
+    /// Tags that aren't override tags, usually typos. The data names the
+    /// closest known tag so a quick fix can swap it in.
+    fn validate_unknown_tags(&self, event: &Event) -> Vec<Diagnostic> {
+        let line = event.range.start.line;
+        event_tags(event)
+            .into_iter()
+            .filter(|(tag, _)| known_tag_name(tag).is_none())
+            .filter_map(|(tag, span)| {
+                let name = written_tag_name(tag);
+                if name.is_empty() {
+                    return None;
+                }
+                let suggestion = closest_override_tag(name);
+                // Tag names are case-sensitive, so `\AN8` is not `\an8`
+                let wrong_case = suggestion.is_some_and(|tag| tag.eq_ignore_ascii_case(name));
+                let (severity, code, message) = match suggestion {
+                    Some(tag) if wrong_case => (
+                        DiagnosticSeverity::ERROR,
+                        "wrong_case_tag",
+                        format!("Override tags are case-sensitive: \\{name} is not a tag, write \\{tag}"),
+                    ),
+                    Some(tag) => (
+                        DiagnosticSeverity::WARNING,
+                        "unknown_tag",
+                        format!("Unknown override tag \\{name}, did you mean \\{tag}?"),
+                    ),
+                    None => (
+                        DiagnosticSeverity::WARNING,
+                        "unknown_tag",
+                        format!("Unknown override tag \\{name}"),
+                    ),
+                };
+                // Just the backslash and the name, which is what a fix replaces
+                let end = span.start + 1 + name.len();
+                Some(Diagnostic {
+                    range: Range {
+                        start: Position::new(line, event.text_start + span.start as u32),
+                        end: Position::new(line, event.text_start + end as u32),
+                    },
+                    severity: Some(severity),
+                    code: Some(NumberOrString::String(code.to_string())),
+                    code_description: None,
+                    source: Some("ass-lsp".to_string()),
+                    message,
+                    related_information: None,
+                    tags: None,
+                    data: Some(serde_json::json!({
+                        "tag": name,
+                        "suggestion": suggestion,
+                    })),
+                })
+            })
+            .collect()
+    }
+
+    /// `\K` in a line that also uses `\k`. Both are valid but mean different
+    /// things, so mixing them is more likely a typo than a choice; the note
+    /// explains the difference rather than offering a fix.
+    fn validate_karaoke_case(&self, event: &Event) -> Vec<Diagnostic> {
+        let line = event.range.start.line;
+        let tags: Vec<_> = event_tags(event)
+            .into_iter()
+            .filter_map(|(tag, span)| Some((known_tag_name(tag)?, span)))
+            .collect();
+        if !tags.iter().any(|(name, _)| *name == "k") {
+            return Vec::new();
+        }
+        tags.into_iter()
+            .filter(|(name, _)| *name == "K")
+            .map(|(_, span)| Diagnostic {
+                range: Range {
+                    start: Position::new(line, event.text_start + span.start as u32),
+                    end: Position::new(line, event.text_start + span.start as u32 + 2),
+                },
+                severity: Some(DiagnosticSeverity::INFORMATION),
+                code: Some(NumberOrString::String("karaoke_tag_case".to_string())),
+                code_description: None,
+                source: Some("ass-lsp".to_string()),
+                message: "\\K sweeps the fill across the syllable like \\kf, while \\k used elsewhere in this line highlights a syllable all at once".to_string(),
+                related_information: None,
+                tags: None,
+                data: None,
+            })
+            .collect()
+    }
+
+    /// Tags whose arguments don't fit the tag, including tags animated by `\t`.
+    fn validate_tag_arguments(&self, event: &Event) -> Vec<Diagnostic> {
+        let line = event.range.start.line;
+        event_tags(event)
+            .into_iter()
+            .filter_map(|(tag, span)| {
+                let name = known_tag_name(tag)?;
+                let arguments = tag_arguments(tag, name);
+                let expected = tag_argument_problem(name, &arguments)?;
+                let found = match arguments.display() {
+                    found if found.is_empty() => "nothing".to_string(),
+                    found => format!("`{found}`"),
+                };
+                Some(Diagnostic {
+                    range: Range {
+                        start: Position::new(line, event.text_start + span.start as u32),
+                        end: Position::new(line, event.text_start + span.end as u32),
+                    },
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    code: Some(NumberOrString::String("invalid_tag_arguments".to_string())),
+                    code_description: None,
+                    source: Some("ass-lsp".to_string()),
+                    message: format!("\\{name} takes {expected}, found {found}"),
+                    related_information: None,
+                    tags: None,
+                    data: None,
+                })
+            })
+            .collect()
+    }
+
+    /// A non-empty Effect field renderers would ignore: an unknown keyword,
+    /// or a known effect whose parameters don't fit. Renderers give no sign
+    /// of either, so `Banner:30` just shows static text.
+    fn validate_effect(&self, event: &Event) -> Option<Diagnostic> {
+        let effect = event.effect.as_str();
+        if effect.is_empty()
+            || event.event_type != "Dialogue"
+            || TEMPLATER_EFFECTS
+                .iter()
+                .any(|marker| marker.eq_ignore_ascii_case(effect))
+        {
+            return None;
+        }
+
+        let mut parts = effect.split(';');
+        let keyword = parts.next()?.trim();
+        let (code, message) = match event_effect(keyword) {
+            None => {
+                // `Banner:30` or `Scroll up,0,0,10`: a known keyword, then the
+                // wrong separator
+                let misseparated = EVENT_EFFECTS.iter().find(|known| {
+                    keyword
+                        .get(..known.keyword.len())
+                        .is_some_and(|start| start.eq_ignore_ascii_case(known.keyword))
+                });
+                let message = match misseparated {
+                    Some(known) => format!(
+                        "Unrecognized effect `{effect}`, renderers ignore it; parameters are separated by `;`, as in `{}`",
+                        known.syntax
+                    ),
+                    None => format!(
+                        "Unrecognized effect `{effect}`, renderers ignore it; known effects are {}",
+                        EVENT_EFFECTS
+                            .iter()
+                            .map(|known| format!("`{}`", known.syntax))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                };
+                ("unknown_effect", message)
+            }
+            Some(known) => {
+                let mut params: Vec<&str> = parts.map(str::trim).collect();
+                // A trailing `;` adds nothing
+                if params.last() == Some(&"") {
+                    params.pop();
+                }
+                let problem = if params.len() < known.required {
+                    format!(
+                        "{} needs {} parameter{}, found {}",
+                        known.keyword,
+                        known.required,
+                        if known.required == 1 { "" } else { "s" },
+                        params.len()
+                    )
+                } else if params.len() > known.max {
+                    format!(
+                        "{} takes at most {} parameters, found {}, and the rest are ignored",
+                        known.keyword,
+                        known.max,
+                        params.len()
+                    )
+                } else if let Some(param) =
+                    params.iter().find(|param| param.parse::<i32>().is_err())
+                {
+                    format!("{} parameter `{param}` is not an integer", known.keyword)
+                } else {
+                    return None;
+                };
+                (
+                    "invalid_effect",
+                    format!("{problem}; the syntax is `{}`", known.syntax),
+                )
+            }
+        };
+
+        let line = event.range.start.line;
+        Some(Diagnostic {
+            range: Range {
+                start: Position::new(line, event.effect_span.start as u32),
+                end: Position::new(line, event.effect_span.end as u32),
+            },
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: Some(NumberOrString::String(code.to_string())),
+            code_description: None,
+            source: Some("ass-lsp".to_string()),
+            message,
+            related_information: None,
+            tags: None,
+            data: None,
+        })
+    }
+
+    /// Colours given to `\c` and `\1c`-`\4c`. A tag without a value resets
+    /// to the style's colour and is left alone.
+    fn validate_tag_colors(&self, event: &Event) -> Vec<Diagnostic> {
+        let line = event.range.start.line;
+        event_tags(event)
+            .into_iter()
+            .filter_map(|(tag, span)| {
+                let name = known_tag_name(tag).filter(|name| COLOR_TAGS.contains(name))?;
+                let rest = &tag[name.len()..];
+                let value = rest.trim();
+                if value.is_empty() || value.starts_with('(') {
+                    return None;
+                }
+                // Past the backslash and the name
+                let start = span.start + 1 + name.len() + rest.len() - rest.trim_start().len();
+                let range = Range {
+                    start: Position::new(line, event.text_start + start as u32),
+                    end: Position::new(line, event.text_start + (start + value.len()) as u32),
+                };
+                color_diagnostic(
+                    range,
+                    &format!("\\{name}"),
+                    value,
+                    ColorContext::OverrideTag,
+                )
+            })
+            .collect()
+    }
+
+    fn validate_transforms(&self, event: &Event) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let line = event.range.start.line;
+        let range_of = |span: Span<usize>| Range {
+            start: Position::new(line, event.text_start + span.start as u32),
+            end: Position::new(line, event.text_start + span.end as u32),
+        };
+        let diagnostic = |span, severity, code: &str, message| Diagnostic {
+            range: range_of(span),
+            severity: Some(severity),
+            code: Some(NumberOrString::String(code.to_string())),
+            code_description: None,
+            source: Some("ass-lsp".to_string()),
+            message,
+            related_information: None,
+            tags: None,
+            data: None,
+        };
+        let duration = event.duration().map(|duration| duration.as_millis() as i64);
+
+        for token in tokenize(&event.text) {
+            let TextToken::Tag { tag, span } = token else {
+                continue;
+            };
+            let transform = match parse_transform(tag, span.clone()) {
+                None => continue,
+                Some(Ok(transform)) => transform,
+                Some(Err(err)) => {
+                    let message = match err {
+                        TransformError::MissingParen(_) => "Transform is missing its closing ')'",
+                        TransformError::InvalidNumber(_) => "Transform argument is not a number",
+                        TransformError::TooManyArguments(_) => {
+                            "Transform takes at most t1, t2 and accel before its tags"
+                        }
+                        TransformError::NoTags(_) => "Transform has no tags to animate",
+                    };
+                    diagnostics.push(diagnostic(
+                        err.span(),
+                        DiagnosticSeverity::ERROR,
+                        "malformed_transform",
+                        message.to_string(),
+                    ));
+                    continue;
+                }
+            };
+
+            if let (Some(t1), Some(t2)) = (transform.t1, transform.t2) {
+                let message = if t1 > t2 {
+                    Some(format!("Transform starts at {t1}ms but ends at {t2}ms"))
+                } else {
+                    duration
+                        .filter(|duration| t1 < 0 || t2 > *duration)
+                        .map(|duration| {
+                            format!(
+                                "Transform runs {t1}-{t2}ms, outside the event's {duration}ms duration"
+                            )
+                        })
+                };
+                if let Some(message) = message {
+                    diagnostics.push(diagnostic(
+                        span.clone(),
+                        DiagnosticSeverity::WARNING,
+                        "invalid_transform_timing",
+                        message,
+                    ));
+                }
+            }
+
+            for (inner, inner_span) in transform.tags {
+                match override_tag_name(inner) {
+                    Some(name) if !ANIMATABLE_TAGS.contains(&name) => diagnostics.push(diagnostic(
+                        inner_span,
+                        DiagnosticSeverity::WARNING,
+                        "non_animatable_tag",
+                        format!("\\{name} cannot be animated inside \\t"),
+                    )),
+                    _ => {}
+                }
+            }
+        }
+
+        diagnostics
+    }
+
+    /// Fades that don't fit the event: `\fad` times adding up to more than
+    /// its duration, and `\fade` times out of order, ending after it, or
+    /// alphas outside 0-255. Each fade tag is checked on its own, and tags
+    /// with malformed arguments are left to the argument check.
+    fn validate_fades(&self, event: &Event) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let line = event.range.start.line;
+        let duration = event.duration().map(|duration| duration.as_millis() as f64);
+
+        for token in tokenize(&event.text) {
+            let TextToken::Tag { tag, span } = token else {
+                continue;
+            };
+            let Some(name @ ("fad" | "fade")) = known_tag_name(tag) else {
+                continue;
+            };
+            let arguments = tag_arguments(tag, name);
+            if tag_argument_problem(name, &arguments).is_some() {
+                continue;
+            }
+            let args = &arguments.args;
+            let values: Vec<f64> = args.iter().filter_map(|arg| arg.parse().ok()).collect();
+            // Argument spans within the event text; the tag's span starts at
+            // its backslash
+            let arg_span = |index: usize| {
+                let arg = argument_span(tag, args[index]);
+                span.start + 1 + arg.start..span.start + 1 + arg.end
+            };
+            let mut report = |arg: Span<usize>, code: &str, message: String| {
+                diagnostics.push(Diagnostic {
+                    range: Range {
+                        start: Position::new(line, event.text_start + arg.start as u32),
+                        end: Position::new(line, event.text_start + arg.end as u32),
+                    },
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(NumberOrString::String(code.to_string())),
+                    code_description: None,
+                    source: Some("ass-lsp".to_string()),
+                    message,
+                    related_information: None,
+                    tags: None,
+                    data: None,
+                })
+            };
+
+            if name == "fad" {
+                let (fade_in, fade_out) = (values[0], values[1]);
+                let Some(duration) = duration.filter(|duration| fade_in + fade_out > *duration)
+                else {
+                    continue;
+                };
+                // Blame the fade that alone outlasts the event, if one does
+                let arg = if fade_in > duration {
+                    arg_span(0)
+                } else if fade_out > duration {
+                    arg_span(1)
+                } else {
+                    arg_span(0).start..arg_span(1).end
+                };
+                report(
+                    arg,
+                    "invalid_fade_timing",
+                    format!(
+                        "Fade in {fade_in}ms and fade out {fade_out}ms add up to more than the event's {duration}ms duration"
+                    ),
+                );
+                continue;
+            }
+
+            for (index, alpha) in values[..3].iter().enumerate() {
+                if !(0.0..=255.0).contains(alpha) {
+                    report(
+                        arg_span(index),
+                        "invalid_fade_alpha",
+                        format!("Fade alpha must be 0 to 255, found {alpha}"),
+                    );
+                }
+            }
+            let times = &values[3..];
+            let context = duration
+                .map(|duration| format!(" (the event lasts {duration}ms)"))
+                .unwrap_or_default();
+            if let Some(index) = (1..times.len()).find(|&index| times[index] < times[index - 1]) {
+                report(
+                    arg_span(3 + index),
+                    "invalid_fade_timing",
+                    format!(
+                        "Fade time t{} ({}ms) is before t{} ({}ms){context}",
+                        index + 1,
+                        times[index],
+                        index,
+                        times[index - 1]
+                    ),
+                );
+            } else if let Some(duration) = duration.filter(|duration| times[3] > *duration) {
+                report(
+                    arg_span(6),
+                    "invalid_fade_timing",
+                    format!(
+                        "Fade ends at {}ms, after the event's {duration}ms duration",
+                        times[3]
+                    ),
+                );
+            }
+        }
+
+        diagnostics
+    }
+
+    /// Clips that parse but likely don't mean what was written: rectangles
+    /// whose corners look swapped, and drawings that aren't a well-formed
+    /// shape.
+    fn validate_clips(&self, event: &Event) -> Vec<Diagnostic> {
+        event_clips(event)
+            .into_iter()
+            .filter_map(|clip| {
+                let (code, message) = match clip.shape {
+                    ClipShape::Rectangle([x1, y1, x2, y2]) => {
+                        let swapped = match (x1 > x2, y1 > y2) {
+                            (true, true) => "x1 and x2, and y1 and y2",
+                            (true, false) => "x1 and x2",
+                            (false, true) => "y1 and y2",
+                            (false, false) => return None,
+                        };
+                        (
+                            "inverted_clip",
+                            format!(
+                                "\\{} rectangle ({x1},{y1})-({x2},{y2}) is inverted; {swapped} may be swapped",
+                                clip.name
+                            ),
+                        )
+                    }
+                    ClipShape::Drawing(commands) => (
+                        "invalid_clip_drawing",
+                        format!("\\{} drawing {}", clip.name, drawing_problem(commands)?),
+                    ),
+                };
+                Some(Diagnostic {
+                    range: Range {
+                        start: Position::new(event.range.start.line, clip.columns.start as u32),
+                        end: Position::new(event.range.start.line, clip.columns.end as u32),
+                    },
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(NumberOrString::String(code.to_string())),
+                    code_description: None,
+                    source: Some("ass-lsp".to_string()),
+                    message,
+                    related_information: None,
+                    tags: None,
+                    data: None,
+                })
+            })
+            .collect()
+    }
+
+    fn validate_fonts(&self, document: &AssDocument, catalog: &FontCatalog) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let referenced = referenced_styles(document);
+
+        for style in &document.styles {
+            let used = referenced.contains(style.name.as_str());
+            if !used || fonts::is_embedded(&style.fontname, &document.attachments) {
+                continue;
+            }
+
+            let (severity, code, message) = match catalog.contains(&style.fontname) {
+                Some(true) => continue,
+                Some(false) => (
+                    DiagnosticSeverity::WARNING,
+                    "missing_font",
+                    format!(
+                        "Font '{}' is not installed or embedded; rendering will fall back to a default font",
+                        style.fontname
+                    ),
+                ),
+                None => (
+                    DiagnosticSeverity::HINT,
+                    "unverified_font",
+                    format!(
+                        "Cannot verify that font '{}' is installed on this system",
+                        style.fontname
+                    ),
+                ),
+            };
+            diagnostics.push(Diagnostic {
+                range: style.range,
+                severity: Some(severity),
+                code: Some(NumberOrString::String(code.to_string())),
+                code_description: None,
+                source: Some("ass-lsp".to_string()),
+                message,
+                related_information: None,
+                tags: None,
+                data: None,
+            });
+        }
+
+        diagnostics
+    }
+
+    /// The span and canonical spelling of a malformed time at `span` on
+    /// `line`, when its value is certain. Well-formed times give `None`.
+    pub(crate) fn time_fix(&self, line: &str, span: Span<usize>) -> Option<(Span<usize>, String)> {
+        if self.time_regex.is_match(line.get(span.clone())?) {
+            return None;
+        }
+        time_repair(line, span).filter(|(_, canonical)| self.time_regex.is_match(canonical))
+    }
+
+    /// Quick fixes for diagnostics that carry their replacement text in `data`.
+    /// The diagnostics come back from the client, so their ranges are already
+    /// client positions; columns kept in `data` are bytes and go through `index`.
+    pub(crate) fn quick_fixes(
+        &self,
+        uri: &Url,
+        index: &LineIndex,
+        diagnostics: &[Diagnostic],
+    ) -> Vec<CodeActionOrCommand> {
+        let action = |title: String, diagnostic: &Diagnostic, edits, preferred| {
+            CodeActionOrCommand::CodeAction(CodeAction {
+                title,
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![diagnostic.clone()]),
+                edit: Some(WorkspaceEdit {
+                    changes: Some(HashMap::from([(uri.clone(), edits)])),
+                    ..Default::default()
+                }),
+                is_preferred: Some(preferred),
+                ..Default::default()
+            })
+        };
+
+        let mut actions = Vec::new();
+        for diagnostic in diagnostics {
+            let Some(NumberOrString::String(code)) = &diagnostic.code else {
+                continue;
+            };
+            let data = diagnostic.data.as_ref().unwrap_or(&serde_json::Value::Null);
+            match code.as_str() {
+                "malformed_section_header" => {
+                    let Some(clean) = data.as_str() else {
+                        continue;
+                    };
+                    let edit = TextEdit {
+                        range: diagnostic.range,
+                        new_text: clean.to_string(),
+                    };
+                    actions.push(action(
+                        format!("Replace with {clean}"),
+                        diagnostic,
+                        vec![edit],
+                        true,
+                    ));
+                }
+                "wrong_case_tag" => {
+                    let Some(tag) = data["suggestion"].as_str() else {
+                        continue;
+                    };
+                    let edit = TextEdit {
+                        range: diagnostic.range,
+                        new_text: format!("\\{tag}"),
+                    };
+                    actions.push(action(
+                        format!("Replace with \\{tag}"),
+                        diagnostic,
+                        vec![edit],
+                        true,
+                    ));
+                }
+                "invalid_time_format" => {
+                    let Ok(range) = serde_json::from_value::<Range>(data["range"].clone()) else {
+                        continue;
+                    };
+                    let text = index.line_text(range.start.line as usize);
+                    let span = range.start.character as usize..range.end.character as usize;
+                    let Some((span, canonical)) = self.time_fix(text, span) else {
+                        continue;
+                    };
+                    let line = range.start.line as usize;
+                    let edit = TextEdit {
+                        range: Range {
+                            start: index.position(line, span.start),
+                            end: index.position(line, span.end),
+                        },
+                        new_text: canonical.clone(),
+                    };
+                    actions.push(action(
+                        format!("Replace with {canonical}"),
+                        diagnostic,
+                        vec![edit],
+                        true,
+                    ));
+                }
+                "invalid_time_order" => {
+                    let (Some(start), Some(end), Ok(start_range), Ok(end_range)) = (
+                        data["start"].as_str(),
+                        data["end"].as_str(),
+                        serde_json::from_value::<Range>(data["startRange"].clone()),
+                        serde_json::from_value::<Range>(data["endRange"].clone()),
+                    ) else {
+                        continue;
+                    };
+                    // Equal times aren't reversed; swapping them changes nothing
+                    if start == end {
+                        continue;
+                    }
+                    let edits = vec![
+                        TextEdit {
+                            range: index.range(start_range),
+                            new_text: end.to_string(),
+                        },
+                        TextEdit {
+                            range: index.range(end_range),
+                            new_text: start.to_string(),
+                        },
+                    ];
+                    actions.push(action(
+                        "Swap start and end times".to_string(),
+                        diagnostic,
+                        edits,
+                        true,
+                    ));
+                }
+                "legacy_color" => {
+                    let Some(canonical) = data["canonical"].as_str() else {
+                        continue;
+                    };
+                    let edit = TextEdit {
+                        range: diagnostic.range,
+                        new_text: canonical.to_string(),
+                    };
+                    actions.push(action(
+                        format!("Convert to {canonical}"),
+                        diagnostic,
+                        vec![edit],
+                        true,
+                    ));
+                }
+                "pasted_timing" => {
+                    let (Some(offset), Ok(events)) = (
+                        data["offset"].as_i64(),
+                        serde_json::from_value::<Vec<ShiftedTimes>>(data["events"].clone()),
+                    ) else {
+                        continue;
+                    };
+                    let edits = events
+                        .iter()
+                        .flat_map(|event| {
+                            let line = event.line as usize;
+                            [
+                                (&event.start_span, &event.start),
+                                (&event.end_span, &event.end),
+                            ]
+                            .map(|(span, time)| TextEdit {
+                                range: Range {
+                                    start: index.position(line, span.start),
+                                    end: index.position(line, span.end),
+                                },
+                                new_text: time.clone(),
+                            })
+                        })
+                        .collect();
+                    let sign = if offset < 0 { "-" } else { "+" };
+                    actions.push(action(
+                        format!(
+                            "Shift pasted lines by {sign}{}",
+                            AssTime(offset.unsigned_abs() as u32)
+                        ),
+                        diagnostic,
+                        edits,
+                        true,
+                    ));
+                }
+                "deprecated_tag" => {
+                    let Some(replacement) = data["replacement"].as_str() else {
+                        continue;
+                    };
+                    let edit = TextEdit {
+                        range: diagnostic.range,
+                        new_text: replacement.to_string(),
+                    };
+                    actions.push(action(
+                        format!("Replace with {replacement}"),
+                        diagnostic,
+                        vec![edit],
+                        true,
+                    ));
+                }
+                "directional_control" => {
+                    let Some(name) = data["name"].as_str() else {
+                        continue;
+                    };
+                    let edit = TextEdit {
+                        range: diagnostic.range,
+                        new_text: String::new(),
+                    };
+                    actions.push(action(
+                        format!("Remove {name}"),
+                        diagnostic,
+                        vec![edit],
+                        true,
+                    ));
+                }
+                "bidi_punctuation" => {
+                    let Ok(columns) =
+                        serde_json::from_value::<Vec<usize>>(data["insertAt"].clone())
+                    else {
+                        continue;
+                    };
+                    let line = diagnostic.range.start.line as usize;
+                    let edits = columns
+                        .into_iter()
+                        .map(|column| {
+                            let at = index.position(line, column);
+                            TextEdit {
+                                range: Range { start: at, end: at },
+                                new_text: RLM.to_string(),
+                            }
+                        })
+                        .collect();
+                    actions.push(action(
+                        "Insert right-to-left marks".to_string(),
+                        diagnostic,
+                        edits,
+                        true,
+                    ));
+                }
+                "high_cps" => {
+                    let Ok(split) = serde_json::from_value::<EventSplit>(data["split"].clone())
+                    else {
+                        continue;
+                    };
+                    let line_number = diagnostic.range.start.line;
+                    let Some(line) = index.text().lines().nth(line_number as usize) else {
+                        continue;
+                    };
+                    let Some((first, second)) = split.lines(line) else {
+                        continue;
+                    };
+                    let edit = TextEdit {
+                        range: Range {
+                            start: Position::new(line_number, 0),
+                            end: index.position(line_number as usize, line.len()),
+                        },
+                        new_text: format!("{first}{}{second}", detect_line_ending(index.text())),
+                    };
+                    let title = if split.at_line_break {
+                        "Split event at \\N"
+                    } else {
+                        "Split event at sentence boundary"
+                    };
+                    actions.push(action(title.to_string(), diagnostic, vec![edit], false));
+                }
+                "missing_section" => {
+                    let Some(section) = data["section"].as_str() else {
+                        continue;
+                    };
+                    let Some((line, column, new_text)) =
+                        missing_section_insertion(index.text(), &index.lines(), section)
+                    else {
+                        continue;
+                    };
+                    let at = index.position(line, column);
+                    actions.push(action(
+                        format!("Insert [{section}] section"),
+                        diagnostic,
+                        vec![TextEdit {
+                            range: Range { start: at, end: at },
+                            new_text,
+                        }],
+                        true,
+                    ));
+                }
+                "undefined_style" => {
+                    let (Some(style), Ok(at), Some(new_text)) = (
+                        data["style"].as_str(),
+                        serde_json::from_value::<Position>(data["insertAt"].clone()),
+                        data["newText"].as_str(),
+                    ) else {
+                        continue;
+                    };
+                    let at = index.position(at.line as usize, at.character as usize);
+                    let edit = TextEdit {
+                        range: Range { start: at, end: at },
+                        new_text: new_text.replace('\n', detect_line_ending(index.text())),
+                    };
+                    actions.push(action(
+                        format!("Create style '{style}'"),
+                        diagnostic,
+                        vec![edit],
+                        true,
+                    ));
+                }
+                "unknown_format_field" => {
+                    let Some(field) = data["suggestion"].as_str() else {
+                        continue;
+                    };
+                    let edit = TextEdit {
+                        range: diagnostic.range,
+                        new_text: field.to_string(),
+                    };
+                    actions.push(action(
+                        format!("Replace with {field}"),
+                        diagnostic,
+                        vec![edit],
+                        true,
+                    ));
+                }
+                "missing_format_line" => {
+                    let Some(section) = data["section"].as_str() else {
+                        continue;
+                    };
+                    let format = if section == "Events" {
+                        default_event_format()
+                    } else {
+                        default_style_format(section)
+                    };
+                    // After the header, which may be the last line
+                    let line = diagnostic.range.start.line as usize;
+                    let at = index.position(line, index.line_text(line).len());
+                    let edit = TextEdit {
+                        range: Range { start: at, end: at },
+                        new_text: format!(
+                            "{}Format: {}",
+                            detect_line_ending(index.text()),
+                            format.join(", ")
+                        ),
+                    };
+                    actions.push(action(
+                        "Insert the default Format line".to_string(),
+                        diagnostic,
+                        vec![edit],
+                        true,
+                    ));
+                }
+                "missing_scaled_border_and_shadow" => {
+                    let Ok(at) = serde_json::from_value::<Position>(data["insertAt"].clone())
+                    else {
+                        continue;
+                    };
+                    let at = index.position(at.line as usize, at.character as usize);
+                    let edit = TextEdit {
+                        range: Range { start: at, end: at },
+                        new_text: "\nScaledBorderAndShadow: yes".to_string(),
+                    };
+                    actions.push(action(
+                        "Add ScaledBorderAndShadow: yes".to_string(),
+                        diagnostic,
+                        vec![edit],
+                        true,
+                    ));
+                }
+                "missing_script_type" => {
+                    let Ok(at) = serde_json::from_value::<Position>(data["insertAt"].clone())
+                    else {
+                        continue;
+                    };
+                    let at = index.position(at.line as usize, at.character as usize);
+                    let edit = TextEdit {
+                        range: Range { start: at, end: at },
+                        new_text: "\nScriptType: v4.00+".to_string(),
+                    };
+                    actions.push(action(
+                        "Add ScriptType: v4.00+".to_string(),
+                        diagnostic,
+                        vec![edit],
+                        true,
+                    ));
+                }
+                "unused_style" => {
+                    let Some(style) = data["style"].as_str() else {
+                        continue;
+                    };
+                    let line = diagnostic.range.start.line;
+                    actions.push(action(
+                        format!("Remove unused style '{style}'"),
+                        diagnostic,
+                        vec![line_deletion(index, line)],
+                        true,
+                    ));
+                }
+                "unmatched_brace" => {
+                    let delete = TextEdit {
+                        range: diagnostic.range,
+                        new_text: String::new(),
+                    };
+                    actions.push(action(
+                        "Remove unmatched brace".to_string(),
+                        diagnostic,
+                        vec![delete],
+                        true,
+                    ));
+                }
+                "unclosed_override" => {
+                    let Ok(closes) = serde_json::from_value::<Vec<usize>>(data["closeAt"].clone())
+                    else {
+                        continue;
+                    };
+                    let line = diagnostic.range.start.line as usize;
+                    let edits = closes
+                        .into_iter()
+                        .map(|column| {
+                            let at = index.position(line, column);
+                            TextEdit {
+                                range: Range { start: at, end: at },
+                                new_text: "}".to_string(),
+                            }
+                        })
+                        .collect();
+                    actions.push(action(
+                        "Close override block".to_string(),
+                        diagnostic,
+                        edits,
+                        true,
+                    ));
+                }
+                "empty_override_block" => {
+                    let delete = TextEdit {
+                        range: diagnostic.range,
+                        new_text: String::new(),
+                    };
+                    actions.push(action(
+                        "Remove empty override block".to_string(),
+                        diagnostic,
+                        vec![delete],
+                        true,
+                    ));
+                }
+                "trailing_override_tags" => {
+                    let delete = TextEdit {
+                        range: diagnostic.range,
+                        new_text: String::new(),
+                    };
+                    let block = data["block"].as_str();
+                    let text_start = data["textStart"].as_u64();
+                    if let (true, Some(block), Some(text_start)) =
+                        (data["moveToStart"] == true, block, text_start)
+                    {
+                        let start = index
+                            .position(diagnostic.range.start.line as usize, text_start as usize);
+                        let insert = TextEdit {
+                            range: Range { start, end: start },
+                            new_text: block.to_string(),
+                        };
+                        actions.push(action(
+                            "Move tags to the start of the line".to_string(),
+                            diagnostic,
+                            vec![insert, delete.clone()],
+                            true,
+                        ));
+                    }
+                    actions.push(action(
+                        "Remove tags".to_string(),
+                        diagnostic,
+                        vec![delete],
+                        false,
+                    ));
+                }
+                _ => {}
+            }
+        }
+        actions
+    }
+
+    fn validate_equivalent_styles(&self, document: &AssDocument) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        // Every field except Name, in Format order
+        let appearance = |style: &Style| -> Vec<(String, String)> {
+            style
+                .fields
+                .iter()
+                .filter(|(name, _)| !name.eq_ignore_ascii_case("Name"))
+                .cloned()
+                .collect()
+        };
+        let mut groups: Vec<Vec<&Style>> = Vec::new();
+        for style in &document.styles {
+            let key = appearance(style);
+            match groups.iter_mut().find(|group| appearance(group[0]) == key) {
+                Some(group) => group.push(style),
+                None => groups.push(vec![style]),
+            }
+        }
+
+        for styles in groups.iter().filter(|styles| styles.len() >= 2) {
+            let first = &styles[0].name;
+            let duplicates: Vec<String> = styles[1..].iter().map(|s| s.name.clone()).collect();
+            for style in &styles[1..] {
+                let equivalents: Vec<&str> = styles
+                    .iter()
+                    .filter(|other| other.name != style.name)
+                    .map(|other| other.name.as_str())
+                    .collect();
+                diagnostics.push(Diagnostic {
+                    range: style.range,
+                    severity: Some(DiagnosticSeverity::INFORMATION),
+                    code: Some(NumberOrString::String("equivalent_style".to_string())),
+                    code_description: None,
+                    source: Some("ass-lsp".to_string()),
+                    message: format!(
+                        "Style '{}' is identical to {}",
+                        style.name,
+                        equivalents
+                            .iter()
+                            .map(|name| format!("'{name}'"))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                    related_information: None,
+                    tags: None,
+                    // The merge target and every style that would be merged into it
+                    data: Some(serde_json::json!({
+                        "mergeInto": first,
+                        "duplicates": duplicates,
+                    })),
+                });
+            }
+        }
+
+        diagnostics
+    }
+
+    /// Styles defined more than once, compared ignoring case. Each later
+    /// definition is reported, pointing back at the first.
+    fn validate_duplicate_styles(&self, document: &AssDocument, uri: &Url) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        for (index, style) in document.styles.iter().enumerate() {
+            let Some(first) = document.styles[..index]
+                .iter()
+                .find(|other| other.name.eq_ignore_ascii_case(&style.name))
+            else {
+                continue;
+            };
+            let first_line = first.range.start.line + 1;
+            // libass looks styles up by exact name from the last definition
+            // back; VSFilter renames later duplicates, keeping the first
+            let message = if first.name == style.name {
+                let last_line = document
+                    .styles
+                    .iter()
+                    .rfind(|other| other.name == style.name)
+                    .map_or(first_line, |last| last.range.start.line + 1);
+                format!(
+                    "Style '{}' is already defined on line {first_line}; libass uses the last definition (line {last_line}) and VSFilter the first",
+                    style.name
+                )
+            } else {
+                format!(
+                    "Style '{}' differs only in case from '{}' on line {first_line}; libass keeps them apart but case-insensitive renderers use just one",
+                    style.name, first.name
+                )
+            };
+            diagnostics.push(Diagnostic {
+                range: style.range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String("duplicate_style".to_string())),
+                code_description: None,
+                source: Some("ass-lsp".to_string()),
+                message,
+                related_information: Some(vec![DiagnosticRelatedInformation {
+                    location: Location::new(uri.clone(), first.range),
+                    message: format!("'{}' first defined here", first.name),
+                }]),
+                tags: None,
+                data: None,
+            });
+        }
+        diagnostics
+    }
+
+    /// Dialogue lines repeating an earlier one's layer, times, style and
+    /// text, overrides included, so the line is drawn twice over itself.
+    /// Copies on other layers or with different overrides are deliberate
+    /// layering and pass.
+    fn validate_duplicate_events(&self, document: &AssDocument, uri: &Url) -> Vec<Diagnostic> {
+        let mut first_seen: HashMap<(i32, AssTime, AssTime, &str, &str), &Event> = HashMap::new();
+        let mut diagnostics = Vec::new();
+        for event in document
+            .events
+            .iter()
+            .filter(|event| event.event_type == "Dialogue")
+        {
+            let (Some(start), Some(end)) = (event.start, event.end) else {
+                continue;
+            };
+            let key = (
+                event.layer,
+                start,
+                end,
+                event.style.as_str(),
+                event.text.as_str(),
+            );
+            let Some(first) = first_seen.get(&key) else {
+                first_seen.insert(key, event);
+                continue;
+            };
+            diagnostics.push(Diagnostic {
+                range: event.range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: Some(NumberOrString::String("duplicate_event".to_string())),
+                code_description: None,
+                source: Some("ass-lsp".to_string()),
+                message: format!(
+                    "Duplicate of the event on line {}, drawn twice over itself",
+                    first.range.start.line + 1
+                ),
+                related_information: Some(vec![DiagnosticRelatedInformation {
+                    location: Location::new(uri.clone(), first.range),
+                    message: "First occurrence".to_string(),
+                }]),
+                tags: None,
+                data: None,
+            });
+        }
+        diagnostics
+    }
+
+    /// Styles no event uses, directly or through `\r`. Default is exempt as
+    /// renderers fall back to it.
+    fn validate_unused_styles(&self, document: &AssDocument) -> Vec<Diagnostic> {
+        unused_styles(document)
+            .into_iter()
+            .map(|style| Diagnostic {
+                range: style.range,
+                severity: Some(DiagnosticSeverity::HINT),
+                code: Some(NumberOrString::String("unused_style".to_string())),
+                code_description: None,
+                source: Some("ass-lsp".to_string()),
+                message: format!("Style '{}' is not used by any event", style.name),
+                related_information: None,
+                tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+                data: Some(serde_json::json!({ "style": style.name })),
+            })
+            .collect()
+    }
+
+    /// The first dialogue line that starts before the one listed above it.
+    /// Renderers don't mind, but QC expects scripts sorted by start time. The
+    /// data holds the Events section for an action that sorts it.
+    /// Timestamps past the end of the video the script was timed against.
+    /// Those already past the [`timestamp_ceiling`](Self::timestamp_ceiling)
+    /// are reported by the line checks instead.
+    pub(crate) fn validate_video_duration(
+        &self,
+        document: &AssDocument,
+        duration: AssTime,
+    ) -> Vec<Diagnostic> {
+        document
+            .events
+            .iter()
+            .filter_map(|event| {
+                let times = [event.start, event.end];
+                let past = times.into_iter().flatten().any(|time| time > duration);
+                let implausible = times
+                    .into_iter()
+                    .flatten()
+                    .any(|time| time.centiseconds() > self.options.timestamp_ceiling);
+                (past && !implausible).then(|| Diagnostic {
+                    range: event.range,
+                    severity: Some(DiagnosticSeverity::INFORMATION),
+                    code: Some(NumberOrString::String("implausible_timestamp".to_string())),
+                    code_description: None,
+                    source: Some("ass-lsp".to_string()),
+                    message: format!(
+                        "Timestamp is past the end of the video at {duration}; is this a typo?"
+                    ),
+                    related_information: None,
+                    tags: None,
+                    data: None,
+                })
+            })
+            .collect()
+    }
+
+    fn validate_event_order(&self, document: &AssDocument) -> Option<Diagnostic> {
+        let mut previous: Option<(&Event, AssTime)> = None;
+        for event in document
+            .events
+            .iter()
+            .filter(|event| event.event_type == "Dialogue")
+        {
+            let Some(start) = event.start else {
+                continue;
+            };
+            match previous {
+                Some((before, before_start)) if start < before_start => {
+                    let line = event.range.start.line;
+                    let events_section = document
+                        .sections
+                        .iter()
+                        .find(|section| section.name == "Events")
+                        .map(|section| section.range);
+                    return Some(Diagnostic {
+                        range: Range {
+                            start: Position::new(line, event.start_span.start as u32),
+                            end: Position::new(line, event.start_span.end as u32),
+                        },
+                        severity: Some(DiagnosticSeverity::INFORMATION),
+                        code: Some(NumberOrString::String("unsorted_events".to_string())),
+                        code_description: None,
+                        source: Some("ass-lsp".to_string()),
+                        message: format!(
+                            "Event starts at {start}, before the event above it on line {} at {before_start}; events are not sorted by start time",
+                            before.range.start.line + 1
+                        ),
+                        related_information: None,
+                        tags: None,
+                        data: Some(serde_json::json!({ "eventsSection": events_section })),
+                    });
+                }
+                _ => previous = Some((event, start)),
+            }
+        }
+        None
+    }
+
+    /// Dialogue an edit just wrote at `lines` whose times are far from both
+    /// the dialogue above and below it, as when lines are pasted from a script
+    /// timed against different video. The data holds every pasted event's
+    /// times shifted so the block starts where the dialogue above it ends.
+    pub(crate) fn validate_pasted_timing(
+        &self,
+        document: &AssDocument,
+        lines: Span<usize>,
+    ) -> Option<Diagnostic> {
+        let first = document
+            .events
+            .partition_point(|event| (event.range.start.line as usize) < lines.start);
+        let last = document
+            .events
+            .partition_point(|event| (event.range.start.line as usize) < lines.end);
+        let block = &document.events[first..last];
+        // Every written line must be dialogue, or this was not a paste of it
+        if block.is_empty()
+            || block.len() != lines.len()
+            || block.iter().any(|event| event.event_type != "Dialogue")
+        {
+            return None;
+        }
+        let is_dialogue = |event: &&Event| event.event_type == "Dialogue";
+        let before = document.events[..first].iter().rfind(is_dialogue)?;
+        let after = document.events[last..].iter().find(is_dialogue);
+
+        let block_start = i64::from(block[0].start?.centiseconds());
+        let block_end = i64::from(block.last()?.end?.centiseconds());
+        let gap = block_start - i64::from(before.end?.centiseconds());
+        let gap_after = after
+            .and_then(|after| after.start)
+            .map(|start| i64::from(start.centiseconds()) - block_end);
+        let threshold = (self.options.pasted_timing_gap_ms / 10) as i64;
+        if gap.abs() <= threshold || gap_after.is_some_and(|gap| gap.abs() <= threshold) {
+            return None;
+        }
+
+        let shift = |time: Option<AssTime>| {
+            let shifted = i64::from(time?.centiseconds()) - gap;
+            u32::try_from(shifted).ok().map(AssTime)
+        };
+        let mut events = Vec::new();
+        for event in block {
+            if event.start_span.is_empty() || event.end_span.is_empty() {
+                return None;
+            }
+            events.push(serde_json::json!({
+                "line": event.range.start.line,
+                "startSpan": event.start_span,
+                "endSpan": event.end_span,
+                "start": shift(event.start)?.to_string(),
+                "end": shift(event.end)?.to_string(),
+            }));
+        }
+
+        let direction = if gap > 0 { "after" } else { "before" };
+        let last_line = block.last()?.range.end;
+        Some(Diagnostic {
+            range: Range {
+                start: block[0].range.start,
+                end: last_line,
+            },
+            severity: Some(DiagnosticSeverity::INFORMATION),
+            code: Some(NumberOrString::String("pasted_timing".to_string())),
+            code_description: None,
+            source: Some("ass-lsp".to_string()),
+            message: format!(
+                "Pasted lines start {} {direction} the end of the line above them and are far from the line below too; they may be timed against different video",
+                AssTime(gap.unsigned_abs() as u32)
+            ),
+            related_information: None,
+            tags: None,
+            data: Some(serde_json::json!({
+                "offset": -gap,
+                "events": events,
+            })),
+        })
+    }
+
+    fn validate_style_references(&self, document: &AssDocument) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let style_names: Vec<&str> = document.styles.iter().map(|s| s.name.as_str()).collect();
+
+        for event in &document.events {
+            if !style_names.contains(&event.style.as_str()) && event.style != "Default" {
+                diagnostics.push(Diagnostic {
+                    range: event.range,
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(NumberOrString::String("undefined_style".to_string())),
+                    code_description: None,
+                    source: Some("ass-lsp".to_string()),
+                    message: format!("Reference to undefined style: {}", event.style),
+                    related_information: None,
+                    tags: None,
+                    data: Some(new_style_insertion(document, &event.style)),
+                });
+            }
+        }
+
+        diagnostics
+    }
+}
+
+/// Every line of the Script Info sections, with its line number.
+fn script_info_lines(document: &AssDocument) -> impl DoubleEndedIterator<Item = (u32, &String)> {
+    document
+        .sections
+        .iter()
+        .filter(|section| section.name == "Script Info")
+        .flat_map(|section| {
+            let first = section.range.start.line;
+            section
+                .content
+                .iter()
+                .enumerate()
+                .map(move |(offset, line)| (first + offset as u32, line))
+        })
+}
+
+/// How a Script Info value is wrong, as a severity and a description of what
+/// the key takes. Values that break layout are errors; values a renderer
+/// falls back from are warnings.
+fn script_info_value_problem(key: &str, value: &str) -> Option<(DiagnosticSeverity, &'static str)> {
+    let is_one_of = |allowed: &[&str]| {
+        allowed
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(value))
+    };
+    let is_digits = |part: &str| !part.is_empty() && part.bytes().all(|byte| byte.is_ascii_digit());
+    let (valid, severity, expected) = match key {
+        "PlayResX" | "PlayResY" | "LayoutResX" | "LayoutResY" => (
+            value.parse::<u32>().is_ok_and(|value| value > 0),
+            DiagnosticSeverity::ERROR,
+            "a positive integer",
+        ),
+        "WrapStyle" => (
+            is_one_of(&["0", "1", "2", "3"]),
+            DiagnosticSeverity::ERROR,
+            "0, 1, 2 or 3",
+        ),
+        "ScaledBorderAndShadow" => (
+            is_one_of(&["yes", "no"]),
+            DiagnosticSeverity::WARNING,
+            "yes or no",
+        ),
+        "ScriptType" => (
+            is_one_of(&["v4.00", "v4.00+", "v4.00++"]),
+            DiagnosticSeverity::WARNING,
+            "v4.00+ (ASS) or v4.00 (SSA)",
+        ),
+        "Timer" => (
+            match value.split_once('.') {
+                Some((whole, fraction)) => is_digits(whole) && is_digits(fraction),
+                None => is_digits(value),
+            },
+            DiagnosticSeverity::WARNING,
+            "a decimal number such as 100.0000",
+        ),
+        "Collisions" => (
+            is_one_of(&["Normal", "Reverse"]),
+            DiagnosticSeverity::WARNING,
+            "Normal or Reverse",
+        ),
+        _ => return None,
+    };
+    (!valid).then_some((severity, expected))
+}
+
+/// Styles no event uses, directly or through `\r`, other than Default.
+fn unused_styles(document: &AssDocument) -> Vec<&Style> {
+    let referenced = referenced_styles(document);
+    document
+        .styles
+        .iter()
+        .filter(|style| style.name != "Default" && !referenced.contains(style.name.as_str()))
+        .collect()
+}
+
+/// Deletes every style `unused_style` reports, or `None` if there are none.
+/// Only the Style lines go, so the section keeps its header and Format line.
+pub fn unused_styles_edit(
+    uri: &Url,
+    document: &AssDocument,
+    index: &LineIndex,
+) -> Option<WorkspaceEdit> {
+    let edits: Vec<TextEdit> = unused_styles(document)
+        .into_iter()
+        .map(|style| line_deletion(index, style.range.start.line))
+        .collect();
+    (!edits.is_empty()).then(|| WorkspaceEdit {
+        changes: Some(HashMap::from([(uri.clone(), edits)])),
+        ..Default::default()
+    })
+}
+
+/// Deletes `line` along with its line break.
+fn line_deletion(index: &LineIndex, line: u32) -> TextEdit {
+    TextEdit {
+        range: index.range(Range {
+            start: Position::new(line, 0),
+            end: Position::new(line + 1, 0),
+        }),
+        new_text: String::new(),
+    }
+}
+
+/// Whether the script declares itself SSA, where `\a` is the alignment tag
+/// rather than a legacy one.
+fn is_ssa_script(document: &AssDocument) -> bool {
+    document
+        .script_info
+        .get("ScriptType")
+        .is_some_and(|script_type| script_type.trim().eq_ignore_ascii_case("v4.00"))
+}
+
+/// The `\an` value a legacy `\a` tag stands for, `None` for other tags,
+/// including `\an` and `\alpha`, and for values with no position.
+fn legacy_alignment(tag: &str) -> Option<u8> {
+    if known_tag_name(tag) != Some("a") {
+        return None;
+    }
+    tag[1..].trim().parse().ok().and_then(numpad_alignment)
+}
+
+/// Rewrites every legacy `\a` tag of the document's Dialogue events to its
+/// `\an` equivalent, or `None` if there are none. SSA scripts are left
+/// alone, as for the `deprecated_tag` diagnostic.
+pub fn legacy_alignment_edit(
+    uri: &Url,
+    document: &AssDocument,
+    index: &LineIndex,
+) -> Option<WorkspaceEdit> {
+    let edits = legacy_alignment_edits(document, index);
+    (!edits.is_empty()).then(|| WorkspaceEdit {
+        changes: Some(HashMap::from([(uri.clone(), edits)])),
+        ..Default::default()
+    })
+}
+
+/// The edits of [`legacy_alignment_edit`].
+pub fn legacy_alignment_edits(document: &AssDocument, index: &LineIndex) -> Vec<TextEdit> {
+    if is_ssa_script(document) {
+        return Vec::new();
+    }
+    document
+        .events
+        .iter()
+        .filter(|event| event.event_type == "Dialogue")
+        .flat_map(|event| {
+            let line = event.range.start.line as usize;
+            let base = event.text_start as usize;
+            event_tags(event)
+                .into_iter()
+                .filter_map(move |(tag, span)| {
+                    Some(TextEdit {
+                        range: Range {
+                            start: index.position(line, base + span.start),
+                            end: index.position(line, base + span.end),
+                        },
+                        new_text: format!("\\an{}", legacy_alignment(tag)?),
+                    })
+                })
+        })
+        .collect()
+}
+
+/// The visible characters of a Dialogue event and how many it shows per
+/// second, or `None` for drawings and events without a positive duration.
+pub fn reading_speed(event: &Event) -> Option<(usize, f64)> {
+    if event.event_type != "Dialogue" {
+        return None;
+    }
+    let duration = event.duration()?.as_millis();
+    let drawing = event_tags(event).into_iter().any(|(tag, _)| {
+        known_tag_name(tag) == Some("p")
+            && tag[1..].trim().parse::<u32>().is_ok_and(|scale| scale > 0)
+    });
+    if duration == 0 || drawing {
+        return None;
+    }
+
+    let characters: usize = visible_rows(&event.text, 0)
+        .iter()
+        .map(|row| row.chars().count())
+        .sum();
+    Some((characters, characters as f64 * 1000.0 / duration as f64))
+}
+
+/// The `split` data of a `high_cps` diagnostic: an event's text divided in
+/// two, and the time the second half takes over.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EventSplit {
+    first: String,
+    second: String,
+    at_line_break: bool,
+    time: String,
+    start_span: Span<usize>,
+    end_span: Span<usize>,
+    text_start: usize,
+}
+
+impl EventSplit {
+    /// The two event lines replacing `line`: the first ending at the split
+    /// time, the second starting there.
+    fn lines(&self, line: &str) -> Option<(String, String)> {
+        let rewrite = |time_span: &Span<usize>, text: &str| {
+            let mut rewritten = line.get(..self.text_start)?.to_string();
+            if time_span.is_empty() {
+                return None;
+            }
+            rewritten.get(time_span.clone())?;
+            rewritten.replace_range(time_span.clone(), &self.time);
+            rewritten.push_str(text);
+            Some(rewritten)
+        };
+        Some((
+            rewrite(&self.end_span, &self.first)?,
+            rewrite(&self.start_span, &self.second)?,
+        ))
+    }
+}
+
+/// One event of a `pasted_timing` diagnostic's data: where its times are on
+/// its line and what they become.
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ShiftedTimes {
+    line: u32,
+    start_span: Span<usize>,
+    end_span: Span<usize>,
+    start: String,
+    end: String,
+}
+
+/// Names of the styles events render with: their Style field and every style
+/// a `\r` tag switches to.
+fn referenced_styles(document: &AssDocument) -> HashSet<&str> {
+    let mut referenced = HashSet::new();
+    for event in &document.events {
+        referenced.insert(event.style.as_str());
+        for (tag, _) in event_tags(event) {
+            if known_tag_name(tag) == Some("r") {
+                referenced.insert(tag[1..].trim());
+            }
+        }
+    }
+    referenced
+}
+
+/// The tags in an event's override blocks, with the tags a well-formed `\t`
+/// animates in place of the `\t` itself. Malformed transforms are left to
+/// the transform checks.
+fn event_tags(event: &Event) -> Vec<(&str, Span<usize>)> {
+    let mut tags = Vec::new();
+    for token in tokenize(&event.text) {
+        let TextToken::Tag { tag, span } = token else {
+            continue;
+        };
+        match parse_transform(tag, span.clone()) {
+            Some(Ok(transform)) => tags.extend(transform.tags),
+            Some(Err(_)) => {}
+            None => tags.push((tag, span)),
+        }
+    }
+    tags
+}
+
+/// A `\clip` or `\iclip` with well-formed arguments.
+struct Clip<'a> {
+    /// `clip` or `iclip`.
+    name: &'static str,
+    shape: ClipShape<'a>,
+    /// Line columns from the first argument to the last.
+    columns: Span<usize>,
+}
+
+enum ClipShape<'a> {
+    /// x1, y1, x2, y2.
+    Rectangle([f64; 4]),
+    /// The drawing commands, without the optional scale.
+    Drawing(&'a str),
+}
+
+/// The clips in an event, including those a `\t` animates. Clips with
+/// malformed arguments are left to the argument check.
+fn event_clips(event: &Event) -> Vec<Clip<'_>> {
+    let mut clips = Vec::new();
+    for (tag, span) in event_tags(event) {
+        let Some(name @ ("clip" | "iclip")) = known_tag_name(tag) else {
+            continue;
+        };
+        let arguments = tag_arguments(tag, name);
+        if tag_argument_problem(name, &arguments).is_some() {
+            continue;
+        }
+        let args = arguments.args.as_slice();
+        let (Some(first), Some(last)) = (args.first(), args.last()) else {
+            continue;
+        };
+        let shape = match args {
+            [x1, y1, x2, y2] => {
+                let value = |arg: &str| arg.parse::<f64>().unwrap_or_default();
+                ClipShape::Rectangle([value(x1), value(y1), value(x2), value(y2)])
+            }
+            [.., drawing] => ClipShape::Drawing(drawing),
+            [] => continue,
+        };
+        // The tag's span starts at its backslash
+        let start = event.text_start as usize + span.start + 1;
+        clips.push(Clip {
+            name,
+            shape,
+            columns: start + argument_span(tag, first).start..start + argument_span(tag, last).end,
+        });
+    }
+    clips
+}
+
+/// An error for a colour `value` that doesn't parse in `context`, or a note
+/// with the canonical spelling for one written in a legacy form. `range`
+/// covers the value and `name` is the field or tag it belongs to.
+fn color_diagnostic(
+    range: Range,
+    name: &str,
+    value: &str,
+    context: ColorContext,
+) -> Option<Diagnostic> {
+    let grammar = context.grammar();
+    let (severity, code, message, data) = match AssColor::parse_in(value, context) {
+        Err(err) => (
+            DiagnosticSeverity::ERROR,
+            "invalid_color",
+            format!(
+                "Invalid colour for {name}: `{value}` ({err}); {} are written {}",
+                grammar.name, grammar.canonical
+            ),
+            None,
+        ),
+        Ok((_, ColorSpelling::Canonical)) => return None,
+        Ok((color, ColorSpelling::Legacy)) => {
+            let canonical = color.canonical(context);
+            (
+                DiagnosticSeverity::INFORMATION,
+                "legacy_color",
+                format!(
+                    "`{value}` is a legacy spelling for {name}, write it as `{canonical}`. Renderers still read {}",
+                    grammar.legacy
+                ),
+                Some(serde_json::json!({ "canonical": canonical })),
+            )
+        }
+    };
+    Some(Diagnostic {
+        range,
+        severity: Some(severity),
+        code: Some(NumberOrString::String(code.to_string())),
+        code_description: None,
+        source: Some("ass-lsp".to_string()),
+        message,
+        related_information: None,
+        tags: None,
+        data,
+    })
+}
+
+/// The `\an` value for a legacy `\a` value: 1-3 are bottom, adding 4
+/// moves to the top and adding 8 to the middle.
+fn numpad_alignment(legacy: u8) -> Option<u8> {
+    match legacy {
+        1..=3 => Some(legacy),
+        5..=7 => Some(legacy + 2),
+        9..=11 => Some(legacy - 5),
+        _ => None,
+    }
+}
+
+/// How far, as a share of the PlayRes dimension, a position may sit past
+/// the frame's edge before it's reported. Signs sliding in from just off
+/// screen are common.
+const POSITION_SLACK: f64 = 0.1;
+
+/// Sections that belong before [Events], after which it is inserted.
+const SECTIONS_BEFORE_EVENTS: &[&str] = &[
+    "Script Info",
+    "Aegisub Project Garbage",
+    "V4+ Styles",
+    "V4 Styles",
+];
+
+/// Where to insert a missing [Script Info] or [Events] section, as a line,
+/// byte column and the text to insert. [Script Info] goes above the first
+/// section and its comment banner, with its usual keys; [Events] goes after
+/// the styles, or whatever else precedes it, with its Format line. The
+/// inserted section is set off by one blank line on each side that has a
+/// neighbour, as the formatter leaves it.
+fn missing_section_insertion(
+    text: &str,
+    lines: &[&str],
+    section: &str,
+) -> Option<(usize, usize, String)> {
+    let line_ending = detect_line_ending(text);
+    let block = match section {
+        "Script Info" => [
+            "[Script Info]",
+            "ScriptType: v4.00+",
+            "WrapStyle: 0",
+            "ScaledBorderAndShadow: yes",
+        ]
+        .join(line_ending),
+        "Events" => format!("[Events]{line_ending}Format: {}", EVENT_FORMAT.join(", ")),
+        _ => return None,
+    };
+    let headers: Vec<(usize, String)> = lines
+        .iter()
+        .enumerate()
+        .filter_map(|(line, text)| Some((line, parse_section_header(text.trim())?.name)))
+        .collect();
+    let is_blank = |line: usize| lines.get(line).is_none_or(|text| text.trim().is_empty());
+
+    // After the last section that precedes [Events]: at the end of its last
+    // line with content, keeping the banner of the section after it below
+    let anchor = (section == "Events")
+        .then(|| {
+            headers
+                .iter()
+                .rposition(|(_, name)| SECTIONS_BEFORE_EVENTS.contains(&name.as_str()))
+        })
+        .flatten();
+    if let Some(anchor) = anchor {
+        let mut end = headers
+            .get(anchor + 1)
+            .map_or(lines.len(), |(line, _)| *line);
+        if end < lines.len() {
+            while end > headers[anchor].0 + 1 && lines[end - 1].trim_start().starts_with(';') {
+                end -= 1;
+            }
+        }
+        while end > headers[anchor].0 + 1 && is_blank(end - 1) {
+            end -= 1;
+        }
+        let last = end - 1;
+        let mut new_text = format!("{line_ending}{line_ending}{block}");
+        if !is_blank(last + 1) {
+            new_text.push_str(line_ending);
+        }
+        return Some((last, lines[last].len(), new_text));
+    }
+
+    // Otherwise above the first section and its banner
+    if let Some(&(first, _)) = headers.first() {
+        let mut start = first;
+        while start > 0 && lines[start - 1].trim_start().starts_with(';') {
+            start -= 1;
+        }
+        return Some((start, 0, format!("{block}{line_ending}{line_ending}")));
+    }
+
+    // A file without sections gets the section at its end
+    match (0..lines.len()).rfind(|&line| !is_blank(line)) {
+        Some(last) => Some((
+            last,
+            lines[last].len(),
+            format!("{line_ending}{line_ending}{block}"),
+        )),
+        None => Some((0, 0, format!("{block}{line_ending}"))),
+    }
+}
+
+/// The span and canonical spelling of the malformed time at `span` on `line`,
+/// when [`AssTime::parse_lenient`] is sure of it. A time written with a
+/// comma, as in SRT, reaches into the next field: `0:00:01,50` reads as
+/// Start `0:00:01` and End `50`, so a fraction of one to three digits in the
+/// field after a time without one is taken back.
+fn time_repair(line: &str, span: Span<usize>) -> Option<(Span<usize>, String)> {
+    let value = line.get(span.clone())?;
+    let mut end = span.end;
+    if !value.contains(['.', ',']) {
+        let rest = line[end..].strip_prefix(',')?;
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let after = rest[digits..].trim_start();
+        if !(1..=3).contains(&digits) || !(after.is_empty() || after.starts_with(',')) {
+            return None;
+        }
+        end += 1 + digits;
+    }
+    let time = AssTime::parse_lenient(&line[span.start..end])?;
+    Some((span.start..end, time.to_string()))
+}
+
+/// Values of a new style's fields where no Default style supplies them: white
+/// Arial with a thin black outline and shadow, at the bottom centre.
+const STYLE_TEMPLATE: &[(&str, &str)] = &[
+    ("fontname", "Arial"),
+    ("fontsize", "20"),
+    ("primarycolour", "&H00FFFFFF"),
+    ("secondarycolour", "&H000000FF"),
+    ("outlinecolour", "&H00000000"),
+    ("tertiarycolour", "&H00000000"),
+    ("backcolour", "&H00000000"),
+    ("scalex", "100"),
+    ("scaley", "100"),
+    ("borderstyle", "1"),
+    ("outline", "2"),
+    ("shadow", "2"),
+    ("alignment", "2"),
+    ("marginl", "10"),
+    ("marginr", "10"),
+    ("marginv", "10"),
+    ("encoding", "1"),
+];
+
+/// The data of an `undefined_style` diagnostic: where to insert a `Style:`
+/// line named `name` and its text, with `\n` for line breaks. The line copies
+/// the Default style, falling back to [`STYLE_TEMPLATE`], and goes at the end
+/// of the styles section. A file without one gets a `[V4+ Styles]` section
+/// with its Format line above `[Events]`, or after the last section.
+fn new_style_insertion(document: &AssDocument, name: &str) -> serde_json::Value {
+    let styles = document
+        .sections
+        .iter()
+        .rev()
+        .find(|section| section.name == "V4+ Styles" || section.name == "V4 Styles");
+    let format = match styles {
+        Some(section) => {
+            let lines: Vec<&str> = section.content.iter().map(String::as_str).collect();
+            style_format_at(&lines, lines.len().saturating_sub(1))
+        }
+        None => default_style_format("V4+ Styles"),
+    };
+    let default = document.style("Default");
+    let values: Vec<&str> = format
+        .iter()
+        .map(|field| {
+            if field.eq_ignore_ascii_case("Name") {
+                return name;
+            }
+            let copied = default.and_then(|style| {
+                style
+                    .fields
+                    .iter()
+                    .find(|(other, _)| other.eq_ignore_ascii_case(field))
+            });
+            match copied {
+                Some((_, value)) => value.as_str(),
+                None => STYLE_TEMPLATE
+                    .iter()
+                    .find(|(other, _)| other.eq_ignore_ascii_case(field))
+                    .map_or("0", |(_, value)| value),
+            }
+        })
+        .collect();
+    let line = format!("Style: {}", values.join(","));
+
+    let section_block = || {
+        format!(
+            "[V4+ Styles]\nFormat: {}\n{line}",
+            V4_PLUS_STYLE_FORMAT.join(", ")
+        )
+    };
+    let events = document
+        .sections
+        .iter()
+        .position(|section| section.name == "Events");
+    let (insert_at, new_text) = match (styles, events) {
+        (Some(section), _) => (section.range.end, format!("\n{line}")),
+        // Above the banner of [Events]: after the last line of the section
+        // before that isn't a comment
+        (None, Some(events)) if events > 0 => {
+            let previous = &document.sections[events - 1];
+            let content = &previous.content;
+            let last = (1..content.len())
+                .rev()
+                .find(|&line| {
+                    let text = content[line].trim();
+                    !text.is_empty() && !text.starts_with(';')
+                })
+                .unwrap_or(0);
+            let at = Position::new(
+                previous.range.start.line + last as u32,
+                content[last].len() as u32,
+            );
+            (at, format!("\n\n{}", section_block()))
+        }
+        (None, Some(events)) => (
+            document.sections[events].range.start,
+            format!("{}\n\n", section_block()),
+        ),
+        (None, None) => match document.sections.last() {
+            Some(last) => (last.range.end, format!("\n\n{}", section_block())),
+            None => (Position::new(0, 0), format!("{}\n", section_block())),
+        },
+    };
+    serde_json::json!({
+        "style": name,
+        "insertAt": insert_at,
+        "newText": new_text,
+    })
+}
+
+/// What a tag takes when its arguments don't fit, or `None` if they do or the
+/// tag has no rule. Tags that reset to the style's value accept no argument.
+fn tag_argument_problem(name: &str, arguments: &TagArguments) -> Option<&'static str> {
+    let args = arguments.args.as_slice();
+    let bare = !arguments.parenthesized;
+    let is_number = |arg: &&str| arg.parse::<f64>().is_ok_and(f64::is_finite);
+    let numbers = |counts: &[usize]| {
+        arguments.parenthesized
+            && arguments.closed
+            && counts.contains(&args.len())
+            && args.iter().all(is_number)
+    };
+    let is_drawing = |arg: &str| {
+        arg.split_whitespace().next().is_some()
+            && arg.split_whitespace().all(|token| {
+                matches!(token, "m" | "n" | "l" | "b" | "s" | "p" | "c") || is_number(&token)
+            })
+    };
+
+    let (valid, expected) = match name {
+        "pos" | "org" | "fad" => (numbers(&[2]), "2 numbers in parentheses"),
+        "move" => (numbers(&[4, 6]), "4 or 6 numbers in parentheses"),
+        "fade" => (numbers(&[7]), "7 numbers in parentheses"),
+        "clip" | "iclip" => {
+            let drawing = arguments.parenthesized
+                && arguments.closed
+                && match args {
+                    [drawing] => is_drawing(drawing),
+                    [scale, drawing] => scale.parse::<u32>().is_ok() && is_drawing(drawing),
+                    _ => false,
+                };
+            (
+                numbers(&[4]) || drawing,
+                "4 numbers or a drawing in parentheses",
+            )
+        }
+        "an" => (
+            bare && args[0].parse::<u8>().is_ok_and(|an| (1..=9).contains(&an)),
+            "an integer from 1 to 9",
+        ),
+        "alpha" | "1a" | "2a" | "3a" | "4a" => {
+            let value = args.first().copied().unwrap_or_default();
+            let is_alpha = value
+                .strip_prefix("&H")
+                .and_then(|hex| hex.strip_suffix('&'))
+                .is_some_and(|hex| {
+                    (1..=2).contains(&hex.len()) && hex.bytes().all(|b| b.is_ascii_hexdigit())
+                });
+            (
+                bare && (value.is_empty() || is_alpha),
+                "an alpha value like &HFF&",
+            )
+        }
+        "fscx" | "fscy" | "frx" | "fry" | "frz" | "fr" => (
+            match args {
+                [value] => (bare && value.is_empty()) || is_number(value),
+                _ => false,
+            } && arguments.closed,
+            "a single number",
+        ),
+        _ => return None,
+    };
+    (!valid).then_some(expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::AssParser;
+
+    const HEADER: &str = "[Script Info]\nScriptType: v4.00+\nPlayResX: 1920\nPlayResY: 1080\n\n[V4+ Styles]\nFormat: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding\nStyle: Default,Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1\n\n[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n";
+
+    fn uri() -> Url {
+        Url::parse("file:///tmp/test.ass").unwrap()
+    }
+
+    fn script(events: &[(&str, &str, &str)]) -> String {
+        let mut script = HEADER.to_string();
+        for (start, end, text) in events {
+            script.push_str(&format!(
+                "Dialogue: 0,{start},{end},Default,,0,0,0,,{text}\n"
+            ));
+        }
+        script
+    }
+
+    fn codes(diagnostics: &[Diagnostic], code: &str) -> usize {
+        diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String(code.to_string())))
+            .count()
+    }
+
+    #[test]
+    fn implausible_timestamp_uses_configured_ceiling() {
+        let document = AssParser::new().parse(&script(&[
+            ("0:00:01.00", "0:00:03.00", "Fine"),
+            ("10:00:00.01", "10:00:02.00", "Past ten hours"),
+            ("99:59:59.98", "99:59:59.99", "Typo"),
+        ]));
+        let mut validation = ValidationProvider::new();
+        let diagnostics = validation.validate(&document, &uri());
+        assert_eq!(codes(&diagnostics, "implausible_timestamp"), 2);
+
+        validation.options.timestamp_ceiling = 200;
+        let diagnostics = validation.validate(&document, &uri());
+        assert_eq!(codes(&diagnostics, "implausible_timestamp"), 3);
+        assert!(diagnostics
+            .iter()
+            .any(|d| d.message == "Timestamp exceeds 0:00:02.00; is this a typo?"));
+    }
+
+    #[test]
+    fn each_later_duplicate_style_points_at_the_first() {
+        let style = HEADER
+            .lines()
+            .find(|line| line.starts_with("Style:"))
+            .unwrap();
+        let text = HEADER.replacen(style, &format!("{style}\n{style}\n{style}"), 1);
+        let document = AssParser::new().parse(&text);
+        let diagnostics: Vec<_> = ValidationProvider::new()
+            .validate(&document, &uri())
+            .into_iter()
+            .filter(|d| d.code == Some(NumberOrString::String("duplicate_style".to_string())))
+            .collect();
+
+        assert_eq!(diagnostics.len(), 2);
+        let last_line = document.styles[2].range.start.line + 1;
+        for (diagnostic, style) in diagnostics.iter().zip(&document.styles[1..]) {
+            assert_eq!(diagnostic.range, style.range);
+            assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::WARNING));
+            assert!(diagnostic.message.contains(&format!(
+                "libass uses the last definition (line {last_line})"
+            )));
+            let related = diagnostic.related_information.as_ref().unwrap();
+            assert_eq!(related.len(), 1);
+            assert_eq!(
+                related[0].location,
+                Location::new(uri(), document.styles[0].range)
+            );
+        }
+    }
+
+    #[test]
+    fn first_event_out_of_order_is_reported_when_opted_in() {
+        let mut text = script(&[
+            ("0:00:05.00", "0:00:06.00", "Later"),
+            ("0:00:02.00", "0:00:03.00", "Earlier"),
+            ("0:00:01.00", "0:00:02.00", "Earlier still"),
+        ]);
+        // Comments are left wherever they are
+        text.insert_str(
+            text.find("Dialogue:").unwrap(),
+            "Comment: 0,0:00:09.00,0:00:10.00,Default,,0,0,0,,Note\n",
+        );
+        let document = AssParser::new().parse(&text);
+        let mut validation = ValidationProvider::new();
+        assert_eq!(
+            codes(&validation.validate(&document, &uri()), "unsorted_events"),
+            0
+        );
+
+        validation.options.check_event_order = true;
+        let diagnostics = validation.validate(&document, &uri());
+        let unsorted: Vec<_> = diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String("unsorted_events".into())))
+            .collect();
+        assert_eq!(unsorted.len(), 1);
+        assert_eq!(
+            unsorted[0].range.start.line,
+            document.events[2].range.start.line
+        );
+        assert_eq!(unsorted[0].severity, Some(DiagnosticSeverity::INFORMATION));
+        assert!(
+            unsorted[0].message.contains("0:00:02.00")
+                && unsorted[0].message.contains("0:00:05.00")
+        );
+    }
+
+    #[test]
+    fn legacy_colour_spellings_are_noted_and_invalid_ones_name_their_grammar() {
+        let range = Range::default();
+        let legacy = [
+            (ColorContext::StyleField, "16777215", "&H00FFFFFF"),
+            (ColorContext::StyleField, "-16777216", "&HFF000000"),
+            (ColorContext::StyleField, "&HFFFFFF", "&H00FFFFFF"),
+            (ColorContext::StyleField, "&H00FFFFFF&", "&H00FFFFFF"),
+            (ColorContext::StyleField, "&hff", "&H000000FF"),
+            (ColorContext::OverrideTag, "&HFFFFFF", "&HFFFFFF&"),
+            (ColorContext::OverrideTag, "HFFFFFF&", "&HFFFFFF&"),
+            (ColorContext::OverrideTag, "&HFF&", "&H0000FF&"),
+            (ColorContext::OverrideTag, "&H80FFFFFF&", "&HFFFFFF&"),
+            (ColorContext::OverrideTag, "FFFFFF", "&HFFFFFF&"),
+        ];
+        for (context, value, canonical) in legacy {
+            let diagnostic = color_diagnostic(range, "Colour", value, context).unwrap();
+            assert_eq!(
+                diagnostic.code,
+                Some(NumberOrString::String("legacy_color".into())),
+                "{value}"
+            );
+            assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::INFORMATION));
+            assert_eq!(diagnostic.data.unwrap()["canonical"], canonical, "{value}");
+            assert!(color_diagnostic(range, "Colour", canonical, context).is_none());
+        }
+
+        let invalid = [
+            (
+                ColorContext::StyleField,
+                "&HGG0000",
+                "style colours are written `&HAABBGGRR`",
+            ),
+            (
+                ColorContext::OverrideTag,
+                "&HXY&",
+                "override tag colours are written `&HBBGGRR&`",
+            ),
+        ];
+        for (context, value, grammar) in invalid {
+            let diagnostic = color_diagnostic(range, "Colour", value, context).unwrap();
+            assert_eq!(
+                diagnostic.code,
+                Some(NumberOrString::String("invalid_color".into()))
+            );
+            assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::ERROR));
+            assert!(
+                diagnostic.message.contains(grammar),
+                "{}",
+                diagnostic.message
+            );
+        }
+    }
+
+    #[test]
+    fn karaoke_ending_off_the_line_by_more_than_the_tolerance_is_reported() {
+        let text = script(&[
+            // Syllables 100cs against 100cs, and 115cs against 100cs
+            ("0:00:01.00", "0:00:02.00", "{\\k50}Ka{\\k50}ra"),
+            ("0:00:02.00", "0:00:03.00", "{\\k50}Ka{\\k65}ra"),
+            // \kt moves the clock back, so these end at 100cs, not 130cs
+            ("0:00:03.00", "0:00:04.00", "{\\k50}Ka{\\kt20\\k80}ra"),
+            ("0:00:04.00", "0:00:05.00", "No karaoke"),
+        ]);
+        let document = AssParser::new().parse(&text);
+        let mut validation = ValidationProvider::new();
+        let mismatches = |validation: &ValidationProvider| -> Vec<Diagnostic> {
+            validation
+                .validate(&document, &uri())
+                .into_iter()
+                .filter(|d| {
+                    d.code == Some(NumberOrString::String("karaoke_timing_mismatch".into()))
+                })
+                .collect()
+        };
+
+        let found = mismatches(&validation);
+        assert_eq!(found.len(), 1);
+        let event = &document.events[1];
+        assert_eq!(found[0].range.start.line, event.range.start.line);
+        let line = text.lines().nth(event.range.start.line as usize).unwrap();
+        let range = found[0].range.start.character as usize..found[0].range.end.character as usize;
+        assert_eq!(&line[range], "\\k65");
+        assert!(found[0].message.contains("115cs") && found[0].message.contains("100cs"));
+
+        validation.options.karaoke_tolerance_cs = 15;
+        assert!(mismatches(&validation).is_empty());
+    }
+
+    #[test]
+    fn stray_mark_in_arabic_is_located_and_punctuation_pinned_when_opted_in() {
+        let text = include_str!("../tests/fixtures/rtl.ass");
+        let document = AssParser::new().parse(text);
+        // Diagnostics count bytes, so the fixes are read against a UTF-8 client
+        let index = LineIndex::new(text.to_string(), crate::line_index::PositionEncoding::Utf8);
+        let mut validation = ValidationProvider::new();
+        let fixed = |validation: &ValidationProvider, diagnostics: &[Diagnostic]| {
+            let edits: Vec<TextEdit> = validation
+                .quick_fixes(&uri(), &index, diagnostics)
+                .into_iter()
+                .filter_map(|action| match action {
+                    CodeActionOrCommand::CodeAction(action) => action.edit?.changes?.remove(&uri()),
+                    _ => None,
+                })
+                .flatten()
+                .collect();
+            crate::line_index::apply_edits(text, crate::line_index::PositionEncoding::Utf8, &edits)
+        };
+
+        let diagnostics = validation.validate(&document, &uri());
+        assert_eq!(codes(&diagnostics, "bidi_punctuation"), 0);
+        let marks: Vec<Diagnostic> = diagnostics
+            .into_iter()
+            .filter(|d| d.code == Some(NumberOrString::String("directional_control".into())))
+            .collect();
+        assert_eq!(marks.len(), 1);
+        let range = marks[0].range;
+        let line = text.lines().nth(range.start.line as usize).unwrap();
+        assert_eq!(range.start.line, document.events[0].range.start.line);
+        assert_eq!(
+            &line[range.start.character as usize..range.end.character as usize],
+            "\u{200E}"
+        );
+        assert!(line[..range.start.character as usize].ends_with("بكم"));
+        assert!(marks[0].message.contains("U+200E"));
+        let without = fixed(&validation, &marks);
+        assert_eq!(without, text.replace('\u{200E}', ""));
+
+        validation.options.check_bidi_punctuation = true;
+        let pinned: Vec<Diagnostic> = validation
+            .validate(&document, &uri())
+            .into_iter()
+            .filter(|d| d.code == Some(NumberOrString::String("bidi_punctuation".into())))
+            .collect();
+        assert_eq!(pinned.len(), 1);
+        assert_eq!(
+            pinned[0].range.start.line,
+            document.events[1].range.start.line
+        );
+        assert!(fixed(&validation, &pinned).contains("قال لي: Hello!\u{200F}\n"));
+    }
+
+    #[test]
+    fn legacy_alignment_is_deprecated_and_strict_compat_adds_upper_k() {
+        let text = script(&[
+            ("0:00:01.00", "0:00:02.00", "{\\a5}Top left"),
+            ("0:00:02.00", "0:00:03.00", "{\\K30}Sweep"),
+        ]);
+        let document = AssParser::new().parse(&text);
+        let index = LineIndex::new(text.clone(), crate::line_index::PositionEncoding::Utf16);
+        let mut validation = ValidationProvider::new();
+        let deprecated = |validation: &ValidationProvider| -> Vec<Diagnostic> {
+            validation
+                .validate(&document, &uri())
+                .into_iter()
+                .filter(|d| d.code == Some(NumberOrString::String("deprecated_tag".into())))
+                .collect()
+        };
+
+        let found = deprecated(&validation);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].tags, Some(vec![DiagnosticTag::DEPRECATED]));
+
+        validation.options.strict_compat = true;
+        let found = deprecated(&validation);
+        assert_eq!(found.len(), 2);
+        let edits: Vec<TextEdit> = validation
+            .quick_fixes(&uri(), &index, &found)
+            .into_iter()
+            .filter_map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => action.edit?.changes?.remove(&uri()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        let fixed = crate::line_index::apply_edits(
+            &text,
+            crate::line_index::PositionEncoding::Utf16,
+            &edits,
+        );
+        assert!(fixed.contains(",,{\\an7}Top left\n"), "{fixed}");
+        assert!(fixed.contains(",,{\\kf30}Sweep\n"), "{fixed}");
+    }
+
+    #[test]
+    fn top_alignment_with_a_bottom_margin_is_reported_for_dialogue_styles_only() {
+        let style = |name: &str, alignment: u8, margin_v: u32| {
+            format!("Style: {name},Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,{alignment},10,10,{margin_v},1\n")
+        };
+        let styles = [
+            // 700 of PlayResY 1080 is 65%, a bottom margin on a top style
+            style("Top", 8, 700),
+            // Signs sit wherever they are needed
+            style("Sign", 8, 900),
+            style("Unused", 8, 900),
+        ]
+        .concat();
+        let mut text = HEADER.replacen("\n\n[Events]", &format!("\n{styles}\n[Events]"), 1);
+        for (style, name) in [("Top", "Dialogue"), ("Sign", "Board")] {
+            text.push_str(&format!(
+                "Dialogue: 0,0:00:01.00,0:00:03.00,{style},,0,0,0,,{name}\n"
+            ));
+        }
+        let document = AssParser::new().parse(&text);
+        let mut validation = ValidationProvider::new();
+        let mismatches = |validation: &ValidationProvider| -> Vec<Diagnostic> {
+            validation
+                .validate(&document, &uri())
+                .into_iter()
+                .filter(|d| {
+                    d.code == Some(NumberOrString::String("alignment_margin_mismatch".into()))
+                })
+                .collect()
+        };
+
+        let found = mismatches(&validation);
+        assert_eq!(found.len(), 1);
+        let top = document.style("Top").unwrap();
+        assert_eq!(found[0].range.start.line, top.range.start.line);
+        assert_eq!(found[0].severity, Some(DiagnosticSeverity::INFORMATION));
+        assert!(found[0].message.contains("65%"), "{}", found[0].message);
+
+        validation.options.top_margin_fraction = 0.7;
+        assert!(mismatches(&validation).is_empty());
+        validation.options.top_margin_fraction = 0.6;
+        validation.options.check_alignment_margins = false;
+        assert!(mismatches(&validation).is_empty());
+    }
+
+    #[test]
+    fn timestamps_past_the_video_are_reported_once() {
+        let document = AssParser::new().parse(&script(&[
+            ("0:23:50.00", "0:23:55.00", "Inside"),
+            ("0:23:58.00", "0:24:10.00", "Runs past the end"),
+            ("12:00:00.00", "12:00:02.00", "Past the ceiling too"),
+        ]));
+        let validation = ValidationProvider::new();
+        let diagnostics = validation.validate_video_duration(&document, AssTime(144000));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].range.start.line,
+            document.events[1].range.start.line
+        );
+        assert!(diagnostics[0].message.contains("0:24:00.00"));
+    }
+
+    #[test]
+    fn missing_fonts_are_checked_against_the_given_font_list() {
+        let mut text = HEADER.replace("Style: Default,Arial,", "Style: Default,Arial Bold,");
+        for (name, font) in [
+            ("Sign", "Chunk Five"),
+            ("Song", "Gandhi Sans"),
+            ("Spare", "Nowhere"),
+        ] {
+            text = text.replace(
+                "\n\n[Events]",
+                &format!("\nStyle: {name},{font},48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1\n\n[Events]"),
+            );
+        }
+        for style in ["Default", "Sign", "Song"] {
+            text.push_str(&format!(
+                "Dialogue: 0,0:00:01.00,0:00:02.00,{style},,0,0,0,,Text\n"
+            ));
+        }
+        text.push_str("\n[Fonts]\nfontname: chunkfive_0.ttf\n!!!!\n");
+        let document = AssParser::new().parse(&text);
+        let validation = ValidationProvider::new();
+
+        let installed = FontCatalog::from_families(["arial", "Times New Roman"]);
+        let diagnostics = validation.validate_fonts(&document, &installed);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(codes(&diagnostics, "missing_font"), 1);
+        assert!(diagnostics[0].message.contains("Gandhi Sans"));
+
+        let diagnostics = validation.validate_fonts(&document, &FontCatalog::Unavailable);
+        assert_eq!(codes(&diagnostics, "unverified_font"), 2);
+    }
+
+    #[test]
+    fn boxed_styles_are_checked_for_shadow_and_contrast() {
+        let text = include_str!("../tests/fixtures/boxed_styles.ass");
+        let document = AssParser::new().parse(text);
+        let diagnostics = ValidationProvider::new().validate(&document, &uri());
+        let on_style = |name: &str, code: &str| {
+            let style = document.style(name).unwrap();
+            diagnostics
+                .iter()
+                .filter(|d| d.range == style.range)
+                .filter(|d| d.code == Some(NumberOrString::String(code.to_string())))
+                .count()
+        };
+        assert_eq!(on_style("Boxed", "invisible_box_shadow"), 0);
+        assert_eq!(on_style("Boxed", "low_contrast"), 0);
+        assert_eq!(on_style("Washed Out", "invisible_box_shadow"), 1);
+        assert_eq!(on_style("Washed Out", "low_contrast"), 1);
+    }
+
+    #[test]
+    fn contrast_is_measured_against_the_box_or_outline() {
+        let style = |border_style: &str, outline: &str, outline_colour: &str, back: &str| {
+            let text = HEADER.replace(
+                "&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,",
+                &format!("{outline_colour},{back},0,0,0,0,100,100,0,0,{border_style},{outline},0,"),
+            );
+            let document = AssParser::new().parse(&text);
+            let diagnostics = ValidationProvider::new().validate_lines(&document, 0..usize::MAX);
+            diagnostics
+                .into_iter()
+                .find(|d| d.code == Some(NumberOrString::String("low_contrast".to_string())))
+                .map(|d| d.message)
+        };
+        // A white outline on white text, but only when there is an outline
+        let message = style("1", "2", "&H00FFFFFF", "&H00000000").unwrap();
+        assert!(message.contains("with the outline, drawn in OutlineColour"));
+        assert_eq!(style("1", "0", "&H00FFFFFF", "&H00000000"), None);
+        // A box is drawn even without padding
+        let message = style("3", "0", "&H00FFFFFF", "&H00000000").unwrap();
+        assert!(message.contains("with the box, drawn in OutlineColour"));
+        assert_eq!(style("3", "0", "&HFFFFFFFF", "&H00000000"), None);
+        // libass's event box is filled with BackColour
+        let message = style("4", "0", "&H00000000", "&H00EEEEEE").unwrap();
+        assert!(message.contains("drawn in BackColour"));
+        assert_eq!(style("4", "0", "&H00FFFFFF", "&H00000000"), None);
+    }
+
+    #[test]
+    fn border_scaling_is_explained_for_each_setting_and_target() {
+        let base = script(&[("0:00:01.00", "0:00:03.00", "Text")]);
+        for target in [RenderTarget::Libass, RenderTarget::VsFilter] {
+            let mut validation = ValidationProvider::new();
+            validation.options.render_target = target;
+            let check = |value: Option<&str>| {
+                let text = match value {
+                    Some(value) => base.replace(
+                        "PlayResY: 1080\n",
+                        &format!("PlayResY: 1080\nScaledBorderAndShadow: {value}\n"),
+                    ),
+                    None => base.clone(),
+                };
+                let document = AssParser::new().parse(&text);
+                let diagnostics = validation.validate(&document, &uri());
+                let index = LineIndex::new(text, crate::line_index::PositionEncoding::Utf16);
+                (diagnostics, index)
+            };
+
+            let (diagnostics, _) = check(Some("yes"));
+            assert_eq!(codes(&diagnostics, "unscaled_border_and_shadow"), 0);
+            assert_eq!(codes(&diagnostics, "missing_scaled_border_and_shadow"), 0);
+
+            let (diagnostics, _) = check(Some("no"));
+            let unscaled: Vec<_> = diagnostics
+                .iter()
+                .filter(|d| {
+                    d.code == Some(NumberOrString::String("unscaled_border_and_shadow".into()))
+                })
+                .collect();
+            assert_eq!(unscaled.len(), 1);
+            assert_eq!(unscaled[0].range.start.line, 4);
+            assert!(unscaled[0].message.contains("authored for 1920x1080"));
+            assert_eq!(codes(&diagnostics, "missing_scaled_border_and_shadow"), 0);
+
+            let (diagnostics, index) = check(None);
+            let missing: Vec<_> = diagnostics
+                .iter()
+                .filter(|d| {
+                    d.code
+                        == Some(NumberOrString::String(
+                            "missing_scaled_border_and_shadow".into(),
+                        ))
+                })
+                .cloned()
+                .collect();
+            assert_eq!(missing.len(), 1);
+            assert_eq!(missing[0].range.start.line, 0);
+            let assumed = match target {
+                RenderTarget::Libass => "libass assumes yes",
+                RenderTarget::VsFilter => "VSFilter assumes no",
+            };
+            assert!(
+                missing[0].message.contains(assumed),
+                "{}",
+                missing[0].message
+            );
+            let fixes = validation.quick_fixes(&uri(), &index, &missing);
+            assert!(fixes.iter().any(|fix| matches!(
+                fix,
+                CodeActionOrCommand::CodeAction(action)
+                    if action.title == "Add ScaledBorderAndShadow: yes"
+            )));
+        }
+    }
+
+    #[test]
+    fn fast_dialogue_splits_at_a_natural_break_with_proportional_timing() {
+        let text = script(&[
+            (
+                "0:00:01.00",
+                "0:00:02.00",
+                "AAAAAAAAAAAAAAAAAAAA\\NBBBBBBBBBB",
+            ),
+            (
+                "0:00:03.00",
+                "0:00:04.00",
+                "First part. Second half is longer",
+            ),
+            (
+                "0:00:05.00",
+                "0:00:06.00",
+                "{\\pos(960,100)\\fad(100,100)}Sign text one.\\NSign text two.",
+            ),
+        ]);
+        let document = AssParser::new().parse(&text);
+        let validation = ValidationProvider::new();
+        let fast: Vec<Diagnostic> = validation
+            .validate(&document, &uri())
+            .into_iter()
+            .filter(|d| d.code == Some(NumberOrString::String("high_cps".into())))
+            .collect();
+        assert_eq!(fast.len(), 3);
+
+        let index = LineIndex::new(text.clone(), crate::line_index::PositionEncoding::Utf16);
+        let actions: Vec<CodeAction> = validation
+            .quick_fixes(&uri(), &index, &fast)
+            .into_iter()
+            .filter_map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => Some(action),
+                _ => None,
+            })
+            .collect();
+        let titles: Vec<&str> = actions.iter().map(|a| a.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            [
+                "Split event at \\N",
+                "Split event at sentence boundary",
+                "Split event at \\N"
+            ]
+        );
+
+        let edits: Vec<TextEdit> = actions
+            .into_iter()
+            .filter_map(|action| action.edit?.changes?.remove(&uri()))
+            .flatten()
+            .collect();
+        let split = crate::line_index::apply_edits(
+            &text,
+            crate::line_index::PositionEncoding::Utf16,
+            &edits,
+        );
+        let events: Vec<&str> = split
+            .lines()
+            .filter(|line| line.starts_with("Dialogue:"))
+            .collect();
+        assert_eq!(
+            events,
+            [
+                // 20 of 30 characters before the \N take two thirds of the second
+                "Dialogue: 0,0:00:01.00,0:00:01.67,Default,,0,0,0,,AAAAAAAAAAAAAAAAAAAA",
+                "Dialogue: 0,0:00:01.67,0:00:02.00,Default,,0,0,0,,BBBBBBBBBB",
+                "Dialogue: 0,0:00:03.00,0:00:03.33,Default,,0,0,0,,First part.",
+                "Dialogue: 0,0:00:03.33,0:00:04.00,Default,,0,0,0,,Second half is longer",
+                "Dialogue: 0,0:00:05.00,0:00:05.50,Default,,0,0,0,,{\\pos(960,100)\\fad(100,100)}Sign text one.",
+                "Dialogue: 0,0:00:05.50,0:00:06.00,Default,,0,0,0,,{\\pos(960,100)\\fad(100,100)}Sign text two.",
+            ]
+        );
+    }
+
+    #[test]
+    fn wrong_case_tags_are_fixed_but_karaoke_case_is_explained() {
+        let text = script(&[
+            ("0:00:01.00", "0:00:02.00", "{\\AN8}Top"),
+            ("0:00:02.00", "0:00:03.00", "{\\Pos(960,540)}Middle"),
+            ("0:00:03.00", "0:00:04.00", "{\\k20}Ka{\\K30}ra"),
+            ("0:00:04.00", "0:00:05.00", "{\\K30}Only{\\K20}sweeps"),
+        ]);
+        let document = AssParser::new().parse(&text);
+        let validation = ValidationProvider::new();
+        let diagnostics = validation.validate(&document, &uri());
+        let index = LineIndex::new(text.clone(), crate::line_index::PositionEncoding::Utf16);
+
+        let wrong_case: Vec<Diagnostic> = diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String("wrong_case_tag".into())))
+            .cloned()
+            .collect();
+        let written: Vec<&str> = wrong_case
+            .iter()
+            .map(|d| {
+                let line = index.line_text(d.range.start.line as usize);
+                &line[d.range.start.character as usize..d.range.end.character as usize]
+            })
+            .collect();
+        assert_eq!(written, ["\\AN", "\\Pos"]);
+        assert!(wrong_case
+            .iter()
+            .all(|d| d.severity == Some(DiagnosticSeverity::ERROR)));
+
+        let edits: Vec<TextEdit> = validation
+            .quick_fixes(&uri(), &index, &wrong_case)
+            .into_iter()
+            .filter_map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => action.edit?.changes?.remove(&uri()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        let fixed = crate::line_index::apply_edits(
+            &text,
+            crate::line_index::PositionEncoding::Utf16,
+            &edits,
+        );
+        assert!(fixed.contains("{\\an8}Top") && fixed.contains("{\\pos(960,540)}Middle"));
+
+        // \K means something else, so only a line mixing it with \k gets a note
+        let notes: Vec<&Diagnostic> = diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String("karaoke_tag_case".into())))
+            .collect();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(
+            notes[0].range.start.line,
+            document.events[2].range.start.line
+        );
+        assert_eq!(notes[0].severity, Some(DiagnosticSeverity::INFORMATION));
+        assert!(validation
+            .quick_fixes(&uri(), &index, &[notes[0].clone()])
+            .is_empty());
+        assert_eq!(codes(&diagnostics, "unknown_tag"), 0);
+    }
+
+    #[test]
+    fn drawing_scales_are_read_as_exponents() {
+        let document = AssParser::new().parse(&script(&[
+            (
+                "0:00:01.00",
+                "0:00:02.00",
+                "{\\p1}m 0 0 l 100 0 100 100 0 100",
+            ),
+            (
+                "0:00:01.00",
+                "0:00:02.00",
+                "{\\p5}m 0 0 l 160 0 160 80 0 80",
+            ),
+            ("0:00:01.00", "0:00:02.00", "{\\p0}m 0 0 l 10 0 10 10"),
+            ("0:00:01.00", "0:00:02.00", "{\\p99}m 0 0 l 10 0 10 10"),
+            ("0:00:01.00", "0:00:02.00", "{\\p40}Not a drawing"),
+        ]));
+        let diagnostics = ValidationProvider::new().validate(&document, &uri());
+        let drawing: Vec<&Diagnostic> = diagnostics
+            .iter()
+            .filter(|d| d.range.start.line >= 11)
+            .collect();
+        let found: Vec<(u32, &str)> = drawing
+            .iter()
+            .filter_map(|d| match &d.code {
+                Some(NumberOrString::String(code)) => Some((d.range.start.line, code.as_str())),
+                _ => None,
+            })
+            .filter(|(_, code)| code.contains("drawing"))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (12, "unused_drawing_precision"),
+                (13, "drawing_as_text"),
+                (14, "drawing_scale_out_of_range"),
+                (15, "drawing_scale_out_of_range"),
+            ]
+        );
+
+        let precision = drawing.iter().find(|d| d.range.start.line == 12).unwrap();
+        assert!(precision
+            .message
+            .contains("divided by 16, so this drawing is 10×5 pixels"));
+        let out_of_range = drawing.iter().find(|d| d.range.start.line == 14).unwrap();
+        assert_eq!(out_of_range.severity, Some(DiagnosticSeverity::WARNING));
+        assert!(out_of_range.message.contains("divided by 2^98"));
+        assert!(!out_of_range.message.contains(&format!("{}", 2f64.powi(98))));
+    }
+
+    #[test]
+    fn play_res_diagnostics_name_each_targets_resolution() {
+        let only_width = HEADER.replace("PlayResY: 1080\n", "");
+        let repeated = HEADER.replace("PlayResY: 1080\n", "PlayResX: 1280\n");
+        let mut validation = ValidationProvider::new();
+        for (target, name, repeated_y) in [
+            (RenderTarget::Libass, "libass", 1024),
+            (RenderTarget::VsFilter, "VSFilter", 1440),
+        ] {
+            validation.options.render_target = target;
+
+            let diagnostics = validation.validate(&AssParser::new().parse(&only_width), &uri());
+            let missing: Vec<&str> = diagnostics
+                .iter()
+                .filter(|d| d.code == Some(NumberOrString::String("missing_play_res".into())))
+                .map(|d| d.message.as_str())
+                .collect();
+            assert_eq!(
+                missing,
+                vec!["PlayResY is not set; libass and VSFilter assume 4:3, giving PlayResY=1440"]
+            );
+
+            let diagnostics = validation.validate(&AssParser::new().parse(&repeated), &uri());
+            let conflict = diagnostics
+                .iter()
+                .find(|d| d.code == Some(NumberOrString::String("conflicting_play_res".into())))
+                .unwrap();
+            assert_eq!(conflict.range.start.line, 3);
+            assert_eq!(
+                conflict.message,
+                format!(
+                    "PlayResX is set again, to 1280 after 1920 on line 3; {name} lays the script out at 1280x{repeated_y}"
+                )
+            );
+        }
+    }
+
+    /// `text` with every quick fix for `diagnostics` applied at once.
+    fn with_fixes(
+        validation: &ValidationProvider,
+        text: &str,
+        diagnostics: &[Diagnostic],
+    ) -> String {
+        let index = LineIndex::new(text.to_string(), crate::line_index::PositionEncoding::Utf16);
+        let edits: Vec<TextEdit> = validation
+            .quick_fixes(&uri(), &index, diagnostics)
+            .into_iter()
+            .filter_map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => action.edit?.changes?.remove(&uri()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        crate::line_index::apply_edits(text, crate::line_index::PositionEncoding::Utf16, &edits)
+    }
+
+    #[test]
+    fn inserted_sections_clear_the_diagnostic_and_need_no_formatting() {
+        let styles = HEADER
+            .split("\n\n")
+            .find(|block| block.starts_with("[V4+ Styles]"))
+            .unwrap();
+        let info = HEADER.split("\n\n").next().unwrap();
+        let events = "[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\nDialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,Hi";
+        let fixtures = [
+            // No [Events]: it goes after the styles, before the fonts and
+            // their banner
+            format!("{info}\n\n{styles}\n\n; Fonts\n[Fonts]\nfontname: a.ttf\n"),
+            // No [Script Info]: it goes above the banner of the first section
+            format!("; Made by hand\n{styles}\n\n{events}\n"),
+            // Neither, with Windows line endings
+            format!("{styles}\n").replace('\n', "\r\n"),
+        ];
+        let parser = AssParser::new();
+        let validation = ValidationProvider::new();
+        let mut results = Vec::new();
+        for (fixture, expected) in fixtures.iter().zip([1, 1, 2]) {
+            let diagnostics = validation.validate(&parser.parse(fixture), &uri());
+            let missing: Vec<Diagnostic> = diagnostics
+                .into_iter()
+                .filter(|d| d.code == Some(NumberOrString::String("missing_section".into())))
+                .collect();
+            assert_eq!(missing.len(), expected, "{fixture}");
+
+            let fixed = with_fixes(&validation, fixture, &missing);
+            let diagnostics = validation.validate(&parser.parse(&fixed), &uri());
+            assert_eq!(codes(&diagnostics, "missing_section"), 0, "{fixed}");
+            assert_eq!(parser.format(&fixed), fixed);
+            results.push(fixed);
+        }
+
+        let at = |text: &str, needle: &str| text.find(needle).unwrap();
+        assert!(at(&results[0], "[V4+ Styles]") < at(&results[0], "[Events]"));
+        assert!(results[0].contains(",10,1\n\n[Events]\nFormat: Layer,"));
+        assert!(results[0].contains("Effect, Text\n\n; Fonts\n[Fonts]"));
+        assert!(results[1].starts_with("[Script Info]\nScriptType: v4.00+\n"));
+        assert!(results[1].contains("ScaledBorderAndShadow: yes\n\n; Made by hand\n"));
+        assert!(results[2].starts_with("[Script Info]\r\n"));
+        assert!(results[2].trim_end().ends_with("Effect, Text"));
+        assert!(!results[2].replace("\r\n", "").contains('\n'));
+    }
+
+    #[test]
+    fn repeated_script_info_keys_point_at_the_earlier_line() {
+        let text = HEADER.replace(
+            "PlayResY: 1080\n",
+            "PlayResY: 1080\nTitle: Draft\nTitle: Final\nWrapStyle: 0\nWrapStyle: 0\n\
+             Encoder: me\nEncoder: you\nPlayResX: 1280\n",
+        );
+        let document = AssParser::new().parse(&text);
+        // The map keeps the last value, as renderers do
+        assert_eq!(document.script_info["PlayResX"], "1280");
+        assert_eq!(document.script_info["Title"], "Final");
+
+        let diagnostics = ValidationProvider::new().validate(&document, &uri());
+        let repeated: Vec<(u32, DiagnosticSeverity, u32)> = diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String("duplicate_script_info_key".into())))
+            .map(|d| {
+                let earlier = &d.related_information.as_ref().unwrap()[0];
+                (
+                    d.range.start.line,
+                    d.severity.unwrap(),
+                    earlier.location.range.start.line,
+                )
+            })
+            .collect();
+        assert_eq!(
+            repeated,
+            vec![
+                (5, DiagnosticSeverity::WARNING, 4),
+                (7, DiagnosticSeverity::INFORMATION, 6),
+                (9, DiagnosticSeverity::INFORMATION, 8),
+            ]
+        );
+        assert!(diagnostics.iter().any(|d| d.message
+            == "Title is set again, to 'Final' after 'Draft' on line 5; the last value, 'Final', is the one that applies"));
+
+        // A second PlayResX is an error that names the resolution it leads to
+        let play_res: Vec<&Diagnostic> = diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String("conflicting_play_res".into())))
+            .collect();
+        assert_eq!(play_res.len(), 1);
+        assert_eq!(play_res[0].range.start.line, 10);
+        assert_eq!(play_res[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            play_res[0].related_information.as_ref().unwrap()[0]
+                .location
+                .range
+                .start
+                .line,
+            2
+        );
+        assert!(play_res[0]
+            .message
+            .ends_with("libass lays the script out at 1280x1080"));
+    }
+
+    #[test]
+    fn worst_case_document_is_deduplicated_and_capped() {
+        let events: Vec<(String, String, String)> = (0..1500)
+            .map(|i| {
+                let start = AssTime(i * 100);
+                let end = AssTime(i * 100 + 50);
+                (
+                    start.to_string(),
+                    end.to_string(),
+                    "{\\b1 never closed".to_string(),
+                )
+            })
+            .collect();
+        let events: Vec<(&str, &str, &str)> = events
+            .iter()
+            .map(|(start, end, text)| (start.as_str(), end.as_str(), text.as_str()))
+            .collect();
+        let document = AssParser::new().parse(&script(&events));
+        let mut validation = ValidationProvider::new();
+        let diagnostics = validation.validate(&document, &uri());
+        // Once per event
+        assert_eq!(codes(&diagnostics, "unclosed_override"), 1500);
+
+        // The same problems reported twice count once
+        let mut doubled = diagnostics.clone();
+        doubled.extend(diagnostics.clone());
+        validation.options.max_diagnostics = usize::MAX;
+        assert_eq!(validation.limit_diagnostics(doubled.clone()), diagnostics);
+
+        validation.options.max_diagnostics = 1000;
+        let limited = validation.limit_diagnostics(doubled);
+        assert_eq!(limited.len(), 1001);
+        let summary = limited.last().unwrap();
+        assert_eq!(
+            summary.code,
+            Some(NumberOrString::String("too_many_diagnostics".into()))
+        );
+        assert_eq!(
+            summary.message,
+            format!(
+                "{} more diagnostics not shown; only the 1000 most severe are listed",
+                diagnostics.len() - 1000
+            )
+        );
+        // The most severe are kept, in the order they were found
+        let kept = &limited[..1000];
+        let least_kept = kept.iter().filter_map(|d| d.severity).max().unwrap();
+        assert!(diagnostics
+            .iter()
+            .filter(|d| !kept.contains(d))
+            .all(|d| d.severity >= Some(least_kept)));
+        let positions: Vec<usize> = kept
+            .iter()
+            .map(|d| diagnostics.iter().position(|found| found == d).unwrap())
+            .collect();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn malformed_times_are_rewritten_only_when_the_reading_is_clear() {
+        let validation = ValidationProvider::new();
+        for (broken, canonical) in [
+            ("0:00:01,50", Some("0:00:01.50")),
+            ("1:2:3.4", Some("1:02:03.40")),
+            ("0:00:01.505", Some("0:00:01.51")),
+            ("0:00:01.504", Some("0:00:01.50")),
+            ("00:00:01,500", Some("0:00:01.50")),
+            ("1:00", None),
+            ("soon", None),
+        ] {
+            let text = script(&[(broken, "0:00:09.00", "Hi")]);
+            // A comma splits the time across fields, so only the first
+            // report counts
+            let diagnostics: Vec<Diagnostic> = validation
+                .validate(&AssParser::new().parse(&text), &uri())
+                .into_iter()
+                .filter(|d| d.code == Some(NumberOrString::String("invalid_time_format".into())))
+                .take(1)
+                .collect();
+            assert_eq!(diagnostics.len(), 1, "{broken}");
+            let index = LineIndex::new(text.clone(), crate::line_index::PositionEncoding::Utf16);
+            let actions = validation.quick_fixes(&uri(), &index, &diagnostics);
+            let fix = actions.iter().find_map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) if action.title.starts_with("Replace") => {
+                    Some(action)
+                }
+                _ => None,
+            });
+            assert_eq!(fix.is_some(), canonical.is_some(), "{broken}");
+            let Some(canonical) = canonical else {
+                continue;
+            };
+            assert_eq!(fix.unwrap().is_preferred, Some(true));
+            let fixed = with_fixes(&validation, &text, &diagnostics);
+            assert!(
+                fixed.ends_with(&format!(
+                    "Dialogue: 0,{canonical},0:00:09.00,Default,,0,0,0,,Hi\n"
+                )),
+                "{broken}: {fixed}"
+            );
+        }
+    }
+
+    #[test]
+    fn reversed_times_swap_in_canonical_form_between_the_commas() {
+        let validation = ValidationProvider::new();
+        let text = script(&[
+            ("00:00:05.00", "0:00:02.00", "Reversed"),
+            ("0:00:03.00", "0:00:03.00", "Flash"),
+        ]);
+        let diagnostics = validation.validate(&AssParser::new().parse(&text), &uri());
+        assert_eq!(codes(&diagnostics, "invalid_time_order"), 1);
+
+        let fixed = with_fixes(&validation, &text, &diagnostics);
+        assert!(
+            fixed.contains("\nDialogue: 0,0:00:02.00,0:00:05.00,Default,,0,0,0,,Reversed\n"),
+            "{fixed}"
+        );
+        assert!(fixed.contains("\nDialogue: 0,0:00:03.00,0:00:03.00,Default,,0,0,0,,Flash\n"));
+
+        // Swapping equal times changes nothing, so none is offered even for
+        // a diagnostic claiming them reversed
+        let mut equal = diagnostics
+            .into_iter()
+            .find(|d| d.code == Some(NumberOrString::String("invalid_time_order".into())))
+            .unwrap();
+        equal.data.as_mut().unwrap()["end"] = serde_json::json!("0:00:05.00");
+        let index = LineIndex::new(text.clone(), crate::line_index::PositionEncoding::Utf16);
+        assert!(validation.quick_fixes(&uri(), &index, &[equal]).is_empty());
+    }
+
+    #[test]
+    fn legacy_alignment_converts_without_touching_alpha_or_an() {
+        let text = script(&[
+            ("0:00:01.00", "0:00:02.00", "{\\a10\\alpha&H80&}Middle"),
+            ("0:00:02.00", "0:00:03.00", "{\\an8}Top {\\a6\\a}Top again"),
+        ]);
+        let expected = script(&[
+            ("0:00:01.00", "0:00:02.00", "{\\an5\\alpha&H80&}Middle"),
+            ("0:00:02.00", "0:00:03.00", "{\\an8}Top {\\an8\\a}Top again"),
+        ]);
+        let document = AssParser::new().parse(&text);
+        let validation = ValidationProvider::new();
+        let deprecated: Vec<Diagnostic> = validation
+            .validate(&document, &uri())
+            .into_iter()
+            .filter(|d| d.code == Some(NumberOrString::String("deprecated_tag".into())))
+            .collect();
+        assert_eq!(deprecated.len(), 2);
+        assert_eq!(with_fixes(&validation, &text, &deprecated), expected);
+
+        let index = LineIndex::new(text.clone(), crate::line_index::PositionEncoding::Utf16);
+        let edit = legacy_alignment_edit(&uri(), &document, &index).unwrap();
+        let edits = &edit.changes.unwrap()[&uri()];
+        assert_eq!(edits.len(), 2);
+        let converted = crate::line_index::apply_edits(
+            &text,
+            crate::line_index::PositionEncoding::Utf16,
+            edits,
+        );
+        assert_eq!(converted, expected);
+        let document = AssParser::new().parse(&converted);
+        let index = LineIndex::new(converted, crate::line_index::PositionEncoding::Utf16);
+        assert!(legacy_alignment_edit(&uri(), &document, &index).is_none());
+    }
+
+    #[test]
+    fn unused_styles_and_empty_blocks_are_removed_leaving_a_valid_script() {
+        let style = |name: &str| {
+            format!("Style: {name},Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1\n")
+        };
+        let default = style("Default");
+        let with_styles = |names: &[&str]| {
+            let extra: String = names.iter().map(|name| style(name)).collect();
+            script(&[("0:00:01.00", "0:00:02.00", "{}Hi {\\b1}there{}")]).replacen(
+                &default,
+                &format!("{default}{extra}"),
+                1,
+            )
+        };
+        let text = with_styles(&["OldKaraoke", "Unused"]);
+        let parser = AssParser::new();
+        let validation = ValidationProvider::new();
+        let diagnostics: Vec<Diagnostic> = validation
+            .validate(&parser.parse(&text), &uri())
+            .into_iter()
+            .filter(|d| {
+                matches!(&d.code, Some(NumberOrString::String(code))
+                    if code == "unused_style" || code == "empty_override_block")
+            })
+            .collect();
+        assert_eq!(codes(&diagnostics, "unused_style"), 2);
+        assert_eq!(codes(&diagnostics, "empty_override_block"), 2);
+
+        let index = LineIndex::new(text.clone(), crate::line_index::PositionEncoding::Utf16);
+        let titles: Vec<String> = validation
+            .quick_fixes(&uri(), &index, &diagnostics)
+            .into_iter()
+            .filter_map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => Some(action.title),
+                _ => None,
+            })
+            .collect();
+        assert!(titles.contains(&"Remove unused style 'OldKaraoke'".to_string()));
+        assert!(titles.contains(&"Remove empty override block".to_string()));
+
+        // Every fix is worked out against the original text, so the block
+        // removed after the earlier one still lands on its braces
+        let fixed = with_fixes(&validation, &text, &diagnostics);
+        let expected = script(&[("0:00:01.00", "0:00:02.00", "Hi {\\b1}there")]);
+        assert_eq!(fixed, expected);
+        let remaining = validation.validate(&parser.parse(&fixed), &uri());
+        assert_eq!(codes(&remaining, "unused_style"), 0);
+        assert_eq!(codes(&remaining, "empty_override_block"), 0);
+        assert_eq!(parser.parse(&fixed).styles.len(), 1);
+
+        let document = parser.parse(&text);
+        let edit = unused_styles_edit(&uri(), &document, &index).unwrap();
+        let edits = &edit.changes.unwrap()[&uri()];
+        assert_eq!(
+            crate::line_index::apply_edits(
+                &text,
+                crate::line_index::PositionEncoding::Utf16,
+                edits
+            ),
+            with_styles(&[])
+        );
+    }
+
+    #[test]
+    fn removing_the_last_style_keeps_the_section_and_its_format_line() {
+        let text = HEADER.replace("Style: Default", "Style: Old");
+        let document = AssParser::new().parse(&text);
+        let index = LineIndex::new(text.clone(), crate::line_index::PositionEncoding::Utf16);
+        let edit = unused_styles_edit(&uri(), &document, &index).unwrap();
+        let edits = &edit.changes.unwrap()[&uri()];
+        let fixed = crate::line_index::apply_edits(
+            &text,
+            crate::line_index::PositionEncoding::Utf16,
+            edits,
+        );
+        assert!(
+            fixed.contains("[V4+ Styles]\nFormat: Name, Fontname,")
+                && fixed.contains("Encoding\n\n[Events]\nFormat:"),
+            "{fixed}"
+        );
+        assert!(AssParser::new().parse(&fixed).styles.is_empty());
+    }
+
+    #[test]
+    fn brace_fixes_close_after_the_tags_and_drop_strays_across_blocks() {
+        let validation = ValidationProvider::new();
+        // Each unclosed report runs from the first unclosed `{` to the end
+        for (broken, fixed, unclosed) in [
+            (
+                "{\\b1}Bold {\\i1Italic {\\u1}under",
+                "{\\b1}Bold {\\i1}Italic {\\u1}under",
+                Some("{\\i1Italic {\\u1}under"),
+            ),
+            (
+                "{\\b1{\\i1}Both",
+                "{\\b1}{\\i1}Both",
+                Some("{\\b1{\\i1}Both"),
+            ),
+            ("{\\b1}Bold} {\\i1}x}", "{\\b1}Bold {\\i1}x", None),
+            (
+                "}{\\fad(100,200)Hi {\\b1}x",
+                "{\\fad(100,200)}Hi {\\b1}x",
+                Some("{\\fad(100,200)Hi {\\b1}x"),
+            ),
+        ] {
+            let text = script(&[("0:00:01.00", "0:00:02.00", broken)]);
+            let diagnostics: Vec<Diagnostic> = validation
+                .validate(&AssParser::new().parse(&text), &uri())
+                .into_iter()
+                .filter(|d| {
+                    matches!(&d.code, Some(NumberOrString::String(code))
+                        if code == "unmatched_brace" || code == "unclosed_override")
+                })
+                .collect();
+            assert!(!diagnostics.is_empty(), "{broken}");
+            let covered: Vec<String> = spans(&text, &diagnostics, "unclosed_override")
+                .into_iter()
+                .map(|(_, span)| span)
+                .collect();
+            assert_eq!(covered, Vec::from_iter(unclosed), "{broken}");
+            assert_eq!(
+                with_fixes(&validation, &text, &diagnostics),
+                script(&[("0:00:01.00", "0:00:02.00", fixed)]),
+                "{broken}"
+            );
+        }
+    }
+
+    #[test]
+    fn trailing_alignment_can_move_and_trailing_colour_is_removed() {
+        let text = include_str!("../tests/fixtures/trailing_tags.ass");
+        let document = AssParser::new().parse(text);
+        let index = LineIndex::new(text.to_string(), crate::line_index::PositionEncoding::Utf16);
+        let validation = ValidationProvider::new();
+        let trailing: Vec<Diagnostic> = validation
+            .validate(&document, &uri())
+            .into_iter()
+            .filter(|d| d.code == Some(NumberOrString::String("trailing_override_tags".into())))
+            .collect();
+        // Not the Comment, the `{\p0}` or the unclosed block
+        assert_eq!(trailing.len(), 2);
+
+        let fixes = |diagnostic: &Diagnostic| -> Vec<(String, String)> {
+            validation
+                .quick_fixes(&uri(), &index, std::slice::from_ref(diagnostic))
+                .into_iter()
+                .filter_map(|action| match action {
+                    CodeActionOrCommand::CodeAction(action) => {
+                        let edits = action.edit?.changes?.remove(&uri())?;
+                        let fixed = crate::line_index::apply_edits(
+                            text,
+                            crate::line_index::PositionEncoding::Utf16,
+                            &edits,
+                        );
+                        let line = fixed.lines().nth(diagnostic.range.start.line as usize)?;
+                        Some((action.title, line.to_string()))
+                    }
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let (alignment, colour) = (&trailing[0], &trailing[1]);
+        let line = text
+            .lines()
+            .nth(alignment.range.start.line as usize)
+            .unwrap();
+        let range =
+            alignment.range.start.character as usize..alignment.range.end.character as usize;
+        assert_eq!(&line[range], "{\\an8}");
+        assert_eq!(
+            fixes(alignment),
+            [
+                (
+                    "Move tags to the start of the line".to_string(),
+                    "Dialogue: 0,0:00:01.00,0:00:03.00,Default,,0,0,0,,{\\an8}Meant for the top"
+                        .to_string()
+                ),
+                (
+                    "Remove tags".to_string(),
+                    "Dialogue: 0,0:00:01.00,0:00:03.00,Default,,0,0,0,,Meant for the top"
+                        .to_string()
+                ),
+            ]
+        );
+
+        let line = text.lines().nth(colour.range.start.line as usize).unwrap();
+        let range = colour.range.start.character as usize..colour.range.end.character as usize;
+        assert_eq!(&line[range], "{\\c&H0000FF&}");
+        assert_eq!(
+            fixes(colour),
+            [(
+                "Remove tags".to_string(),
+                "Dialogue: 0,0:00:04.00,0:00:06.00,Default,,0,0,0,,Meant to be red".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn drawings_comments_and_unclosed_blocks_have_no_trailing_tags() {
+        for text in [
+            include_str!("../tests/fixtures/highlight.ass"),
+            include_str!("../tests/fixtures/sample.ass"),
+            include_str!("../tests/fixtures/cjk.ass"),
+        ] {
+            let document = AssParser::new().parse(text);
+            let diagnostics = ValidationProvider::new().validate(&document, &uri());
+            assert_eq!(codes(&diagnostics, "trailing_override_tags"), 0);
+        }
+    }
+
+    #[test]
+    fn each_broken_tag_gets_one_diagnostic_on_its_span() {
+        let text = include_str!("../tests/fixtures/tag_arguments.ass");
+        let document = AssParser::new().parse(text);
+        let found: Vec<(u32, String, String)> = ValidationProvider::new()
+            .validate(&document, &uri())
+            .into_iter()
+            .filter(|d| d.code == Some(NumberOrString::String("invalid_tag_arguments".into())))
+            .map(|d| {
+                let line = text.lines().nth(d.range.start.line as usize).unwrap();
+                assert_eq!(d.range.start.line, d.range.end.line);
+                let span = d.range.start.character as usize..d.range.end.character as usize;
+                (d.range.start.line, line[span].to_string(), d.message)
+            })
+            .collect();
+        let expected = [
+            (
+                13,
+                "\\pos(960)",
+                "\\pos takes 2 numbers in parentheses, found `(960)`",
+            ),
+            (
+                16,
+                "\\move(0,0,100,100,0)",
+                "\\move takes 4 or 6 numbers in parentheses, found `(0,0,100,100,0)`",
+            ),
+            (
+                18,
+                "\\fade(255,0,255,0,200,800)",
+                "\\fade takes 7 numbers in parentheses, found `(255,0,255,0,200,800)`",
+            ),
+            (
+                22,
+                "\\clip(0,0,960)",
+                "\\clip takes 4 numbers or a drawing in parentheses, found `(0,0,960)`",
+            ),
+            (
+                23,
+                "\\clip(m 0 0 l x y)",
+                "\\clip takes 4 numbers or a drawing in parentheses, found `(m 0 0 l x y)`",
+            ),
+            (
+                25,
+                "\\an10",
+                "\\an takes an integer from 1 to 9, found `10`",
+            ),
+            (
+                27,
+                "\\alpha&H1FF&",
+                "\\alpha takes an alpha value like &HFF&, found `&H1FF&`",
+            ),
+            (
+                28,
+                "\\pos(960,40,1)",
+                "\\pos takes 2 numbers in parentheses, found `(960,40,1)`",
+            ),
+        ]
+        .map(|(line, tag, message)| (line, tag.to_string(), message.to_string()));
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn tolerant_section_headers_are_recognised_and_fixed() {
+        // Header line as written, and the line after applying every quick fix
+        let cases = [
+            ("[Events]   ", "[Events]   "),
+            ("[Events] extra words", "[Events]"),
+            ("[Events", "[Events]"),
+            ("  [Events]", "  [Events]"),
+            ("  [Events] extra", "  [Events]"),
+        ];
+        let validation = ValidationProvider::new();
+        for (header, expected) in cases {
+            let text = script(&[("0:00:01.00", "0:00:02.00", "Hi")]).replacen(
+                "[Events]\n",
+                &format!("{header}\n"),
+                1,
+            );
+            let document = AssParser::new().parse(&text);
+            assert!(
+                document
+                    .sections
+                    .iter()
+                    .any(|section| section.name == "Events"),
+                "{header:?} should open [Events]"
+            );
+            assert_eq!(document.events.len(), 1, "{header:?}");
+
+            let malformed: Vec<Diagnostic> = validation
+                .validate(&document, &uri())
+                .into_iter()
+                .filter(|d| {
+                    d.code == Some(NumberOrString::String("malformed_section_header".into()))
+                })
+                .collect();
+            assert_eq!(
+                malformed.len(),
+                usize::from(header != expected),
+                "{header:?}"
+            );
+
+            let index = LineIndex::new(text.clone(), crate::line_index::PositionEncoding::Utf16);
+            let edits: Vec<TextEdit> = validation
+                .quick_fixes(&uri(), &index, &malformed)
+                .into_iter()
+                .filter_map(|action| match action {
+                    CodeActionOrCommand::CodeAction(action) => action.edit?.changes?.remove(&uri()),
+                    _ => None,
+                })
+                .flatten()
+                .collect();
+            let fixed = crate::line_index::apply_edits(
+                &text,
+                crate::line_index::PositionEncoding::Utf16,
+                &edits,
+            );
+            assert_eq!(fixed.lines().nth(9), Some(expected));
+        }
+    }
+
+    #[test]
+    fn positions_outside_play_res_are_marked_on_the_number() {
+        let text = script(&[
+            ("0:00:01.00", "0:00:02.00", "{\\pos(2500,540)}Off right"),
+            (
+                "0:00:02.00",
+                "0:00:03.00",
+                "{\\move(3000,540,960,-400)}Both ends off",
+            ),
+            (
+                "0:00:03.00",
+                "0:00:04.00",
+                "{\\pos(2000,1150)}Just past the edge",
+            ),
+            ("0:00:04.00", "0:00:05.00", "{\\org(5000,540)\\frz10}Bent"),
+        ]);
+        let document = AssParser::new().parse(&text);
+        let index = LineIndex::new(text.clone(), crate::line_index::PositionEncoding::Utf16);
+        let found: Vec<(u32, &str, Option<DiagnosticSeverity>)> = ValidationProvider::new()
+            .validate(&document, &uri())
+            .into_iter()
+            .filter(|d| d.code == Some(NumberOrString::String("position_outside_play_res".into())))
+            .map(|d| {
+                let line = index.line_text(d.range.start.line as usize);
+                let number =
+                    &line[d.range.start.character as usize..d.range.end.character as usize];
+                (d.range.start.line, number, d.severity)
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (11, "2500", Some(DiagnosticSeverity::WARNING)),
+                (12, "3000", Some(DiagnosticSeverity::WARNING)),
+                (12, "-400", Some(DiagnosticSeverity::WARNING)),
+                (14, "5000", Some(DiagnosticSeverity::INFORMATION)),
+            ]
+        );
+
+        // Without a resolution there is no frame to compare against
+        let unset = text.replace("PlayResX: 1920\nPlayResY: 1080\n", "");
+        let diagnostics =
+            ValidationProvider::new().validate(&AssParser::new().parse(&unset), &uri());
+        assert_eq!(codes(&diagnostics, "position_outside_play_res"), 0);
+    }
+
+    /// `text` after applying the quick fixes for its `code` diagnostics.
+    fn fixed(text: &str, code: &str) -> String {
+        let validation = ValidationProvider::new();
+        let found: Vec<Diagnostic> = validation
+            .validate(&AssParser::new().parse(text), &uri())
+            .into_iter()
+            .filter(|d| d.code == Some(NumberOrString::String(code.into())))
+            .collect();
+        let index = LineIndex::new(text.to_string(), crate::line_index::PositionEncoding::Utf16);
+        let edits: Vec<TextEdit> = validation
+            .quick_fixes(&uri(), &index, &found)
+            .into_iter()
+            .filter_map(|action| match action {
+                CodeActionOrCommand::CodeAction(action) => action.edit?.changes?.remove(&uri()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        crate::line_index::apply_edits(text, crate::line_index::PositionEncoding::Utf16, &edits)
+    }
+
+    #[test]
+    fn missing_style_is_created_from_default_or_the_template() {
+        let sign = "Dialogue: 0,0:00:01.00,0:00:02.00,Sign,,0,0,0,,Hi\n";
+        let rest = "0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1";
+
+        // Copies Default under the section's Format, after the last style
+        let text = format!("{HEADER}{sign}");
+        let created = fixed(&text, "undefined_style");
+        assert_eq!(
+            created.lines().nth(8),
+            Some(
+                format!("Style: Sign,Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,{rest}")
+                    .as_str()
+            )
+        );
+        let document = AssParser::new().parse(&created);
+        assert!(document.style("Sign").is_some());
+        assert_eq!(
+            codes(
+                &ValidationProvider::new().validate(&document, &uri()),
+                "undefined_style"
+            ),
             0
+        );
+
+        // Without a Default the built-in template fills the fields
+        let text = format!(
+            "{}{sign}",
+            HEADER.replace("Style: Default,", "Style: Main,")
+        );
+        assert_eq!(
+            fixed(&text, "undefined_style").lines().nth(8),
+            Some(
+                format!("Style: Sign,Arial,20,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,{rest}")
+                    .as_str()
+            )
+        );
+
+        // Without a styles section one is added, with its Format line, above
+        // [Events]
+        let text = format!(
+            "[Script Info]\nScriptType: v4.00+\n\n[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n{sign}"
+        );
+        let created = fixed(&text, "undefined_style");
+        let lines: Vec<&str> = created.lines().collect();
+        assert_eq!(lines[3], "[V4+ Styles]");
+        assert_eq!(
+            lines[4],
+            format!("Format: {}", V4_PLUS_STYLE_FORMAT.join(", "))
+        );
+        assert!(lines[5].starts_with("Style: Sign,Arial,20,"));
+        assert_eq!(&lines[6..8], ["", "[Events]"]);
+        let document = AssParser::new().parse(&created);
+        assert_eq!(document.style("Sign").map(|style| style.fontsize), Some(20));
+    }
+
+    #[test]
+    fn attachment_headers_are_checked() {
+        let text = format!(
+            "{HEADER}\n[Fonts]\nfontname: Sign Font.ttf\nM0\nfontname: main.ttf\nM0\nfontname: MAIN.ttf\nM0\n\n[Graphics]\nfontname: logo.png\nM0\nfilename: main.ttf\nM0\n"
+        );
+        let document = AssParser::new().parse(&text);
+        let index = LineIndex::new(text.clone(), crate::line_index::PositionEncoding::Utf16);
+        let found: Vec<(u32, String, String)> = ValidationProvider::new()
+            .validate(&document, &uri())
+            .into_iter()
+            .filter_map(|d| {
+                let Some(NumberOrString::String(code)) = &d.code else {
+                    return None;
+                };
+                code.contains("attachment").then(|| {
+                    let line = index.line_text(d.range.start.line as usize);
+                    let span =
+                        &line[d.range.start.character as usize..d.range.end.character as usize];
+                    (d.range.start.line, code.clone(), span.to_string())
+                })
+            })
+            .collect();
+        // The same filename in another section isn't a duplicate
+        let expected = [
+            (13, "unsafe_attachment_filename", "Sign Font.ttf"),
+            (17, "duplicate_attachment", "MAIN.ttf"),
+            (21, "attachment_key_mismatch", "fontname: "),
+        ]
+        .map(|(line, code, span)| (line, code.to_string(), span.to_string()));
+        assert_eq!(found, expected);
+    }
+
+    /// The text each `code` diagnostic covers, with its line.
+    fn spans(text: &str, diagnostics: &[Diagnostic], code: &str) -> Vec<(u32, String)> {
+        let index = LineIndex::new(text.to_string(), crate::line_index::PositionEncoding::Utf16);
+        diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String(code.into())))
+            .map(|d| {
+                let line = index.line_text(d.range.start.line as usize);
+                let span = &line[d.range.start.character as usize..d.range.end.character as usize];
+                (d.range.start.line, span.to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn empty_override_blocks_are_unnecessary_but_markers_are_not() {
+        let text = script(&[
+            ("0:00:01.00", "0:00:02.00", "{}Hello{ }there"),
+            (
+                "0:00:02.00",
+                "0:00:03.00",
+                "{*}Marked{TL note}text{\\b1}bold",
+            ),
+        ]);
+        let diagnostics =
+            ValidationProvider::new().validate(&AssParser::new().parse(&text), &uri());
+        assert_eq!(
+            spans(&text, &diagnostics, "empty_override_block"),
+            [(11, "{}".to_string()), (11, "{ }".to_string())]
+        );
+        assert!(diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String("empty_override_block".into())))
+            .all(|d| d.tags == Some(vec![DiagnosticTag::UNNECESSARY])));
+    }
+
+    #[test]
+    fn effects_are_checked_against_their_syntax() {
+        let effects = [
+            ("Banner;30", None),
+            ("Scroll up;100;400;20", None),
+            ("Banner;30;1;", None),
+            ("karaoke", None),
+            ("Banner:30", Some("unknown_effect")),
+            ("Shake", Some("unknown_effect")),
+            ("Scroll up;100", Some("invalid_effect")),
+            ("Banner;fast", Some("invalid_effect")),
+            ("Banner;1;0;10;5", Some("invalid_effect")),
+        ];
+        let mut text = HEADER.to_string();
+        for (effect, _) in effects {
+            text.push_str(&format!(
+                "Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,{effect},Hi\n"
+            ));
+        }
+        let diagnostics =
+            ValidationProvider::new().validate(&AssParser::new().parse(&text), &uri());
+
+        let mut found = spans(&text, &diagnostics, "unknown_effect");
+        found.extend(spans(&text, &diagnostics, "invalid_effect"));
+        found.sort();
+        let expected: Vec<(u32, String)> = (11..)
+            .zip(effects)
+            .filter(|(_, (_, code))| code.is_some())
+            .map(|(line, (effect, _))| (line, effect.to_string()))
+            .collect();
+        assert_eq!(found, expected);
+
+        let message = |line: u32| {
+            diagnostics
+                .iter()
+                .find(|d| {
+                    d.range.start.line == line
+                        && matches!(&d.code, Some(NumberOrString::String(code)) if code.ends_with("_effect"))
+                })
+                .map(|d| d.message.as_str())
+                .unwrap_or_default()
+        };
+        assert!(message(15).contains("parameters are separated by `;`"));
+        assert!(message(17).contains("Scroll up needs 3 parameters, found 1"));
+        assert!(message(18).contains("`fast` is not an integer"));
+        assert!(message(19).contains("at most 3 parameters, found 4"));
+    }
+
+    #[test]
+    fn fades_are_checked_against_the_event_duration() {
+        let texts = [
+            "{\\fad(200,300)}Fits",
+            "{\\fad(1200,100)}Fade in too long",
+            "{\\fad(600,600)}Together too long",
+            "{\\fade(255,0,255,0,200,800,1000)}Fits",
+            "{\\fade(300,0,255,0,200,800,1000)}Alpha",
+            "{\\fade(255,0,255,500,400,900,1000)}Out of order",
+            "{\\fade(255,0,255,0,200,800,1500)}Ends late",
+        ];
+        // Each event lasts a second
+        let text = script(&texts.map(|text| ("0:00:01.00", "0:00:02.00", text)));
+        let diagnostics =
+            ValidationProvider::new().validate(&AssParser::new().parse(&text), &uri());
+
+        let expected = [(12, "1200"), (13, "600,600"), (16, "400"), (17, "1500")]
+            .map(|(line, span)| (line, span.to_string()));
+        assert_eq!(spans(&text, &diagnostics, "invalid_fade_timing"), expected);
+        assert_eq!(
+            spans(&text, &diagnostics, "invalid_fade_alpha"),
+            [(15, "300".to_string())]
+        );
+    }
+
+    #[test]
+    fn inverted_rectangles_and_broken_clip_drawings_are_reported() {
+        let texts = [
+            "{\\clip(100,100,500,400)}Rectangle",
+            "{\\clip(500,100,100,400)}Swapped x",
+            "{\\iclip(100,400,500,100)}Swapped y",
+            "{\\clip(m 0 0 l 100 0 100 100)}Drawing",
+            "{\\clip(l 0 0 100 100)}No move",
+            "{\\clip(2,m 0 0 b 10 10 20 20)}Short curve",
+        ];
+        let text = script(&texts.map(|text| ("0:00:01.00", "0:00:02.00", text)));
+        let diagnostics =
+            ValidationProvider::new().validate(&AssParser::new().parse(&text), &uri());
+
+        let expected = [(12, "500,100,100,400"), (13, "100,400,500,100")]
+            .map(|(line, span)| (line, span.to_string()));
+        assert_eq!(spans(&text, &diagnostics, "inverted_clip"), expected);
+        let expected = [(15, "l 0 0 100 100"), (16, "2,m 0 0 b 10 10 20 20")]
+            .map(|(line, span)| (line, span.to_string()));
+        assert_eq!(spans(&text, &diagnostics, "invalid_clip_drawing"), expected);
+
+        let messages: Vec<&str> = diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String("invalid_clip_drawing".into())))
+            .map(|d| d.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "\\clip drawing doesn't start with a move (m)",
+                "\\clip drawing has 2 points after `b`, which takes them in threes",
+            ]
+        );
+    }
+
+    #[test]
+    fn format_lines_are_checked() {
+        const FORMAT_CODES: [&str; 4] = [
+            "unknown_format_field",
+            "duplicate_format_field",
+            "misplaced_text_field",
+            "missing_format_line",
+        ];
+        let validation = ValidationProvider::new();
+        let hi = ("0:00:01.00", "0:00:02.00", "Hi");
+
+        // The usual lines, and SSA's Marked column, are fine
+        let text = script(&[hi]).replace("Format: Layer,", "Format: Marked,");
+        let diagnostics = validation.validate(&AssParser::new().parse(&text), &uri());
+        assert!(FORMAT_CODES
+            .iter()
+            .all(|code| codes(&diagnostics, code) == 0));
+
+        let text = script(&[hi])
+            .replace(
+                "Name, Fontname, Fontsize,",
+                "Name, Fontnmae, Fontsize, Fontsize,",
+            )
+            .replace("Effect, Text", "Text, Effect");
+        let diagnostics = validation.validate(&AssParser::new().parse(&text), &uri());
+        assert_eq!(
+            spans(&text, &diagnostics, "unknown_format_field"),
+            [(6, "Fontnmae".to_string())]
+        );
+        let unknown = diagnostics
+            .iter()
+            .find(|d| d.code == Some(NumberOrString::String("unknown_format_field".into())))
+            .unwrap();
+        assert_eq!(
+            unknown.data,
+            Some(serde_json::json!({ "suggestion": "Fontname" }))
+        );
+
+        assert_eq!(
+            spans(&text, &diagnostics, "duplicate_format_field"),
+            [(6, "Fontsize".to_string())]
+        );
+        let duplicate = diagnostics
+            .iter()
+            .find(|d| d.code == Some(NumberOrString::String("duplicate_format_field".into())))
+            .unwrap();
+        let first = &duplicate.related_information.as_ref().unwrap()[0]
+            .location
+            .range;
+        assert!(first.start.character < duplicate.range.start.character);
+
+        assert_eq!(
+            spans(&text, &diagnostics, "misplaced_text_field"),
+            [(10, "Text".to_string())]
+        );
+
+        // Dialogue lines without a Format line above them
+        let text = script(&[hi]).replace(
+            "Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n",
+            "",
+        );
+        let diagnostics = validation.validate(&AssParser::new().parse(&text), &uri());
+        assert_eq!(
+            spans(&text, &diagnostics, "missing_format_line"),
+            [(9, "[Events]".to_string())]
+        );
+    }
+
+    #[test]
+    fn script_type_is_checked_against_the_styles_section() {
+        let validation = ValidationProvider::new();
+        let check = |text: &str| {
+            let diagnostics = validation.validate(&AssParser::new().parse(text), &uri());
+            let mut found = spans(text, &diagnostics, "script_type_mismatch");
+            found.extend(spans(text, &diagnostics, "missing_script_type"));
+            (found, diagnostics)
+        };
+
+        let (found, _) = check(HEADER);
+        assert!(found.is_empty());
+        // Renderers keep the last ScriptType
+        let (found, _) = check(&HEADER.replace(
+            "ScriptType: v4.00+\n",
+            "ScriptType: v4.00\nScriptType: v4.00+\n",
+        ));
+        assert!(found.is_empty());
+        // SSA doesn't need a ScriptType
+        let ssa = HEADER
+            .replace("ScriptType: v4.00+\n", "")
+            .replace("[V4+ Styles]", "[V4 Styles]");
+        let (found, _) = check(&ssa);
+        assert!(found.is_empty());
+
+        let (found, diagnostics) =
+            check(&HEADER.replace("ScriptType: v4.00+", "ScriptType: v4.00"));
+        assert_eq!(found, [(1, "v4.00".to_string())]);
+        let mismatch = diagnostics
+            .iter()
+            .find(|d| d.code == Some(NumberOrString::String("script_type_mismatch".into())))
+            .unwrap();
+        let related = mismatch.related_information.as_ref().unwrap();
+        assert_eq!(related[0].location.range.start.line, 5);
+
+        let (found, _) = check(&HEADER.replace("ScriptType: v4.00+\n", ""));
+        assert_eq!(found, [(0, "[Script Info]".to_string())]);
+    }
+
+    #[test]
+    fn skipped_lines_are_reported_as_errors() {
+        let text = script(&[("0:00:01.00", "0:00:02.00", "Hi")])
+            .replace("PlayResY: 1080\n", "PlayResY: 1080\nTitle Example\n")
+            .replace("\n\n[Events]\n", "\nStyle: Short,Arial\n\n[Events]\n")
+            + "Dialog: 0,0:00:02.00,0:00:03.00,Default,,0,0,0,,Typo\n";
+        let diagnostics =
+            ValidationProvider::new().validate(&AssParser::new().parse(&text), &uri());
+        let found: Vec<(u32, &str, Option<DiagnosticSeverity>)> = diagnostics
+            .iter()
+            .filter_map(|d| match &d.code {
+                Some(NumberOrString::String(code))
+                    if ["too_few_fields", "missing_colon", "unknown_line_prefix"]
+                        .contains(&code.as_str()) =>
+                {
+                    Some((d.range.start.line, code.as_str(), d.severity))
+                }
+                _ => None,
+            })
+            .collect();
+        let error = Some(DiagnosticSeverity::ERROR);
+        assert_eq!(
+            found,
+            [
+                (4, "missing_colon", error),
+                (9, "too_few_fields", error),
+                (14, "unknown_line_prefix", error),
+            ]
+        );
+        // The whole line is marked
+        let typo = diagnostics
+            .iter()
+            .find(|d| d.range.start.line == 14)
+            .unwrap();
+        assert_eq!(
+            typo.range.end.character as usize,
+            text.lines().nth(14).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn only_reversed_times_are_out_of_order() {
+        let text = script(&[
+            ("0:00:01.00", "0:00:02.50", "Forward"),
+            ("0:00:04.00", "0:00:03.00", "Reversed"),
+            ("0:00:05.00", "0:00:05.00", "Zero length"),
+            ("0:00:0x.00", "0:00:03.00", "Malformed"),
+        ]);
+        let document = AssParser::new().parse(&text);
+        let durations: Vec<Option<AssTime>> = document.events.iter().map(Event::duration).collect();
+        assert_eq!(
+            durations,
+            [Some(AssTime(150)), None, Some(AssTime(0)), None]
+        );
+
+        let diagnostics = ValidationProvider::new().validate(&document, &uri());
+        let reversed: Vec<&Diagnostic> = diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String("invalid_time_order".into())))
+            .collect();
+        assert_eq!(reversed.len(), 1);
+        assert_eq!(reversed[0].range.start.line, 12);
+        let data = reversed[0].data.as_ref().unwrap();
+        assert_eq!(
+            (data["start"].as_str(), data["end"].as_str()),
+            (Some("0:00:04.00"), Some("0:00:03.00"))
+        );
+    }
+
+    #[test]
+    fn transforms_are_checked_for_form_timing_and_tags() {
+        let texts = [
+            "{\\t(0,500,\\fscx120)}Grows",
+            "{\\t(\\frz30)}Whole line",
+            "{\\t(800,200,\\blur2)}Backwards",
+            "{\\t(0,2000,\\blur2)}Too long",
+            "{\\t(0,500,\\an8\\fs40)}Jumps",
+            "{\\t(0,500,\\blur2}Unclosed",
+            "{\\t(0,500)}Nothing",
+            "{\\t(0,1,2,3,\\blur2)}Crowded",
+        ];
+        let text = script(&texts.map(|text| ("0:00:01.00", "0:00:02.00", text)));
+        let diagnostics =
+            ValidationProvider::new().validate(&AssParser::new().parse(&text), &uri());
+        let expected = |found: &[(u32, &str)]| -> Vec<(u32, String)> {
+            found
+                .iter()
+                .map(|(line, span)| (*line, span.to_string()))
+                .collect()
         };
+        assert_eq!(
+            spans(&text, &diagnostics, "invalid_transform_timing"),
+            expected(&[(13, "\\t(800,200,\\blur2)"), (14, "\\t(0,2000,\\blur2)")])
+        );
+        assert_eq!(
+            spans(&text, &diagnostics, "non_animatable_tag"),
+            expected(&[(15, "\\an8")])
+        );
+        // The extra argument is marked on its own
+        assert_eq!(
+            spans(&text, &diagnostics, "malformed_transform"),
+            expected(&[(16, "\\t(0,500,\\blur2"), (17, "\\t(0,500)"), (18, "3")])
+        );
+
+        let messages: Vec<&str> = diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String("invalid_transform_timing".into())))
+            .map(|d| d.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "Transform starts at 800ms but ends at 200ms",
+                "Transform runs 0-2000ms, outside the event's 1000ms duration",
+            ]
+        );
+    }
+
+    #[test]
+    fn events_with_the_wrong_field_count_are_kept_and_reported() {
+        let text = format!(
+            "{HEADER}Dialogue: 0,0:00:01.00,0:00:02.00,Default,Short\n\
+             Dialogue: 0,0:00:03.00,0:00:04.00,Default,Mio,Sr,0,0,0,,Extra comma\n\
+             Dialogue: 0,0:00:05.00,0:00:06.00,Default,,0,0,0,,Commas, in the text, are fine\n"
+        );
+        let document = AssParser::new().parse(&text);
+        let kept: Vec<(&str, Option<(usize, usize)>)> = document
+            .events
+            .iter()
+            .map(|event| (event.text.as_str(), event.field_count_mismatch))
+            .collect();
+        assert_eq!(
+            kept,
+            [
+                ("Short", Some((10, 5))),
+                // Fields still go by the Format, so the extra one spills
+                // into Text
+                (",Extra comma", Some((10, 11))),
+                ("Commas, in the text, are fine", None),
+            ]
+        );
+
+        let diagnostics = ValidationProvider::new().validate(&document, &uri());
+        let messages: Vec<(u32, &str)> = diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String("field_count_mismatch".into())))
+            .map(|d| (d.range.start.line, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (11, "Event has 5 fields but the Events Format declares 10"),
+                (12, "Event has 11 fields but the Events Format declares 10"),
+            ]
+        );
+    }
+
+    #[test]
+    fn well_known_script_info_values_are_checked() {
+        let text = "[Script Info]\nScriptType: v4.00+\nWrapStyle: 2\nScaledBorderAndShadow: Yes\nTimer: 100.0000\nCollisions: reverse\nOriginal Script: anything at all\n\
+                    PlayResX: 0\nWrapStyle: 4\nscaledborderandshadow: true\nTimer: fast\nCollisions: Sideways\nScriptType: v5\n";
+        let diagnostics = ValidationProvider::new().validate(&AssParser::new().parse(text), &uri());
+        let found: Vec<(u32, &str, Option<DiagnosticSeverity>)> = diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String("invalid_script_info_value".into())))
+            .map(|d| (d.range.start.line, d.message.as_str(), d.severity))
+            .collect();
+        let (error, warning) = (
+            Some(DiagnosticSeverity::ERROR),
+            Some(DiagnosticSeverity::WARNING),
+        );
+        assert_eq!(
+            found,
+            [
+                (
+                    7,
+                    "Invalid PlayResX '0': expected a positive integer",
+                    error
+                ),
+                (8, "Invalid WrapStyle '4': expected 0, 1, 2 or 3", error),
+                // Keys are matched ignoring case and named canonically
+                (
+                    9,
+                    "Invalid ScaledBorderAndShadow 'true': expected yes or no",
+                    warning
+                ),
+                (
+                    10,
+                    "Invalid Timer 'fast': expected a decimal number such as 100.0000",
+                    warning
+                ),
+                (
+                    11,
+                    "Invalid Collisions 'Sideways': expected Normal or Reverse",
+                    warning
+                ),
+                (
+                    12,
+                    "Invalid ScriptType 'v5': expected v4.00+ (ASS) or v4.00 (SSA)",
+                    warning
+                ),
+            ]
+        );
+        assert_eq!(
+            spans(text, &diagnostics, "invalid_script_info_value")[2].1,
+            "true"
+        );
+    }
+
+    #[test]
+    fn unknown_tags_suggest_the_closest_known_tag() {
+        let text = script(&[
+            ("0:00:01.00", "0:00:02.00", "{\\blurr3\\bord2}Typo"),
+            ("0:00:02.00", "0:00:03.00", "{\\zzz}Nonsense"),
+            (
+                "0:00:03.00",
+                "0:00:04.00",
+                "{\\fscx120\\1c&HFF&\\kf20}Known",
+            ),
+        ]);
+        let diagnostics =
+            ValidationProvider::new().validate(&AssParser::new().parse(&text), &uri());
+        assert_eq!(
+            spans(&text, &diagnostics, "unknown_tag"),
+            [(11, "\\blurr".to_string()), (12, "\\zzz".to_string())]
+        );
+        let found: Vec<(&str, &serde_json::Value)> = diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String("unknown_tag".into())))
+            .map(|d| (d.message.as_str(), d.data.as_ref().unwrap()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "Unknown override tag \\blurr, did you mean \\blur?",
+                    &serde_json::json!({ "tag": "blurr", "suggestion": "blur" })
+                ),
+                (
+                    "Unknown override tag \\zzz",
+                    &serde_json::json!({ "tag": "zzz", "suggestion": null })
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reading_speed_is_graded_against_both_limits() {
+        let a = |count: usize| "a".repeat(count);
+        let texts = [
+            a(36),
+            format!("{{\\b1\\c&HFF&}}{}", a(36)),
+            a(40),
+            a(60),
+            format!("{{\\p1}}m 0 0 l {}", "100 0 ".repeat(20)),
+        ];
+        let events: Vec<(&str, &str, &str)> = texts
+            .iter()
+            .map(|text| ("0:00:01.00", "0:00:03.00", text.as_str()))
+            .collect();
+        let text = script(&events);
+        let diagnostics =
+            ValidationProvider::new().validate(&AssParser::new().parse(&text), &uri());
+        let found: Vec<(u32, Option<DiagnosticSeverity>, &str)> = diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String("high_cps".into())))
+            .map(|d| (d.range.start.line, d.severity, d.message.as_str()))
+            .collect();
+        // 18 per second is allowed, and tags and drawings aren't read
+        assert_eq!(
+            found,
+            [
+                (
+                    13,
+                    Some(DiagnosticSeverity::INFORMATION),
+                    "20.0 characters per second is above the limit of 18: 40 characters in 2.00s"
+                ),
+                (
+                    14,
+                    Some(DiagnosticSeverity::WARNING),
+                    "30.0 characters per second is above the limit of 25: 60 characters in 2.00s"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn zero_length_and_flash_dialogue_is_flagged() {
+        let text = script(&[
+            ("0:00:01.00", "0:00:01.00", "Never shown"),
+            ("0:00:02.00", "0:00:02.20", "Flash"),
+            ("0:00:03.00", "0:00:03.50", "Just long enough"),
+        ]) + "Comment: 0,0:00:04.00,0:00:04.00,Default,,0,0,0,,Note\n";
+        let diagnostics =
+            ValidationProvider::new().validate(&AssParser::new().parse(&text), &uri());
+
+        // The Comment line isn't shown either way
+        assert_eq!(
+            spans(&text, &diagnostics, "zero_duration"),
+            [(11, "0:00:01.00,0:00:01.00".to_string())]
+        );
+        assert_eq!(
+            spans(&text, &diagnostics, "short_duration"),
+            [(12, "0:00:02.00,0:00:02.20".to_string())]
+        );
+        let flash = diagnostics
+            .iter()
+            .find(|d| d.code == Some(NumberOrString::String("short_duration".into())))
+            .unwrap();
+        assert_eq!(flash.severity, Some(DiagnosticSeverity::INFORMATION));
+        assert_eq!(
+            flash.message,
+            "Event is shown for only 200ms, under the 500ms minimum to be readable"
+        );
+
+        let mut relaxed = ValidationProvider::new();
+        relaxed.options.min_duration_ms = 100;
+        let diagnostics = relaxed.validate(&AssParser::new().parse(&text), &uri());
+        assert_eq!(codes(&diagnostics, "short_duration"), 0);
+    }
+
+    #[test]
+    fn styles_count_as_used_through_events_and_reset_tags() {
+        let default = HEADER.lines().nth(7).unwrap();
+        let extra: String = ["Sign", "Alt", "Orphan", "Muted"]
+            .iter()
+            .map(|name| format!("{}\n", default.replacen("Default", name, 1)))
+            .collect();
+        let text = HEADER.replacen(&format!("{default}\n"), &format!("{default}\n{extra}"), 1)
+            + "Dialogue: 0,0:00:01.00,0:00:02.00,Sign,,0,0,0,,Shop {\\rAlt}sale\n\
+               Comment: 0,0:00:02.00,0:00:03.00,Muted,,0,0,0,,Still a use\n";
+        let diagnostics =
+            ValidationProvider::new().validate(&AssParser::new().parse(&text), &uri());
+        let unused: Vec<&Diagnostic> = diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String("unused_style".into())))
+            .collect();
+        // Default is never reported, as renderers fall back to it
+        assert_eq!(unused.len(), 1);
+        assert_eq!(unused[0].range.start.line, 10);
+        assert_eq!(unused[0].message, "Style 'Orphan' is not used by any event");
+        assert_eq!(unused[0].severity, Some(DiagnosticSeverity::HINT));
+        assert_eq!(unused[0].tags, Some(vec![DiagnosticTag::UNNECESSARY]));
+    }
+
+    #[test]
+    fn rule_levels_retune_or_silence_codes_and_rows_can_be_capped() {
+        let text = script(&[
+            ("0:00:01.00", "0:00:01.20", "Flash"),
+            (
+                "0:00:02.00",
+                "0:00:04.00",
+                "Short row\\NA much longer second row",
+            ),
+        ]);
+        let mut validation = ValidationProvider::new();
+        validation.options.max_line_length = Some(20);
+        validation
+            .options
+            .rule_levels
+            .insert("long_line".into(), RuleLevel::Error);
+        validation
+            .options
+            .rule_levels
+            .insert("short_duration".into(), RuleLevel::Off);
+        let diagnostics = validation.validate(&AssParser::new().parse(&text), &uri());
+        let diagnostics = validation.apply_rule_levels(diagnostics);
+
+        assert_eq!(codes(&diagnostics, "short_duration"), 0);
+        let long = diagnostics
+            .iter()
+            .find(|d| d.code == Some(NumberOrString::String("long_line".into())))
+            .unwrap();
+        assert_eq!(long.range.start.line, 12);
+        assert_eq!(long.severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(
+            long.message,
+            "Row 2 has 24 characters, above the limit of 20"
+        );
+
+        validation.options.max_line_length = None;
+        let diagnostics = validation.validate(&AssParser::new().parse(&text), &uri());
+        assert_eq!(codes(&diagnostics, "long_line"), 0);
+    }
+
+    #[test]
+    fn only_exact_copies_on_the_same_layer_are_duplicates() {
+        let text = script(&[
+            ("0:00:01.00", "0:00:02.00", "Twice"),
+            ("0:00:01.00", "0:00:02.00", "Twice"),
+            ("0:00:01.00", "0:00:02.00", "{\\i1}Twice"),
+        ]) + "Dialogue: 1,0:00:01.00,0:00:02.00,Default,,0,0,0,,Twice\n\
+              Comment: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,Twice\n";
+        let diagnostics =
+            ValidationProvider::new().validate(&AssParser::new().parse(&text), &uri());
 
-        hours * 360000 + minutes * 6000 + seconds * 100 + centiseconds
+        let duplicates: Vec<&Diagnostic> = diagnostics
+            .iter()
+            .filter(|d| d.code == Some(NumberOrString::String("duplicate_event".into())))
+            .collect();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].range.start.line, 12);
+        assert_eq!(
+            duplicates[0].message,
+            "Duplicate of the event on line 12, drawn twice over itself"
+        );
+        let first = &duplicates[0].related_information.as_ref().unwrap()[0];
+        assert_eq!(first.location.range.start.line, 11);
     }
 }
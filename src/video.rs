@@ -0,0 +1,55 @@
+use crate::parser::AssTime;
+use std::path::Path;
+use std::process::Command;
+
+/// Length of the video at `path`, read with `ffprobe`. `None` if the file or
+/// `ffprobe` is missing, or the container doesn't say.
+pub fn probe_duration(path: &Path) -> Option<AssTime> {
+    if !path.is_file() {
+        return None;
+    }
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "format=duration",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(path)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_duration(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Reads `ffprobe`'s duration in seconds, such as `1425.482000`, rounded to
+/// the nearest centisecond.
+fn parse_duration(seconds: &str) -> Option<AssTime> {
+    let seconds: f64 = seconds.trim().parse().ok()?;
+    let centiseconds = (seconds * 100.0).round();
+    (seconds.is_finite() && (0.0..=f64::from(u32::MAX)).contains(&centiseconds))
+        .then_some(AssTime(centiseconds as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ffprobe_seconds() {
+        assert_eq!(parse_duration("1425.482000\n"), Some(AssTime(142548)));
+        assert_eq!(parse_duration("0.004"), Some(AssTime(0)));
+        assert_eq!(parse_duration("N/A\n"), None);
+        assert_eq!(parse_duration("-1.0"), None);
+        assert_eq!(parse_duration("1e12"), None);
+    }
+
+    #[test]
+    fn missing_file_has_no_duration() {
+        assert_eq!(probe_duration(Path::new("/nonexistent/video.mkv")), None);
+    }
+}
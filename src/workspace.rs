@@ -0,0 +1,373 @@
+use crate::encoding;
+use crate::parser::{AssDocument, AssParser, AssTime};
+use crate::text::visible_rows;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::*;
+
+/// Scripts read from disk per folder at most, so a folder holding a whole
+/// season isn't indexed line by line.
+const MAX_FOLDER_FILES: usize = 32;
+
+/// Scripts larger than this, in bytes, aren't read from disk.
+const MAX_FILE_SIZE: u64 = 4 * 1024 * 1024;
+
+/// A dialogue line as the cross-file comparison sees it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedEvent {
+    /// Visible text, without override blocks.
+    pub text: String,
+    pub start: AssTime,
+    pub end: AssTime,
+    /// The event line, in byte columns.
+    pub range: Range,
+}
+
+/// A line of one script that another script in its folder also has, such
+/// as a sign left in both the dialogue and the signs file.
+#[derive(Debug, Clone)]
+pub struct CrossFileDuplicate {
+    pub event: IndexedEvent,
+    pub other_path: PathBuf,
+    pub other: IndexedEvent,
+}
+
+/// Dialogue of the scripts in the folders of open documents, for finding
+/// lines that split scripts share. Open documents are indexed from their
+/// editor text and the rest of the folder from disk.
+#[derive(Debug, Default)]
+pub struct WorkspaceIndex {
+    files: HashMap<PathBuf, IndexedFile>,
+    /// Folders whose scripts have been read from disk.
+    loaded: HashSet<PathBuf>,
+    /// Duplicates found per file, dropped for a whole folder when one of
+    /// its entries changes.
+    duplicates: HashMap<PathBuf, Vec<CrossFileDuplicate>>,
+}
+
+#[derive(Debug)]
+struct IndexedFile {
+    events: Vec<IndexedEvent>,
+    /// Indices into `events` by text.
+    by_text: HashMap<String, Vec<usize>>,
+    /// Follows the editor rather than the disk.
+    open: bool,
+}
+
+impl IndexedFile {
+    fn new(events: Vec<IndexedEvent>, open: bool) -> Self {
+        let mut by_text: HashMap<String, Vec<usize>> = HashMap::new();
+        for (index, event) in events.iter().enumerate() {
+            by_text.entry(event.text.clone()).or_default().push(index);
+        }
+        Self {
+            events,
+            by_text,
+            open,
+        }
+    }
+}
+
+impl WorkspaceIndex {
+    /// Whether `folder`'s scripts still need reading with [`read_folder`].
+    pub fn needs_folder(&self, folder: &Path) -> bool {
+        !self.loaded.contains(folder)
+    }
+
+    /// Adds scripts read from `folder`, leaving any that were opened in the
+    /// meantime as the editor has them.
+    pub fn add_folder(&mut self, folder: &Path, scripts: Vec<(PathBuf, Vec<IndexedEvent>)>) {
+        self.loaded.insert(folder.to_path_buf());
+        for (path, events) in scripts {
+            if !self.files.get(&path).is_some_and(|file| file.open) {
+                self.set(path, IndexedFile::new(events, false));
+            }
+        }
+    }
+
+    /// Indexes an open document's current text. Returns whether its entry
+    /// changed, in which case other scripts in the folder may have gained or
+    /// lost duplicates.
+    pub fn update_open(&mut self, path: &Path, document: &AssDocument) -> bool {
+        let events = indexed_events(document);
+        let unchanged = self
+            .files
+            .get(path)
+            .is_some_and(|file| file.events == events);
+        if unchanged {
+            if let Some(file) = self.files.get_mut(path) {
+                file.open = true;
+            }
+            return false;
+        }
+        self.set(path.to_path_buf(), IndexedFile::new(events, true));
+        true
+    }
+
+    /// Goes back to the saved copy of a closed document, as its unsaved
+    /// edits are gone. Returns whether its entry changed.
+    pub fn close(&mut self, path: &Path, parser: &AssParser) -> bool {
+        let Some(file) = self.files.get(path) else {
+            return false;
+        };
+        let events = read_script(path, parser).unwrap_or_default();
+        if file.events == events {
+            self.files.get_mut(path).unwrap().open = false;
+            return false;
+        }
+        self.set(path.to_path_buf(), IndexedFile::new(events, false));
+        true
+    }
+
+    /// Follows a change on disk to the script at `path` while it isn't
+    /// open, reading it again or dropping it once it is gone. New scripts
+    /// join folders already read while there is room. Returns whether its
+    /// entry changed.
+    pub fn reload(&mut self, path: &Path, parser: &AssParser) -> bool {
+        let folder = path.parent().unwrap_or(Path::new(""));
+        let tracked = self.files.contains_key(path);
+        let room = self
+            .files
+            .keys()
+            .filter(|other| other.parent() == Some(folder))
+            .count()
+            < MAX_FOLDER_FILES;
+        let joins = self.loaded.contains(folder) && is_script(path) && room;
+        if self.files.get(path).is_some_and(|file| file.open) || !(tracked || joins) {
+            return false;
+        }
+        match read_script(path, parser) {
+            Some(events)
+                if self
+                    .files
+                    .get(path)
+                    .is_some_and(|file| file.events == events) =>
+            {
+                false
+            }
+            Some(events) => {
+                self.set(path.to_path_buf(), IndexedFile::new(events, false));
+                true
+            }
+            None if tracked => {
+                self.files.remove(path);
+                self.duplicates
+                    .retain(|cached, _| cached.parent() != Some(folder));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Lines of the script at `path` that another script in its folder
+    /// has with the same visible text and an overlapping time.
+    pub fn duplicates(&mut self, path: &Path) -> Vec<CrossFileDuplicate> {
+        if let Some(duplicates) = self.duplicates.get(path) {
+            return duplicates.clone();
+        }
+        let Some(file) = self.files.get(path) else {
+            return Vec::new();
+        };
+
+        let mut others: Vec<(&PathBuf, &IndexedFile)> = self
+            .files
+            .iter()
+            .filter(|(other, _)| *other != path && other.parent() == path.parent())
+            .collect();
+        others.sort_by(|a, b| a.0.cmp(b.0));
+
+        let mut duplicates = Vec::new();
+        for event in &file.events {
+            let found = others.iter().find_map(|(other_path, other)| {
+                let index = other.by_text.get(&event.text)?.iter().find(|&&index| {
+                    let other = &other.events[index];
+                    event.start < other.end && other.start < event.end
+                })?;
+                Some((*other_path, &other.events[*index]))
+            });
+            if let Some((other_path, other)) = found {
+                duplicates.push(CrossFileDuplicate {
+                    event: event.clone(),
+                    other_path: other_path.clone(),
+                    other: other.clone(),
+                });
+            }
+        }
+        self.duplicates
+            .insert(path.to_path_buf(), duplicates.clone());
+        duplicates
+    }
+
+    fn set(&mut self, path: PathBuf, file: IndexedFile) {
+        self.duplicates
+            .retain(|cached, _| cached.parent() != path.parent());
+        self.files.insert(path, file);
+    }
+}
+
+/// Reads the scripts in `folder`, in name order and at most
+/// [`MAX_FOLDER_FILES`] of them, skipping `open` ones.
+pub fn read_folder(
+    folder: &Path,
+    open: &HashSet<PathBuf>,
+    parser: &AssParser,
+) -> Vec<(PathBuf, Vec<IndexedEvent>)> {
+    let Ok(entries) = std::fs::read_dir(folder) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| is_script(path))
+        .collect();
+    paths.sort();
+    paths.truncate(MAX_FOLDER_FILES);
+
+    paths
+        .into_iter()
+        .filter(|path| !open.contains(path))
+        .filter_map(|path| {
+            let events = read_script(&path, parser)?;
+            Some((path, events))
+        })
+        .collect()
+}
+
+/// Whether `path` names a script the index reads: an `.ass` file.
+fn is_script(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("ass"))
+}
+
+fn read_script(path: &Path, parser: &AssParser) -> Option<Vec<IndexedEvent>> {
+    let metadata = std::fs::metadata(path).ok()?;
+    if !metadata.is_file() || metadata.len() > MAX_FILE_SIZE {
+        return None;
+    }
+    let bytes = std::fs::read(path).ok()?;
+    let decoded = encoding::decode(&bytes, None).ok()?;
+    Some(indexed_events(&parser.parse(&decoded.text)))
+}
+
+/// Timed dialogue lines with visible text.
+fn indexed_events(document: &AssDocument) -> Vec<IndexedEvent> {
+    document
+        .events
+        .iter()
+        .filter(|event| event.event_type == "Dialogue")
+        .filter_map(|event| {
+            let text = visible_rows(&event.text, 2).join("\n").trim().to_string();
+            if text.is_empty() {
+                return None;
+            }
+            Some(IndexedEvent {
+                text,
+                start: event.start?,
+                end: event.end?,
+                range: event.range,
+            })
+        })
+        .collect()
+}
+
+/// An information diagnostic for a line another script also has. `related`
+/// points at the other line when that script is open, in client positions.
+pub fn duplicate_diagnostic(
+    duplicate: &CrossFileDuplicate,
+    related: Option<Location>,
+) -> Diagnostic {
+    Diagnostic {
+        range: duplicate.event.range,
+        severity: Some(DiagnosticSeverity::INFORMATION),
+        code: Some(NumberOrString::String("cross_file_duplicate".to_string())),
+        code_description: None,
+        source: Some("ass-lsp".to_string()),
+        message: format!(
+            "Also in {} on line {} at an overlapping time; muxed together, the line renders twice",
+            duplicate.other_path.display(),
+            duplicate.other.range.start.line + 1
+        ),
+        related_information: related.map(|location| {
+            vec![DiagnosticRelatedInformation {
+                location,
+                message: "Same line in the other script".to_string(),
+            }]
+        }),
+        tags: None,
+        data: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split_folder() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/split")
+    }
+
+    fn indexed_folder(parser: &AssParser) -> WorkspaceIndex {
+        let folder = split_folder();
+        let mut index = WorkspaceIndex::default();
+        assert!(index.needs_folder(&folder));
+        let scripts = read_folder(&folder, &HashSet::new(), parser);
+        index.add_folder(&folder, scripts);
+        assert!(!index.needs_folder(&folder));
+        index
+    }
+
+    #[test]
+    fn shared_sign_is_found_from_each_side() {
+        let parser = AssParser::new();
+        let mut index = indexed_folder(&parser);
+        let dialogue = split_folder().join("dialogue.ass");
+        let signs = split_folder().join("signs.ass");
+
+        // The same text at times that don't overlap isn't a duplicate
+        let found = index.duplicates(&dialogue);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].event.text, "Kiyosato Station");
+        assert_eq!(found[0].event.range.start.line, 13);
+        assert_eq!(found[0].other_path, signs);
+        assert_eq!(found[0].other.range.start.line, 12);
+
+        let found = index.duplicates(&signs);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].other_path, dialogue);
+        assert_eq!(found[0].other.range.start.line, 13);
+
+        let diagnostic = duplicate_diagnostic(&found[0], None);
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::INFORMATION));
+        assert!(diagnostic
+            .message
+            .starts_with(&format!("Also in {} on line 14", dialogue.display())));
+    }
+
+    #[test]
+    fn only_a_changed_entry_drops_the_folders_findings() {
+        let parser = AssParser::new();
+        let mut index = indexed_folder(&parser);
+        let dialogue = split_folder().join("dialogue.ass");
+        let signs = split_folder().join("signs.ass");
+        assert_eq!(index.duplicates(&signs).len(), 1);
+
+        // Opening the script as it is on disk changes nothing
+        let text = std::fs::read_to_string(&dialogue).unwrap();
+        assert!(!index.update_open(&dialogue, &parser.parse(&text)));
+        assert!(index.duplicates.contains_key(&signs));
+
+        // Deleting the sign from the dialogue clears it from the signs
+        let edited: String = text
+            .lines()
+            .filter(|line| !line.contains("Kiyosato"))
+            .map(|line| format!("{line}\n"))
+            .collect();
+        assert!(index.update_open(&dialogue, &parser.parse(&edited)));
+        assert!(index.duplicates.is_empty());
+        assert!(index.duplicates(&signs).is_empty());
+        assert!(index.duplicates(&dialogue).is_empty());
+
+        // Closing it unsaved goes back to the copy on disk
+        assert!(index.close(&dialogue, &parser));
+        assert_eq!(index.duplicates(&signs).len(), 1);
+    }
+}
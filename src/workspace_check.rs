@@ -0,0 +1,96 @@
+use crate::encoding;
+use std::path::{Path, PathBuf};
+
+/// Command that checks every script in the workspace folders, for clients
+/// that don't pull workspace diagnostics. Findings in scripts that aren't
+/// open are published; open ones keep their own diagnostics.
+pub const CHECK_WORKSPACE: &str = "assLsp.checkWorkspace";
+
+/// How much of the workspace folders a workspace check reads.
+#[derive(Debug, Clone)]
+pub struct WorkspaceCheck {
+    /// Scripts checked at most.
+    pub max_files: usize,
+    /// Scripts larger than this, in bytes, are skipped.
+    pub max_file_size: u64,
+}
+
+impl WorkspaceCheck {
+    pub fn new() -> Self {
+        Self {
+            max_files: 500,
+            max_file_size: 4 * 1024 * 1024,
+        }
+    }
+
+    /// The `.ass` and `.ssa` files under `folders`, each walked in name order
+    /// until [`max_files`](Self::max_files) are found. Hidden and symlinked
+    /// directories aren't entered, and a folder inside another is walked
+    /// only once. `found` is called with the count so far as each is found.
+    pub fn scripts(&self, folders: &[PathBuf], found: &mut dyn FnMut(usize)) -> Vec<PathBuf> {
+        let mut scripts = Vec::new();
+        for folder in folders {
+            let nested = folders
+                .iter()
+                .any(|other| other != folder && folder.starts_with(other));
+            if !nested {
+                self.walk(folder, &mut scripts, found);
+            }
+        }
+        scripts
+    }
+
+    fn walk(&self, dir: &Path, scripts: &mut Vec<PathBuf>, found: &mut dyn FnMut(usize)) {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        let mut entries: Vec<_> = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                Some((entry.path(), entry.file_type().ok()?))
+            })
+            .collect();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        for (path, file_type) in entries {
+            if scripts.len() >= self.max_files {
+                return;
+            }
+            let hidden = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if file_type.is_dir() && !hidden {
+                self.walk(&path, scripts, found);
+            } else if file_type.is_file() && is_script(&path) && self.fits(&path) {
+                scripts.push(path);
+                found(scripts.len());
+            }
+        }
+    }
+
+    /// The decoded text of the script at `path`, unless it can't be read or
+    /// has grown past [`max_file_size`](Self::max_file_size).
+    pub fn read(&self, path: &Path) -> Option<String> {
+        if !self.fits(path) {
+            return None;
+        }
+        let bytes = std::fs::read(path).ok()?;
+        Some(encoding::decode(&bytes, None).ok()?.text)
+    }
+
+    fn fits(&self, path: &Path) -> bool {
+        std::fs::metadata(path).is_ok_and(|metadata| metadata.len() <= self.max_file_size)
+    }
+}
+
+impl Default for WorkspaceCheck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn is_script(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| {
+        extension.eq_ignore_ascii_case("ass") || extension.eq_ignore_ascii_case("ssa")
+    })
+}
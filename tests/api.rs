@@ -0,0 +1,196 @@
+//! The prelude as library users see it. Each item is named with the
+//! signature, fields or variants they rely on, so a change to the public
+//! surface stops this from compiling and has to be made here on purpose.
+
+use ass_lsp::prelude::*;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+const SCRIPT: &str = "[Script Info]\nScriptType: v4.00+\n\n\
+    [V4+ Styles]\nFormat: Name, Fontname, Fontsize, PrimaryColour\nStyle: Default,Arial,48,&H00FFFFFF\n\n\
+    [Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n\
+    Dialogue: 0,0:00:01.00,0:00:03.50,Default,,0,0,0,,Hello\n";
+
+fn displays<T: Display>() {}
+
+#[test]
+fn export_functions() {
+    let _: fn(AssTime) -> String = srt_timestamp;
+    let _: fn(&AssDocument) -> String = to_srt;
+}
+
+#[test]
+fn parser() {
+    let _: fn() -> AssParser = AssParser::new;
+    let _: fn() -> AssParser = AssParser::default;
+    let _: fn(&AssParser, &str) -> AssDocument = AssParser::parse;
+    let _: fn(&AssParser, &str) -> String = AssParser::format;
+}
+
+#[test]
+fn documents_styles_and_events() {
+    let document = AssParser::new().parse(SCRIPT);
+    let AssDocument {
+        script_info,
+        styles,
+        events,
+        ..
+    } = &document;
+    let _: (&HashMap<String, String>, &Vec<Style>, &Vec<Event>) = (script_info, styles, events);
+    let _: for<'a> fn(&'a AssDocument, &str) -> Option<&'a Style> = AssDocument::style;
+
+    let Style {
+        name,
+        fontname,
+        fontsize,
+        primary,
+        secondary,
+        outline,
+        back,
+        range,
+        ..
+    } = &styles[0];
+    let _: (&String, &String, &u32) = (name, fontname, fontsize);
+    let _: [&Option<AssColor>; 4] = [primary, secondary, outline, back];
+    let _: &Range = range;
+    let _: for<'a> fn(&'a Style, &str) -> Option<&'a str> = Style::field;
+
+    let Event {
+        event_type,
+        start_time,
+        end_time,
+        start,
+        end,
+        style,
+        actor,
+        effect,
+        text,
+        range,
+        ..
+    } = &events[0];
+    let _: [&String; 7] = [event_type, start_time, end_time, style, actor, effect, text];
+    let _: [&Option<AssTime>; 2] = [start, end];
+    let _: &Range = range;
+    let _: fn(&Event) -> Option<AssTime> = Event::duration;
+}
+
+#[test]
+fn times_and_colours() {
+    let AssTime(centiseconds) = AssTime::default();
+    let _: u32 = centiseconds;
+    let _: fn(AssTime) -> u32 = AssTime::centiseconds;
+    let _: fn(AssTime) -> u64 = AssTime::as_millis;
+    let _: fn(AssTime, AssTime) -> Option<AssTime> = AssTime::checked_sub;
+    let _: fn(AssTime, AssTime) -> AssTime = AssTime::saturating_sub;
+    let _: fn(&str) -> Result<AssTime, ()> = AssTime::from_str;
+    displays::<AssTime>();
+
+    let AssColor { r, g, b, a } = AssColor::default();
+    let _: [u8; 4] = [r, g, b, a];
+    let _: fn(u32) -> AssColor = AssColor::from_packed;
+    let _: fn(AssColor) -> u32 = AssColor::packed;
+    let _: fn(AssColor) -> bool = AssColor::is_transparent;
+    let _: fn(&str) -> Result<AssColor, ColorError> = AssColor::from_str;
+    displays::<AssColor>();
+
+    let _ = |error: ColorError| match error {
+        ColorError::Empty | ColorError::TooManyDigits | ColorError::InvalidNumber => None,
+        ColorError::InvalidCharacters(characters) => Some(characters),
+    };
+    displays::<ColorError>();
+}
+
+#[test]
+fn render_targets() {
+    let _ = |target: RenderTarget| match target {
+        RenderTarget::Libass | RenderTarget::VsFilter => target.name(),
+    };
+    let _: fn(&str) -> Result<RenderTarget, ()> = RenderTarget::from_str;
+    assert_eq!(RenderTarget::default(), RenderTarget::Libass);
+}
+
+#[test]
+fn rule_levels() {
+    let _ = |level: RuleLevel| match level {
+        RuleLevel::Error | RuleLevel::Warning | RuleLevel::Info | RuleLevel::Hint => {
+            level.severity()
+        }
+        RuleLevel::Off => None,
+    };
+    let _: fn(RuleLevel) -> Option<DiagnosticSeverity> = RuleLevel::severity;
+}
+
+#[test]
+fn validation() {
+    let ValidationOptions {
+        timestamp_ceiling,
+        check_missing_fonts,
+        check_equivalent_styles,
+        check_event_order,
+        check_padding,
+        check_bidi_punctuation,
+        check_alignment_margins,
+        top_margin_fraction,
+        check_pasted_timing,
+        pasted_timing_gap_ms,
+        check_cross_file_duplicates,
+        padding_prefixes,
+        render_target,
+        strict_compat,
+        min_duration_ms,
+        karaoke_tolerance_cs,
+        cps_soft_limit,
+        cps_hard_limit,
+        max_line_length,
+        rule_levels,
+        max_diagnostics,
+    } = ValidationOptions::default();
+    let _: u32 = timestamp_ceiling;
+    let _: bool = check_missing_fonts;
+    let _: bool = check_equivalent_styles;
+    let _: bool = check_event_order;
+    let _: bool = check_padding;
+    let _: bool = check_bidi_punctuation;
+    let _: bool = check_alignment_margins;
+    let _: f64 = top_margin_fraction;
+    let _: bool = check_pasted_timing;
+    let _: u64 = pasted_timing_gap_ms;
+    let _: bool = check_cross_file_duplicates;
+    let _: Vec<String> = padding_prefixes;
+    let _: RenderTarget = render_target;
+    let _: bool = strict_compat;
+    let _: u64 = min_duration_ms;
+    let _: u32 = karaoke_tolerance_cs;
+    let _: f64 = cps_soft_limit;
+    let _: f64 = cps_hard_limit;
+    let _: Option<usize> = max_line_length;
+    let _: HashMap<String, RuleLevel> = rule_levels;
+    let _: usize = max_diagnostics;
+
+    let _: fn() -> ValidationProvider = ValidationProvider::new;
+    let _: fn() -> ValidationProvider = ValidationProvider::default;
+    let _: fn(ValidationOptions) -> ValidationProvider = ValidationProvider::with_options;
+    let _: fn(&ValidationProvider) -> &ValidationOptions = ValidationProvider::options;
+    let _: fn(&ValidationProvider, &AssDocument, &Url) -> Vec<Diagnostic> =
+        ValidationProvider::validate;
+}
+
+#[test]
+fn diagnostic_codes() {
+    let _: fn(&Diagnostic) -> Option<DiagnosticCode> = DiagnosticCode::of;
+    let _: fn(DiagnosticCode) -> &'static str = DiagnosticCode::as_str;
+    let _: fn(&str) -> Result<DiagnosticCode, UnknownDiagnosticCode> = DiagnosticCode::from_str;
+    assert!(DiagnosticCode::all().any(|code| code.as_str() == "undefined_style"));
+    displays::<DiagnosticCode>();
+
+    let UnknownDiagnosticCode(code) = UnknownDiagnosticCode(String::new());
+    let _: String = code;
+    let _: Box<dyn std::error::Error> = Box::new(UnknownDiagnosticCode(String::new()));
+}
+
+#[test]
+fn lsp_types() {
+    let _: (Position, Option<DiagnosticSeverity>) = (Range::default().start, None);
+    assert!(Url::parse("file:///episode.ass").is_ok());
+}